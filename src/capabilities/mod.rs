@@ -2,7 +2,7 @@
 
 use std::collections::HashMap;
 
-use crate::organism::{OrganKind, OrganId};
+use crate::organism::{self, OrganKind, OrganId, PeripheralKind, SystemTopology};
 
 /// High-level capability types that AION can reason about.
 ///
@@ -37,6 +37,24 @@ pub enum CapabilityKind {
     Other,
 }
 
+impl CapabilityKind {
+    /// Parse a short shell name such as `gpu` or `GpuWorkload`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "cortexcompute" | "compute" => Some(CapabilityKind::CortexCompute),
+            "storageio" | "storage" => Some(CapabilityKind::StorageIo),
+            "memoryaccess" | "memory" => Some(CapabilityKind::MemoryAccess),
+            "networkio" | "network" | "net" => Some(CapabilityKind::NetworkIo),
+            "sensorinput" | "sensor" => Some(CapabilityKind::SensorInput),
+            "motorcontrol" | "motor" => Some(CapabilityKind::MotorControl),
+            "gpuworkload" | "gpu" => Some(CapabilityKind::GpuWorkload),
+            "orchestration" => Some(CapabilityKind::Orchestration),
+            "other" => Some(CapabilityKind::Other),
+            _ => None,
+        }
+    }
+}
+
 /// A single capability instance attached to an organ.
 #[derive(Debug, Clone)]
 pub struct Capability {
@@ -112,7 +130,7 @@ impl CapabilityRegistry {
         let id = self.next_id;
         self.next_id = self.next_id.wrapping_add(1);
 
-        let cap = Capability::new(id, organ_id.clone(), kind, label, description, priority);
+        let cap = Capability::new(id, organ_id, kind, label, description, priority);
         self.by_id.insert(id, cap);
 
         self.by_organ
@@ -155,11 +173,27 @@ impl CapabilityRegistry {
         }
     }
 
+    /// Enable or disable every capability of a kind. Returns the IDs
+    /// whose state actually changed.
+    pub fn set_enabled_by_kind(&mut self, kind: CapabilityKind, enabled: bool) -> Vec<u64> {
+        let mut changed = Vec::new();
+        for cap in self.by_id.values_mut() {
+            if cap.kind == kind && cap.enabled != enabled {
+                cap.enabled = enabled;
+                changed.push(cap.id);
+            }
+        }
+        changed.sort();
+        changed
+    }
+
     /// Simple text dump for debugging / CLI.
     pub fn describe_all(&self) -> String {
         let mut out = String::new();
         out.push_str("Capabilities:\n");
-        for cap in self.by_id.values() {
+        let mut caps: Vec<&Capability> = self.by_id.values().collect();
+        caps.sort_by_key(|c| c.id);
+        for cap in caps {
             out.push_str(&format!(
                 " - #{id} organ={organ} kind={kind:?} [{state}] prio={prio:.2} :: {label}\n   {desc}\n",
                 id = cap.id,
@@ -174,3 +208,46 @@ impl CapabilityRegistry {
         out
     }
 }
+
+/// Build a registry from the organism topology.
+///
+/// Organ-level capabilities map onto their closest capability kind, and
+/// every GPU peripheral adds a `GpuWorkload` capability on its organ.
+pub fn registry_from_topology(topology: &SystemTopology) -> CapabilityRegistry {
+    let mut reg = CapabilityRegistry::new();
+
+    for organ in &topology.organs {
+        for cap in &organ.caps {
+            let (kind, priority) = match cap {
+                organism::CapabilityKind::Compute => (CapabilityKind::CortexCompute, 0.9),
+                organism::CapabilityKind::Perception => (CapabilityKind::SensorInput, 0.5),
+                organism::CapabilityKind::Actuation => (CapabilityKind::MotorControl, 0.5),
+                organism::CapabilityKind::Storage => (CapabilityKind::StorageIo, 0.8),
+                organism::CapabilityKind::Networking => (CapabilityKind::NetworkIo, 0.7),
+                organism::CapabilityKind::Planning => (CapabilityKind::Orchestration, 0.6),
+                organism::CapabilityKind::Learning => (CapabilityKind::Other, 0.3),
+            };
+            reg.register(
+                organ.id,
+                kind,
+                format!("{:?}/{:?}", organ.kind, cap),
+                format!("{:?} capability of the {:?} organ", cap, organ.kind),
+                priority,
+            );
+        }
+
+        for p in &organ.peripherals {
+            if let PeripheralKind::Gpu = p.kind {
+                reg.register(
+                    organ.id,
+                    CapabilityKind::GpuWorkload,
+                    format!("{}/gpu", p.name),
+                    format!("GPU workloads on {}", p.name),
+                    0.4,
+                );
+            }
+        }
+    }
+
+    reg
+}
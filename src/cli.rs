@@ -0,0 +1,181 @@
+//! `aion` subcommands that talk to a running kernel.
+//!
+//! `aion run` (or no subcommand) boots the kernel and `aion run-script`
+//! runs a batch script in one; everything else here connects to the
+//! control socket of a kernel that is already running, using the same
+//! AION_CONTROL settings, and runs shell lines in a JSON session:
+//!
+//! ```text
+//! aion status
+//! aion exec "damage cortex 0.1 && organs"
+//! aion watch kind=alert
+//! aion replay aion_pulses --fast
+//! aion snapshot export state.json
+//! ```
+//!
+//! The exit code is that of the shell result (0 ok, 1 failed, 2 not
+//! runnable as written), or 3 when the kernel cannot be reached.
+
+use std::fs;
+use std::path::Path;
+
+use crate::json::JsonValue;
+use crate::kernel::control::ControlClient;
+use crate::kernel::shell::quote;
+use crate::kernel::{ControlConfig, Pulse};
+
+pub const USAGE: &str = "usage: aion [run] | aion run-script <file> | aion status [--json] | \
+                         aion exec [--json] <line> | aion watch [--json] [kind=..] [source=..] [type=..] | \
+                         aion replay <journal> [--fast | --speed <x>] [--since <30s|5m|id>] [filters] | \
+                         aion snapshot export [file]";
+
+const EXIT_USAGE: i32 = 2;
+/// No kernel to talk to, or it went away.
+const EXIT_UNREACHABLE: i32 = 3;
+
+/// Run a client subcommand; returns the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let (json, args): (bool, Vec<&str>) = match args.iter().position(|a| *a == "--json") {
+        Some(i) if args.first() != Some(&"replay") => {
+            let mut rest = args.clone();
+            rest.remove(i);
+            (true, rest)
+        }
+        _ => (false, args),
+    };
+
+    let line = match args.as_slice() {
+        ["status"] => "status".to_string(),
+        ["exec", words @ ..] if !words.is_empty() => words.join(" "),
+        ["watch", filters @ ..] => return watch(filters, json),
+        ["replay", journal, options @ ..] => match replay_line(journal, options) {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("{}", e);
+                return EXIT_USAGE;
+            }
+        },
+        ["snapshot", "export"] | ["snapshot", "export", _] => "snapshot".to_string(),
+        ["help" | "--help" | "-h"] => {
+            println!("{}", USAGE);
+            return 0;
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            return EXIT_USAGE;
+        }
+    };
+
+    let mut client = match connect() {
+        Ok(c) => c,
+        Err(code) => return code,
+    };
+    let result = match client.call(&line) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_UNREACHABLE;
+        }
+    };
+    let text = |key: &str| result.get(key).and_then(JsonValue::as_str).unwrap_or("");
+    let exit = result.get("exit").and_then(JsonValue::as_f64).unwrap_or(1.0) as i32;
+
+    if let ["snapshot", "export", path] = args.as_slice() {
+        if exit == 0 {
+            if let Err(e) = fs::write(path, format!("{}\n", text("output"))) {
+                eprintln!("failed to write {}: {}", path, e);
+                return 1;
+            }
+            eprintln!("snapshot written to {}", path);
+            return 0;
+        }
+    }
+
+    if json {
+        println!("{}", result.to_json());
+    } else if exit == 0 {
+        if !text("output").is_empty() {
+            println!("{}", text("output"));
+        }
+    } else if !text("error").is_empty() {
+        eprintln!("{}", text("error"));
+    }
+    exit
+}
+
+fn connect() -> Result<ControlClient, i32> {
+    let config = match ControlConfig::from_env() {
+        Ok(Some(config)) => config,
+        Ok(None) => {
+            eprintln!("the control socket is off (AION_CONTROL=off)");
+            return Err(EXIT_UNREACHABLE);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            return Err(EXIT_USAGE);
+        }
+    };
+    ControlClient::connect(&config).map_err(|e| {
+        eprintln!("{}", e);
+        EXIT_UNREACHABLE
+    })
+}
+
+/// `replay` with the journal made absolute, since the kernel may run
+/// somewhere else.
+fn replay_line(journal: &str, options: &[&str]) -> Result<String, String> {
+    let dir = Path::new(journal)
+        .canonicalize()
+        .map_err(|e| format!("no journal at {}: {}", journal, e))?;
+    let mut words = vec!["replay".to_string(), quote(&dir.to_string_lossy())];
+    words.extend(options.iter().map(|o| quote(o)));
+    Ok(words.join(" "))
+}
+
+/// Subscribe and print pulses until the kernel goes away.
+fn watch(filters: &[&str], json: bool) -> i32 {
+    let mut client = match connect() {
+        Ok(c) => c,
+        Err(code) => return code,
+    };
+    let line = std::iter::once("subscribe".to_string())
+        .chain(filters.iter().map(|f| quote(f)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    match client.call(&line) {
+        Ok(r) if r.get("ok") == Some(&JsonValue::Bool(true)) => {
+            eprintln!("{}", r.get("output").and_then(JsonValue::as_str).unwrap_or(""));
+        }
+        Ok(r) => {
+            eprintln!("{}", r.get("error").and_then(JsonValue::as_str).unwrap_or(""));
+            return r.get("exit").and_then(JsonValue::as_f64).unwrap_or(1.0) as i32;
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_UNREACHABLE;
+        }
+    }
+
+    loop {
+        let line = match client.next_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => {
+                eprintln!("the kernel closed the connection");
+                return EXIT_UNREACHABLE;
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                return EXIT_UNREACHABLE;
+            }
+        };
+        let Some(pulse) = JsonValue::parse(&line).ok().and_then(|v| v.get("pulse").cloned()) else {
+            continue;
+        };
+        if json {
+            println!("{}", pulse.to_json());
+        } else if let Ok(p) = Pulse::from_json(&pulse.to_json()) {
+            println!("{}", p.log_line());
+        }
+    }
+}
@@ -0,0 +1,544 @@
+//! External model advisor for the cortex.
+//!
+//! An advisor is anything that speaks a tiny HTTP/JSON protocol: the
+//! cortex POSTs an `Observation` (see `Observation::to_json`) and the
+//! advisor answers with a proposal:
+//!
+//! ```text
+//! {
+//!   "policy": "reduce_load",
+//!   "confidence": 0.82,
+//!   "rationale": "memory pressure rising",
+//!   "actions": [ { "type": "sim_level", "value": "low", "confidence": 0.9 } ]
+//! }
+//! ```
+//!
+//! Nothing an advisor says is trusted as-is. The policy must be one the
+//! cortex knows, every action type must be on the allow-list and carry a
+//! valid value, and both the proposal and each action must clear the
+//! confidence threshold. Anything else is rejected and the `AdvisorBrain`
+//! falls back to its local brain.
+//!
+//! Requests run on a background thread so a slow or hung advisor never
+//! stalls the kernel loop; the brain uses the most recent accepted advice
+//! while it is still fresh.
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cortex::brain::{CortexBrain, Observation};
+use crate::cortex::journal::DecisionRecord;
+use crate::cortex::policy::PolicyDecision;
+use crate::json::JsonValue;
+
+/// Policies an advisor is allowed to propose.
+pub const KNOWN_POLICIES: &[&str] = &[
+    "push_capacity",
+    "maintain_load",
+    "reduce_load",
+    "protect_core(sim_off)",
+    "recover_offline",
+];
+
+/// Advisor connection and validation settings.
+#[derive(Debug, Clone)]
+pub struct AdvisorConfig {
+    /// `http://host:port/path`
+    pub endpoint: String,
+    pub timeout: Duration,
+    /// Action types (`sim_level`, …) the advisor may propose.
+    pub allowed_actions: Vec<String>,
+    /// Minimum confidence for the proposal and for each action.
+    pub min_confidence: f32,
+    /// Consult the advisor every N cortex cycles.
+    pub every_cycles: u64,
+    /// Advice older than this many cycles is ignored.
+    pub max_age_cycles: u64,
+}
+
+impl AdvisorConfig {
+    pub fn new(endpoint: impl Into<String>) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            timeout: Duration::from_millis(800),
+            allowed_actions: vec!["sim_level".to_string()],
+            min_confidence: 0.6,
+            every_cycles: 1,
+            max_age_cycles: 2,
+        }
+    }
+
+    /// Build from `AION_ADVISOR_*` environment variables, if an endpoint
+    /// is configured.
+    pub fn from_env() -> Option<Self> {
+        let endpoint = std::env::var("AION_ADVISOR_URL").ok()?;
+        let mut cfg = Self::new(endpoint);
+        if let Some(ms) = env_parse::<u64>("AION_ADVISOR_TIMEOUT_MS") {
+            cfg.timeout = Duration::from_millis(ms);
+        }
+        if let Some(c) = env_parse::<f32>("AION_ADVISOR_MIN_CONFIDENCE") {
+            cfg.min_confidence = c.clamp(0.0, 1.0);
+        }
+        if let Some(n) = env_parse::<u64>("AION_ADVISOR_EVERY") {
+            cfg.every_cycles = n.max(1);
+        }
+        if let Ok(list) = std::env::var("AION_ADVISOR_ACTIONS") {
+            cfg.allowed_actions = list
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
+        Some(cfg)
+    }
+}
+
+fn env_parse<T: std::str::FromStr>(key: &str) -> Option<T> {
+    std::env::var(key).ok()?.parse().ok()
+}
+
+/// A validated proposal from the advisor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Advice {
+    pub policy: String,
+    pub confidence: f32,
+    pub rationale: String,
+    /// Normalised `type=value` actions, same form as policy-set actions.
+    pub actions: Vec<String>,
+}
+
+/// Talks to one advisor endpoint.
+pub struct AdvisorClient {
+    pub config: AdvisorConfig,
+}
+
+impl AdvisorClient {
+    pub fn new(config: AdvisorConfig) -> Self {
+        Self { config }
+    }
+
+    /// Send one observation and return validated advice.
+    pub fn request(&self, observation_json: &str) -> Result<Advice, String> {
+        let body = http_post_json(&self.config.endpoint, observation_json, self.config.timeout)?;
+        let value = JsonValue::parse(&body).map_err(|e| format!("bad advisor JSON: {}", e))?;
+        self.validate(&value)
+    }
+
+    /// Check a parsed response against the allow-list and thresholds.
+    pub fn validate(&self, value: &JsonValue) -> Result<Advice, String> {
+        let policy = value
+            .get("policy")
+            .and_then(JsonValue::as_str)
+            .ok_or("response has no 'policy'")?;
+        let policy = policy.strip_prefix("policy=").unwrap_or(policy);
+        if !KNOWN_POLICIES.contains(&policy) {
+            return Err(format!("policy '{}' is not allowed", policy));
+        }
+
+        let confidence = value
+            .get("confidence")
+            .and_then(JsonValue::as_f64)
+            .ok_or("response has no 'confidence'")? as f32;
+        if confidence < self.config.min_confidence {
+            return Err(format!(
+                "confidence {:.2} below threshold {:.2}",
+                confidence, self.config.min_confidence
+            ));
+        }
+
+        let mut actions = Vec::new();
+        let proposed = value.get("actions").and_then(JsonValue::as_array).unwrap_or(&[]);
+        for action in proposed {
+            let kind = action
+                .get("type")
+                .and_then(JsonValue::as_str)
+                .ok_or("action has no 'type'")?;
+            if !self.config.allowed_actions.iter().any(|a| a == kind) {
+                return Err(format!("action type '{}' is not allowed", kind));
+            }
+            let value = action
+                .get("value")
+                .and_then(JsonValue::as_str)
+                .ok_or_else(|| format!("action '{}' has no 'value'", kind))?;
+            if !valid_action_value(kind, value) {
+                return Err(format!("invalid value '{}' for action '{}'", value, kind));
+            }
+            let action_conf = action
+                .get("confidence")
+                .and_then(JsonValue::as_f64)
+                .map(|c| c as f32)
+                .unwrap_or(confidence);
+            if action_conf < self.config.min_confidence {
+                return Err(format!(
+                    "action '{}' confidence {:.2} below threshold {:.2}",
+                    kind, action_conf, self.config.min_confidence
+                ));
+            }
+            actions.push(format!("{}={}", kind, value.to_lowercase()));
+        }
+
+        let rationale = value
+            .get("rationale")
+            .and_then(JsonValue::as_str)
+            .unwrap_or("")
+            .to_string();
+
+        Ok(Advice {
+            policy: policy.to_string(),
+            confidence,
+            rationale,
+            actions,
+        })
+    }
+}
+
+fn valid_action_value(kind: &str, value: &str) -> bool {
+    match kind {
+        "sim_level" => matches!(value.to_lowercase().as_str(), "off" | "low" | "high"),
+        _ => false,
+    }
+}
+
+/// Minimal blocking HTTP/1.0 POST with a hard timeout. Returns the body
+/// of a 2xx response.
+fn http_post_json(endpoint: &str, body: &str, timeout: Duration) -> Result<String, String> {
+    let rest = endpoint
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("only http:// endpoints are supported: {}", endpoint))?;
+    let (host, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let addr: SocketAddr = host
+        .to_socket_addrs()
+        .map_err(|e| format!("cannot resolve {}: {}", host, e))?
+        .next()
+        .ok_or_else(|| format!("cannot resolve {}", host))?;
+
+    let deadline = Instant::now() + timeout;
+    let mut stream = TcpStream::connect_timeout(&addr, timeout)
+        .map_err(|e| format!("connect to {} failed: {}", host, e))?;
+    stream.set_write_timeout(Some(timeout)).ok();
+
+    let request = format!(
+        "POST {} HTTP/1.0\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        host,
+        body.len(),
+        body
+    );
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|e| format!("send failed: {}", e))?;
+
+    let mut raw = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(format!("advisor timed out after {:?}", timeout));
+        }
+        stream.set_read_timeout(Some(left)).ok();
+        match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => raw.extend_from_slice(&buf[..n]),
+            Err(e)
+                if e.kind() == std::io::ErrorKind::WouldBlock
+                    || e.kind() == std::io::ErrorKind::TimedOut =>
+            {
+                return Err(format!("advisor timed out after {:?}", timeout));
+            }
+            Err(e) => return Err(format!("read failed: {}", e)),
+        }
+    }
+
+    let text = String::from_utf8_lossy(&raw);
+    let (head, body) = text
+        .split_once("\r\n\r\n")
+        .ok_or("malformed HTTP response")?;
+    let status: u16 = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .ok_or("malformed HTTP status line")?;
+    if !(200..300).contains(&status) {
+        return Err(format!("advisor returned HTTP {}", status));
+    }
+    Ok(body.to_string())
+}
+
+/// What the worker thread last heard from the advisor.
+#[derive(Debug, Default)]
+struct AdvisorState {
+    /// Cycle the latest result was requested for, and the result.
+    latest: Option<(u64, Result<Advice, String>)>,
+    in_flight: bool,
+    requests: u64,
+    accepted: u64,
+    rejected: u64,
+}
+
+/// A cortex brain that follows an external advisor when it can and a
+/// local brain when it cannot.
+pub struct AdvisorBrain {
+    config: AdvisorConfig,
+    fallback: Box<dyn CortexBrain>,
+    tx: Sender<(u64, String)>,
+    state: Arc<Mutex<AdvisorState>>,
+}
+
+impl AdvisorBrain {
+    pub fn new(config: AdvisorConfig, fallback: Box<dyn CortexBrain>) -> Self {
+        let (tx, rx) = mpsc::channel::<(u64, String)>();
+        let state = Arc::new(Mutex::new(AdvisorState::default()));
+
+        let client = AdvisorClient::new(config.clone());
+        let worker_state = Arc::clone(&state);
+        thread::spawn(move || Self::worker(client, rx, worker_state));
+
+        Self {
+            config,
+            fallback,
+            tx,
+            state,
+        }
+    }
+
+    fn worker(client: AdvisorClient, rx: Receiver<(u64, String)>, state: Arc<Mutex<AdvisorState>>) {
+        while let Ok(mut job) = rx.recv() {
+            // Only the newest observation matters.
+            while let Ok(newer) = rx.try_recv() {
+                job = newer;
+            }
+            let (cycle, obs) = job;
+            let result = client.request(&obs);
+
+            if let Ok(mut st) = state.lock() {
+                match &result {
+                    Ok(_) => st.accepted += 1,
+                    Err(_) => st.rejected += 1,
+                }
+                st.latest = Some((cycle, result));
+                st.in_flight = false;
+            }
+        }
+    }
+
+    fn fresh_advice(&self, cycle: u64) -> Result<Advice, String> {
+        let st = self.state.lock().map_err(|_| "advisor state poisoned".to_string())?;
+        match &st.latest {
+            Some((at, Ok(advice))) if cycle.saturating_sub(*at) <= self.config.max_age_cycles => {
+                Ok(advice.clone())
+            }
+            Some((_, Ok(_))) => Err("advice is stale".to_string()),
+            Some((_, Err(e))) => Err(e.clone()),
+            None => Err("no advice yet".to_string()),
+        }
+    }
+}
+
+impl CortexBrain for AdvisorBrain {
+    fn name(&self) -> &'static str {
+        "advisor"
+    }
+
+    fn decide(&mut self, obs: &Observation) -> PolicyDecision {
+        if obs.cycle.is_multiple_of(self.config.every_cycles) {
+            if let Ok(mut st) = self.state.lock() {
+                if !st.in_flight {
+                    st.in_flight = true;
+                    st.requests += 1;
+                    let _ = self.tx.send((obs.cycle, obs.to_json()));
+                }
+            }
+        }
+
+        match self.fresh_advice(obs.cycle) {
+            Ok(advice) => {
+                let rule = if advice.rationale.is_empty() {
+                    format!("advisor (confidence {:.2})", advice.confidence)
+                } else {
+                    format!(
+                        "advisor (confidence {:.2}): {}",
+                        advice.confidence, advice.rationale
+                    )
+                };
+                PolicyDecision {
+                    policy: format!("policy={}", advice.policy),
+                    rule,
+                    actions: advice.actions,
+                }
+            }
+            Err(why) => {
+                let mut d = self.fallback.decide(obs);
+                d.rule = format!("fallback to {} ({}): {}", self.fallback.name(), why, d.rule);
+                d
+            }
+        }
+    }
+
+    fn feedback(&mut self, record: &DecisionRecord) {
+        self.fallback.feedback(record);
+    }
+
+    fn describe(&self) -> String {
+        let (requests, accepted, rejected, last) = match self.state.lock() {
+            Ok(st) => {
+                let last = match &st.latest {
+                    Some((c, Ok(a))) => format!("cycle {} ok: {}", c, a.policy),
+                    Some((c, Err(e))) => format!("cycle {} rejected: {}", c, e),
+                    None => "none".to_string(),
+                };
+                (st.requests, st.accepted, st.rejected, last)
+            }
+            Err(_) => (0, 0, 0, "unavailable".to_string()),
+        };
+        format!(
+            "advisor: {} (timeout {:?}, min confidence {:.2}, actions [{}]) fallback={} :: \
+             requests {} accepted {} rejected {} :: last {}",
+            self.config.endpoint,
+            self.config.timeout,
+            self.config.min_confidence,
+            self.config.allowed_actions.join(","),
+            self.fallback.name(),
+            requests,
+            accepted,
+            rejected,
+            last
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiny_http::{Response, Server};
+
+    /// Start a stub advisor that answers every request with `reply`
+    /// after `delay`. Returns its endpoint URL.
+    fn stub_advisor(reply: &'static str, delay: Duration) -> String {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        thread::spawn(move || {
+            for mut req in server.incoming_requests() {
+                let mut body = String::new();
+                let _ = req.as_reader().read_to_string(&mut body);
+                assert!(JsonValue::parse(&body).is_ok(), "observation must be JSON");
+                thread::sleep(delay);
+                let _ = req.respond(Response::from_string(reply));
+            }
+        });
+        format!("http://{}/advise", addr)
+    }
+
+    fn client(endpoint: String) -> AdvisorClient {
+        let mut cfg = AdvisorConfig::new(endpoint);
+        cfg.timeout = Duration::from_millis(300);
+        AdvisorClient::new(cfg)
+    }
+
+    #[test]
+    fn accepts_valid_advice() {
+        let url = stub_advisor(
+            r#"{"policy":"reduce_load","confidence":0.9,"rationale":"hot",
+                "actions":[{"type":"sim_level","value":"Low","confidence":0.8}]}"#,
+            Duration::ZERO,
+        );
+        let advice = client(url).request(r#"{"cycle":1}"#).unwrap();
+        assert_eq!(advice.policy, "reduce_load");
+        assert_eq!(advice.actions, vec!["sim_level=low".to_string()]);
+        assert_eq!(advice.rationale, "hot");
+    }
+
+    #[test]
+    fn rejects_action_not_on_allow_list() {
+        let url = stub_advisor(
+            r#"{"policy":"reduce_load","confidence":0.9,
+                "actions":[{"type":"reboot","value":"now"}]}"#,
+            Duration::ZERO,
+        );
+        let err = client(url).request("{}").unwrap_err();
+        assert!(err.contains("not allowed"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_unknown_policy_and_low_confidence() {
+        let url = stub_advisor(r#"{"policy":"format_disk","confidence":0.99}"#, Duration::ZERO);
+        assert!(client(url).request("{}").unwrap_err().contains("not allowed"));
+
+        let url = stub_advisor(r#"{"policy":"maintain_load","confidence":0.2}"#, Duration::ZERO);
+        assert!(client(url).request("{}").unwrap_err().contains("below threshold"));
+
+        let url = stub_advisor(
+            r#"{"policy":"maintain_load","confidence":0.9,
+                "actions":[{"type":"sim_level","value":"off","confidence":0.1}]}"#,
+            Duration::ZERO,
+        );
+        assert!(client(url).request("{}").unwrap_err().contains("below threshold"));
+    }
+
+    #[test]
+    fn times_out_on_slow_advisor() {
+        let url = stub_advisor(
+            r#"{"policy":"maintain_load","confidence":0.9}"#,
+            Duration::from_millis(1000),
+        );
+        let err = client(url).request("{}").unwrap_err();
+        assert!(err.contains("timed out"), "{}", err);
+    }
+
+    #[test]
+    fn brain_falls_back_then_follows_advice() {
+        use crate::cortex::brain::LadderBrain;
+        use crate::memory::MemoryBus;
+        use crate::organism;
+        use crate::telemetry::SimLevel;
+
+        let url = stub_advisor(
+            r#"{"policy":"reduce_load","confidence":0.9,
+                "actions":[{"type":"sim_level","value":"low"}]}"#,
+            Duration::ZERO,
+        );
+        let mut cfg = AdvisorConfig::new(url);
+        cfg.timeout = Duration::from_millis(300);
+        let mut brain = AdvisorBrain::new(cfg, Box::new(LadderBrain));
+
+        let obs = |cycle| Observation {
+            cycle,
+            topology: organism::sample_topology(),
+            metrics: None,
+            memory: MemoryBus::new(),
+            alerts: Vec::new(),
+            awareness: 0.95,
+            sim_level: SimLevel::High,
+        };
+
+        // First cycle: nothing heard yet, so the local ladder decides.
+        let first = brain.decide(&obs(1));
+        assert_eq!(first.policy, "policy=push_capacity");
+        assert!(first.rule.starts_with("fallback to ladder"));
+
+        // Once the worker has an answer, the advice wins.
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let mut decision = brain.decide(&obs(2));
+        while decision.policy != "policy=reduce_load" && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(20));
+            decision = brain.decide(&obs(2));
+        }
+        assert_eq!(decision.policy, "policy=reduce_load");
+        assert_eq!(decision.actions, vec!["sim_level=low".to_string()]);
+    }
+
+    #[test]
+    fn unreachable_advisor_is_an_error_not_a_hang() {
+        // Port 9 (discard) on localhost is almost never listening.
+        let err = client("http://127.0.0.1:9/advise".to_string())
+            .request("{}")
+            .unwrap_err();
+        assert!(err.contains("failed") || err.contains("timed out"), "{}", err);
+    }
+}
@@ -0,0 +1,522 @@
+//! Pluggable cortex "brains".
+//!
+//! A `CortexBrain` looks at an `Observation` of the organism and returns
+//! a `PolicyDecision`. The AiDaemon owns no policy logic of its own any
+//! more; it builds the observation, asks the active brain, applies the
+//! decision and journals it.
+//!
+//! Shipped brains:
+//! - `ladder`  – the original hardcoded awareness threshold ladder
+//! - `rules`   – a `PolicySet` rule engine (built-in or loaded from file)
+//! - `bandit`  – an epsilon-greedy contextual bandit that learns from the
+//!   awareness delta the journal records a few cycles after each decision
+
+use std::collections::HashMap;
+
+use crate::cortex::journal::DecisionRecord;
+use crate::cortex::policy::{PolicyDecision, PolicySet};
+use crate::cortex::DecisionInputs;
+use crate::kernel::TelemetrySnapshot;
+use crate::json;
+use crate::memory::{MemoryBus, MemoryScope};
+use crate::organism::{self, SystemTopology};
+use crate::telemetry::SimLevel;
+
+/// An organ whose health is below "ok".
+#[derive(Debug, Clone)]
+pub struct OrganAlert {
+    pub organ: String,
+    pub health: f32,
+    pub level: &'static str,
+}
+
+/// Everything a brain gets to look at for one cortex cycle.
+pub struct Observation {
+    pub cycle: u64,
+    pub topology: SystemTopology,
+    pub metrics: Option<TelemetrySnapshot>,
+    pub memory: MemoryBus,
+    pub alerts: Vec<OrganAlert>,
+    pub awareness: f32,
+    pub sim_level: SimLevel,
+}
+
+impl Observation {
+    /// The compact form that gets journaled and that rule sets match on.
+    pub fn inputs(&self) -> DecisionInputs {
+        DecisionInputs {
+            awareness: self.awareness,
+            sim_level: format!("{:?}", self.sim_level),
+            organs: self
+                .topology
+                .organs
+                .iter()
+                .map(|o| (format!("{:?}", o.kind), o.health))
+                .collect(),
+        }
+    }
+
+    /// Structured JSON form, as sent to external advisors.
+    pub fn to_json(&self) -> String {
+        let nodes: Vec<String> = self
+            .topology
+            .nodes
+            .iter()
+            .map(|n| {
+                format!(
+                    r#"{{"id":{},"label":"{}","role":"{}"}}"#,
+                    n.id.0,
+                    json::escape(&n.label),
+                    json::escape(&n.role)
+                )
+            })
+            .collect();
+        let organs: Vec<String> = self
+            .topology
+            .organs
+            .iter()
+            .map(|o| {
+                format!(
+                    r#"{{"id":{},"node":{},"kind":"{:?}","health":{:.3}}}"#,
+                    o.id.0, o.node.0, o.kind, o.health
+                )
+            })
+            .collect();
+        let alerts: Vec<String> = self
+            .alerts
+            .iter()
+            .map(|a| {
+                format!(
+                    r#"{{"organ":"{}","health":{:.3},"level":"{}"}}"#,
+                    json::escape(&a.organ),
+                    a.health,
+                    a.level
+                )
+            })
+            .collect();
+        let memory: Vec<String> = self
+            .memory
+            .entries(MemoryScope::Global)
+            .iter()
+            .map(|(k, v)| format!(r#""{}":{}"#, json::escape(k), v.to_json()))
+            .collect();
+        let metrics = match self.metrics {
+            Some(m) => m.to_json(),
+            None => "null".to_string(),
+        };
+
+        format!(
+            concat!(
+                r#"{{"cycle":{},"awareness":{:.3},"awareness_label":"{}","sim_level":"{:?}","#,
+                r#""topology":{{"nodes":[{}],"organs":[{}]}},"#,
+                r#""metrics":{},"alerts":[{}],"memory":{{{}}}}}"#
+            ),
+            self.cycle,
+            self.awareness,
+            organism::describe_awareness(self.awareness),
+            self.sim_level,
+            nodes.join(","),
+            organs.join(","),
+            metrics,
+            alerts.join(","),
+            memory.join(",")
+        )
+    }
+}
+
+/// A decision-making strategy for the cortex.
+pub trait CortexBrain: Send {
+    fn name(&self) -> &'static str;
+
+    /// Decide what to do for this cycle.
+    fn decide(&mut self, obs: &Observation) -> PolicyDecision;
+
+    /// Called once the outcome of one of this brain's earlier decisions
+    /// is known. Brains that do not learn can ignore it.
+    fn feedback(&mut self, _record: &DecisionRecord) {}
+
+    /// Short status text for `cortex brain`.
+    fn describe(&self) -> String {
+        self.name().to_string()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Threshold ladder
+// ---------------------------------------------------------------------------
+
+/// The Phase 1 ladder: awareness thresholds straight to a policy.
+pub struct LadderBrain;
+
+impl CortexBrain for LadderBrain {
+    fn name(&self) -> &'static str {
+        "ladder"
+    }
+
+    fn decide(&mut self, obs: &Observation) -> PolicyDecision {
+        let a = obs.awareness;
+        let (policy, rule, actions) = if a >= 0.85 {
+            ("push_capacity", "awareness >= 0.85", vec![])          // safe to run heavy workloads
+        } else if a >= 0.60 {
+            ("maintain_load", "awareness >= 0.60", vec![])          // keep as is
+        } else if a >= 0.35 {
+            ("reduce_load", "awareness >= 0.35", vec![])            // consider reducing sim/load
+        } else if a > 0.0 {
+            // Critical: also force sim_level off as a protective reflex.
+            ("protect_core(sim_off)", "awareness > 0.0", vec!["sim_level=off".to_string()])
+        } else {
+            ("recover_offline", "awareness == 0.0", vec![])         // unconscious
+        };
+        PolicyDecision {
+            policy: format!("policy={}", policy),
+            rule: rule.to_string(),
+            actions,
+        }
+    }
+
+    fn describe(&self) -> String {
+        "ladder: fixed awareness thresholds 0.85 / 0.60 / 0.35".to_string()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Rule engine
+// ---------------------------------------------------------------------------
+
+/// Evaluates a `PolicySet`.
+pub struct RuleBrain {
+    pub set: PolicySet,
+}
+
+impl RuleBrain {
+    pub fn new(set: PolicySet) -> Self {
+        Self { set }
+    }
+}
+
+impl CortexBrain for RuleBrain {
+    fn name(&self) -> &'static str {
+        "rules"
+    }
+
+    fn decide(&mut self, obs: &Observation) -> PolicyDecision {
+        self.set.evaluate(&obs.inputs())
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "rules: policy set '{}' ({} rule(s))",
+            self.set.name,
+            self.set.rules.len()
+        )
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Contextual bandit
+// ---------------------------------------------------------------------------
+
+/// One choice the bandit can make: a policy plus its actions.
+struct Arm {
+    policy: &'static str,
+    actions: &'static [&'static str],
+}
+
+const ARMS: &[Arm] = &[
+    Arm { policy: "push_capacity", actions: &[] },
+    Arm { policy: "maintain_load", actions: &[] },
+    Arm { policy: "reduce_load", actions: &["sim_level=low"] },
+    Arm { policy: "protect_core(sim_off)", actions: &["sim_level=off"] },
+];
+
+/// Running reward estimate for one (context, arm) pair.
+#[derive(Debug, Clone, Copy, Default)]
+struct ArmStats {
+    pulls: u32,
+    mean_reward: f32,
+}
+
+/// Epsilon-greedy contextual bandit.
+///
+/// The context is the awareness label plus whether any organ is alerting;
+/// the reward is the awareness delta measured `OUTCOME_CYCLES` after the
+/// decision. Untried arms are always explored first.
+pub struct BanditBrain {
+    epsilon: f32,
+    stats: HashMap<(String, usize), ArmStats>,
+    rng: u64,
+}
+
+impl BanditBrain {
+    /// `seed` drives exploration; the kernel seeds from its clock so
+    /// virtual runs repeat.
+    pub fn new(epsilon: f32, seed: u64) -> Self {
+        Self {
+            epsilon: epsilon.clamp(0.0, 1.0),
+            stats: HashMap::new(),
+            rng: seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1,
+        }
+    }
+
+    fn context_of(awareness: f32, alerting: bool) -> String {
+        format!(
+            "{}{}",
+            organism::describe_awareness(awareness),
+            if alerting { "+alerts" } else { "" }
+        )
+    }
+
+    /// xorshift64*, good enough for exploration.
+    fn next_f32(&mut self) -> f32 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        let v = self.rng.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        (v >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn choose(&mut self, context: &str) -> (usize, &'static str) {
+        let untried = (0..ARMS.len())
+            .find(|i| !self.stats.contains_key(&(context.to_string(), *i)));
+        if let Some(i) = untried {
+            return (i, "explore (untried)");
+        }
+
+        if self.next_f32() < self.epsilon {
+            let i = ((self.next_f32() * ARMS.len() as f32) as usize).min(ARMS.len() - 1);
+            return (i, "explore (epsilon)");
+        }
+
+        let best = (0..ARMS.len())
+            .max_by(|a, b| {
+                let ra = self.stats[&(context.to_string(), *a)].mean_reward;
+                let rb = self.stats[&(context.to_string(), *b)].mean_reward;
+                ra.total_cmp(&rb)
+            })
+            .unwrap_or(1);
+        (best, "exploit")
+    }
+}
+
+impl CortexBrain for BanditBrain {
+    fn name(&self) -> &'static str {
+        "bandit"
+    }
+
+    fn decide(&mut self, obs: &Observation) -> PolicyDecision {
+        let context = Self::context_of(obs.awareness, !obs.alerts.is_empty());
+        let (arm, why) = self.choose(&context);
+        PolicyDecision {
+            policy: format!("policy={}", ARMS[arm].policy),
+            rule: format!("bandit[{}] {}", context, why),
+            actions: ARMS[arm].actions.iter().map(|a| a.to_string()).collect(),
+        }
+    }
+
+    fn feedback(&mut self, record: &DecisionRecord) {
+        let Some(reward) = record.outcome else {
+            return;
+        };
+        let Some(arm) = ARMS
+            .iter()
+            .position(|a| record.policy == format!("policy={}", a.policy))
+        else {
+            return;
+        };
+
+        let alerting = record.inputs.organs.iter().any(|(_, h)| *h < 0.85);
+        let context = Self::context_of(record.inputs.awareness, alerting);
+        let stats = self.stats.entry((context, arm)).or_default();
+        stats.pulls += 1;
+        stats.mean_reward += (reward - stats.mean_reward) / stats.pulls as f32;
+    }
+
+    fn describe(&self) -> String {
+        let mut out = format!(
+            "bandit: epsilon-greedy (ε={:.2}), {} learned context/arm pair(s)",
+            self.epsilon,
+            self.stats.len()
+        );
+        let mut keys: Vec<&(String, usize)> = self.stats.keys().collect();
+        keys.sort();
+        for key in keys {
+            let s = self.stats[key];
+            out.push_str(&format!(
+                "\n     {:<18} {:<24} pulls {:>4} mean Δ {:+.4}",
+                key.0, ARMS[key.1].policy, s.pulls, s.mean_reward
+            ));
+        }
+        out
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Brain selection
+// ---------------------------------------------------------------------------
+
+/// All brains known to the cortex, with one marked active.
+///
+/// Inactive brains are kept (not rebuilt) so a learning brain keeps what
+/// it learned when the operator switches away and back.
+pub struct CortexBrains {
+    brains: Vec<Box<dyn CortexBrain>>,
+    active: usize,
+}
+
+impl CortexBrains {
+    /// The standard set: ladder, rules (using `rules`), bandit (seeded
+    /// with `seed`).
+    pub fn standard(rules: PolicySet, seed: u64) -> Self {
+        Self {
+            brains: vec![
+                Box::new(LadderBrain),
+                Box::new(RuleBrain::new(rules)),
+                Box::new(BanditBrain::new(0.1, seed)),
+            ],
+            active: 0,
+        }
+    }
+
+    pub fn active_name(&self) -> &'static str {
+        self.brains[self.active].name()
+    }
+
+    pub fn active_mut(&mut self) -> &mut dyn CortexBrain {
+        self.brains[self.active].as_mut()
+    }
+
+    /// Switch the active brain by name.
+    pub fn select(&mut self, name: &str) -> Result<(), String> {
+        match self.brains.iter().position(|b| b.name() == name) {
+            Some(i) => {
+                self.active = i;
+                Ok(())
+            }
+            None => Err(format!(
+                "unknown brain '{}' (known: {})",
+                name,
+                self.names().join(", ")
+            )),
+        }
+    }
+
+    /// Add or replace a brain with the same name.
+    pub fn install(&mut self, brain: Box<dyn CortexBrain>) {
+        match self.brains.iter().position(|b| b.name() == brain.name()) {
+            Some(i) => self.brains[i] = brain,
+            None => self.brains.push(brain),
+        }
+    }
+
+    /// Route an outcome back to whichever brain made the decision.
+    pub fn feedback(&mut self, record: &DecisionRecord) {
+        if let Some(b) = self.brains.iter_mut().find(|b| b.name() == record.brain) {
+            b.feedback(record);
+        }
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.brains.iter().map(|b| b.name()).collect()
+    }
+
+    /// Listing for `cortex brain`.
+    pub fn describe(&self) -> String {
+        let mut out = String::from("Cortex brains:\n");
+        for (i, b) in self.brains.iter().enumerate() {
+            let marker = if i == self.active { "*" } else { " " };
+            out.push_str(&format!(" {} {}\n", marker, b.describe()));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::organism::sample_topology;
+
+    fn observe(awareness: f32, alerting: bool) -> Observation {
+        let alerts = match alerting {
+            true => vec![OrganAlert { organ: "Cortex".to_string(), health: 0.5, level: "degraded" }],
+            false => Vec::new(),
+        };
+        Observation {
+            cycle: 1,
+            topology: sample_topology(),
+            metrics: None,
+            memory: MemoryBus::new(),
+            alerts,
+            awareness,
+            sim_level: SimLevel::Low,
+        }
+    }
+
+    fn outcome(brain: &str, policy: &str, awareness: f32, delta: Option<f32>) -> DecisionRecord {
+        DecisionRecord {
+            id: 1,
+            cycle: 1,
+            timestamp: 0,
+            inputs: DecisionInputs { awareness, sim_level: "Low".to_string(), organs: Vec::new() },
+            brain: brain.to_string(),
+            rule: String::new(),
+            policy: policy.to_string(),
+            actions: Vec::new(),
+            outcome: delta,
+        }
+    }
+
+    #[test]
+    fn the_bandit_tries_every_arm_then_exploits_the_best() {
+        let mut bandit = BanditBrain::new(0.0, 7);
+        let obs = observe(0.7, false);
+        for arm in ARMS {
+            let decision = bandit.decide(&obs);
+            assert_eq!(decision.policy, format!("policy={}", arm.policy));
+            assert!(decision.rule.ends_with("explore (untried)"), "{}", decision.rule);
+            let reward = if arm.policy == "reduce_load" { 0.2 } else { -0.1 };
+            bandit.feedback(&outcome("bandit", &decision.policy, 0.7, Some(reward)));
+        }
+
+        let decision = bandit.decide(&obs);
+        assert_eq!(decision.policy, "policy=reduce_load");
+        assert_eq!(decision.actions, ["sim_level=low"]);
+        assert!(decision.rule.ends_with("exploit"));
+        // Alerts are a different context, learned from scratch.
+        assert!(bandit.decide(&observe(0.7, true)).rule.contains("+alerts] explore (untried)"));
+    }
+
+    #[test]
+    fn the_bandit_averages_rewards_and_ignores_what_it_cannot_use() {
+        let mut bandit = BanditBrain::new(0.0, 7);
+        bandit.feedback(&outcome("bandit", "policy=maintain_load", 0.7, None));
+        bandit.feedback(&outcome("bandit", "policy=made_up", 0.7, Some(1.0)));
+        assert!(bandit.describe().contains("0 learned context/arm pair(s)"));
+
+        bandit.feedback(&outcome("bandit", "policy=maintain_load", 0.7, Some(0.1)));
+        bandit.feedback(&outcome("bandit", "policy=maintain_load", 0.7, Some(0.3)));
+        let described = bandit.describe();
+        assert!(described.contains("1 learned context/arm pair(s)"), "{}", described);
+        assert!(described.contains("pulls    2 mean Δ +0.2000"), "{}", described);
+    }
+
+    #[test]
+    fn exploration_draws_stay_in_range() {
+        let mut bandit = BanditBrain::new(1.0, 7);
+        assert!((0..1000).map(|_| bandit.next_f32()).all(|x| (0.0..1.0).contains(&x)));
+    }
+
+    #[test]
+    fn outcomes_go_back_to_the_brain_that_decided() {
+        let mut brains = CortexBrains::standard(PolicySet::default_ladder(), 7);
+        assert_eq!(brains.active_name(), "ladder");
+        assert!(brains.select("oracle").unwrap_err().contains("known: ladder, rules, bandit"));
+        brains.select("bandit").unwrap();
+        brains.feedback(&outcome("bandit", "policy=push_capacity", 0.9, Some(0.05)));
+        brains.feedback(&outcome("ladder", "policy=push_capacity", 0.9, Some(0.05)));
+        assert!(brains.describe().contains("* bandit: epsilon-greedy (ε=0.10), 1 learned"));
+
+        brains.install(Box::new(BanditBrain::new(0.5, 7)));
+        assert_eq!(brains.names(), ["ladder", "rules", "bandit"]);
+        assert!(brains.describe().contains("(ε=0.50), 0 learned"));
+    }
+}
@@ -0,0 +1,496 @@
+//! Append-only journal of cortex policy decisions.
+//!
+//! Every AiDaemon cycle produces one `DecisionRecord`: what the cortex
+//! saw, which rule fired, what it did about it, and — filled in a few
+//! cycles later — how awareness moved afterwards.
+//!
+//! On disk the journal is a plain text file with one tab-separated
+//! record per line. Two record types exist:
+//!
+//! ```text
+//! decision <id> <cycle> <unix_secs> <awareness> <sim_level> <organs> <rule> <policy> <actions> <brain>
+//! outcome  <id> <awareness_delta>
+//! ```
+//!
+//! Journals written before brains existed lack the `<brain>` column;
+//! those decisions are read back as made by `ladder`.
+//!
+//! Outcomes are appended as separate lines so the file never has to be
+//! rewritten. Loading replays both record types back into memory.
+//!
+//! Once the file would grow past `MAX_FILE_BYTES` it is renamed to
+//! `<path>.1`, replacing the previous one, and a fresh file is started.
+//! Reading covers both files, so at most twice the cap is kept.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::json;
+use crate::kernel::SharedClock;
+
+/// How many cycles after a decision we measure its awareness delta.
+pub const OUTCOME_CYCLES: u64 = 3;
+
+/// How many records we keep in memory for queries.
+const MAX_IN_MEMORY: usize = 2048;
+
+/// Size at which the journal file is rotated.
+pub const MAX_FILE_BYTES: u64 = 8 * 1024 * 1024;
+
+/// What the cortex observed when it made a decision.
+#[derive(Debug, Clone)]
+pub struct DecisionInputs {
+    pub awareness: f32,
+    pub sim_level: String,
+    /// (organ name, health) pairs, in topology order.
+    pub organs: Vec<(String, f32)>,
+}
+
+/// One cortex decision and (eventually) its outcome.
+#[derive(Debug, Clone)]
+pub struct DecisionRecord {
+    pub id: u64,
+    pub cycle: u64,
+    /// Wall-clock time of the decision (seconds since UNIX epoch).
+    pub timestamp: u64,
+    pub inputs: DecisionInputs,
+    /// Name of the cortex brain that made the decision.
+    pub brain: String,
+    /// The rule or reason that selected this policy.
+    pub rule: String,
+    pub policy: String,
+    /// Side effects the cortex actually applied (may be empty).
+    pub actions: Vec<String>,
+    /// Awareness change `OUTCOME_CYCLES` cycles later, once known.
+    pub outcome: Option<f32>,
+}
+
+impl DecisionRecord {
+    /// One-line human summary for the shell.
+    pub fn summary(&self) -> String {
+        let actions = if self.actions.is_empty() {
+            "-".to_string()
+        } else {
+            self.actions.join(",")
+        };
+        let outcome = match self.outcome {
+            Some(d) => format!("{:+.3}", d),
+            None => "pending".to_string(),
+        };
+        format!(
+            "#{} cycle {} @{} :: awareness {:.2} sim={} :: {} {} [{}] :: actions {} :: Δ {}",
+            self.id,
+            self.cycle,
+            self.timestamp,
+            self.inputs.awareness,
+            self.inputs.sim_level,
+            self.brain,
+            self.policy,
+            self.rule,
+            actions,
+            outcome
+        )
+    }
+
+    /// JSON object for the HTTP API.
+    pub fn to_json(&self) -> String {
+        let organs: Vec<String> = self
+            .inputs
+            .organs
+            .iter()
+            .map(|(name, h)| format!(r#""{}":{:.3}"#, json::escape(name), h))
+            .collect();
+        let actions: Vec<String> = self
+            .actions
+            .iter()
+            .map(|a| format!(r#""{}""#, json::escape(a)))
+            .collect();
+        let outcome = match self.outcome {
+            Some(d) => format!("{:.4}", d),
+            None => "null".to_string(),
+        };
+        format!(
+            concat!(
+                r#"{{"id":{},"cycle":{},"timestamp":{},"#,
+                r#""inputs":{{"awareness":{:.3},"sim_level":"{}","organs":{{{}}}}},"#,
+                r#""brain":"{}","rule":"{}","policy":"{}","actions":[{}],"awareness_delta":{}}}"#
+            ),
+            self.id,
+            self.cycle,
+            self.timestamp,
+            self.inputs.awareness,
+            json::escape(&self.inputs.sim_level),
+            organs.join(","),
+            json::escape(&self.brain),
+            json::escape(&self.rule),
+            json::escape(&self.policy),
+            actions.join(","),
+            outcome
+        )
+    }
+
+    fn to_line(&self) -> String {
+        let organs: Vec<String> = self
+            .inputs
+            .organs
+            .iter()
+            .map(|(name, h)| format!("{}:{:.5}", name, h))
+            .collect();
+        format!(
+            "decision\t{}\t{}\t{}\t{:.5}\t{}\t{}\t{}\t{}\t{}\t{}",
+            self.id,
+            self.cycle,
+            self.timestamp,
+            self.inputs.awareness,
+            self.inputs.sim_level,
+            organs.join(","),
+            clean_field(&self.rule),
+            clean_field(&self.policy),
+            self.actions
+                .iter()
+                .map(|a| clean_field(a))
+                .collect::<Vec<_>>()
+                .join(";"),
+            clean_field(&self.brain),
+        )
+    }
+
+    fn from_line(fields: &[&str]) -> Option<Self> {
+        if fields.len() != 10 && fields.len() != 11 {
+            return None;
+        }
+        let organs = fields[6]
+            .split(',')
+            .filter(|s| !s.is_empty())
+            .filter_map(|pair| {
+                let (name, h) = pair.split_once(':')?;
+                Some((name.to_string(), h.parse().ok()?))
+            })
+            .collect();
+        let actions = fields[9]
+            .split(';')
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .collect();
+
+        Some(Self {
+            id: fields[1].parse().ok()?,
+            cycle: fields[2].parse().ok()?,
+            timestamp: fields[3].parse().ok()?,
+            inputs: DecisionInputs {
+                awareness: fields[4].parse().ok()?,
+                sim_level: fields[5].to_string(),
+                organs,
+            },
+            brain: fields.get(10).unwrap_or(&"ladder").to_string(),
+            rule: fields[7].to_string(),
+            policy: fields[8].to_string(),
+            actions,
+            outcome: None,
+        })
+    }
+}
+
+/// The journal itself: a bounded in-memory window over an append-only file.
+pub struct DecisionJournal {
+    path: Option<PathBuf>,
+    /// Source of decision timestamps; virtual in tests.
+    clock: SharedClock,
+    max_bytes: u64,
+    records: VecDeque<DecisionRecord>,
+    next_id: u64,
+    /// First ID recorded by this process. Older records belong to a
+    /// previous run whose cycle counter no longer applies, so their
+    /// pending outcomes are left unresolved.
+    session_first_id: u64,
+}
+
+impl DecisionJournal {
+    /// A journal that only lives in memory (nothing is persisted).
+    pub fn in_memory(clock: SharedClock) -> Self {
+        Self {
+            path: None,
+            clock,
+            max_bytes: MAX_FILE_BYTES,
+            records: VecDeque::new(),
+            next_id: 1,
+            session_first_id: 1,
+        }
+    }
+
+    /// Open (or create) a journal file, loading any existing records.
+    pub fn open(path: impl Into<PathBuf>, clock: SharedClock) -> Self {
+        let path = path.into();
+        let mut journal = Self::in_memory(clock);
+
+        if let Ok(records) = read_records(&path) {
+            for rec in records {
+                journal.next_id = journal.next_id.max(rec.id + 1);
+                journal.push_bounded(rec);
+            }
+        }
+
+        journal.path = Some(path);
+        journal.session_first_id = journal.next_id;
+        journal
+    }
+
+    /// Record a new decision and return its ID.
+    pub fn record(
+        &mut self,
+        cycle: u64,
+        inputs: DecisionInputs,
+        brain: impl Into<String>,
+        rule: impl Into<String>,
+        policy: impl Into<String>,
+        actions: Vec<String>,
+    ) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let rec = DecisionRecord {
+            id,
+            cycle,
+            timestamp: self.clock.unix_millis() / 1000,
+            inputs,
+            brain: brain.into(),
+            rule: rule.into(),
+            policy: policy.into(),
+            actions,
+            outcome: None,
+        };
+
+        self.append_line(&rec.to_line());
+        self.push_bounded(rec);
+        id
+    }
+
+    /// Fill in outcomes for every decision made `OUTCOME_CYCLES` or more
+    /// cycles before `cycle`, using the awareness observed now. Returns the
+    /// records that were resolved so learning brains can be rewarded.
+    pub fn resolve_outcomes(&mut self, cycle: u64, awareness: f32) -> Vec<DecisionRecord> {
+        let mut lines = Vec::new();
+        let mut resolved = Vec::new();
+        for rec in self.records.iter_mut() {
+            if rec.id < self.session_first_id || rec.outcome.is_some() {
+                continue;
+            }
+            if cycle >= rec.cycle + OUTCOME_CYCLES {
+                let delta = awareness - rec.inputs.awareness;
+                rec.outcome = Some(delta);
+                lines.push(format!("outcome\t{}\t{:.5}", rec.id, delta));
+                resolved.push(rec.clone());
+            }
+        }
+        for line in lines {
+            self.append_line(&line);
+        }
+        resolved
+    }
+
+    /// The most recent `limit` records, oldest first, optionally
+    /// restricted to a policy name (substring match).
+    pub fn recent(&self, limit: usize, policy: Option<&str>) -> Vec<&DecisionRecord> {
+        let mut out: Vec<&DecisionRecord> = self
+            .records
+            .iter()
+            .rev()
+            .filter(|r| policy.is_none_or(|p| r.policy.contains(p)))
+            .take(limit)
+            .collect();
+        out.reverse();
+        out
+    }
+
+    /// Shell-friendly listing of recent decisions.
+    pub fn history_report(&self, limit: usize, policy: Option<&str>) -> String {
+        let recs = self.recent(limit, policy);
+        let mut out = String::new();
+        out.push_str(&format!(
+            "Cortex history (last {} of {} in memory):\n",
+            recs.len(),
+            self.records.len()
+        ));
+        if recs.is_empty() {
+            out.push_str(" (no decisions recorded)\n");
+        }
+        for rec in recs {
+            out.push_str(&format!(" - {}\n", rec.summary()));
+        }
+        out
+    }
+
+    /// JSON array of recent decisions for the HTTP API.
+    pub fn history_json(&self, limit: usize, policy: Option<&str>) -> String {
+        let items: Vec<String> = self
+            .recent(limit, policy)
+            .into_iter()
+            .map(|r| r.to_json())
+            .collect();
+        format!("[{}]", items.join(","))
+    }
+
+    fn push_bounded(&mut self, rec: DecisionRecord) {
+        if self.records.len() >= MAX_IN_MEMORY {
+            self.records.pop_front();
+        }
+        self.records.push_back(rec);
+    }
+
+    fn append_line(&self, line: &str) {
+        let path = match &self.path {
+            Some(p) => p,
+            None => return,
+        };
+        let size = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        if size > 0 && size + line.len() as u64 + 1 > self.max_bytes {
+            if let Err(e) = fs::rename(path, rotated(path)) {
+                eprintln!("[AION-CORTEX] failed to rotate journal {:?}: {}", path, e);
+            }
+        }
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut f| writeln!(f, "{}", line));
+        if let Err(e) = result {
+            eprintln!("[AION-CORTEX] failed to append to journal {:?}: {}", path, e);
+        }
+    }
+}
+
+/// Read every decision in a journal file and its rotated predecessor
+/// (no in-memory bound), with outcomes merged in. Used by `open` and by
+/// offline tools such as the what-if simulator.
+pub fn read_records(path: &Path) -> io::Result<Vec<DecisionRecord>> {
+    let content = fs::read_to_string(rotated(path)).unwrap_or_default() + &fs::read_to_string(path)?;
+    let mut records: Vec<DecisionRecord> = Vec::new();
+    let mut index: HashMap<u64, usize> = HashMap::new();
+
+    for line in content.lines() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        match fields.first().copied() {
+            Some("decision") => {
+                if let Some(rec) = DecisionRecord::from_line(&fields) {
+                    index.insert(rec.id, records.len());
+                    records.push(rec);
+                }
+            }
+            Some("outcome") if fields.len() == 3 => {
+                let id: Option<u64> = fields[1].parse().ok();
+                let delta: Option<f32> = fields[2].parse().ok();
+                if let (Some(id), Some(delta)) = (id, delta) {
+                    if let Some(&i) = index.get(&id) {
+                        records[i].outcome = Some(delta);
+                    }
+                }
+            }
+            _ => continue,
+        }
+    }
+
+    Ok(records)
+}
+
+/// Where the previous journal file goes on rotation.
+fn rotated(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".1");
+    PathBuf::from(name)
+}
+
+/// Journal fields are tab-separated; keep separators out of free text.
+fn clean_field(s: &str) -> String {
+    s.replace(['\t', '\n', ';'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::clock::{VirtualClock, VIRTUAL_EPOCH_MS};
+    use std::sync::Arc;
+
+    fn clock() -> SharedClock {
+        Arc::new(VirtualClock::new())
+    }
+
+    fn scratch(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("aion-journal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(rotated(&path));
+        path
+    }
+
+    fn inputs(awareness: f32) -> DecisionInputs {
+        DecisionInputs {
+            awareness,
+            sim_level: "Low".to_string(),
+            organs: vec![("Cortex".to_string(), 0.9), ("Memory".to_string(), 0.5)],
+        }
+    }
+
+    #[test]
+    fn decisions_and_outcomes_replay_from_the_file() {
+        let path = scratch("replay");
+        let mut journal = DecisionJournal::open(&path, clock());
+        journal.record(1, inputs(0.7), "ladder", "awareness >= 0.60", "policy=maintain_load", vec![]);
+        let actions = vec!["sim_level=off".to_string()];
+        journal.record(2, inputs(0.3), "bandit", "arm 2", "policy=protect_core(sim_off)", actions);
+        let resolved = journal.resolve_outcomes(1 + OUTCOME_CYCLES, 0.8);
+        assert_eq!(resolved.iter().map(|r| r.id).collect::<Vec<_>>(), [1]);
+
+        let records = read_records(&path).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].outcome.map(|d| (d * 100.0).round()), Some(10.0));
+        assert_eq!(records[0].inputs.organs, inputs(0.7).organs);
+        assert_eq!((records[1].brain.as_str(), records[1].outcome), ("bandit", None));
+        assert_eq!(records[1].actions, ["sim_level=off"]);
+        assert_eq!(records[1].timestamp, VIRTUAL_EPOCH_MS / 1000);
+
+        // A reopened journal carries on numbering, and leaves the
+        // previous run's pending outcomes alone.
+        let mut reopened = DecisionJournal::open(&path, clock());
+        assert_eq!(reopened.recent(10, None).len(), 2);
+        assert!(reopened.resolve_outcomes(100, 0.5).is_empty());
+        assert_eq!(reopened.record(3, inputs(0.5), "ladder", "r", "p", vec![]), 3);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn history_report_filters_by_policy() {
+        let mut journal = DecisionJournal::in_memory(clock());
+        assert!(journal.history_report(5, None).contains("(no decisions recorded)"));
+        for (cycle, policy) in [(1, "policy=maintain_load"), (2, "policy=reduce_load"), (3, "policy=maintain_load")] {
+            journal.record(cycle, inputs(0.6), "ladder", "rule", policy, vec![]);
+        }
+
+        let report = journal.history_report(5, Some("maintain"));
+        assert!(report.starts_with("Cortex history (last 2 of 3 in memory):"), "{}", report);
+        assert!(report.contains("#1 cycle 1") && report.contains("#3 cycle 3"));
+        assert!(!report.contains("reduce_load"));
+        let last = journal.history_report(1, None);
+        assert!(last.contains("#3 cycle 3") && last.contains("Δ pending"), "{}", last);
+        assert!(journal.history_json(1, None).starts_with(r#"[{"id":3,"#));
+    }
+
+    #[test]
+    fn the_file_rotates_and_both_halves_are_read() {
+        let path = scratch("rotate");
+        let mut journal = DecisionJournal::open(&path, clock());
+        journal.max_bytes = 512;
+        for cycle in 0..20 {
+            journal.record(cycle, inputs(0.5), "ladder", "rule", "policy=maintain_load", vec![]);
+        }
+        assert!(fs::metadata(&path).unwrap().len() <= 512);
+        assert!(fs::metadata(rotated(&path)).unwrap().len() <= 512);
+
+        let ids: Vec<u64> = read_records(&path).unwrap().iter().map(|r| r.id).collect();
+        assert_eq!(ids.last(), Some(&20));
+        assert!(ids.windows(2).all(|w| w[1] == w[0] + 1), "{:?}", ids);
+        assert!(ids.len() < 20);
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(rotated(&path));
+    }
+}
@@ -0,0 +1,18 @@
+//! Cortex support code for AION.
+//!
+//! The `AiDaemon` in the kernel is the live cortex loop; this module
+//! holds the pieces it leans on that are not tied to the daemon loop
+//! itself: the pluggable brains that make decisions (including the
+//! external HTTP advisor), the decision
+//! journal, policy sets, and the offline what-if simulator that replays
+//! recorded history through them.
+
+pub mod advisor;
+pub mod brain;
+pub mod journal;
+pub mod policy;
+pub mod whatif;
+
+pub use brain::{CortexBrains, Observation, OrganAlert};
+pub use journal::{DecisionInputs, DecisionJournal};
+pub use policy::PolicySet;
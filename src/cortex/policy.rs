@@ -0,0 +1,353 @@
+//! Cortex policy sets.
+//!
+//! A `PolicySet` is an ordered list of rules. Each rule has a condition
+//! over what the cortex observes and, when it matches, names a policy and
+//! the actions that go with it. The first matching rule wins.
+//!
+//! Policy files are plain text, one rule per line:
+//!
+//! ```text
+//! # comments and blank lines are ignored
+//! name cautious
+//! awareness >= 0.90                     => push_capacity
+//! awareness >= 0.60                     => maintain_load
+//! health cortex < 0.40                  => protect_core ; sim_level=off
+//! awareness > 0.0 and sim_level == high => reduce_load ; sim_level=low
+//! always                                => recover_offline
+//! ```
+//!
+//! Conditions support `awareness`, `health <organ>` and `sim_level`,
+//! joined with `and`. The only action today is `sim_level=<off|low|high>`.
+
+use std::fs;
+
+use crate::cortex::DecisionInputs;
+
+/// Comparison operator in a rule condition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cmp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl Cmp {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "<" => Some(Cmp::Lt),
+            "<=" => Some(Cmp::Le),
+            ">" => Some(Cmp::Gt),
+            ">=" => Some(Cmp::Ge),
+            "==" => Some(Cmp::Eq),
+            "!=" => Some(Cmp::Ne),
+            _ => None,
+        }
+    }
+
+    pub fn apply(self, lhs: f32, rhs: f32) -> bool {
+        match self {
+            Cmp::Lt => lhs < rhs,
+            Cmp::Le => lhs <= rhs,
+            Cmp::Gt => lhs > rhs,
+            Cmp::Ge => lhs >= rhs,
+            Cmp::Eq => (lhs - rhs).abs() < f32::EPSILON,
+            Cmp::Ne => (lhs - rhs).abs() >= f32::EPSILON,
+        }
+    }
+}
+
+/// A single clause of a rule condition.
+#[derive(Debug, Clone)]
+pub enum Clause {
+    Always,
+    Awareness(Cmp, f32),
+    /// Organ name is matched case-insensitively against the observation.
+    Health(String, Cmp, f32),
+    SimLevel(bool, String),
+}
+
+impl Clause {
+    fn matches(&self, inputs: &DecisionInputs) -> bool {
+        match self {
+            Clause::Always => true,
+            Clause::Awareness(cmp, v) => cmp.apply(inputs.awareness, *v),
+            Clause::Health(organ, cmp, v) => inputs
+                .organs
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(organ))
+                .is_some_and(|(_, h)| cmp.apply(*h, *v)),
+            Clause::SimLevel(equal, level) => {
+                inputs.sim_level.eq_ignore_ascii_case(level) == *equal
+            }
+        }
+    }
+}
+
+/// One line of a policy set.
+#[derive(Debug, Clone)]
+pub struct PolicyRule {
+    /// The condition as written, kept for journals and reports.
+    pub text: String,
+    pub clauses: Vec<Clause>,
+    pub policy: String,
+    pub actions: Vec<String>,
+}
+
+impl PolicyRule {
+    pub fn matches(&self, inputs: &DecisionInputs) -> bool {
+        self.clauses.iter().all(|c| c.matches(inputs))
+    }
+}
+
+/// What a policy set decided for one observation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyDecision {
+    /// `policy=<name>`, matching what the cortex writes to memory.
+    pub policy: String,
+    pub rule: String,
+    pub actions: Vec<String>,
+}
+
+impl PolicyDecision {
+    /// The subset of actions that would actually change something given
+    /// the current sim level. `sim_level=off` while already off is a no-op
+    /// and is not reported as fired.
+    pub fn effective_actions(&self, current_sim_level: &str) -> Vec<String> {
+        self.actions
+            .iter()
+            .filter(|a| match a.split_once('=') {
+                Some(("sim_level", level)) => !level.eq_ignore_ascii_case(current_sim_level),
+                _ => true,
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// An ordered set of cortex rules.
+#[derive(Debug, Clone)]
+pub struct PolicySet {
+    pub name: String,
+    pub rules: Vec<PolicyRule>,
+}
+
+impl PolicySet {
+    /// The original Phase 1 awareness ladder.
+    pub fn default_ladder() -> Self {
+        Self::parse(
+            "default",
+            "awareness >= 0.85 => push_capacity\n\
+             awareness >= 0.60 => maintain_load\n\
+             awareness >= 0.35 => reduce_load\n\
+             awareness > 0.0   => protect_core(sim_off) ; sim_level=off\n\
+             always            => recover_offline\n",
+        )
+        .expect("built-in policy set must parse")
+    }
+
+    /// Load a policy set by name: `default` is built in, anything else
+    /// is treated as a path to a policy file.
+    pub fn load(name_or_path: &str) -> Result<Self, String> {
+        if name_or_path == "default" {
+            return Ok(Self::default_ladder());
+        }
+        let content = fs::read_to_string(name_or_path)
+            .map_err(|e| format!("failed to read policy file {}: {}", name_or_path, e))?;
+        Self::parse(name_or_path, &content)
+    }
+
+    /// Parse policy text. A `name <x>` line overrides `default_name`.
+    pub fn parse(default_name: &str, text: &str) -> Result<Self, String> {
+        let mut name = default_name.to_string();
+        let mut rules = Vec::new();
+
+        for (lineno, raw) in text.lines().enumerate() {
+            let line = raw.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(n) = line.strip_prefix("name ") {
+                name = n.trim().to_string();
+                continue;
+            }
+            let rule = parse_rule(line).map_err(|e| format!("line {}: {}", lineno + 1, e))?;
+            rules.push(rule);
+        }
+
+        if rules.is_empty() {
+            return Err("policy set has no rules".to_string());
+        }
+        Ok(Self { name, rules })
+    }
+
+    /// Evaluate the rules against an observation. If nothing matches we
+    /// fall back to holding the current load.
+    pub fn evaluate(&self, inputs: &DecisionInputs) -> PolicyDecision {
+        for rule in &self.rules {
+            if rule.matches(inputs) {
+                return PolicyDecision {
+                    policy: format!("policy={}", rule.policy),
+                    rule: rule.text.clone(),
+                    actions: rule.actions.clone(),
+                };
+            }
+        }
+        PolicyDecision {
+            policy: "policy=maintain_load".to_string(),
+            rule: "no rule matched".to_string(),
+            actions: Vec::new(),
+        }
+    }
+
+    /// Shell-friendly listing of the rules.
+    pub fn describe(&self) -> String {
+        let mut out = format!("Policy set '{}':\n", self.name);
+        for (i, r) in self.rules.iter().enumerate() {
+            let actions = if r.actions.is_empty() {
+                String::new()
+            } else {
+                format!(" ; {}", r.actions.join(" ; "))
+            };
+            out.push_str(&format!(" {:>2}. {} => {}{}\n", i + 1, r.text, r.policy, actions));
+        }
+        out
+    }
+}
+
+fn parse_rule(line: &str) -> Result<PolicyRule, String> {
+    let (cond, rhs) = line
+        .split_once("=>")
+        .ok_or_else(|| "expected '<condition> => <policy>'".to_string())?;
+
+    let mut rhs_parts = rhs.split(';').map(str::trim);
+    let policy = rhs_parts
+        .next()
+        .filter(|p| !p.is_empty())
+        .ok_or_else(|| "missing policy name".to_string())?
+        .to_string();
+
+    let mut actions = Vec::new();
+    for action in rhs_parts.filter(|a| !a.is_empty()) {
+        validate_action(action)?;
+        actions.push(action.to_string());
+    }
+
+    let mut clauses = Vec::new();
+    for clause in cond.split(" and ") {
+        clauses.push(parse_clause(clause.trim())?);
+    }
+
+    Ok(PolicyRule {
+        text: cond.split_whitespace().collect::<Vec<_>>().join(" "),
+        clauses,
+        policy,
+        actions,
+    })
+}
+
+fn parse_clause(s: &str) -> Result<Clause, String> {
+    let words: Vec<&str> = s.split_whitespace().collect();
+    match words.as_slice() {
+        ["always"] => Ok(Clause::Always),
+        ["awareness", op, v] => {
+            let cmp = Cmp::parse(op).ok_or_else(|| format!("bad operator '{}'", op))?;
+            let v = v.parse().map_err(|_| format!("bad number '{}'", v))?;
+            Ok(Clause::Awareness(cmp, v))
+        }
+        ["health", organ, op, v] => {
+            let cmp = Cmp::parse(op).ok_or_else(|| format!("bad operator '{}'", op))?;
+            let v = v.parse().map_err(|_| format!("bad number '{}'", v))?;
+            Ok(Clause::Health(organ.to_string(), cmp, v))
+        }
+        ["sim_level", op, level] => match *op {
+            "==" => Ok(Clause::SimLevel(true, level.to_string())),
+            "!=" => Ok(Clause::SimLevel(false, level.to_string())),
+            _ => Err(format!("sim_level only supports == and !=, got '{}'", op)),
+        },
+        _ => Err(format!("cannot parse condition '{}'", s)),
+    }
+}
+
+fn validate_action(action: &str) -> Result<(), String> {
+    match action.split_once('=') {
+        Some(("sim_level", "off" | "low" | "high")) => Ok(()),
+        _ => Err(format!("unknown action '{}'", action)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs(awareness: f32, sim_level: &str, cortex: f32) -> DecisionInputs {
+        DecisionInputs {
+            awareness,
+            sim_level: sim_level.to_string(),
+            organs: vec![("Cortex".to_string(), cortex)],
+        }
+    }
+
+    const CAUTIOUS: &str = "# comments and blank lines are ignored\n\
+                            \n\
+                            name cautious\n\
+                            awareness >= 0.90                     => push_capacity\n\
+                            health cortex < 0.40                  => protect_core ; sim_level=off\n\
+                            awareness > 0.0 and sim_level == high => reduce_load ; sim_level=low\n\
+                            always                                => recover_offline\n";
+
+    #[test]
+    fn rules_parse_in_order() {
+        let set = PolicySet::parse("file.policy", CAUTIOUS).unwrap();
+        assert_eq!(set.name, "cautious");
+        assert_eq!(set.rules.len(), 4);
+        assert_eq!(set.rules[2].text, "awareness > 0.0 and sim_level == high");
+        assert_eq!(set.rules[2].clauses.len(), 2);
+        assert_eq!(set.rules[2].actions, ["sim_level=low"]);
+        assert!(set.describe().contains(" 2. health cortex < 0.40 => protect_core ; sim_level=off"));
+        assert_eq!(PolicySet::default_ladder().rules.len(), 5);
+    }
+
+    #[test]
+    fn bad_rules_name_their_line() {
+        let err = |text| PolicySet::parse("t", text).unwrap_err();
+        assert_eq!(err("# nothing\n"), "policy set has no rules");
+        assert_eq!(err("always => a\nawareness ~ 1 => b\n"), "line 2: bad operator '~'");
+        assert_eq!(err("awareness > x => a"), "line 1: bad number 'x'");
+        assert_eq!(err("always"), "line 1: expected '<condition> => <policy>'");
+        assert_eq!(err("always => ; sim_level=off"), "line 1: missing policy name");
+        assert_eq!(err("always => a ; reboot"), "line 1: unknown action 'reboot'");
+        assert!(err("sim_level < high => a").starts_with("line 1: sim_level only supports"));
+        assert_eq!(err("mood == good => a"), "line 1: cannot parse condition 'mood == good'");
+    }
+
+    #[test]
+    fn the_first_matching_rule_wins() {
+        let set = PolicySet::parse("t", CAUTIOUS).unwrap();
+        let decide = |a, sim, cortex| set.evaluate(&inputs(a, sim, cortex));
+        assert_eq!(decide(0.95, "High", 0.1).policy, "policy=push_capacity");
+        let protect = decide(0.5, "High", 0.1);
+        assert_eq!((protect.policy.as_str(), protect.rule.as_str()), ("policy=protect_core", "health cortex < 0.40"));
+        assert_eq!(decide(0.5, "High", 0.9).actions, ["sim_level=low"]);
+        assert_eq!(decide(0.5, "Low", 0.9).policy, "policy=recover_offline");
+
+        // A set whose rules all miss holds the load.
+        let narrow = PolicySet::parse("t", "awareness > 2 => never").unwrap();
+        assert_eq!(narrow.evaluate(&inputs(0.5, "Off", 1.0)).rule, "no rule matched");
+    }
+
+    #[test]
+    fn comparisons_and_effective_actions() {
+        assert!(Cmp::Le.apply(0.5, 0.5) && !Cmp::Lt.apply(0.5, 0.5));
+        assert!(Cmp::Eq.apply(0.1 + 0.2, 0.3) && Cmp::Ne.apply(0.3, 0.31));
+        let decision = PolicyDecision {
+            policy: "policy=protect_core".to_string(),
+            rule: "always".to_string(),
+            actions: vec!["sim_level=off".to_string()],
+        };
+        assert!(decision.effective_actions("Off").is_empty());
+        assert_eq!(decision.effective_actions("High"), ["sim_level=off"]);
+    }
+}
@@ -0,0 +1,275 @@
+//! Offline "what-if" simulator for cortex policy sets.
+//!
+//! Replays a recorded timeline — the observations stored in the decision
+//! journal — through one or two `PolicySet`s and reports what each would
+//! have decided. Nothing here touches the live organism: the timeline is
+//! read from disk and actions are reported but never applied. The sim
+//! level starts as recorded and from then on follows the policy set's
+//! own actions, as if it had been in charge the whole time.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::cortex::journal::{self, DecisionRecord};
+use crate::cortex::policy::{PolicyDecision, PolicySet};
+
+/// One timeline step evaluated against a policy set.
+#[derive(Debug, Clone)]
+pub struct WhatIfStep {
+    pub timestamp: u64,
+    pub cycle: u64,
+    pub awareness: f32,
+    /// What the live cortex actually decided at this point.
+    pub recorded_policy: String,
+    pub decision: PolicyDecision,
+    /// Actions that would have changed state at this point.
+    pub fired: Vec<String>,
+}
+
+/// A full pass of one policy set over a timeline.
+#[derive(Debug, Clone)]
+pub struct WhatIfRun {
+    pub policy_name: String,
+    pub steps: Vec<WhatIfStep>,
+}
+
+impl WhatIfRun {
+    /// Number of steps where this policy set disagrees with the recording.
+    pub fn divergence(&self) -> usize {
+        self.steps
+            .iter()
+            .filter(|s| s.decision.policy != s.recorded_policy)
+            .count()
+    }
+
+    /// Text report: policy transitions, fired actions and totals.
+    pub fn report(&self) -> String {
+        let mut out = format!(
+            "What-if '{}' over {} recorded step(s):\n",
+            self.policy_name,
+            self.steps.len()
+        );
+
+        let mut prev: Option<&str> = None;
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        let mut fired_total = 0;
+
+        for step in &self.steps {
+            *counts.entry(step.decision.policy.as_str()).or_default() += 1;
+
+            let changed = prev != Some(step.decision.policy.as_str());
+            if changed || !step.fired.is_empty() {
+                let fired = if step.fired.is_empty() {
+                    String::new()
+                } else {
+                    format!(" :: fires {}", step.fired.join(","))
+                };
+                out.push_str(&format!(
+                    " - cycle {} @{} :: awareness {:.2} :: {} [{}]{}\n",
+                    step.cycle,
+                    step.timestamp,
+                    step.awareness,
+                    step.decision.policy,
+                    step.decision.rule,
+                    fired
+                ));
+            }
+            fired_total += step.fired.len();
+            prev = Some(step.decision.policy.as_str());
+        }
+
+        out.push_str("Totals:\n");
+        for (policy, n) in counts {
+            out.push_str(&format!("   {:<32} {}\n", policy, n));
+        }
+        out.push_str(&format!(
+            "   actions fired: {} :: differs from recording on {} step(s)\n",
+            fired_total,
+            self.divergence()
+        ));
+        out
+    }
+}
+
+/// Evaluate a policy set against every step of a recorded timeline.
+pub fn simulate(set: &PolicySet, timeline: &[DecisionRecord]) -> WhatIfRun {
+    let mut sim_level = timeline.first().map(|rec| rec.inputs.sim_level.clone());
+    let steps = timeline
+        .iter()
+        .map(|rec| {
+            let mut inputs = rec.inputs.clone();
+            if let Some(level) = &sim_level {
+                inputs.sim_level = level.clone();
+            }
+            let decision = set.evaluate(&inputs);
+            let fired = decision.effective_actions(&inputs.sim_level);
+            for action in &fired {
+                if let Some(("sim_level", level)) = action.split_once('=') {
+                    sim_level = Some(level.to_string());
+                }
+            }
+            WhatIfStep {
+                timestamp: rec.timestamp,
+                cycle: rec.cycle,
+                awareness: rec.inputs.awareness,
+                recorded_policy: rec.policy.clone(),
+                decision,
+                fired,
+            }
+        })
+        .collect();
+
+    WhatIfRun {
+        policy_name: set.name.clone(),
+        steps,
+    }
+}
+
+/// Side-by-side comparison of two runs over the same timeline. Only the
+/// steps where the two policy sets disagree are listed.
+pub fn compare(a: &WhatIfRun, b: &WhatIfRun) -> String {
+    let mut out = format!(
+        "What-if comparison: A='{}' vs B='{}'\n",
+        a.policy_name, b.policy_name
+    );
+    out.push_str(&format!(
+        " {:>8} {:>6}  {:<36} {:<36}\n",
+        "cycle", "aware", "A", "B"
+    ));
+
+    let mut differences = 0;
+    for (sa, sb) in a.steps.iter().zip(b.steps.iter()) {
+        if sa.decision.policy == sb.decision.policy && sa.fired == sb.fired {
+            continue;
+        }
+        differences += 1;
+        out.push_str(&format!(
+            " {:>8} {:>6.2}  {:<36} {:<36}\n",
+            sa.cycle,
+            sa.awareness,
+            describe_step(sa),
+            describe_step(sb)
+        ));
+    }
+
+    if differences == 0 {
+        out.push_str(" (no differences)\n");
+    }
+
+    let fired = |r: &WhatIfRun| r.steps.iter().map(|s| s.fired.len()).sum::<usize>();
+    out.push_str(&format!(
+        "summary: {} step(s), {} differ :: actions fired A={} B={} :: vs recording A={} B={}\n",
+        a.steps.len(),
+        differences,
+        fired(a),
+        fired(b),
+        a.divergence(),
+        b.divergence()
+    ));
+    out
+}
+
+/// Load a timeline and run one or two policy sets against it, returning
+/// the text report used by the `cortex whatif` command.
+pub fn run_from_files(timeline: &Path, policy_a: &str, policy_b: Option<&str>) -> String {
+    let records = match journal::read_records(timeline) {
+        Ok(r) => r,
+        Err(e) => return format!("failed to read timeline {:?}: {}", timeline, e),
+    };
+    if records.is_empty() {
+        return format!("timeline {:?} has no recorded decisions", timeline);
+    }
+
+    let set_a = match PolicySet::load(policy_a) {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+    let run_a = simulate(&set_a, &records);
+
+    match policy_b {
+        None => run_a.report(),
+        Some(name) => {
+            let set_b = match PolicySet::load(name) {
+                Ok(s) => s,
+                Err(e) => return e,
+            };
+            let run_b = simulate(&set_b, &records);
+            compare(&run_a, &run_b)
+        }
+    }
+}
+
+fn describe_step(step: &WhatIfStep) -> String {
+    if step.fired.is_empty() {
+        step.decision.policy.clone()
+    } else {
+        format!("{} +{}", step.decision.policy, step.fired.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cortex::DecisionInputs;
+
+    fn timeline(awareness: &[f32]) -> Vec<DecisionRecord> {
+        awareness
+            .iter()
+            .enumerate()
+            .map(|(i, &a)| DecisionRecord {
+                id: i as u64 + 1,
+                cycle: i as u64 + 1,
+                timestamp: 1000 + i as u64,
+                inputs: DecisionInputs {
+                    awareness: a,
+                    sim_level: "High".to_string(),
+                    organs: Vec::new(),
+                },
+                brain: "ladder".to_string(),
+                rule: "recorded".to_string(),
+                policy: "policy=maintain_load".to_string(),
+                actions: Vec::new(),
+                outcome: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn actions_fire_once_the_set_has_applied_them() {
+        // The recording stays at High throughout; the set's own
+        // `sim_level=off` holds from the step it first fires.
+        let run = simulate(&PolicySet::default_ladder(), &timeline(&[0.7, 0.2, 0.1, 0.2, 0.7]));
+        let fired: Vec<usize> = run.steps.iter().map(|s| s.fired.len()).collect();
+        assert_eq!(fired, [0, 1, 0, 0, 0]);
+        assert_eq!(run.divergence(), 3);
+
+        let report = run.report();
+        assert!(report.contains("over 5 recorded step(s)"), "{}", report);
+        assert!(report.contains("actions fired: 1 :: differs from recording on 3 step(s)"), "{}", report);
+    }
+
+    #[test]
+    fn conditions_see_the_simulated_sim_level() {
+        let set = PolicySet::parse(
+            "t",
+            "sim_level == high => cool_down ; sim_level=low\nalways => maintain_load\n",
+        )
+        .unwrap();
+        let run = simulate(&set, &timeline(&[0.5, 0.5, 0.5]));
+        let policies: Vec<&str> = run.steps.iter().map(|s| s.decision.policy.as_str()).collect();
+        assert_eq!(policies, ["policy=cool_down", "policy=maintain_load", "policy=maintain_load"]);
+    }
+
+    #[test]
+    fn comparison_lists_only_differences() {
+        let steps = timeline(&[0.9, 0.5, 0.2]);
+        let a = simulate(&PolicySet::default_ladder(), &steps);
+        let lenient = PolicySet::parse("lenient", "awareness >= 0.85 => push_capacity\nalways => maintain_load\n").unwrap();
+        let out = compare(&a, &simulate(&lenient, &steps));
+        assert!(out.contains("A='default' vs B='lenient'"), "{}", out);
+        assert!(!out.contains("push_capacity"), "{}", out);
+        assert!(out.contains("policy=protect_core(sim_off) +sim_level=off"), "{}", out);
+        assert!(out.contains("summary: 3 step(s), 2 differ :: actions fired A=1 B=0"), "{}", out);
+        assert!(compare(&a, &a).contains("(no differences)"));
+    }
+}
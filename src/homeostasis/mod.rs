@@ -0,0 +1,490 @@
+//! Homeostasis for AION.
+//!
+//! A setpoint says where a metric should stay ("keep cpu_temp_c under
+//! 70", "keep awareness above 0.8"). Each setpoint owns a PID controller
+//! whose output (0.0–1.0) drives one actuator knob:
+//!
+//! - `throttle`   – workload throttle handed to the telemetry provider
+//! - `sim_level`  – caps the simulation level (≥0.33 → low, ≥0.66 → off)
+//!   and restores the previous level once the output falls back to zero,
+//!   unless someone else has changed the level in the meantime
+//! - `caps:<kind>` – disables capabilities of a kind while output ≥ 0.5,
+//!   re-enables them once it drops below 0.2. A kind a reflex just
+//!   switched is left alone until the reflex's refractory period ends.
+//!
+//! When several controllers drive the same knob, the strongest wins.
+
+use std::collections::HashMap;
+
+use crate::capabilities::{CapabilityKind, CapabilityRegistry};
+use crate::telemetry::SimLevel;
+
+/// Which side of the target is healthy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Keep the metric at or below the target.
+    Below,
+    /// Keep the metric at or above the target.
+    Above,
+}
+
+/// The knob a controller turns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Actuator {
+    Throttle,
+    SimLevel,
+    Capability(CapabilityKind),
+}
+
+impl Actuator {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "throttle" => Some(Actuator::Throttle),
+            "sim_level" | "sim" => Some(Actuator::SimLevel),
+            _ => s
+                .strip_prefix("caps:")
+                .and_then(CapabilityKind::from_name)
+                .map(Actuator::Capability),
+        }
+    }
+
+    fn label(&self) -> String {
+        match self {
+            Actuator::Throttle => "throttle".to_string(),
+            Actuator::SimLevel => "sim_level".to_string(),
+            Actuator::Capability(k) => format!("caps:{:?}", k),
+        }
+    }
+}
+
+/// A textbook PID with output clamped to 0.0–1.0 and a bounded integral.
+#[derive(Debug, Clone)]
+pub struct Pid {
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+    integral: f32,
+    prev_error: Option<f32>,
+    /// Last individual terms, for the shell.
+    pub p_term: f32,
+    pub i_term: f32,
+    pub d_term: f32,
+}
+
+impl Pid {
+    pub fn new(kp: f32, ki: f32, kd: f32) -> Self {
+        Self {
+            kp,
+            ki,
+            kd,
+            integral: 0.0,
+            prev_error: None,
+            p_term: 0.0,
+            i_term: 0.0,
+            d_term: 0.0,
+        }
+    }
+
+    /// Advance the controller by `dt` seconds with the current error
+    /// (positive = metric is on the wrong side of the setpoint).
+    pub fn update(&mut self, error: f32, dt: f32) -> f32 {
+        let dt = dt.max(1e-3);
+
+        // Anti-windup: never let the integral alone demand more than full
+        // output, and don't bank "credit" while the metric is healthy so
+        // the controller reacts as soon as it crosses the setpoint.
+        self.integral += error * dt;
+        let limit = if self.ki > 0.0 { 1.0 / self.ki } else { 0.0 };
+        self.integral = self.integral.clamp(0.0, limit);
+
+        let derivative = match self.prev_error {
+            Some(prev) => (error - prev) / dt,
+            None => 0.0,
+        };
+        self.prev_error = Some(error);
+
+        self.p_term = self.kp * error;
+        self.i_term = self.ki * self.integral;
+        self.d_term = self.kd * derivative;
+        (self.p_term + self.i_term + self.d_term).clamp(0.0, 1.0)
+    }
+
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+        self.prev_error = None;
+    }
+}
+
+/// One homeostatic setpoint and its controller.
+#[derive(Debug, Clone)]
+pub struct Setpoint {
+    pub id: u32,
+    pub metric: String,
+    pub direction: Direction,
+    pub target: f32,
+    pub actuator: Actuator,
+    pub pid: Pid,
+    pub enabled: bool,
+    /// Last observed value, error and output.
+    pub value: Option<f32>,
+    pub error: f32,
+    pub output: f32,
+}
+
+impl Setpoint {
+    fn error_for(&self, value: f32) -> f32 {
+        match self.direction {
+            Direction::Below => value - self.target,
+            Direction::Above => self.target - value,
+        }
+    }
+}
+
+/// Default PID gains for a metric, scaled to its usual range.
+pub fn default_gains(metric: &str) -> (f32, f32, f32) {
+    match metric {
+        // Degrees: 20°C over target saturates the proportional term.
+        "cpu_temp_c" => (0.05, 0.01, 0.0),
+        // Milliseconds.
+        "disk_latency_ms" | "net_latency_ms" => (0.05, 0.01, 0.0),
+        // Everything else is a 0..1 ratio or score.
+        _ => (4.0, 0.5, 0.0),
+    }
+}
+
+/// Knob positions produced by one control step.
+#[derive(Debug, Clone, Default)]
+pub struct ControlOutputs {
+    /// Workload throttle for the telemetry provider.
+    pub throttle: f32,
+    /// Sim level to apply, if it should change.
+    pub sim_level: Option<SimLevel>,
+    /// Capability kinds to disable (true) or re-enable (false).
+    pub capabilities: Vec<(CapabilityKind, bool)>,
+}
+
+/// All setpoints plus the actuator state they own.
+#[derive(Debug, Default)]
+pub struct Homeostasis {
+    setpoints: Vec<Setpoint>,
+    next_id: u32,
+    /// Sim level in force before homeostasis lowered it.
+    saved_sim_level: Option<SimLevel>,
+    /// The level homeostasis lowered it to.
+    applied_sim_level: Option<SimLevel>,
+    /// Capability kinds currently held disabled by homeostasis.
+    disabled_caps: Vec<CapabilityKind>,
+    pub throttle: f32,
+    steps: u64,
+}
+
+impl Homeostasis {
+    pub fn new() -> Self {
+        Self {
+            next_id: 1,
+            ..Self::default()
+        }
+    }
+
+    /// The stock setpoints: keep the CPU under 70°C by throttling, and
+    /// keep awareness above 0.8 by backing off the simulation.
+    pub fn with_defaults() -> Self {
+        let mut h = Self::new();
+        h.add("cpu_temp_c", Direction::Below, 70.0, Actuator::Throttle, None);
+        h.add("awareness", Direction::Above, 0.8, Actuator::SimLevel, None);
+        h
+    }
+
+    /// Add a setpoint, returning its ID.
+    pub fn add(
+        &mut self,
+        metric: &str,
+        direction: Direction,
+        target: f32,
+        actuator: Actuator,
+        gains: Option<(f32, f32, f32)>,
+    ) -> u32 {
+        let (kp, ki, kd) = gains.unwrap_or_else(|| default_gains(metric));
+        let id = self.next_id;
+        self.next_id += 1;
+        self.setpoints.push(Setpoint {
+            id,
+            metric: metric.to_string(),
+            direction,
+            target,
+            actuator,
+            pid: Pid::new(kp, ki, kd),
+            enabled: true,
+            value: None,
+            error: 0.0,
+            output: 0.0,
+        });
+        id
+    }
+
+    pub fn remove(&mut self, id: u32) -> bool {
+        let before = self.setpoints.len();
+        self.setpoints.retain(|s| s.id != id);
+        self.setpoints.len() != before
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut Setpoint> {
+        self.setpoints.iter_mut().find(|s| s.id == id)
+    }
+
+    /// Run every controller once. `read` maps a metric name to its
+    /// current value; `current_sim` is the sim level in force now and
+    /// `held` the capability kinds reflexes hold.
+    pub fn step(
+        &mut self,
+        dt: f32,
+        current_sim: SimLevel,
+        held: &[CapabilityKind],
+        read: impl Fn(&str) -> Option<f32>,
+    ) -> ControlOutputs {
+        self.steps += 1;
+
+        let mut throttle: f32 = 0.0;
+        let mut sim_drive: f32 = 0.0;
+        let mut cap_drive: HashMap<CapabilityKind, f32> = HashMap::new();
+
+        for sp in self.setpoints.iter_mut() {
+            if !sp.enabled {
+                sp.output = 0.0;
+                continue;
+            }
+            sp.value = read(&sp.metric);
+            let Some(value) = sp.value else {
+                continue;
+            };
+            sp.error = sp.error_for(value);
+            sp.output = sp.pid.update(sp.error, dt);
+
+            match sp.actuator {
+                Actuator::Throttle => throttle = throttle.max(sp.output),
+                Actuator::SimLevel => sim_drive = sim_drive.max(sp.output),
+                Actuator::Capability(kind) => {
+                    let e = cap_drive.entry(kind).or_default();
+                    *e = e.max(sp.output);
+                }
+            }
+        }
+
+        let mut out = ControlOutputs {
+            throttle,
+            ..ControlOutputs::default()
+        };
+        self.throttle = throttle;
+
+        // Sim level: cap while driven, restore once released.
+        let cap = if sim_drive >= 0.66 {
+            Some(SimLevel::Off)
+        } else if sim_drive >= 0.33 {
+            Some(SimLevel::Low)
+        } else {
+            None
+        };
+        match cap {
+            Some(limit) if sim_rank(current_sim) > sim_rank(limit) => {
+                self.saved_sim_level.get_or_insert(current_sim);
+                self.applied_sim_level = Some(limit);
+                out.sim_level = Some(limit);
+            }
+            Some(_) => {}
+            None if sim_drive <= 0.0 => {
+                // A level set by hand since then stays.
+                let applied = self.applied_sim_level.take();
+                if let Some(saved) = self.saved_sim_level.take() {
+                    if applied == Some(current_sim) {
+                        out.sim_level = Some(saved);
+                    }
+                }
+            }
+            None => {}
+        }
+
+        // Capabilities, with hysteresis.
+        for (kind, drive) in cap_drive {
+            if held.contains(&kind) {
+                continue;
+            }
+            let held = self.disabled_caps.contains(&kind);
+            if drive >= 0.5 && !held {
+                self.disabled_caps.push(kind);
+                out.capabilities.push((kind, true));
+            } else if drive < 0.2 && held {
+                self.disabled_caps.retain(|k| *k != kind);
+                out.capabilities.push((kind, false));
+            }
+        }
+
+        out
+    }
+
+    /// Apply capability decisions to the registry.
+    pub fn apply_capabilities(outputs: &ControlOutputs, registry: &mut CapabilityRegistry) {
+        for (kind, disable) in &outputs.capabilities {
+            registry.set_enabled_by_kind(*kind, !*disable);
+        }
+    }
+
+    /// Shell report of every controller and the knobs it drives.
+    pub fn report(&self) -> String {
+        let mut out = format!("Homeostasis ({} control step(s)):\n", self.steps);
+        if self.setpoints.is_empty() {
+            out.push_str(" (no setpoints)\n");
+        }
+        for sp in &self.setpoints {
+            let dir = match sp.direction {
+                Direction::Below => "below",
+                Direction::Above => "above",
+            };
+            let value = match sp.value {
+                Some(v) => format!("{:.3}", v),
+                None => "n/a".to_string(),
+            };
+            out.push_str(&format!(
+                " - #{} keep {} {} {} -> {}{}\n   value {} :: error {:+.3} :: P {:.3} I {:.3} D {:.3} :: output {:.2} (kp {} ki {} kd {})\n",
+                sp.id,
+                sp.metric,
+                dir,
+                sp.target,
+                sp.actuator.label(),
+                if sp.enabled { "" } else { " [disabled]" },
+                value,
+                sp.error,
+                sp.pid.p_term,
+                sp.pid.i_term,
+                sp.pid.d_term,
+                sp.output,
+                sp.pid.kp,
+                sp.pid.ki,
+                sp.pid.kd,
+            ));
+        }
+        out.push_str(&format!(
+            "actuators :: throttle {:.2} :: sim_level held {} :: caps disabled [{}]\n",
+            self.throttle,
+            match self.saved_sim_level {
+                Some(l) => format!("(was {:?})", l),
+                None => "no".to_string(),
+            },
+            self.disabled_caps
+                .iter()
+                .map(|k| format!("{:?}", k))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+        out
+    }
+}
+
+fn sim_rank(level: SimLevel) -> u8 {
+    match level {
+        SimLevel::Off => 0,
+        SimLevel::Low => 1,
+        SimLevel::High => 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pid_terms_and_anti_windup() {
+        let mut pid = Pid::new(2.0, 0.5, 1.0);
+        // First step: no derivative yet.
+        let out = pid.update(0.1, 1.0);
+        assert!((out - (0.2 + 0.05)).abs() < 1e-6, "{}", out);
+        assert_eq!(pid.d_term, 0.0);
+        // The error falls, so the derivative pulls the output down.
+        let out = pid.update(0.05, 1.0);
+        assert!((pid.d_term + 0.05).abs() < 1e-6, "{}", pid.d_term);
+        assert!((out - (0.1 + 0.075 - 0.05)).abs() < 1e-6, "{}", out);
+
+        // A long stall banks at most full output in the integral, and
+        // none while the metric is healthy.
+        for _ in 0..100 {
+            pid.update(1.0, 1.0);
+        }
+        assert_eq!(pid.i_term, 1.0);
+        assert_eq!(pid.update(5.0, 1.0), 1.0);
+        pid.reset();
+        assert_eq!(pid.update(-1.0, 1.0), 0.0);
+        assert_eq!(pid.i_term, 0.0);
+    }
+
+    #[test]
+    fn setpoints_drive_the_strongest_knob() {
+        let mut h = Homeostasis::new();
+        let hot = h.add("cpu_temp_c", Direction::Below, 70.0, Actuator::Throttle, Some((0.05, 0.0, 0.0)));
+        h.add("load", Direction::Below, 0.5, Actuator::Throttle, Some((1.0, 0.0, 0.0)));
+        let read = |m: &str| match m {
+            "cpu_temp_c" => Some(80.0),
+            "load" => Some(0.6),
+            _ => None,
+        };
+        let out = h.step(1.0, SimLevel::Off, &[], read);
+        assert!((out.throttle - 0.5).abs() < 1e-6, "{}", out.throttle);
+
+        h.get_mut(hot).unwrap().enabled = false;
+        let out = h.step(1.0, SimLevel::Off, &[], read);
+        assert!((out.throttle - 0.1).abs() < 1e-6, "{}", out.throttle);
+        assert!(h.remove(hot) && !h.remove(hot));
+        assert!(h.report().contains("keep load below 0.5 -> throttle"));
+    }
+
+    fn awareness_guard() -> Homeostasis {
+        let mut h = Homeostasis::new();
+        h.add("awareness", Direction::Above, 0.8, Actuator::SimLevel, Some((4.0, 0.0, 0.0)));
+        h
+    }
+
+    #[test]
+    fn sim_level_is_capped_and_restored() {
+        let mut h = awareness_guard();
+        // 0.6 is 0.2 short: output 0.8 turns the simulation off.
+        assert_eq!(h.step(1.0, SimLevel::High, &[], |_| Some(0.6)).sim_level, Some(SimLevel::Off));
+        assert!(h.report().contains("sim_level held (was High)"));
+        assert_eq!(h.step(1.0, SimLevel::Off, &[], |_| Some(0.7)).sim_level, None);
+        assert_eq!(h.step(1.0, SimLevel::Off, &[], |_| Some(0.9)).sim_level, Some(SimLevel::High));
+        assert_eq!(h.step(1.0, SimLevel::High, &[], |_| Some(0.9)).sim_level, None);
+    }
+
+    #[test]
+    fn a_sim_level_changed_meanwhile_is_not_overwritten() {
+        let mut h = awareness_guard();
+        assert_eq!(h.step(1.0, SimLevel::High, &[], |_| Some(0.6)).sim_level, Some(SimLevel::Off));
+        // Someone sets Low by hand while the cap is in force.
+        assert_eq!(h.step(1.0, SimLevel::Low, &[], |_| Some(0.9)).sim_level, None);
+        assert!(h.report().contains("sim_level held no"));
+        assert_eq!(h.step(1.0, SimLevel::Low, &[], |_| Some(0.9)).sim_level, None);
+    }
+
+    #[test]
+    fn capabilities_switch_with_hysteresis() {
+        let kind = CapabilityKind::CortexCompute;
+        let mut h = Homeostasis::new();
+        h.add("load", Direction::Below, 0.5, Actuator::Capability(kind), Some((1.0, 0.0, 0.0)));
+        let mut step = |load: f32| h.step(1.0, SimLevel::Off, &[], |_| Some(load)).capabilities;
+        assert_eq!(step(1.2), [(kind, true)]);
+        assert!(step(0.8).is_empty());
+        assert!(step(1.0).is_empty());
+        assert_eq!(step(0.65), [(kind, false)]);
+    }
+
+    #[test]
+    fn capabilities_held_by_a_reflex_are_left_alone() {
+        let kind = CapabilityKind::GpuWorkload;
+        let mut h = Homeostasis::new();
+        h.add("load", Direction::Below, 0.5, Actuator::Capability(kind), Some((1.0, 0.0, 0.0)));
+        // A reflex just re-enabled the kind; the controller waits.
+        assert!(h.step(1.0, SimLevel::Off, &[kind], |_| Some(1.2)).capabilities.is_empty());
+        assert!(h.step(1.0, SimLevel::Off, &[kind], |_| Some(1.2)).capabilities.is_empty());
+        assert_eq!(h.step(1.0, SimLevel::Off, &[], |_| Some(1.2)).capabilities, [(kind, true)]);
+        // And does not undo a reflex's enable while it holds either.
+        assert!(h.step(1.0, SimLevel::Off, &[kind], |_| Some(0.0)).capabilities.is_empty());
+        assert_eq!(h.step(1.0, SimLevel::Off, &[], |_| Some(0.0)).capabilities, [(kind, false)]);
+    }
+}
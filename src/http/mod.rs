@@ -1,13 +1,38 @@
+use std::io::{Read, Write};
+use std::sync::mpsc::RecvTimeoutError;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use tiny_http::{Header, Response, Server};
+use tiny_http::{Header, Method, Request, Response, Server};
 
-use crate::kernel::{compute_overall_health, TelemetrySnapshot};
+use crate::cortex::DecisionJournal;
+use crate::json::JsonValue;
+use crate::kernel::control::same_token;
+use crate::kernel::{
+    compute_overall_health, lock, pubsub, BusHandle, LogFilter, OutputMode, PubSub, PulseFilter,
+    PulseHistory, PulseKind, ShellHandle, Since, TelemetrySnapshot,
+};
+use crate::telemetry::SimLevel;
 use crate::memory::MemoryBus;
 use crate::organism::{self, SystemTopology};
 
 mod homepage;
 
+/// How long `/shell` waits for the command daemon.
+const SHELL_TIMEOUT: Duration = Duration::from_secs(10);
+/// Largest request body the POST endpoints read.
+const MAX_BODY_BYTES: u64 = 64 * 1024;
+/// How often a quiet `/subscribe` stream sends a blank line, to find out
+/// whether its client is still there.
+const KEEPALIVE: Duration = Duration::from_secs(15);
+
+/// What the POST endpoints need: the shell, and the control socket's
+/// token, which callers must send as `Authorization: Bearer <token>`.
+pub struct ShellEndpoint {
+    pub shell: ShellHandle,
+    pub token: String,
+}
+
 pub struct HttpServer {
     addr: String,
 }
@@ -24,42 +49,55 @@ impl HttpServer {
         topology: Arc<Mutex<SystemTopology>>,
         metrics: Arc<Mutex<Option<TelemetrySnapshot>>>,
         memory: MemoryBus,
+        journal: Arc<Mutex<DecisionJournal>>,
+        bus: BusHandle,
+        shell: Option<ShellEndpoint>,
     ) {
         let addr = self.addr.clone();
 
         std::thread::spawn(move || {
+            let pubsub = bus.pubsub();
+            let history = bus.history();
+
             let server = Server::http(&addr).unwrap();
             println!("[AION-HTTP] Listening on http://{}", addr);
 
-            for req in server.incoming_requests() {
+            for mut req in server.incoming_requests() {
                 let url = req.url().to_string();
+                let (path, query) = match url.split_once('?') {
+                    Some((p, q)) => (p.to_string(), q.to_string()),
+                    None => (url.clone(), String::new()),
+                };
+
+                // Streaming subscriptions take over the connection.
+                if path == "/subscribe" {
+                    stream_pulses(req, &query, &pubsub);
+                    continue;
+                }
 
                 // Snapshot of health + awareness for each request.
                 let (health_score, health_label, awareness_score, awareness_label) = {
-                    if let Ok(topo) = topology.lock() {
-                        let h = compute_overall_health(&*topo);
-                        let hl = if h >= 0.85 {
-                            "ok"
-                        } else if h >= 0.60 {
-                            "degraded"
-                        } else if h >= 0.35 {
-                            "impaired"
-                        } else if h > 0.0 {
-                            "critical"
-                        } else {
-                            "failed"
-                        };
+                    let topo = lock(&topology);
+                    let h = compute_overall_health(&topo);
+                    let hl = if h >= 0.85 {
+                        "ok"
+                    } else if h >= 0.60 {
+                        "degraded"
+                    } else if h >= 0.35 {
+                        "impaired"
+                    } else if h > 0.0 {
+                        "critical"
+                    } else {
+                        "failed"
+                    };
 
-                        let a = organism::compute_awareness(&*topo);
-                        let al = organism::describe_awareness(a);
+                    let a = organism::compute_awareness(&topo);
+                    let al = organism::describe_awareness(a);
 
-                        (h, hl.to_string(), a, al.to_string())
-                    } else {
-                        (1.0, "ok".to_string(), 1.0, "optimal".to_string())
-                    }
+                    (h, hl.to_string(), a, al.to_string())
                 };
 
-                let response = match url.as_str() {
+                let response = match path.as_str() {
                     "/" => {
                         let html = homepage::homepage_html(
                             health_score,
@@ -86,29 +124,10 @@ impl HttpServer {
                     }
 
                     "/metrics" => {
-                        let guard = metrics.lock().unwrap();
+                        let guard = lock(&metrics);
 
                         if let Some(snap) = *guard {
-                            let body = format!(
-                                concat!(
-                                    r#"{{"cpu":{{"cpu_load":{:.3},"cpu_temp_c":{:.1},"throttling_events":{},"gpu_load":{:.3},"gpu_mem_util":{:.3}}},"#,
-                                    r#""memory":{{"ram_used_ratio":{:.3},"swap_used_ratio":{:.3},"major_page_faults":{:.3},"disk_latency_ms":{:.3}}},"#,
-                                    r#""io":{{"net_packet_loss":{:.3},"net_latency_ms":{:.3},"io_queue_depth":{:.3},"io_error_rate":{:.3}}}}}"#
-                                ),
-                                snap.cpu.cpu_load,
-                                snap.cpu.cpu_temp_c,
-                                snap.cpu.throttling_events,
-                                snap.cpu.gpu_load,
-                                snap.cpu.gpu_mem_util,
-                                snap.mem.ram_used_ratio,
-                                snap.mem.swap_used_ratio,
-                                snap.mem.major_page_faults,
-                                snap.mem.disk_latency_ms,
-                                snap.io.net_packet_loss,
-                                snap.io.net_latency_ms,
-                                snap.io.io_queue_depth,
-                                snap.io.io_error_rate,
-                            );
+                            let body = snap.to_json();
 
                             Response::from_string(body).with_header(
                                 Header::from_bytes("Content-Type", "application/json").unwrap(),
@@ -138,6 +157,72 @@ impl HttpServer {
                         )
                     }
 
+                    "/cortex/history" => {
+                        // Optional ?limit=N&policy=<substring>
+                        let limit = query_param(&query, "limit")
+                            .and_then(|v| v.parse().ok())
+                            .unwrap_or(50);
+                        let policy = query_param(&query, "policy");
+                        let body = lock(&journal).history_json(limit, policy.as_deref());
+                        Response::from_string(body).with_header(
+                            Header::from_bytes("Content-Type", "application/json").unwrap(),
+                        )
+                    }
+
+                    "/control/sim" | "/control/logs" | "/pulse" => {
+                        let refused = |status: u16, error: &str| {
+                            (status, format!(r#"{{"error":"{}"}}"#, crate::json::escape(error)))
+                        };
+                        let (status, body) = match &shell {
+                            None => refused(403, "the control endpoints need the control socket"),
+                            Some(_) if *req.method() != Method::Post => refused(405, "use POST"),
+                            Some(endpoint) => {
+                                match authorize(&req, &endpoint.token).and_then(|()| read_body(&mut req)) {
+                                    Err((status, error)) => refused(status, error),
+                                    Ok(text) => control(&path, &query, &text, &bus),
+                                }
+                            }
+                        };
+                        Response::from_string(body)
+                            .with_status_code(status)
+                            .with_header(
+                                Header::from_bytes("Content-Type", "application/json").unwrap(),
+                            )
+                    }
+
+                    "/shell" => {
+                        let refused = |status: u16, error: &str| {
+                            let body = format!(r#"{{"error":"{}"}}"#, crate::json::escape(error));
+                            (status, body, OutputMode::Json)
+                        };
+                        let (status, body, mode) = match &shell {
+                            None => refused(403, "the shell endpoint needs the control socket"),
+                            Some(_) if *req.method() != Method::Post => refused(405, "use POST"),
+                            Some(endpoint) => {
+                                match authorize(&req, &endpoint.token).and_then(|()| read_body(&mut req)) {
+                                    Err((status, error)) => refused(status, error),
+                                    Ok(text) => run_shell(&query, &text, &endpoint.shell),
+                                }
+                            }
+                        };
+                        let content_type = match mode {
+                            OutputMode::Json => "application/json",
+                            OutputMode::Text => "text/plain; charset=utf-8",
+                        };
+                        Response::from_string(body)
+                            .with_status_code(status)
+                            .with_header(Header::from_bytes("Content-Type", content_type).unwrap())
+                    }
+
+                    "/events" => {
+                        let (status, body) = events_json(&query, &history);
+                        Response::from_string(body)
+                            .with_status_code(status)
+                            .with_header(
+                                Header::from_bytes("Content-Type", "application/json").unwrap(),
+                            )
+                    }
+
                     _ => {
                         Response::from_string(r#"{"error":"not found"}"#)
                             .with_status_code(404)
@@ -152,3 +237,306 @@ impl HttpServer {
         });
     }
 }
+
+/// POST endpoints that act on the kernel through the bus handle. The
+/// change is applied by the kernel loop, so the reply only says it was
+/// queued.
+///
+/// - `/control/sim?level=off|low|high`
+/// - `/control/logs?filter=all|commands|silent&format=json|text`
+/// - `/pulse?kind=<kind>&source=<name>` with `{"message":"..."}`
+fn control(path: &str, query: &str, body: &str, bus: &BusHandle) -> (u16, String) {
+    let bad = |msg: &str| (400, format!(r#"{{"error":"{}"}}"#, crate::json::escape(msg)));
+
+    let queued = match path {
+        "/control/sim" => match query_param(query, "level").as_deref().and_then(SimLevel::from_name) {
+            Some(level) => bus.set_sim_level(level, "http"),
+            None => return bad("level must be off, low or high"),
+        },
+        "/control/logs" => {
+            let filter = match query_param(query, "filter").as_deref() {
+                None => None,
+                Some("all") => Some(LogFilter::All),
+                Some("commands") => Some(LogFilter::CommandsOnly),
+                Some("silent" | "off") => Some(LogFilter::Silent),
+                Some(_) => return bad("filter must be all, commands or silent"),
+            };
+            let json = match query_param(query, "format").as_deref() {
+                None => None,
+                Some("json") => Some(true),
+                Some("text") => Some(false),
+                Some(_) => return bad("format must be json or text"),
+            };
+            if filter.is_none() && json.is_none() {
+                return bad("give filter and/or format");
+            }
+            bus.set_log_filter(filter, json, "http")
+        }
+        _ => {
+            let kind = match query_param(query, "kind") {
+                Some(k) => match PulseKind::from_name(&k) {
+                    Some(kind) => kind,
+                    None => return bad("unknown pulse kind"),
+                },
+                None => PulseKind::Command,
+            };
+            let source = query_param(query, "source").unwrap_or_else(|| "http".to_string());
+            let message = JsonValue::parse(body)
+                .ok()
+                .and_then(|v| v.get("message").and_then(JsonValue::as_str).map(|m| m.trim().to_string()))
+                .unwrap_or_default();
+            if message.is_empty() {
+                return bad(r#"expected {"message":"<text>"}"#);
+            }
+            bus.emit(kind, source, message)
+        }
+    };
+
+    if queued {
+        (202, r#"{"queued":true}"#.to_string())
+    } else {
+        (503, r#"{"error":"kernel loop not running"}"#.to_string())
+    }
+}
+
+/// A request header's value, if it was sent.
+fn header<'a>(req: &'a Request, name: &'static str) -> Option<&'a str> {
+    req.headers().iter().find(|h| h.field.equiv(name)).map(|h| h.value.as_str())
+}
+
+/// The POST endpoints change the kernel (`/shell` runs anything the
+/// console can), so they take the control token and only JSON bodies. A
+/// page in the operator's browser can send neither without a preflight
+/// we never answer, and anything carrying an `Origin` is turned away
+/// outright.
+fn authorize(req: &Request, token: &str) -> Result<(), (u16, &'static str)> {
+    if header(req, "Origin").is_some() {
+        return Err((403, "browser requests are not accepted"));
+    }
+    let bearer = header(req, "Authorization").and_then(|v| v.strip_prefix("Bearer "));
+    if !bearer.is_some_and(|t| same_token(t.trim(), token)) {
+        return Err((401, "send the control token as Authorization: Bearer <token>"));
+    }
+    let json = header(req, "Content-Type")
+        .is_some_and(|v| v.split(';').next().unwrap_or("").trim() == "application/json");
+    if !json {
+        return Err((415, "send the body as application/json"));
+    }
+    Ok(())
+}
+
+/// The request body, up to MAX_BODY_BYTES.
+fn read_body(req: &mut Request) -> Result<String, (u16, &'static str)> {
+    let mut body = Vec::new();
+    req.as_reader()
+        .take(MAX_BODY_BYTES + 1)
+        .read_to_end(&mut body)
+        .map_err(|_| (400, "failed to read the body"))?;
+    if body.len() as u64 > MAX_BODY_BYTES {
+        return Err((413, "the body is too large"));
+    }
+    String::from_utf8(body).map_err(|_| (400, "the body is not UTF-8"))
+}
+
+/// `POST /shell?output=json|text` with `{"line":"..."}` as the body: run
+/// the line through the shell and return its result, JSON unless asked
+/// for text. 200 if it succeeded, 422 if a command failed, 400 if the
+/// line could not be run as written.
+fn run_shell(query: &str, body: &str, shell: &ShellHandle) -> (u16, String, OutputMode) {
+    let mode = match query_param(query, "output").as_deref().map(OutputMode::from_name) {
+        None => OutputMode::Json,
+        Some(Some(mode)) => mode,
+        Some(None) => {
+            return (400, r#"{"error":"output must be json or text"}"#.to_string(), OutputMode::Json)
+        }
+    };
+    let line = JsonValue::parse(body)
+        .ok()
+        .and_then(|v| v.get("line").and_then(JsonValue::as_str).map(|l| l.trim().to_string()))
+        .unwrap_or_default();
+    if line.is_empty() {
+        let body = r#"{"error":"expected {\"line\":\"<command line>\"}"}"#.to_string();
+        return (400, body, OutputMode::Json);
+    }
+    match shell.call(line).recv_timeout(SHELL_TIMEOUT) {
+        Ok(mut result) => {
+            result.mode = mode;
+            let status = match result.code.exit_code() {
+                0 => 200,
+                1 => 422,
+                _ => 400,
+            };
+            (status, result.render(), mode)
+        }
+        Err(_) => (
+            504,
+            r#"{"error":"no reply from the command daemon"}"#.to_string(),
+            OutputMode::Json,
+        ),
+    }
+}
+
+/// Filter terms (`kind`, `source`, `type`) taken from the query string.
+fn query_filter(query: &str) -> Result<PulseFilter, String> {
+    let terms: Vec<String> = ["kind", "source", "type"]
+        .iter()
+        .filter_map(|k| query_param(query, k).map(|v| format!("{}={}", k, v)))
+        .collect();
+    PulseFilter::parse(terms.iter().map(String::as_str))
+}
+
+/// `/events?kind=..&source=..&type=..&since_id=N&since_ms=T&limit=N`:
+/// filtered pulse history, oldest first.
+fn events_json(query: &str, history: &Arc<Mutex<PulseHistory>>) -> (u16, String) {
+    let filter = match query_filter(query) {
+        Ok(f) => f,
+        Err(e) => return (400, format!(r#"{{"error":"{}"}}"#, crate::json::escape(&e))),
+    };
+    let num = |key: &str| query_param(query, key).and_then(|v| v.parse::<u64>().ok());
+    let since = match (num("since_id"), num("since_ms")) {
+        (Some(id), _) => Since::Id(id),
+        (None, Some(ms)) => Since::Millis(ms),
+        (None, None) => Since::Any,
+    };
+    let limit = num("limit").unwrap_or(100) as usize;
+
+    let pulses: Vec<String> = lock(history)
+        .query(&filter, since, limit)
+        .iter()
+        .map(|p| p.to_json())
+        .collect();
+    (
+        200,
+        format!(r#"{{"count":{},"pulses":[{}]}}"#, pulses.len(), pulses.join(",")),
+    )
+}
+
+/// `/subscribe?kind=..&source=..&type=..&cap=N`: stream matching pulses
+/// as JSON lines until the client goes away, with a blank line after
+/// KEEPALIVE of quiet. Each stream gets its own thread and bounded queue,
+/// so a slow client only loses its own pulses.
+fn stream_pulses(req: Request, query: &str, pubsub: &Arc<Mutex<PubSub>>) {
+    let parsed = query_filter(query).and_then(|filter| match query_param(query, "cap") {
+        Some(cap) => Ok((filter, pubsub::parse_capacity(&cap)?)),
+        None => Ok((filter, pubsub::DEFAULT_CAPACITY)),
+    });
+    let (filter, capacity) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            let body = format!(r#"{{"error":"{}"}}"#, crate::json::escape(&e));
+            let _ = req.respond(
+                Response::from_string(body)
+                    .with_status_code(400)
+                    .with_header(Header::from_bytes("Content-Type", "application/json").unwrap()),
+            );
+            return;
+        }
+    };
+    let name = match req.remote_addr() {
+        Some(addr) => format!("http {}", addr),
+        None => "http".to_string(),
+    };
+
+    let (_, rx) = lock(pubsub).subscribe(&name, filter, capacity);
+
+    std::thread::spawn(move || {
+        let mut writer = req.into_writer();
+        let head = "HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n";
+        if writer.write_all(head.as_bytes()).and_then(|_| writer.flush()).is_err() {
+            return;
+        }
+        loop {
+            let line = match rx.recv_timeout(KEEPALIVE) {
+                Ok(pulse) => format!("{}\n", pulse.to_json()),
+                Err(RecvTimeoutError::Timeout) => "\n".to_string(),
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            if writer.write_all(line.as_bytes()).and_then(|_| writer.flush()).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Pull a single `key=value` out of a raw query string, decoded.
+fn query_param(query: &str, key: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| url_decode(k) == key)
+        .map(|(_, v)| url_decode(v))
+}
+
+/// Undo form encoding: `+` is a space and `%XX` a byte. Malformed
+/// escapes are kept as they are.
+fn url_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = |b: u8| (b as char).to_digit(16);
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                    (Some(hi), Some(lo)) => {
+                        out.push((hi * 16 + lo) as u8);
+                        i += 3;
+                        continue;
+                    }
+                    _ => out.push(b'%'),
+                }
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_values_are_decoded() {
+        let query = "source=a%2Cb&type=alert+x&bad=100%&k%65y=v";
+        assert_eq!(query_param(query, "source").as_deref(), Some("a,b"));
+        assert_eq!(query_param(query, "type").as_deref(), Some("alert x"));
+        assert_eq!(query_param(query, "bad").as_deref(), Some("100%"));
+        assert_eq!(query_param(query, "key").as_deref(), Some("v"));
+        assert_eq!(url_decode("%e2%9c%94%zz"), "✔%zz");
+    }
+
+    #[test]
+    fn post_endpoints_take_the_token_and_json_only() {
+        use tiny_http::TestRequest;
+
+        let header = |h: &str| h.parse::<Header>().unwrap();
+        let post = || {
+            TestRequest::new()
+                .with_method(Method::Post)
+                .with_path("/control/sim?level=low")
+                .with_header(header("Authorization: Bearer s3cret"))
+        };
+        let check = |req: TestRequest| authorize(&req.into(), "s3cret").map_err(|(status, _)| status);
+
+        assert_eq!(check(post().with_header(header("Content-Type: application/json"))), Ok(()));
+        // A cross-site form post: text/plain and an Origin.
+        let page = post()
+            .with_header(header("Content-Type: text/plain"))
+            .with_header(header("Origin: http://evil.example"));
+        assert_eq!(check(page), Err(403));
+        assert_eq!(check(post().with_header(header("Content-Type: text/plain"))), Err(415));
+        let unsigned = TestRequest::new()
+            .with_method(Method::Post)
+            .with_header(header("Content-Type: application/json"));
+        assert_eq!(check(unsigned), Err(401));
+
+        let mut small: Request = TestRequest::new().with_body(r#"{"message":"hi"}"#).into();
+        assert_eq!(read_body(&mut small).unwrap(), r#"{"message":"hi"}"#);
+        let huge = "a".repeat(MAX_BODY_BYTES as usize + 1).leak();
+        let mut big: Request = TestRequest::new().with_body(huge).into();
+        assert_eq!(read_body(&mut big).map_err(|(status, _)| status), Err(413));
+    }
+}
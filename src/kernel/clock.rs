@@ -0,0 +1,109 @@
+//! Time sources for the kernel.
+//!
+//! Everything that schedules or measures organism time asks a `Clock`
+//! instead of calling `Instant::now()` itself. `RealClock` is the wall
+//! clock; `VirtualClock` only moves when told to, so tests can run an
+//! hour of organism life in milliseconds.
+
+use std::sync::Arc;
+#[cfg(test)]
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+#[cfg(test)]
+use std::time::Duration;
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Milliseconds since the Unix epoch, for pulse timestamps.
+    fn unix_millis(&self) -> u64;
+
+    /// Whether time only moves when advanced. The scheduler sleeps on a
+    /// real clock and jumps a virtual one to the next deadline.
+    fn is_virtual(&self) -> bool {
+        false
+    }
+
+    /// Move a virtual clock forward to `t`; real clocks ignore this.
+    fn advance_to(&self, _t: Instant) {}
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+pub struct RealClock;
+
+impl Clock for RealClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn unix_millis(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0)
+    }
+}
+
+pub fn real() -> SharedClock {
+    Arc::new(RealClock)
+}
+
+/// Where every virtual clock starts on the Unix time line, so virtual
+/// runs (and anything seeded from the clock) repeat exactly.
+#[cfg(test)]
+pub const VIRTUAL_EPOCH_MS: u64 = 1_700_000_000_000;
+
+/// A clock that stands still until `advance_to` moves it. Only the
+/// test harness drives one.
+#[cfg(test)]
+pub struct VirtualClock {
+    origin: Instant,
+    origin_ms: u64,
+    elapsed: Mutex<Duration>,
+}
+
+#[cfg(test)]
+impl Default for VirtualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+impl VirtualClock {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            origin_ms: VIRTUAL_EPOCH_MS,
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed.lock().map(|e| *e).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+impl Clock for VirtualClock {
+    fn now(&self) -> Instant {
+        self.origin + self.elapsed()
+    }
+
+    fn unix_millis(&self) -> u64 {
+        self.origin_ms + self.elapsed().as_millis() as u64
+    }
+
+    fn is_virtual(&self) -> bool {
+        true
+    }
+
+    /// Never moves backwards.
+    fn advance_to(&self, t: Instant) {
+        let target = t.saturating_duration_since(self.origin);
+        if let Ok(mut e) = self.elapsed.lock() {
+            *e = (*e).max(target);
+        }
+    }
+}
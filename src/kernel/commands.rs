@@ -0,0 +1,1118 @@
+//! The kernel's built-in shell commands.
+
+use std::fs;
+use std::path::Path;
+
+use crate::cortex::brain::RuleBrain;
+use crate::cortex::policy::Cmp;
+use crate::cortex::{whatif, PolicySet};
+use crate::homeostasis::{Actuator, Direction, Pid};
+use crate::json::escape;
+use crate::kernel::registry::COMMAND_DAEMON;
+use crate::kernel::shell::{
+    ArgKind, ArgSpec, BuiltinCommand, CommandRegistry, OutputMode, ShellContext, ShellError,
+};
+use crate::kernel::{
+    classify_health, compute_overall_health, is_known_metric, lock, metric_value, parse_organ_kind,
+    pubsub, pulse_journal, Bus, DaemonControl, DaemonSpec, FactoryContext, KernelState, PulseFilter,
+    PulseKind, ReplayOptions, Since, TelemetryMode,
+};
+use crate::memory::MemoryScope;
+use crate::organism::{self, format_topology_brief, SystemTopology};
+use crate::reflex::Reflex;
+use crate::telemetry::SimLevel;
+
+const SIM_USAGE: &str = "sim status | sim level <off|low|high>";
+const MEM_USAGE: &str = "mem [ls] | mem get <key> | mem set <key> <value>";
+const CORTEX_USAGE: &str = "cortex history [n] [policy] | cortex brain [name] | cortex brain rules <name|path> | \
+                            cortex policy <name|path> | cortex whatif <policy> [policy-b] [--timeline <path>]";
+const SETPOINT_USAGE: &str = "setpoint add <metric> <below|above> <target> <throttle|sim_level|caps:<kind>> [kp ki kd] \
+                              | setpoint rm <id> | setpoint gains <id> <kp> <ki> <kd> | setpoint on|off <id>";
+const PULSES_USAGE: &str = "pulses [kind|all] [source] [--since <30s|5m|1h|id>] [-n <count>]";
+const REPLAY_USAGE: &str =
+    "replay [dir] [--fast | --speed <x>] [--since <30s|5m|id>] [kind=..] [source=..] [type=..]";
+const SUBSCRIBE_USAGE: &str = "subscribe [kind=a,b] [source=a,b] [type=a,b] [cap=N]";
+const REFLEX_USAGE: &str = "reflex add <metric> <op> <value> => <action> [refractory=<secs>] | reflex rm <id>";
+const ASSERT_USAGE: &str = "assert <metric> <op> <value> | assert health <organ> <op> <value>";
+const DAEMON_USAGE: &str = "daemon start|stop|restart|rm <name> | daemon kinds | \
+                            daemon add <kind> [every=..] [jitter=..] [missed=..] [timeout=..] [key=value ..]";
+
+const ORGAN_AMOUNT: &[ArgSpec] = &[
+    ArgSpec::required("organ", ArgKind::Organ),
+    ArgSpec::required("amount", ArgKind::Number),
+];
+
+const BUILTIN: &[BuiltinCommand] = &[
+    BuiltinCommand {
+        name: "help",
+        aliases: &[],
+        usage: "help [command]",
+        help: "list commands, or show one command's usage and arguments",
+        args: &[ArgSpec::optional("command", ArgKind::Command)],
+        run: help,
+    },
+    BuiltinCommand {
+        name: "status",
+        aliases: &[],
+        usage: "status",
+        help: "topology summary, overall health and awareness",
+        args: &[],
+        run: status,
+    },
+    BuiltinCommand {
+        name: "topology",
+        aliases: &[],
+        usage: "topology",
+        help: "nodes and the organs on them",
+        args: &[],
+        run: topology,
+    },
+    BuiltinCommand {
+        name: "nodes",
+        aliases: &[],
+        usage: "nodes",
+        help: "list nodes",
+        args: &[],
+        run: nodes,
+    },
+    BuiltinCommand {
+        name: "organs",
+        aliases: &[],
+        usage: "organs",
+        help: "list organs with their node and health",
+        args: &[],
+        run: organs,
+    },
+    BuiltinCommand {
+        name: "peripherals",
+        aliases: &[],
+        usage: "peripherals",
+        help: "peripherals by organ",
+        args: &[],
+        run: peripherals,
+    },
+    BuiltinCommand {
+        name: "health",
+        aliases: &[],
+        usage: "health",
+        help: "organ health with ok/warning/critical labels",
+        args: &[],
+        run: health,
+    },
+    BuiltinCommand {
+        name: "awareness",
+        aliases: &[],
+        usage: "awareness",
+        help: "awareness index",
+        args: &[],
+        run: awareness,
+    },
+    BuiltinCommand {
+        name: "metrics",
+        aliases: &[],
+        usage: "metrics",
+        help: "latest telemetry snapshot",
+        args: &[],
+        run: metrics,
+    },
+    BuiltinCommand {
+        name: "mode",
+        aliases: &[],
+        usage: "mode",
+        help: "telemetry source, sim level and workload throttle",
+        args: &[],
+        run: mode,
+    },
+    BuiltinCommand {
+        name: "alerts",
+        aliases: &[],
+        usage: "alerts",
+        help: "organs that are not ok",
+        args: &[],
+        run: alerts,
+    },
+    BuiltinCommand {
+        name: "sim",
+        aliases: &[],
+        usage: SIM_USAGE,
+        help: "simulation status, or set the simulation level",
+        args: &[
+            ArgSpec::required("subcommand", ArgKind::Choice(&["status", "level"])),
+            ArgSpec::optional("level", ArgKind::SimLevel),
+        ],
+        run: sim,
+    },
+    BuiltinCommand {
+        name: "mem",
+        aliases: &[],
+        usage: MEM_USAGE,
+        help: "read and write global memory",
+        args: &[
+            ArgSpec::optional("subcommand", ArgKind::Choice(&["ls", "get", "set"])),
+            ArgSpec::optional("key", ArgKind::MemoryKey),
+            ArgSpec::optional("value", ArgKind::Rest),
+        ],
+        run: mem,
+    },
+    BuiltinCommand {
+        name: "cortex",
+        aliases: &[],
+        usage: CORTEX_USAGE,
+        help: "decision history, brain selection, policy sets and what-if runs",
+        args: &[
+            ArgSpec::required("subcommand", ArgKind::Choice(&["history", "brain", "policy", "whatif"])),
+            ArgSpec::optional("args", ArgKind::Rest),
+        ],
+        run: cortex,
+    },
+    BuiltinCommand {
+        name: "homeostasis",
+        aliases: &["setpoints"],
+        usage: "homeostasis",
+        help: "setpoints and their controllers",
+        args: &[],
+        run: homeostasis,
+    },
+    BuiltinCommand {
+        name: "setpoint",
+        aliases: &[],
+        usage: SETPOINT_USAGE,
+        help: "add, remove, tune or toggle a setpoint",
+        args: &[
+            ArgSpec::required("subcommand", ArgKind::Choice(&["add", "rm", "gains", "on", "off"])),
+            ArgSpec::optional("args", ArgKind::Rest),
+        ],
+        run: setpoint,
+    },
+    BuiltinCommand {
+        name: "caps",
+        aliases: &["capabilities"],
+        usage: "caps",
+        help: "capabilities derived from the topology",
+        args: &[],
+        run: caps,
+    },
+    BuiltinCommand {
+        name: "pulses",
+        aliases: &[],
+        usage: PULSES_USAGE,
+        help: "recent pulses from the history buffer",
+        args: &[ArgSpec::optional("filters", ArgKind::Rest)],
+        run: pulses,
+    },
+    BuiltinCommand {
+        name: "pulse-journal",
+        aliases: &[],
+        usage: "pulse-journal",
+        help: "state of the on-disk pulse journal",
+        args: &[],
+        run: pulse_journal_report,
+    },
+    BuiltinCommand {
+        name: "replay",
+        aliases: &[],
+        usage: REPLAY_USAGE,
+        help: "replay journaled pulses to subscribers",
+        args: &[ArgSpec::optional("options", ArgKind::Rest)],
+        run: replay,
+    },
+    BuiltinCommand {
+        name: "subscribe",
+        aliases: &[],
+        usage: SUBSCRIBE_USAGE,
+        help: "print matching pulses in the shell as they arrive",
+        args: &[ArgSpec::optional("filters", ArgKind::Rest)],
+        run: subscribe,
+    },
+    BuiltinCommand {
+        name: "unsubscribe",
+        aliases: &[],
+        usage: "unsubscribe <id>",
+        help: "remove a subscription",
+        args: &[ArgSpec::required("id", ArgKind::Number)],
+        run: unsubscribe,
+    },
+    BuiltinCommand {
+        name: "subscriptions",
+        aliases: &[],
+        usage: "subscriptions",
+        help: "pub/sub subscribers and their queues",
+        args: &[],
+        run: subscriptions,
+    },
+    BuiltinCommand {
+        name: "reflexes",
+        aliases: &[],
+        usage: "reflexes",
+        help: "reflex arcs and when they last fired",
+        args: &[],
+        run: reflexes,
+    },
+    BuiltinCommand {
+        name: "reflex",
+        aliases: &[],
+        usage: REFLEX_USAGE,
+        help: "add or remove a reflex arc",
+        args: &[
+            ArgSpec::required("subcommand", ArgKind::Choice(&["add", "rm"])),
+            ArgSpec::optional("args", ArgKind::Rest),
+        ],
+        run: reflex,
+    },
+    BuiltinCommand {
+        name: "damage",
+        aliases: &[],
+        usage: "damage <organ> <amount>",
+        help: "lower an organ's health",
+        args: ORGAN_AMOUNT,
+        run: damage,
+    },
+    BuiltinCommand {
+        name: "heal",
+        aliases: &[],
+        usage: "heal <organ> <amount>",
+        help: "raise an organ's health",
+        args: ORGAN_AMOUNT,
+        run: heal,
+    },
+    BuiltinCommand {
+        name: "save",
+        aliases: &[],
+        usage: "save state",
+        help: "write organ health to aion_state.txt",
+        args: &[ArgSpec::required("what", ArgKind::Choice(&["state"]))],
+        run: save_state,
+    },
+    BuiltinCommand {
+        name: "load",
+        aliases: &[],
+        usage: "load state",
+        help: "restore organ health from aion_state.txt",
+        args: &[ArgSpec::required("what", ArgKind::Choice(&["state"]))],
+        run: load_state,
+    },
+    BuiltinCommand {
+        name: "daemons",
+        aliases: &[],
+        usage: "daemons",
+        help: "daemon states, schedules and crash counts",
+        args: &[],
+        run: daemons,
+    },
+    BuiltinCommand {
+        name: "daemon",
+        aliases: &[],
+        usage: DAEMON_USAGE,
+        help: "start, stop, restart, remove or add a daemon",
+        args: &[
+            ArgSpec::required(
+                "subcommand",
+                ArgKind::Choice(&["start", "stop", "restart", "rm", "remove", "kinds", "add"]),
+            ),
+            ArgSpec::optional("args", ArgKind::Rest),
+        ],
+        run: daemon,
+    },
+    BuiltinCommand {
+        name: "snapshot",
+        aliases: &[],
+        usage: "snapshot",
+        help: "kernel state as one JSON object: organs, health, mode, telemetry, memory",
+        args: &[],
+        run: snapshot,
+    },
+    BuiltinCommand {
+        name: "assert",
+        aliases: &[],
+        usage: ASSERT_USAGE,
+        help: "fail unless a metric meets a condition, e.g. assert health cortex < 0.5",
+        args: &[
+            ArgSpec::required("metric", ArgKind::Word),
+            ArgSpec::required("condition", ArgKind::Rest),
+        ],
+        run: assert,
+    },
+    BuiltinCommand {
+        name: "output",
+        aliases: &[],
+        usage: "output [text|json]",
+        help: "show or set how this session shows results",
+        args: &[ArgSpec::optional("mode", ArgKind::Choice(&["text", "json"]))],
+        run: output,
+    },
+    BuiltinCommand {
+        name: "quit",
+        aliases: &[],
+        usage: "quit",
+        help: "shut the kernel down",
+        args: &[],
+        run: quit,
+    },
+];
+
+pub fn register(r: &mut CommandRegistry) {
+    r.register_all(BUILTIN);
+}
+
+fn usage(text: &str) -> ShellError {
+    ShellError::usage(text)
+}
+
+fn help(ctx: &mut ShellContext, argv: &[&str]) -> Result<String, ShellError> {
+    match argv.get(1) {
+        Some(name) => ctx.state.commands.help_for(name),
+        None => Ok(ctx.state.commands.help()),
+    }
+}
+
+/// Run `f` on the locked topology.
+fn with_topology<T>(ctx: &ShellContext, f: impl FnOnce(&mut SystemTopology) -> T) -> Result<T, ShellError> {
+    Ok(f(&mut lock(&ctx.state.topology)))
+}
+
+fn status(ctx: &mut ShellContext, _argv: &[&str]) -> Result<String, ShellError> {
+    let awareness = ctx.bus.awareness_score;
+    with_topology(ctx, |topo| {
+        let brief = format_topology_brief(topo);
+        let overall_health = compute_overall_health(topo);
+        let health_label = classify_health(overall_health);
+        let awareness_label = organism::describe_awareness(awareness);
+        format!(
+            "manual status :: {} :: health {:.2} ({}) :: awareness {:.2} ({})",
+            brief, overall_health, health_label, awareness, awareness_label
+        )
+    })
+}
+
+fn topology(ctx: &mut ShellContext, _argv: &[&str]) -> Result<String, ShellError> {
+    with_topology(ctx, |topo| {
+        let mut details = String::new();
+        details.push_str("Topology detail:\n");
+        for node in &topo.nodes {
+            details.push_str(&format!(" - Node {} [{}]: {}\n", node.id.0, node.label, node.role));
+        }
+        for organ in &topo.organs {
+            details.push_str(&format!(
+                "   - Organ {:?} on Node {} (health {:.2})\n",
+                organ.kind, organ.node.0, organ.health
+            ));
+        }
+        details
+    })
+}
+
+fn nodes(ctx: &mut ShellContext, _argv: &[&str]) -> Result<String, ShellError> {
+    with_topology(ctx, |topo| {
+        let mut details = String::new();
+        details.push_str("Nodes:\n");
+        for node in &topo.nodes {
+            details.push_str(&format!(" - Node {} [{}]: {}\n", node.id.0, node.label, node.role));
+        }
+        details
+    })
+}
+
+fn organs(ctx: &mut ShellContext, _argv: &[&str]) -> Result<String, ShellError> {
+    with_topology(ctx, |topo| {
+        let mut details = String::new();
+        details.push_str("Organs:\n");
+        for organ in &topo.organs {
+            details.push_str(&format!(
+                " - Organ {:?} on Node {} (health {:.2})\n",
+                organ.kind, organ.node.0, organ.health
+            ));
+        }
+        details
+    })
+}
+
+fn peripherals(ctx: &mut ShellContext, _argv: &[&str]) -> Result<String, ShellError> {
+    with_topology(ctx, |topo| {
+        let mut details = String::new();
+        details.push_str("Peripherals by organ:\n");
+        for organ in &topo.organs {
+            if organ.peripherals.is_empty() {
+                continue;
+            }
+            details.push_str(&format!(" - Organ {:?}:\n", organ.kind));
+            for p in &organ.peripherals {
+                details.push_str(&format!("    - {:?}: {}\n", p.kind, p.name));
+            }
+        }
+        if !details.contains("Organ") {
+            details.push_str(" (no peripherals registered)\n");
+        }
+        details
+    })
+}
+
+fn health(ctx: &mut ShellContext, _argv: &[&str]) -> Result<String, ShellError> {
+    with_topology(ctx, |topo| {
+        let mut out = String::new();
+        out.push_str("Organ health:\n");
+        for organ in &topo.organs {
+            let label = classify_health(organ.health);
+            out.push_str(&format!(" - {:?}: {:.2} ({})\n", organ.kind, organ.health, label));
+        }
+        out
+    })
+}
+
+fn awareness(ctx: &mut ShellContext, _argv: &[&str]) -> Result<String, ShellError> {
+    with_topology(ctx, |topo| {
+        let awareness = organism::compute_awareness(topo);
+        let label = organism::describe_awareness(awareness);
+        format!("awareness index: {:.2} :: {}", awareness, label)
+    })
+}
+
+fn alerts(ctx: &mut ShellContext, _argv: &[&str]) -> Result<String, ShellError> {
+    with_topology(ctx, |topo| {
+        let mut out = String::new();
+        out.push_str("Alerts:\n");
+
+        let mut any = false;
+        let mut min_health: f32 = 1.0;
+
+        for organ in &topo.organs {
+            min_health = min_health.min(organ.health);
+            let label = classify_health(organ.health);
+            if label != "ok" {
+                any = true;
+                out.push_str(&format!(" - {:?}: {:.2} [{}]\n", organ.kind, organ.health, label));
+            }
+        }
+
+        if !any {
+            out.push_str(" (no active alerts; all organs healthy)\n");
+        } else {
+            out.push_str(&format!("overall: {}\n", classify_health(min_health)));
+        }
+
+        out
+    })
+}
+
+fn mode(ctx: &mut ShellContext, _argv: &[&str]) -> Result<String, ShellError> {
+    let bus = &ctx.bus;
+    let tele_str = match bus.telemetry_mode {
+        TelemetryMode::Simulated => "simulated",
+        TelemetryMode::Real => "real",
+    };
+    Ok(format!(
+        "mode :: telemetry={} :: sim_level={:?} :: throttle={:.2}",
+        tele_str, bus.sim_level, bus.workload_throttle
+    ))
+}
+
+fn metrics(ctx: &mut ShellContext, _argv: &[&str]) -> Result<String, ShellError> {
+    let guard = lock(&ctx.state.metrics_snapshot);
+    let snap = match *guard {
+        Some(snap) => snap,
+        None => {
+            return Ok("metrics not yet available (status daemon has not produced a snapshot)".to_string())
+        }
+    };
+    let mut out = String::new();
+    out.push_str("Metrics snapshot (from status daemon):\n");
+    out.push_str(" Cortex / CPU+GPU:\n");
+    out.push_str(&format!(
+        "  cpu_load      : {:.2}\n  cpu_temp_c    : {:.1}\n  throttling    : {}\n  gpu_load      : {:.2}\n  gpu_mem_util  : {:.2}\n",
+        snap.cpu.cpu_load,
+        snap.cpu.cpu_temp_c,
+        snap.cpu.throttling_events,
+        snap.cpu.gpu_load,
+        snap.cpu.gpu_mem_util,
+    ));
+    out.push_str(" Memory:\n");
+    out.push_str(&format!(
+        "  ram_used      : {:.2}\n  swap_used     : {:.2}\n  page_faults   : {:.1}\n  disk_latency  : {:.1} ms\n",
+        snap.mem.ram_used_ratio,
+        snap.mem.swap_used_ratio,
+        snap.mem.major_page_faults,
+        snap.mem.disk_latency_ms,
+    ));
+    out.push_str(" IoBridge / IO+Net:\n");
+    out.push_str(&format!(
+        "  net_loss      : {:.3}\n  net_latency   : {:.1} ms\n  io_queue      : {:.2}\n  io_error_rate : {:.3}\n",
+        snap.io.net_packet_loss,
+        snap.io.net_latency_ms,
+        snap.io.io_queue_depth,
+        snap.io.io_error_rate,
+    ));
+    Ok(out)
+}
+
+fn sim(ctx: &mut ShellContext, argv: &[&str]) -> Result<String, ShellError> {
+    match (argv[1], argv.get(2)) {
+        ("status", None) => {
+            let (level, awareness) = (ctx.bus.sim_level, ctx.bus.awareness_score);
+            with_topology(ctx, |topo| {
+                format!(
+                    "simulation status: level={:?} :: min health {:.2} :: awareness {:.2}",
+                    level,
+                    compute_overall_health(topo),
+                    awareness
+                )
+            })
+        }
+        ("level", Some(name)) => {
+            let level = SimLevel::from_name(name).ok_or_else(|| usage(SIM_USAGE))?;
+            ctx.bus.sim_level = level;
+            Ok(format!("simulation level set to {}", name.to_lowercase()))
+        }
+        _ => Err(usage(SIM_USAGE)),
+    }
+}
+
+fn mem(ctx: &mut ShellContext, argv: &[&str]) -> Result<String, ShellError> {
+    let memory = &mut ctx.bus.memory;
+    match argv {
+        [_] | [_, "ls"] => Ok(memory.dump()),
+        [_, "get", key] => match memory.get(MemoryScope::Global, key) {
+            Some(v) => Ok(format!("mem[{}] = {}", key, v)),
+            None => Err(format!("mem: key '{}' not found", key).into()),
+        },
+        [_, "set", key, value @ ..] if !value.is_empty() => {
+            memory.set_text(MemoryScope::Global, *key, value.join(" "));
+            Ok(format!("mem[{}] updated", key))
+        }
+        _ => Err(usage(MEM_USAGE)),
+    }
+}
+
+fn cortex(ctx: &mut ShellContext, argv: &[&str]) -> Result<String, ShellError> {
+    let state = ctx.state;
+    match argv[1] {
+        // cortex whatif <policy> [policy-b] [--timeline <path>]
+        "whatif" => {
+            let mut timeline = "aion_cortex_journal.log";
+            let mut policies = Vec::new();
+            let mut args = argv[2..].iter();
+            while let Some(arg) = args.next() {
+                match (*arg, args.as_slice().first()) {
+                    ("--timeline", Some(path)) => {
+                        timeline = path;
+                        args.next();
+                    }
+                    _ => policies.push(*arg),
+                }
+            }
+            match policies.as_slice() {
+                [a] => Ok(whatif::run_from_files(Path::new(timeline), a, None)),
+                [a, b] => Ok(whatif::run_from_files(Path::new(timeline), a, Some(b))),
+                _ => Err(usage("cortex whatif <policy> [policy-b] [--timeline <path>]")),
+            }
+        }
+
+        // cortex brain [name] | cortex brain rules <name|path>
+        "brain" => {
+            let mut brains = lock(&state.brains);
+            match argv.len() {
+                2 => Ok(brains.describe()),
+                3 => {
+                    brains.select(argv[2])?;
+                    Ok(format!("cortex brain set to {}", argv[2]))
+                }
+                4 if argv[2] == "rules" => match PolicySet::load(argv[3]) {
+                    Ok(set) => {
+                        let name = set.name.clone();
+                        brains.install(Box::new(RuleBrain::new(set)));
+                        let _ = brains.select("rules");
+                        Ok(format!("cortex brain set to rules (policy set '{}')", name))
+                    }
+                    Err(e) => Err(format!("invalid policy set: {}", e).into()),
+                },
+                _ => Err(usage("cortex brain [name] | cortex brain rules <name|path>")),
+            }
+        }
+
+        // cortex policy <name|path> – parse and show a policy set
+        "policy" if argv.len() == 3 => match PolicySet::load(argv[2]) {
+            Ok(set) => Ok(set.describe()),
+            Err(e) => Err(format!("invalid policy set: {}", e).into()),
+        },
+
+        // cortex history [n] [policy-filter]
+        "history" => {
+            let mut limit = 10;
+            let mut policy = None;
+            for arg in &argv[2..] {
+                match arg.parse::<usize>() {
+                    Ok(n) => limit = n,
+                    Err(_) => policy = Some(*arg),
+                }
+            }
+            Ok(lock(&state.journal).history_report(limit, policy))
+        }
+        _ => Err(usage(CORTEX_USAGE)),
+    }
+}
+
+fn homeostasis(ctx: &mut ShellContext, _argv: &[&str]) -> Result<String, ShellError> {
+    Ok(lock(&ctx.state.homeostasis).report())
+}
+
+fn setpoint(ctx: &mut ShellContext, argv: &[&str]) -> Result<String, ShellError> {
+    let mut h = lock(&ctx.state.homeostasis);
+
+    match argv[1] {
+        "add" if argv.len() == 6 || argv.len() == 9 => {
+            let metric = argv[2];
+            if !is_known_metric(metric) {
+                return Err(format!("unknown metric '{}'", metric).into());
+            }
+            let direction = match argv[3] {
+                "below" | "under" => Direction::Below,
+                "above" | "over" => Direction::Above,
+                other => return Err(format!("direction must be below|above, got '{}'", other).into()),
+            };
+            let target: f32 = argv[4]
+                .parse()
+                .map_err(|_| format!("invalid target: {}", argv[4]))?;
+            let actuator =
+                Actuator::parse(argv[5]).ok_or_else(|| format!("unknown actuator '{}'", argv[5]))?;
+            let gains = if argv.len() == 9 {
+                match (argv[6].parse(), argv[7].parse(), argv[8].parse()) {
+                    (Ok(p), Ok(i), Ok(d)) => Some((p, i, d)),
+                    _ => return Err("invalid gains".into()),
+                }
+            } else {
+                None
+            };
+            let id = h.add(metric, direction, target, actuator, gains);
+            Ok(format!("setpoint #{} added", id))
+        }
+        "rm" if argv.len() == 3 => match argv[2].parse() {
+            Ok(id) if h.remove(id) => Ok(format!("setpoint #{} removed", id)),
+            _ => Err(format!("no setpoint '{}'", argv[2]).into()),
+        },
+        "gains" if argv.len() == 6 => {
+            let id: u32 = argv[2].parse().unwrap_or(0);
+            let gains = (argv[3].parse(), argv[4].parse(), argv[5].parse());
+            match (h.get_mut(id), gains) {
+                (Some(sp), (Ok(p), Ok(i), Ok(d))) => {
+                    sp.pid = Pid::new(p, i, d);
+                    Ok(format!("setpoint #{} gains set to kp {} ki {} kd {}", id, p, i, d))
+                }
+                (None, _) => Err(format!("no setpoint '{}'", argv[2]).into()),
+                _ => Err("invalid gains".into()),
+            }
+        }
+        state @ ("on" | "off") if argv.len() == 3 => {
+            let id: u32 = argv[2].parse().unwrap_or(0);
+            match h.get_mut(id) {
+                Some(sp) => {
+                    sp.enabled = state == "on";
+                    sp.pid.reset();
+                    Ok(format!("setpoint #{} {}", id, if sp.enabled { "enabled" } else { "disabled" }))
+                }
+                None => Err(format!("no setpoint '{}'", argv[2]).into()),
+            }
+        }
+        _ => Err(usage(SETPOINT_USAGE)),
+    }
+}
+
+fn caps(ctx: &mut ShellContext, _argv: &[&str]) -> Result<String, ShellError> {
+    Ok(lock(&ctx.state.capabilities).describe_all())
+}
+
+fn pulses(ctx: &mut ShellContext, argv: &[&str]) -> Result<String, ShellError> {
+    let bus = &ctx.bus;
+    let now_ms = bus.clock().unix_millis();
+    let mut filter = PulseFilter::default();
+    let mut since = Since::Any;
+    let mut limit = 20;
+    let mut positional = 0;
+
+    let mut args = argv[1..].iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--since" => match args.next().and_then(|v| Since::parse(v, now_ms)) {
+                Some(s) => since = s,
+                None => return Err(usage(PULSES_USAGE)),
+            },
+            "-n" => match args.next().and_then(|v| v.parse().ok()) {
+                Some(n) => limit = n,
+                None => return Err(usage(PULSES_USAGE)),
+            },
+            _ if positional == 0 && (*arg == "all" || *arg == "*") => positional += 1,
+            _ if positional == 0 && PulseKind::from_name(arg).is_some() => {
+                filter.kinds.extend(PulseKind::from_name(arg));
+                positional += 1;
+            }
+            _ if positional <= 1 => {
+                filter.sources.push(arg.to_string());
+                positional = 2;
+            }
+            _ => return Err(usage(PULSES_USAGE)),
+        }
+    }
+
+    let history = bus.history();
+    let history = lock(&history);
+    let pulses = history.query(&filter, since, limit);
+    let mut out = format!(
+        "Pulses ({} shown, {} of {} buffered) :: {}\n",
+        pulses.len(),
+        history.len(),
+        history.capacity(),
+        filter.describe()
+    );
+    for p in &pulses {
+        out.push_str(&format!(
+            " {:>7.1}s ago  {}\n",
+            now_ms.saturating_sub(p.timestamp_ms) as f64 / 1000.0,
+            p.log_line()
+        ));
+    }
+    Ok(out)
+}
+
+fn pulse_journal_report(ctx: &mut ShellContext, _argv: &[&str]) -> Result<String, ShellError> {
+    match ctx.bus.pulse_journal() {
+        Some(j) => Ok(j.report()),
+        None => Ok("pulse journal is off (AION_PULSE_JOURNAL=off)".to_string()),
+    }
+}
+
+fn replay(ctx: &mut ShellContext, argv: &[&str]) -> Result<String, ShellError> {
+    let bus: &Bus = ctx.bus;
+    let mut dir = bus
+        .pulse_journal()
+        .map(|j| j.dir().to_path_buf())
+        .unwrap_or_else(|| pulse_journal::DEFAULT_DIR.into());
+    let mut opts = ReplayOptions {
+        speed: Some(1.0),
+        filter: PulseFilter::default(),
+        since: Since::Any,
+    };
+    let mut terms = Vec::new();
+
+    let mut args = argv[1..].iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--fast" => opts.speed = None,
+            "--speed" => match args.next().and_then(|v| v.parse::<f64>().ok()) {
+                Some(x) if x > 0.0 => opts.speed = Some(x),
+                _ => return Err(usage(REPLAY_USAGE)),
+            },
+            "--since" => match args.next().and_then(|v| Since::parse(v, bus.clock().unix_millis())) {
+                Some(s) => opts.since = s,
+                None => return Err(usage(REPLAY_USAGE)),
+            },
+            a if a.contains('=') => terms.push(a),
+            a => dir = a.into(),
+        }
+    }
+    opts.filter = PulseFilter::parse(terms).map_err(|e| format!("{}\n{}", e, usage(REPLAY_USAGE)))?;
+
+    let pace = match opts.speed {
+        Some(x) => format!("{}x original pace", x),
+        None => "as fast as possible".to_string(),
+    };
+    let filter = opts.filter.describe();
+    let subscribers = lock(&bus.pubsub()).len();
+    pulse_journal::replay(&dir, opts, bus.handle())?;
+    Ok(format!(
+        "replaying {} ({}) to {} subscriber(s), {}",
+        dir.display(),
+        filter,
+        subscribers,
+        pace
+    ))
+}
+
+fn subscribe(ctx: &mut ShellContext, argv: &[&str]) -> Result<String, ShellError> {
+    let mut capacity = pubsub::DEFAULT_CAPACITY;
+    let mut terms = Vec::new();
+    for term in &argv[1..] {
+        match term.strip_prefix("cap=") {
+            Some(n) => {
+                capacity = pubsub::parse_capacity(n)
+                    .map_err(|e| format!("{}\n{}", usage(SUBSCRIBE_USAGE), e))?
+            }
+            None => terms.push(*term),
+        }
+    }
+    let filter = PulseFilter::parse(terms).map_err(|e| format!("{}\n{}", e, usage(SUBSCRIBE_USAGE)))?;
+    let described = filter.describe();
+    let pubsub = ctx.bus.pubsub();
+    let mut pubsub = lock(&pubsub);
+    let (id, rx) = pubsub.subscribe("shell", filter, capacity);
+    ctx.watches.push((id, rx));
+    Ok(format!("subscription #{} :: {}", id, described))
+}
+
+fn unsubscribe(ctx: &mut ShellContext, argv: &[&str]) -> Result<String, ShellError> {
+    let id: u64 = argv[1].parse().unwrap_or(0);
+    if lock(&ctx.bus.pubsub()).unsubscribe(id) {
+        Ok(format!("subscription #{} removed", id))
+    } else {
+        Err(format!("no subscription '{}'", argv[1]).into())
+    }
+}
+
+fn subscriptions(ctx: &mut ShellContext, _argv: &[&str]) -> Result<String, ShellError> {
+    Ok(lock(&ctx.bus.pubsub()).report())
+}
+
+fn reflexes(ctx: &mut ShellContext, _argv: &[&str]) -> Result<String, ShellError> {
+    Ok(lock(&ctx.state.reflexes).report(ctx.bus.clock().now()))
+}
+
+fn reflex(ctx: &mut ShellContext, argv: &[&str]) -> Result<String, ShellError> {
+    let mut arcs = lock(&ctx.state.reflexes);
+
+    match argv[1] {
+        "add" => {
+            // `;` chains commands, so the refractory period may also be
+            // given as a plain `refractory=N` word.
+            let text = argv[2..]
+                .iter()
+                .map(|w| match w.starts_with("refractory=") {
+                    true => format!("; {}", w),
+                    false => w.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            match Reflex::parse(&text) {
+                Ok(r) if !is_known_metric(&r.metric) => Err(format!("unknown metric '{}'", r.metric).into()),
+                Ok(r) => Ok(format!("reflex #{} added", arcs.add(r))),
+                Err(e) => Err(format!("{}\n{}", e, usage(REFLEX_USAGE)).into()),
+            }
+        }
+        "rm" if argv.len() == 3 => match argv[2].parse() {
+            Ok(id) if arcs.remove(id) => Ok(format!("reflex #{} removed", id)),
+            _ => Err(format!("no reflex '{}'", argv[2]).into()),
+        },
+        _ => Err(usage(REFLEX_USAGE)),
+    }
+}
+
+fn damage(ctx: &mut ShellContext, argv: &[&str]) -> Result<String, ShellError> {
+    adjust_health(ctx, argv, -1.0, "damaged")
+}
+
+fn heal(ctx: &mut ShellContext, argv: &[&str]) -> Result<String, ShellError> {
+    adjust_health(ctx, argv, 1.0, "healed")
+}
+
+/// `damage` and `heal`: move one organ's health by `sign * amount`.
+fn adjust_health(ctx: &mut ShellContext, argv: &[&str], sign: f32, verb: &str) -> Result<String, ShellError> {
+    // Both were checked against the schema.
+    let kind = parse_organ_kind(argv[1]).ok_or_else(|| format!("unknown organ '{}'", argv[1]))?;
+    let amount: f32 = argv[2]
+        .parse()
+        .map_err(|_| format!("invalid amount: {}", argv[2]))?;
+
+    let mut topo = lock(&ctx.state.topology);
+    let organ = topo
+        .organs
+        .iter_mut()
+        .find(|o| o.kind == kind)
+        .ok_or_else(|| format!("organ {:?} not found in topology", kind))?;
+    organ.health = (organ.health + sign * amount).clamp(0.0, 1.0);
+    let h = organ.health;
+
+    let awareness = organism::compute_awareness(&topo);
+    ctx.bus.awareness_score = awareness;
+    let label = organism::describe_awareness(awareness);
+    Ok(format!(
+        "{} {:?} by {:.2}, new health {:.2} (awareness {:.2} {})",
+        verb, kind, amount, h, awareness, label
+    ))
+}
+
+fn save_state(ctx: &mut ShellContext, _argv: &[&str]) -> Result<String, ShellError> {
+    let topo = lock(&ctx.state.topology);
+    let mut lines = Vec::new();
+    for organ in &topo.organs {
+        lines.push(format!("{:?} {:.5}", organ.kind, organ.health));
+    }
+    match fs::write("aion_state.txt", lines.join("\n")) {
+        Ok(_) => Ok("state saved to aion_state.txt".to_string()),
+        Err(e) => Err(format!("failed to save state: {}", e).into()),
+    }
+}
+
+fn load_state(ctx: &mut ShellContext, _argv: &[&str]) -> Result<String, ShellError> {
+    let content =
+        fs::read_to_string("aion_state.txt").map_err(|e| format!("failed to load state: {}", e))?;
+
+    let mut topo = lock(&ctx.state.topology);
+    for line in content.lines() {
+        let mut parts = line.split_whitespace();
+        let (kind, h) = match (parts.next(), parts.next()) {
+            (Some(k), Some(h)) => (k, h),
+            _ => continue,
+        };
+        let kind = match parse_organ_kind(kind) {
+            Some(k) => k,
+            None => continue,
+        };
+        let h: f32 = match h.parse() {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        if let Some(organ) = topo.organs.iter_mut().find(|o| o.kind == kind) {
+            organ.health = h.clamp(0.0, 1.0);
+        }
+    }
+
+    let awareness = organism::compute_awareness(&topo);
+    ctx.bus.awareness_score = awareness;
+    let label = organism::describe_awareness(awareness);
+    Ok(format!(
+        "state loaded from aion_state.txt (awareness {:.2} {})",
+        awareness, label
+    ))
+}
+
+fn daemons(ctx: &mut ShellContext, _argv: &[&str]) -> Result<String, ShellError> {
+    Ok(lock(&ctx.state.daemons).report())
+}
+
+fn daemon(ctx: &mut ShellContext, argv: &[&str]) -> Result<String, ShellError> {
+    match argv[1] {
+        "kinds" if argv.len() == 2 => return Ok(ctx.state.registry.describe()),
+        "add" if argv.len() > 2 => return add_daemon(ctx, &argv[2..].join(" ")),
+        _ => {}
+    }
+    let (control, name) = match argv {
+        [_, verb, name] => match DaemonControl::from_name(verb) {
+            Some(c) => (c, *name),
+            None => return Err(usage(DAEMON_USAGE)),
+        },
+        _ => return Err(usage(DAEMON_USAGE)),
+    };
+    if matches!(control, DaemonControl::Stop | DaemonControl::Remove) && name == COMMAND_DAEMON {
+        return Err("refusing to stop the command daemon: nothing could start it again".into());
+    }
+    let mut table = lock(&ctx.state.daemons);
+    table.request(name, control)?;
+    Ok(format!("daemon {} :: {} requested", name, argv[1]))
+}
+
+fn add_daemon(ctx: &mut ShellContext, line: &str) -> Result<String, ShellError> {
+    let state = ctx.state;
+    let spec = DaemonSpec::parse(line)?;
+    let kind = state
+        .registry
+        .kind(&spec.kind)
+        .ok_or_else(|| format!("unknown daemon kind '{}' (see `daemon kinds`)", spec.kind))?;
+    let mut table = lock(&state.daemons);
+    if table.contains(kind) {
+        return Err(format!("daemon '{}' is already running", kind).into());
+    }
+    let waker = table
+        .waker_for(kind)
+        .ok_or_else(|| "daemon table is not attached to a scheduler".to_string())?;
+    let factory = FactoryContext { state, waker };
+    let daemon = state.registry.build(&spec, &factory)?;
+    table.add(daemon, spec.schedule)?;
+    Ok(format!("daemon {} added :: {}", kind, spec.schedule.describe()))
+}
+
+fn snapshot(ctx: &mut ShellContext, _argv: &[&str]) -> Result<String, ShellError> {
+    let telemetry = match *lock(&ctx.state.metrics_snapshot)
+    {
+        Some(snap) => snap.to_json(),
+        None => "null".to_string(),
+    };
+    let memory: Vec<String> = ctx
+        .bus
+        .memory
+        .entries(MemoryScope::Global)
+        .iter()
+        .map(|(k, v)| format!(r#""{}":{}"#, escape(k), v.to_json()))
+        .collect();
+    let bus = &ctx.bus;
+    let mode = format!(
+        r#"{{"telemetry":"{}","sim_level":"{:?}","throttle":{:.3}}}"#,
+        match bus.telemetry_mode {
+            TelemetryMode::Simulated => "simulated",
+            TelemetryMode::Real => "real",
+        },
+        bus.sim_level,
+        bus.workload_throttle
+    );
+    let timestamp_ms = bus.clock().unix_millis();
+
+    with_topology(ctx, |topo| {
+        let health = compute_overall_health(topo);
+        let awareness = organism::compute_awareness(topo);
+        let organs: Vec<String> = topo
+            .organs
+            .iter()
+            .map(|o| format!(r#"{{"kind":"{:?}","node":{},"health":{:.5}}}"#, o.kind, o.node.0, o.health))
+            .collect();
+        format!(
+            concat!(
+                r#"{{"timestamp_ms":{},"health":{{"score":{:.3},"label":"{}"}},"#,
+                r#""awareness":{{"score":{:.3},"label":"{}"}},"mode":{},"organs":[{}],"#,
+                r#""telemetry":{},"memory":{{{}}}}}"#
+            ),
+            timestamp_ms,
+            health,
+            classify_health(health),
+            awareness,
+            organism::describe_awareness(awareness),
+            mode,
+            organs.join(","),
+            telemetry,
+            memory.join(",")
+        )
+    })
+}
+
+/// `<metric> <op> <value>`, where the metric may also be written
+/// `health <organ>`. Returns the metric name, operator and threshold.
+pub fn parse_condition(words: &[&str]) -> Result<(String, Cmp, f32), ShellError> {
+    let (metric, op, value) = match words {
+        ["health", organ, op, value] => (format!("health.{}", organ.to_lowercase()), *op, *value),
+        [metric, op, value] => (metric.to_string(), *op, *value),
+        _ => return Err(usage(ASSERT_USAGE)),
+    };
+    if !is_known_metric(&metric) {
+        return Err(format!("unknown metric '{}'", metric).into());
+    }
+    let cmp = Cmp::parse(op).ok_or_else(|| format!("bad operator '{}' (use < <= > >= == !=)", op))?;
+    let threshold = value.parse().map_err(|_| format!("bad number '{}'", value))?;
+    Ok((metric, cmp, threshold))
+}
+
+/// Check `<metric> <op> <value>` against the current readings.
+pub fn check_condition(state: &KernelState, words: &[&str]) -> Result<String, ShellError> {
+    let (metric, cmp, threshold) = parse_condition(words)?;
+    let condition = words.join(" ");
+    let snapshot = *lock(&state.metrics_snapshot);
+    let value = {
+        let topo = lock(&state.topology);
+        metric_value(&metric, snapshot.as_ref(), &topo)
+    }
+    .ok_or_else(|| format!("assertion failed: {} (no {} reading yet)", condition, metric))?;
+
+    if cmp.apply(value, threshold) {
+        Ok(format!("ok: {} ({} = {:.3})", condition, metric, value))
+    } else {
+        Err(format!("assertion failed: {} ({} = {:.3})", condition, metric, value).into())
+    }
+}
+
+fn assert(ctx: &mut ShellContext, argv: &[&str]) -> Result<String, ShellError> {
+    check_condition(ctx.state, &argv[1..])
+}
+
+fn output(ctx: &mut ShellContext, argv: &[&str]) -> Result<String, ShellError> {
+    if let Some(name) = argv.get(1) {
+        ctx.output = OutputMode::from_name(name).ok_or_else(|| usage("output [text|json]"))?;
+    }
+    Ok(format!("output: {}", ctx.output.name()))
+}
+
+fn quit(ctx: &mut ShellContext, _argv: &[&str]) -> Result<String, ShellError> {
+    ctx.quit = true;
+    Ok("shutting down kernel (process::exit(0))".to_string())
+}
@@ -0,0 +1,496 @@
+//! The control socket: shell sessions for clients without a terminal.
+//!
+//! AION_CONTROL says where to listen: a Unix socket path (by default
+//! `aion_control.sock`), `tcp:127.0.0.1:<port>` for a localhost port, or
+//! `off`. A client's first line must be `auth <token>`. The token is
+//! AION_CONTROL_TOKEN, or else a random one the kernel writes to
+//! AION_CONTROL_TOKEN_FILE (`aion_control.token`, mode 0600) at startup.
+//! The same token guards `POST /shell` on the HTTP server. Lines over
+//! 64 KiB end the connection, and at most 16 clients are served at once.
+//!
+//! After that every line runs through the shell, in a session of the
+//! client's own: `output json` there changes only that client. Text
+//! responses start with `ok <n>` or `error <code> <n>` followed by the n
+//! output lines; in JSON mode each response is one line. Pulses from the
+//! session's subscriptions arrive between responses, as `[SUB#id] ...`
+//! lines or `{"subscription":id,"pulse":{...}}`.
+//!
+//! ```text
+//! $ socat - UNIX-CONNECT:aion_control.sock
+//! auth 3f9c...
+//! ok 1
+//! session 1
+//! organs | grep Cortex
+//! ok 1
+//!  - Organ Cortex on Node 1 (health 0.98)
+//! ```
+
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::json::JsonValue;
+use crate::kernel::shell::{OutputMode, ShellResult};
+use crate::kernel::ShellHandle;
+
+pub const DEFAULT_SOCKET: &str = "aion_control.sock";
+pub const DEFAULT_TOKEN_FILE: &str = "aion_control.token";
+
+/// Wrong tokens a client may send before it is disconnected.
+const MAX_AUTH_FAILURES: u32 = 3;
+/// How long a client waits for a line's result.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(60);
+/// Longest line a client may send; longer ones end the connection.
+const MAX_LINE_BYTES: u64 = 64 * 1024;
+/// Clients served at once; more are turned away.
+const MAX_CLIENTS: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlAddr {
+    Unix(PathBuf),
+    Tcp(SocketAddr),
+}
+
+impl ControlAddr {
+    /// `unix:<path>`, `tcp:<host:port>` (loopback only) or a bare path.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.split_once(':') {
+            Some(("tcp", addr)) => {
+                let addr: SocketAddr = addr.parse().map_err(|_| {
+                    format!("invalid control address '{}' (try tcp:127.0.0.1:7070)", addr)
+                })?;
+                if !addr.ip().is_loopback() {
+                    return Err(format!("control port must be on localhost, not {}", addr.ip()));
+                }
+                Ok(ControlAddr::Tcp(addr))
+            }
+            Some(("unix", path)) => Ok(ControlAddr::Unix(PathBuf::from(path))),
+            _ => Ok(ControlAddr::Unix(PathBuf::from(s))),
+        }
+    }
+}
+
+impl fmt::Display for ControlAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+            ControlAddr::Tcp(addr) => write!(f, "tcp:{}", addr),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ControlConfig {
+    pub addr: ControlAddr,
+    /// AION_CONTROL_TOKEN, if set.
+    pub token: Option<String>,
+    pub token_file: PathBuf,
+}
+
+impl ControlConfig {
+    /// From AION_CONTROL, AION_CONTROL_TOKEN and AION_CONTROL_TOKEN_FILE.
+    /// `Ok(None)` if the socket is switched off.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let env = |key: &str| std::env::var(key).ok().filter(|v| !v.is_empty());
+        let addr = match env("AION_CONTROL").as_deref() {
+            Some("off") => return Ok(None),
+            Some(addr) => ControlAddr::parse(addr)?,
+            None => ControlAddr::Unix(PathBuf::from(DEFAULT_SOCKET)),
+        };
+        Ok(Some(Self {
+            addr,
+            token: env("AION_CONTROL_TOKEN"),
+            token_file: env("AION_CONTROL_TOKEN_FILE")
+                .map_or_else(|| PathBuf::from(DEFAULT_TOKEN_FILE), PathBuf::from),
+        }))
+    }
+
+    /// The token a client should send: AION_CONTROL_TOKEN, or the one
+    /// the kernel wrote to the token file.
+    pub fn client_token(&self) -> Result<String, String> {
+        if let Some(token) = &self.token {
+            return Ok(token.clone());
+        }
+        fs::read_to_string(&self.token_file)
+            .map(|t| t.trim().to_string())
+            .map_err(|e| format!("failed to read {}: {}", self.token_file.display(), e))
+    }
+
+    /// The configured token, or a fresh one written to the token file
+    /// where only this user can read it.
+    fn server_token(&self) -> Result<String, String> {
+        if let Some(token) = &self.token {
+            return Ok(token.clone());
+        }
+        let token = random_token()?;
+        let mut options = OpenOptions::new();
+        options.create(true).write(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options
+            .open(&self.token_file)
+            .and_then(|mut f| {
+                // `mode` only applies to a new file; tighten an old one
+                // before the token goes in.
+                #[cfg(unix)]
+                f.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+                f.write_all(format!("{}\n", token).as_bytes())
+            })
+            .map_err(|e| format!("failed to write {}: {}", self.token_file.display(), e))?;
+        Ok(token)
+    }
+}
+
+/// 128 random bits as hex, from the system's random source.
+#[cfg(unix)]
+fn random_token() -> Result<String, String> {
+    let mut bytes = [0u8; 16];
+    fs::File::open("/dev/urandom")
+        .and_then(|mut f| f.read_exact(&mut bytes))
+        .map_err(|e| format!("failed to read /dev/urandom: {}", e))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Without a system random source, set AION_CONTROL_TOKEN instead.
+#[cfg(not(unix))]
+fn random_token() -> Result<String, String> {
+    Err("no random source for a token; set AION_CONTROL_TOKEN".to_string())
+}
+
+/// Compare without stopping at the first difference.
+pub fn same_token(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+type Writer = Arc<Mutex<Box<dyn Write + Send>>>;
+
+/// Connect to a control address, as a reader and a writer.
+fn connect(addr: &ControlAddr) -> std::io::Result<(Box<dyn Read + Send>, Box<dyn Write + Send>)> {
+    match addr {
+        #[cfg(unix)]
+        ControlAddr::Unix(path) => {
+            let stream = std::os::unix::net::UnixStream::connect(path)?;
+            Ok((Box::new(stream.try_clone()?), Box::new(stream)))
+        }
+        #[cfg(not(unix))]
+        ControlAddr::Unix(_) => Err(std::io::Error::other("Unix sockets are not available")),
+        ControlAddr::Tcp(a) => {
+            let stream = TcpStream::connect(a)?;
+            Ok((Box::new(stream.try_clone()?), Box::new(stream)))
+        }
+    }
+}
+
+/// The other end of the socket: one authenticated session in JSON mode.
+pub struct ControlClient {
+    reader: BufReader<Box<dyn Read + Send>>,
+    writer: Box<dyn Write + Send>,
+}
+
+impl ControlClient {
+    pub fn connect(config: &ControlConfig) -> Result<Self, String> {
+        let token = config.client_token()?;
+        let (reader, writer) =
+            connect(&config.addr).map_err(|e| format!("no kernel at {}: {}", config.addr, e))?;
+        let mut client = Self {
+            reader: BufReader::new(reader),
+            writer,
+        };
+        client.send(&format!("auth {}", token))?;
+        match client.next_line()?.as_deref() {
+            Some("ok 1") => {
+                client.next_line()?;
+            }
+            _ => return Err(format!("{} refused the token", config.addr)),
+        }
+        client.call("output json")?;
+        Ok(client)
+    }
+
+    fn send(&mut self, line: &str) -> Result<(), String> {
+        self.writer
+            .write_all(format!("{}\n", line).as_bytes())
+            .and_then(|_| self.writer.flush())
+            .map_err(|e| format!("lost the kernel: {}", e))
+    }
+
+    /// The next line from the kernel; `None` once it hangs up.
+    pub fn next_line(&mut self) -> Result<Option<String>, String> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(line.trim_end().to_string())),
+            Err(e) => Err(format!("lost the kernel: {}", e)),
+        }
+    }
+
+    /// Run a line and return its result object. Subscription pulses
+    /// that arrive first are skipped.
+    pub fn call(&mut self, line: &str) -> Result<JsonValue, String> {
+        self.send(line)?;
+        loop {
+            let reply = self.next_line()?.ok_or("the kernel closed the connection")?;
+            let Ok(value) = JsonValue::parse(&reply) else {
+                continue;
+            };
+            if value.get("subscription").is_none() {
+                return Ok(value);
+            }
+        }
+    }
+}
+
+enum Listener {
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixListener),
+    Tcp(TcpListener),
+}
+
+impl Listener {
+    fn bind(addr: &ControlAddr) -> Result<Self, String> {
+        match addr {
+            #[cfg(unix)]
+            ControlAddr::Unix(path) => {
+                use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+                use std::os::unix::net::{UnixListener, UnixStream};
+
+                if let Ok(meta) = fs::symlink_metadata(path) {
+                    if !meta.file_type().is_socket() {
+                        return Err(format!("{} exists and is not a socket", path.display()));
+                    }
+                    if UnixStream::connect(path).is_ok() {
+                        return Err(format!("another kernel is listening on {}", addr));
+                    }
+                    // Left behind by a kernel that did not shut down cleanly.
+                    let _ = fs::remove_file(path);
+                }
+                let listener = UnixListener::bind(path)
+                    .map_err(|e| format!("failed to bind {}: {}", addr, e))?;
+                let _ = fs::set_permissions(path, fs::Permissions::from_mode(0o600));
+                Ok(Listener::Unix(listener))
+            }
+            #[cfg(not(unix))]
+            ControlAddr::Unix(_) => {
+                Err("Unix sockets are not available; use tcp:127.0.0.1:<port>".to_string())
+            }
+            ControlAddr::Tcp(a) => TcpListener::bind(a)
+                .map(Listener::Tcp)
+                .map_err(|e| format!("failed to bind {}: {}", addr, e)),
+        }
+    }
+
+    /// The next client as a reader and a shared writer.
+    fn accept(&self) -> std::io::Result<(Box<dyn Read + Send>, Writer)> {
+        match self {
+            #[cfg(unix)]
+            Listener::Unix(l) => {
+                let (stream, _) = l.accept()?;
+                Ok((Box::new(stream.try_clone()?), Arc::new(Mutex::new(Box::new(stream)))))
+            }
+            Listener::Tcp(l) => {
+                let (stream, _): (TcpStream, _) = l.accept()?;
+                Ok((Box::new(stream.try_clone()?), Arc::new(Mutex::new(Box::new(stream)))))
+            }
+        }
+    }
+}
+
+/// Start listening. Each client gets its own thread, up to
+/// MAX_CLIENTS at once. Returns the address, for the boot log, and the
+/// token clients must present.
+pub fn start(config: &ControlConfig, shell: ShellHandle) -> Result<(String, String), String> {
+    let listener = Listener::bind(&config.addr)?;
+    let token = config.server_token()?;
+    let client_token = token.clone();
+    shell.open_listener();
+    let clients = Arc::new(AtomicUsize::new(0));
+    thread::spawn(move || loop {
+        match listener.accept() {
+            Ok((reader, writer)) => {
+                if clients.fetch_add(1, Ordering::SeqCst) >= MAX_CLIENTS {
+                    clients.fetch_sub(1, Ordering::SeqCst);
+                    send(&writer, &frame(false, "busy", "too many clients"));
+                    continue;
+                }
+                let (shell, token, clients) = (shell.clone(), token.clone(), Arc::clone(&clients));
+                thread::spawn(move || {
+                    serve(BufReader::new(reader), writer, &token, &shell);
+                    clients.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+            Err(_) => thread::sleep(Duration::from_millis(100)),
+        }
+    });
+    Ok((config.addr.to_string(), client_token))
+}
+
+/// Write one response or event; false if the client has gone.
+fn send(writer: &Writer, text: &str) -> bool {
+    match writer.lock() {
+        Ok(mut w) => w.write_all(format!("{}\n", text).as_bytes()).and_then(|_| w.flush()).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// A text response: a status line with the line count, then the lines.
+fn frame(ok: bool, code: &str, output: &str) -> String {
+    let lines: Vec<&str> = output.lines().collect();
+    let head = match ok {
+        true => format!("ok {}", lines.len()),
+        false => format!("error {} {}", code, lines.len()),
+    };
+    std::iter::once(head.as_str()).chain(lines).collect::<Vec<_>>().join("\n")
+}
+
+fn render(result: &ShellResult) -> String {
+    match result.mode {
+        OutputMode::Json => result.to_json(),
+        OutputMode::Text => frame(result.ok(), result.code.name(), &result.output),
+    }
+}
+
+/// The next line, or `None` at the end of input, on a read error or
+/// when the line runs past MAX_LINE_BYTES.
+fn read_line(reader: &mut impl BufRead) -> Option<String> {
+    let mut line = String::new();
+    match reader.take(MAX_LINE_BYTES + 1).read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(n) if n as u64 > MAX_LINE_BYTES => None,
+        Ok(_) => Some(line),
+    }
+}
+
+fn serve(mut reader: impl BufRead, writer: Writer, token: &str, shell: &ShellHandle) {
+    let mut session = None;
+    let mut failures = 0;
+
+    while let Some(line) = read_line(&mut reader) {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let Some(id) = session else {
+            match line.strip_prefix("auth ") {
+                Some(t) if same_token(t.trim(), token) => {
+                    let (events, rx) = mpsc::channel();
+                    let id = shell.open_session(events);
+                    let w = Arc::clone(&writer);
+                    thread::spawn(move || forward_events(rx, w));
+                    session = Some(id);
+                    send(&writer, &frame(true, "", &format!("session {}", id)));
+                }
+                _ => {
+                    failures += 1;
+                    send(&writer, &frame(false, "auth", "authenticate first: auth <token>"));
+                    if failures >= MAX_AUTH_FAILURES {
+                        break;
+                    }
+                }
+            }
+            continue;
+        };
+
+        let response = match shell.call_in(id, line).recv_timeout(REPLY_TIMEOUT) {
+            Ok(result) => render(&result),
+            Err(_) => frame(false, "failed", "no reply from the command daemon"),
+        };
+        if !send(&writer, &response) {
+            break;
+        }
+    }
+
+    if let Some(id) = session {
+        shell.close_session(id);
+    }
+}
+
+/// Subscription output for one client, until its session closes.
+fn forward_events(rx: Receiver<String>, writer: Writer) {
+    for line in rx {
+        if !send(&writer, &line) {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("aion-{}-{}", name, std::process::id()))
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn token_files_are_private_and_tokens_random() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let token_file = scratch("token");
+        fs::write(&token_file, "old\n").unwrap();
+        fs::set_permissions(&token_file, fs::Permissions::from_mode(0o644)).unwrap();
+        let config = ControlConfig {
+            addr: ControlAddr::Unix(scratch("token-sock")),
+            token: None,
+            token_file: token_file.clone(),
+        };
+        let token = config.server_token().unwrap();
+        let mode = fs::metadata(&token_file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(config.client_token().unwrap(), token);
+
+        assert_eq!(token.len(), 32);
+        assert!(token.bytes().all(|b| b.is_ascii_hexdigit()));
+        assert_ne!(config.server_token().unwrap(), token);
+        let _ = fs::remove_file(&token_file);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn binding_leaves_other_files_alone() {
+        let path = scratch("not-a-socket");
+        fs::write(&path, "keep me").unwrap();
+        let err = Listener::bind(&ControlAddr::Unix(path.clone())).err().unwrap();
+        assert!(err.contains("is not a socket"), "{}", err);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "keep me");
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn overlong_lines_end_the_connection() {
+        use crate::kernel::clock::VirtualClock;
+        use crate::kernel::Scheduler;
+
+        let scheduler = Scheduler::new(Arc::new(VirtualClock::new()));
+        let (shell, _requests) = ShellHandle::channel(scheduler.waker(None));
+        let out = Arc::new(Mutex::new(Vec::new()));
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let writer: Writer = Arc::new(Mutex::new(Box::new(Shared(Arc::clone(&out)))));
+
+        // An endless line is cut off without ever being buffered whole.
+        let endless = std::io::repeat(b'a');
+        serve(BufReader::new(endless), Arc::clone(&writer), "s3cret", &shell);
+        assert!(out.lock().unwrap().is_empty());
+
+        let input = format!("x\nauth {}\n", "a".repeat(MAX_LINE_BYTES as usize));
+        serve(input.as_bytes(), writer, "s3cret", &shell);
+        let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        assert_eq!(out, "error auth 1\nauthenticate first: auth <token>\n");
+    }
+}
@@ -0,0 +1,622 @@
+//! The interactive AION shell prompt.
+//!
+//! When stdin is a terminal it is switched out of canonical mode (with
+//! `stty`) and keys are handled here: cursor movement and kill keys,
+//! history on up/down, reverse search on Ctrl-R and tab completion,
+//! which the command daemon answers from the command registry. History
+//! is kept in AION_HISTORY (default ~/.aion_history; `off` keeps it in
+//! memory only).
+//!
+//! Console output goes through `print_line`, which clears the prompt,
+//! prints and draws the prompt again, so pulses never land in the middle
+//! of a half-typed command. When stdin is not a terminal, lines are read
+//! as they come.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::process::{self, Command, Stdio};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::kernel::shell::quote;
+use crate::kernel::ShellHandle;
+
+pub const PROMPT: &str = "AION> ";
+pub const HISTORY_MAX: usize = 1000;
+const COMPLETION_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Lines entered so far, oldest first, optionally backed by a file.
+pub struct History {
+    entries: Vec<String>,
+    path: Option<PathBuf>,
+}
+
+impl History {
+    /// AION_HISTORY=<path>, `off` for none, default ~/.aion_history.
+    pub fn from_env() -> Self {
+        let path = match std::env::var("AION_HISTORY") {
+            Ok(v) if v == "off" => None,
+            Ok(v) => Some(PathBuf::from(v)),
+            Err(_) => Some(
+                std::env::var("HOME")
+                    .map(PathBuf::from)
+                    .unwrap_or_default()
+                    .join(".aion_history"),
+            ),
+        };
+        Self::load(path)
+    }
+
+    /// Read the last HISTORY_MAX lines of `path`, trimming the file if
+    /// it has grown past that.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let mut entries: Vec<String> = path
+            .as_ref()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .map(|text| text.lines().filter(|l| !l.trim().is_empty()).map(String::from).collect())
+            .unwrap_or_default();
+        if entries.len() > HISTORY_MAX {
+            entries.drain(..entries.len() - HISTORY_MAX);
+            if let Some(p) = &path {
+                let _ = fs::write(p, entries.join("\n") + "\n");
+            }
+        }
+        Self { entries, path }
+    }
+
+    /// Remember `line`, unless it is blank or repeats the last entry.
+    pub fn add(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() || self.entries.last().is_some_and(|l| l == line) {
+            return;
+        }
+        self.entries.push(line.to_string());
+        if self.entries.len() > HISTORY_MAX {
+            self.entries.remove(0);
+        }
+        if let Some(p) = &self.path {
+            if let Ok(mut f) = OpenOptions::new().create(true).append(true).open(p) {
+                let _ = writeln!(f, "{}", line);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, i: usize) -> Option<&str> {
+        self.entries.get(i).map(String::as_str)
+    }
+
+    /// The newest entry at or before `from` that contains `query`.
+    pub fn search(&self, query: &str, from: usize) -> Option<usize> {
+        let end = (from + 1).min(self.entries.len());
+        self.entries[..end].iter().rposition(|l| l.contains(query))
+    }
+}
+
+/// A decoded keypress.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    Up,
+    Down,
+    Tab,
+    /// Ctrl-K
+    KillToEnd,
+    /// Ctrl-U
+    KillToStart,
+    /// Ctrl-W
+    DeleteWord,
+    /// Ctrl-R
+    Search,
+    /// Ctrl-G
+    Cancel,
+    /// Ctrl-C
+    Interrupt,
+    /// Ctrl-D
+    Eof,
+    Ignore,
+}
+
+/// Decode the next key from raw terminal bytes.
+pub fn read_key(bytes: &mut impl Iterator<Item = u8>) -> Option<Key> {
+    let b = bytes.next()?;
+    Some(match b {
+        1 => Key::Home,
+        2 => Key::Left,
+        3 => Key::Interrupt,
+        4 => Key::Eof,
+        5 => Key::End,
+        6 => Key::Right,
+        7 => Key::Cancel,
+        8 | 127 => Key::Backspace,
+        9 => Key::Tab,
+        10 | 13 => Key::Enter,
+        11 => Key::KillToEnd,
+        14 => Key::Down,
+        16 => Key::Up,
+        18 => Key::Search,
+        21 => Key::KillToStart,
+        23 => Key::DeleteWord,
+        27 => match bytes.next() {
+            Some(b'[') | Some(b'O') => match bytes.next() {
+                Some(b'A') => Key::Up,
+                Some(b'B') => Key::Down,
+                Some(b'C') => Key::Right,
+                Some(b'D') => Key::Left,
+                Some(b'H') => Key::Home,
+                Some(b'F') => Key::End,
+                Some(d @ b'0'..=b'9') => {
+                    let mut code = vec![d];
+                    for b in bytes.by_ref() {
+                        if b == b'~' || !b.is_ascii_digit() {
+                            break;
+                        }
+                        code.push(b);
+                    }
+                    match code.as_slice() {
+                        b"1" | b"7" => Key::Home,
+                        b"4" | b"8" => Key::End,
+                        b"3" => Key::Delete,
+                        _ => Key::Ignore,
+                    }
+                }
+                _ => Key::Ignore,
+            },
+            _ => Key::Cancel,
+        },
+        b if b < 32 => Key::Ignore,
+        b if b < 128 => Key::Char(b as char),
+        b => {
+            // UTF-8: the leading byte says how many follow.
+            let extra = b.leading_ones().saturating_sub(1) as usize;
+            let mut buf = vec![b];
+            buf.extend(bytes.take(extra.min(3)));
+            match std::str::from_utf8(&buf).ok().and_then(|s| s.chars().next()) {
+                Some(c) => Key::Char(c),
+                None => Key::Ignore,
+            }
+        }
+    })
+}
+
+/// What the caller should do after a key.
+#[derive(Debug, PartialEq)]
+pub enum Edit {
+    Redraw,
+    Submit(String),
+    Complete,
+    Eof,
+    Interrupt,
+}
+
+struct Search {
+    query: String,
+    hit: Option<usize>,
+    /// The line being edited when the search started.
+    saved: Vec<char>,
+}
+
+/// The line being edited, as a state machine over keys.
+pub struct LineEditor {
+    pub history: History,
+    buffer: Vec<char>,
+    cursor: usize,
+    /// The history entry shown by up/down; `None` while on a new line.
+    recall: Option<usize>,
+    /// The new line, kept while browsing history.
+    draft: Vec<char>,
+    search: Option<Search>,
+}
+
+impl LineEditor {
+    pub fn new(history: History) -> Self {
+        Self {
+            history,
+            buffer: Vec::new(),
+            cursor: 0,
+            recall: None,
+            draft: Vec::new(),
+            search: None,
+        }
+    }
+
+    pub fn line(&self) -> String {
+        self.buffer.iter().collect()
+    }
+
+    /// The text the completion applies to.
+    pub fn before_cursor(&self) -> String {
+        self.buffer[..self.cursor].iter().collect()
+    }
+
+    fn set_line(&mut self, line: &str) {
+        self.buffer = line.chars().collect();
+        self.cursor = self.buffer.len();
+    }
+
+    pub fn handle(&mut self, key: Key) -> Edit {
+        // A key that ends a search goes on to act on the found line.
+        if let Some(edit) = self.search_key(key) {
+            return edit;
+        }
+        match key {
+            Key::Char(c) => {
+                self.buffer.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.buffer.remove(self.cursor);
+            }
+            Key::Eof if self.buffer.is_empty() => return Edit::Eof,
+            // Ctrl-D on a non-empty line deletes, as in readline.
+            Key::Delete | Key::Eof if self.cursor < self.buffer.len() => {
+                self.buffer.remove(self.cursor);
+            }
+            Key::Left => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right => self.cursor = (self.cursor + 1).min(self.buffer.len()),
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = self.buffer.len(),
+            Key::KillToEnd => self.buffer.truncate(self.cursor),
+            Key::KillToStart => {
+                self.buffer.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            Key::DeleteWord => {
+                let mut start = self.cursor;
+                while start > 0 && self.buffer[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+                while start > 0 && !self.buffer[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+                self.buffer.drain(start..self.cursor);
+                self.cursor = start;
+            }
+            Key::Up => {
+                let prev = match self.recall {
+                    None if !self.history.is_empty() => {
+                        self.draft = self.buffer.clone();
+                        self.history.len() - 1
+                    }
+                    Some(i) if i > 0 => i - 1,
+                    _ => return Edit::Redraw,
+                };
+                self.recall = Some(prev);
+                let line = self.history.get(prev).unwrap_or("").to_string();
+                self.set_line(&line);
+            }
+            Key::Down => match self.recall {
+                Some(i) if i + 1 < self.history.len() => {
+                    self.recall = Some(i + 1);
+                    let line = self.history.get(i + 1).unwrap_or("").to_string();
+                    self.set_line(&line);
+                }
+                Some(_) => {
+                    self.recall = None;
+                    self.buffer = std::mem::take(&mut self.draft);
+                    self.cursor = self.buffer.len();
+                }
+                None => {}
+            },
+            Key::Search => {
+                self.search = Some(Search {
+                    query: String::new(),
+                    hit: None,
+                    saved: self.buffer.clone(),
+                })
+            }
+            Key::Tab => return Edit::Complete,
+            Key::Enter => {
+                let line = self.line();
+                self.history.add(&line);
+                self.buffer.clear();
+                self.cursor = 0;
+                self.recall = None;
+                return Edit::Submit(line);
+            }
+            Key::Interrupt if self.buffer.is_empty() => return Edit::Interrupt,
+            Key::Interrupt => {
+                self.buffer.clear();
+                self.cursor = 0;
+                self.recall = None;
+            }
+            _ => {}
+        }
+        Edit::Redraw
+    }
+
+    /// Keys during a reverse search. `None` ends the search and lets the
+    /// key act on the line that was found.
+    fn search_key(&mut self, key: Key) -> Option<Edit> {
+        let search = self.search.as_mut()?;
+        let newest = self.history.len().saturating_sub(1);
+        match key {
+            Key::Char(c) => {
+                search.query.push(c);
+                search.hit = self.history.search(&search.query, search.hit.unwrap_or(newest));
+            }
+            Key::Backspace => {
+                search.query.pop();
+                search.hit = self.history.search(&search.query, newest);
+            }
+            Key::Search => {
+                if let Some(older) = search.hit.and_then(|i| i.checked_sub(1)) {
+                    search.hit = self.history.search(&search.query, older).or(search.hit);
+                }
+            }
+            Key::Cancel | Key::Interrupt => {
+                let saved = std::mem::take(&mut search.saved);
+                self.search = None;
+                self.buffer = saved;
+                self.cursor = self.buffer.len();
+            }
+            _ => {
+                let found = search.hit.and_then(|i| self.history.get(i)).unwrap_or("").to_string();
+                self.search = None;
+                self.set_line(&found);
+                return None;
+            }
+        }
+        if let Some(line) = self
+            .search
+            .as_ref()
+            .and_then(|s| s.hit)
+            .and_then(|i| self.history.get(i))
+            .map(String::from)
+        {
+            self.set_line(&line);
+        }
+        Some(Edit::Redraw)
+    }
+
+    /// Apply completion candidates to the word before the cursor. With
+    /// several candidates and nothing more in common, returns them for
+    /// display.
+    pub fn complete(&mut self, candidates: &[String]) -> Option<String> {
+        let mut start = self.cursor;
+        while start > 0 && !is_word_break(self.buffer[start - 1]) {
+            start -= 1;
+        }
+        let word: String = self.buffer[start..self.cursor].iter().collect();
+        let matches: Vec<&String> = candidates.iter().filter(|c| c.starts_with(&word)).collect();
+        let replacement = match matches.as_slice() {
+            [] => return None,
+            [only] => format!("{} ", quote(only)),
+            [first, rest @ ..] => {
+                let mut common = first.len();
+                for m in rest {
+                    common = common.min(first.bytes().zip(m.bytes()).take_while(|(a, b)| a == b).count());
+                }
+                while !first.is_char_boundary(common) {
+                    common -= 1;
+                }
+                if common <= word.len() {
+                    let names: Vec<&str> = matches.iter().map(|m| m.as_str()).collect();
+                    return Some(names.join("  "));
+                }
+                first[..common].to_string()
+            }
+        };
+        let tail = self.buffer.split_off(self.cursor);
+        self.buffer.truncate(start);
+        self.buffer.extend(replacement.chars());
+        self.cursor = self.buffer.len();
+        self.buffer.extend(tail);
+        None
+    }
+
+    /// The prompt line as it should appear, cursor included.
+    pub fn render(&self, prompt: &str) -> String {
+        if let Some(s) = &self.search {
+            let failing = if s.hit.is_none() && !s.query.is_empty() { "failing " } else { "" };
+            return format!("({}reverse-i-search)`{}': {}", failing, s.query, self.line());
+        }
+        let mut out = format!("{}{}", prompt, self.line());
+        let back = self.buffer.len() - self.cursor;
+        if back > 0 {
+            out.push_str(&format!("\x1b[{}D", back));
+        }
+        out
+    }
+}
+
+fn is_word_break(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '|' | ';' | '&')
+}
+
+/// The prompt as last drawn, while the editor owns the terminal.
+struct Screen {
+    active: bool,
+    rendered: String,
+}
+
+static SCREEN: Mutex<Screen> = Mutex::new(Screen {
+    active: false,
+    rendered: String::new(),
+});
+
+/// Terminal settings to restore on exit (`stty -g`).
+static SAVED_TTY: Mutex<Option<String>> = Mutex::new(None);
+
+/// Print a line of console output above the prompt.
+pub fn print_line(text: &str) {
+    let screen = SCREEN.lock().unwrap_or_else(|e| e.into_inner());
+    if !screen.active {
+        println!("{}", text);
+        return;
+    }
+    let mut out = io::stdout().lock();
+    let _ = write!(out, "\r\x1b[K{}\n{}", text, screen.rendered);
+    let _ = out.flush();
+}
+
+fn draw(rendered: String) {
+    let mut screen = SCREEN.lock().unwrap_or_else(|e| e.into_inner());
+    let mut out = io::stdout().lock();
+    let _ = write!(out, "\r\x1b[K{}", rendered);
+    let _ = out.flush();
+    screen.active = true;
+    screen.rendered = rendered;
+}
+
+fn stty(args: &[&str]) -> Option<String> {
+    let out = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    out.status
+        .success()
+        .then(|| String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+/// Take keys one at a time, without echo. Ctrl-C comes through as a key.
+fn enter_raw_mode() -> bool {
+    let saved = match stty(&["-g"]) {
+        Some(s) => s,
+        None => return false,
+    };
+    if stty(&["-icanon", "-echo", "-isig", "min", "1", "time", "0"]).is_none() {
+        return false;
+    }
+    *SAVED_TTY.lock().unwrap_or_else(|e| e.into_inner()) = Some(saved);
+    true
+}
+
+/// Put the terminal back the way it was, if the editor changed it.
+pub fn restore_terminal() {
+    let saved = SAVED_TTY.lock().unwrap_or_else(|e| e.into_inner()).take();
+    if let Some(saved) = saved {
+        let mut screen = SCREEN.lock().unwrap_or_else(|e| e.into_inner());
+        if screen.active {
+            println!();
+            screen.active = false;
+        }
+        stty(&[&saved]);
+    }
+}
+
+/// Read commands from stdin until it closes.
+pub fn run(shell: ShellHandle) {
+    if !io::stdin().is_terminal() || !enter_raw_mode() {
+        return run_plain(shell);
+    }
+    println!("[AION-CMD] Type commands (help lists them); Tab completes, Ctrl-R searches history");
+    let mut editor = LineEditor::new(History::from_env());
+    draw(editor.render(PROMPT));
+
+    let mut bytes = io::stdin().lock().bytes().map_while(Result::ok);
+    while let Some(key) = read_key(&mut bytes) {
+        match editor.handle(key) {
+            Edit::Redraw => {}
+            Edit::Submit(line) => {
+                // Leave the line as typed above the new prompt.
+                print_line(&format!("{}{}", PROMPT, line));
+                if !line.trim().is_empty() {
+                    shell.submit(line);
+                }
+            }
+            Edit::Complete => {
+                let candidates = shell
+                    .complete(editor.before_cursor())
+                    .recv_timeout(COMPLETION_TIMEOUT)
+                    .unwrap_or_default();
+                if let Some(listing) = editor.complete(&candidates) {
+                    print_line(&listing);
+                }
+            }
+            Edit::Eof => break,
+            Edit::Interrupt => {
+                restore_terminal();
+                process::exit(130);
+            }
+        }
+        draw(editor.render(PROMPT));
+    }
+    restore_terminal();
+    shell.close_input();
+}
+
+fn run_plain(shell: ShellHandle) {
+    println!("[AION-CMD] Type commands: help, status, topology");
+    print!("{}", PROMPT);
+    let _ = io::stdout().flush();
+
+    for line in io::stdin().lock().lines() {
+        match line {
+            Ok(cmd) => {
+                let cmd = cmd.trim().to_string();
+                if !cmd.is_empty() {
+                    shell.submit(cmd);
+                }
+                print!("{}", PROMPT);
+                let _ = io::stdout().flush();
+            }
+            Err(_) => break,
+        }
+    }
+    shell.close_input();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_editor_keeps_history_and_searches_it() {
+        let path = std::env::temp_dir().join(format!("aion-history-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut ed = LineEditor::new(History::load(Some(path.clone())));
+        let typed = |ed: &mut LineEditor, text: &str| {
+            let mut bytes = text.bytes();
+            let mut last = Edit::Redraw;
+            while let Some(key) = read_key(&mut bytes) {
+                last = ed.handle(key);
+            }
+            last
+        };
+
+        assert_eq!(typed(&mut ed, "organs\r"), Edit::Submit("organs".into()));
+        assert_eq!(typed(&mut ed, "sim level low\r"), Edit::Submit("sim level low".into()));
+        // Recall, left twice, delete the 'l', type 'w', then 'o' at the end.
+        assert_eq!(typed(&mut ed, "\x1b[A\x1b[D\x1b[D\x7fw\x05o\r"), Edit::Submit("sim level wowo".into()));
+        assert_eq!(typed(&mut ed, "status\x15awareness\r"), Edit::Submit("awareness".into()));
+
+        // Ctrl-R "org" finds the first command; Enter runs it.
+        typed(&mut ed, "\x12org");
+        assert!(ed.render("AION> ").starts_with("(reverse-i-search)`org': organs"));
+        assert_eq!(typed(&mut ed, "\r"), Edit::Submit("organs".into()));
+        typed(&mut ed, "\x12zzz");
+        assert!(ed.render("AION> ").starts_with("(failing reverse-i-search)"));
+        typed(&mut ed, "\x07");
+        assert_eq!(ed.line(), "");
+
+        typed(&mut ed, "mem get m");
+        assert_eq!(ed.complete(&["motd".into()]), None);
+        assert_eq!(ed.line(), "mem get motd ");
+        assert_eq!(ed.handle(Key::Interrupt), Edit::Redraw);
+        assert_eq!(ed.handle(Key::Eof), Edit::Eof);
+
+        let reloaded = History::load(Some(path.clone()));
+        assert_eq!(reloaded.len(), 5);
+        assert_eq!(reloaded.get(4), Some("organs"));
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,265 @@
+//! A thread-safe handle onto the kernel `Bus`.
+//!
+//! Daemons get `&mut Bus` on every tick, but other threads (the HTTP
+//! server, the stdin reader, replay workers, future network listeners)
+//! can't. A `BusHandle` is cheap to clone and `Send + Sync`: it queues
+//! requests that the kernel loop drains, in arrival order, at the start
+//! of every iteration. It also shares the subscriber table and the pulse
+//! history, which are already behind locks.
+//!
+//! A `ShellHandle` does the same for the command daemon: anything can
+//! submit a command line and, if it asks, get the output back, either
+//! once or inside a session that remembers its output mode.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use crate::kernel::shell::ShellResult;
+use crate::kernel::{LogFilter, PubSub, PulseHistory, PulseKind, PulsePayload, Waker};
+use crate::telemetry::SimLevel;
+
+/// Something another thread wants the bus to do.
+#[derive(Debug, Clone)]
+pub enum BusRequest {
+    Emit {
+        kind: PulseKind,
+        source: String,
+        payload: PulsePayload,
+    },
+    SetSimLevel {
+        level: SimLevel,
+        source: String,
+    },
+    /// Change the console filter and/or JSON format.
+    SetLogFilter {
+        filter: Option<LogFilter>,
+        json: Option<bool>,
+        source: String,
+    },
+}
+
+#[derive(Clone)]
+pub struct BusHandle {
+    pub(super) tx: Sender<BusRequest>,
+    pub(super) pubsub: Arc<Mutex<PubSub>>,
+    pub(super) history: Arc<Mutex<PulseHistory>>,
+    pub(super) waker: Option<Waker>,
+}
+
+impl BusHandle {
+    /// Queue a request and wake the kernel loop. Returns false if the
+    /// loop is gone.
+    pub fn request(&self, req: BusRequest) -> bool {
+        let sent = self.tx.send(req).is_ok();
+        if let Some(waker) = &self.waker {
+            waker.wake();
+        }
+        sent
+    }
+
+    /// Queue a pulse; it gets its ID when the kernel loop drains it.
+    pub fn emit(
+        &self,
+        kind: PulseKind,
+        source: impl Into<String>,
+        payload: impl Into<PulsePayload>,
+    ) -> bool {
+        self.request(BusRequest::Emit {
+            kind,
+            source: source.into(),
+            payload: payload.into(),
+        })
+    }
+
+    pub fn set_sim_level(&self, level: SimLevel, source: impl Into<String>) -> bool {
+        self.request(BusRequest::SetSimLevel {
+            level,
+            source: source.into(),
+        })
+    }
+
+    pub fn set_log_filter(
+        &self,
+        filter: Option<LogFilter>,
+        json: Option<bool>,
+        source: impl Into<String>,
+    ) -> bool {
+        self.request(BusRequest::SetLogFilter {
+            filter,
+            json,
+            source: source.into(),
+        })
+    }
+
+    pub fn pubsub(&self) -> Arc<Mutex<PubSub>> {
+        Arc::clone(&self.pubsub)
+    }
+
+    pub fn history(&self) -> Arc<Mutex<PulseHistory>> {
+        Arc::clone(&self.history)
+    }
+}
+
+/// Identifies a shell session: the console or a control socket client.
+/// Each has its own output mode and subscriptions.
+pub type SessionId = u64;
+
+/// Lines typed at the console.
+pub const CONSOLE_SESSION: SessionId = 0;
+
+/// Input for the command daemon.
+pub enum ShellRequest {
+    /// Run a command line. The output is emitted as usual and the
+    /// result is also sent to `reply`, if any. Lines outside a session
+    /// start in text mode and leave no mode behind.
+    Run {
+        line: String,
+        session: Option<SessionId>,
+        reply: Option<Sender<ShellResult>>,
+    },
+    /// A remote session starts; its subscription output goes to `events`.
+    OpenSession {
+        session: SessionId,
+        events: Sender<String>,
+    },
+    CloseSession(SessionId),
+    /// Completions for the last word of `line`.
+    Complete {
+        line: String,
+        reply: Sender<Vec<String>>,
+    },
+    /// The interactive input (stdin) has closed.
+    InputClosed,
+    /// A control listener is up, so commands can still arrive after
+    /// stdin closes.
+    ListenerOpened,
+}
+
+#[derive(Clone)]
+pub struct ShellHandle {
+    tx: Sender<ShellRequest>,
+    waker: Waker,
+    next_session: Arc<AtomicU64>,
+}
+
+impl ShellHandle {
+    /// `waker` must wake the command daemon.
+    pub fn channel(waker: Waker) -> (Self, Receiver<ShellRequest>) {
+        let (tx, rx) = mpsc::channel();
+        let next_session = Arc::new(AtomicU64::new(CONSOLE_SESSION + 1));
+        (Self { tx, waker, next_session }, rx)
+    }
+
+    fn send(&self, req: ShellRequest) -> bool {
+        let sent = self.tx.send(req).is_ok();
+        self.waker.wake();
+        sent
+    }
+
+    /// Run a line as if typed at the console.
+    pub fn submit(&self, line: impl Into<String>) -> bool {
+        self.send(ShellRequest::Run {
+            line: line.into(),
+            session: Some(CONSOLE_SESSION),
+            reply: None,
+        })
+    }
+
+    /// Run a line outside any session; its result arrives on the
+    /// returned receiver.
+    pub fn call(&self, line: impl Into<String>) -> Receiver<ShellResult> {
+        self.call_with(None, line.into())
+    }
+
+    /// Run a line in a session opened with `open_session`.
+    pub fn call_in(&self, session: SessionId, line: impl Into<String>) -> Receiver<ShellResult> {
+        self.call_with(Some(session), line.into())
+    }
+
+    fn call_with(&self, session: Option<SessionId>, line: String) -> Receiver<ShellResult> {
+        let (tx, rx) = mpsc::channel();
+        self.send(ShellRequest::Run {
+            line,
+            session,
+            reply: Some(tx),
+        });
+        rx
+    }
+
+    /// Start a session whose subscription output is sent to `events`.
+    pub fn open_session(&self, events: Sender<String>) -> SessionId {
+        let session = self.next_session.fetch_add(1, Ordering::Relaxed);
+        self.send(ShellRequest::OpenSession { session, events });
+        session
+    }
+
+    /// End a session and drop its subscriptions.
+    pub fn close_session(&self, session: SessionId) {
+        self.send(ShellRequest::CloseSession(session));
+    }
+
+    /// Ask the command daemon how the last word of `line` could go on.
+    pub fn complete(&self, line: impl Into<String>) -> Receiver<Vec<String>> {
+        let (tx, rx) = mpsc::channel();
+        self.send(ShellRequest::Complete {
+            line: line.into(),
+            reply: tx,
+        });
+        rx
+    }
+
+    pub fn close_input(&self) {
+        self.send(ShellRequest::InputClosed);
+    }
+
+    pub fn open_listener(&self) {
+        self.send(ShellRequest::ListenerOpened);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::{lock, Bus, PulseFilter, MAX_REQUESTS_PER_DRAIN};
+    use std::thread;
+
+    #[test]
+    fn requests_from_other_threads_apply_in_order() {
+        let mut bus = Bus::new();
+        let (_, rx) = lock(&bus.pubsub()).subscribe("test", PulseFilter::default(), 64);
+        let handle = bus.handle();
+        thread::spawn(move || {
+            handle.emit(PulseKind::Command, "worker", "first");
+            handle.set_sim_level(SimLevel::High, "worker");
+            handle.emit(PulseKind::Command, "worker", "last");
+        })
+        .join()
+        .unwrap();
+
+        // Nothing happens until the kernel loop drains the queue.
+        assert_eq!(bus.sim_level, SimLevel::Low);
+        assert_eq!(bus.drain_requests(), 3);
+        assert_eq!(bus.sim_level, SimLevel::High);
+        let got: Vec<String> = rx.try_iter().map(|p| p.payload.summary()).collect();
+        assert_eq!(got.len(), 3);
+        assert_eq!((got[0].as_str(), got[2].as_str()), ("first", "last"));
+        assert!(got[1].contains("sim_level") && got[1].contains("High"), "{}", got[1]);
+    }
+
+    #[test]
+    fn a_flood_is_drained_over_several_passes() {
+        let mut bus = Bus::new();
+        let handle = bus.handle();
+        for i in 0..MAX_REQUESTS_PER_DRAIN + 10 {
+            assert!(handle.emit(PulseKind::Command, "flood", format!("#{}", i)));
+        }
+        assert_eq!(bus.drain_requests(), MAX_REQUESTS_PER_DRAIN);
+        assert_eq!(bus.drain_requests(), 10);
+        assert_eq!(bus.drain_requests(), 0);
+        assert!(lock(&bus.history()).len() > 0);
+
+        drop(bus);
+        assert!(!handle.emit(PulseKind::Command, "late", "nobody listens"));
+    }
+}
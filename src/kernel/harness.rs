@@ -0,0 +1,774 @@
+//! Headless kernel for tests.
+//!
+//! The standard daemons run on a `VirtualClock`: no HTTP server, no
+//! stdin, nothing written to disk and no sleeping. `advance` jumps the
+//! clock from deadline to deadline, so an hour of organism life takes
+//! milliseconds.
+
+use std::path::PathBuf;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::cortex::{CortexBrains, DecisionJournal, PolicySet};
+use crate::kernel::clock::VirtualClock;
+use crate::kernel::control::{self, ControlAddr, ControlConfig};
+use crate::kernel::pubsub;
+use crate::kernel::{
+    install_daemons, Bus, Clock, Daemon, DaemonConfig, KernelState, LogFilter, Placement, Pulse, PulseFilter,
+    PulsePayload, Schedule, Scheduler, ShellHandle, Subscriber, Worker, WorkerDaemon,
+};
+use crate::organism::{self, OrganKind};
+use crate::telemetry::SimLevel;
+
+pub struct Harness {
+    pub clock: Arc<VirtualClock>,
+    pub bus: Bus,
+    pub state: KernelState,
+    scheduler: Scheduler,
+    shell: ShellHandle,
+    feed: Subscriber,
+    /// Every pulse emitted so far, oldest first.
+    pub pulses: Vec<Pulse>,
+}
+
+impl Harness {
+    pub fn new() -> Self {
+        Self::with_alert_dwell(Duration::ZERO)
+    }
+
+    pub fn with_alert_dwell(alert_dwell: Duration) -> Self {
+        let clock = Arc::new(VirtualClock::new());
+        let mut bus = Bus::new();
+        bus.set_clock(clock.clone());
+        bus.log_sinks.apply_preset(LogFilter::Silent);
+
+        let mut scheduler = Scheduler::new(clock.clone());
+        bus.set_waker(scheduler.waker(None));
+        let (shell, rx) = ShellHandle::channel(scheduler.waker(Some("command")));
+
+        let state = KernelState::new(
+            Arc::new(Mutex::new(organism::sample_topology())),
+            Arc::new(Mutex::new(None)),
+            Arc::new(Mutex::new(DecisionJournal::in_memory(clock.clone()))),
+            CortexBrains::standard(PolicySet::default_ladder(), clock.unix_millis()),
+            scheduler.table(),
+            shell.clone(),
+        );
+        let config = DaemonConfig::builtin("sim", alert_dwell);
+        let errors = install_daemons(&mut scheduler, &state, &config, rx);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let (_, feed) = bus
+            .pubsub()
+            .lock()
+            .unwrap()
+            .subscribe("harness", PulseFilter::default(), pubsub::MAX_CAPACITY);
+
+        Self {
+            clock,
+            bus,
+            state,
+            scheduler,
+            shell,
+            feed,
+            pulses: Vec::new(),
+        }
+    }
+
+    /// Run the kernel for `by` of virtual time.
+    pub fn advance(&mut self, by: Duration) {
+        let target = self.clock.now() + by;
+        loop {
+            self.scheduler.wait(Some(target));
+            self.bus.drain_requests();
+            self.scheduler.run_due(self.clock.now(), &mut self.bus);
+            self.pulses.extend(self.feed.try_iter());
+            if self.clock.now() >= target {
+                break;
+            }
+        }
+    }
+
+    /// Type a shell command and return its output.
+    pub fn command(&mut self, line: &str) -> String {
+        self.shell.submit(line);
+        let seen = self.pulses.len();
+        self.advance(Duration::ZERO);
+        self.pulses[seen..]
+            .iter()
+            .find_map(|p| match &p.payload {
+                PulsePayload::CommandResult { command, output } if command == line => {
+                    Some(output.clone())
+                }
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    /// Register an extra daemon alongside the standard ones.
+    pub fn add_daemon(&mut self, daemon: Box<dyn Daemon>, schedule: Schedule) {
+        self.scheduler.add(daemon, schedule);
+    }
+
+    /// Register a worker daemon, woken when its runs finish.
+    pub fn add_worker(&mut self, worker: Box<dyn Worker>, placement: Placement, schedule: Schedule) {
+        let waker = self.scheduler.waker(Some(worker.name()));
+        self.scheduler.add(Box::new(WorkerDaemon::new(worker, placement, waker)), schedule);
+    }
+
+    /// Keep the kernel turning at the current virtual time until `done`
+    /// holds, for work finishing on real threads. Gives up after 5s.
+    pub fn settle(&mut self, done: impl Fn(&Harness) -> bool) -> bool {
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while std::time::Instant::now() < deadline {
+            self.advance(Duration::ZERO);
+            if done(self) {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        false
+    }
+
+    /// Start a control listener on a private Unix socket with the token
+    /// "s3cret". Returns its config and the socket path to clean up.
+    pub fn start_control(&self, name: &str) -> (ControlConfig, PathBuf) {
+        let socket = std::env::temp_dir().join(format!("aion-{}-{}.sock", name, std::process::id()));
+        let config = ControlConfig {
+            addr: ControlAddr::Unix(socket.clone()),
+            token: Some("s3cret".to_string()),
+            token_file: socket.with_extension("token"),
+        };
+        control::start(&config, self.shell.clone()).unwrap();
+        (config, socket)
+    }
+
+    pub fn health(&self, kind: OrganKind) -> f32 {
+        let topo = self.state.topology.lock().unwrap();
+        topo.organs.iter().find(|o| o.kind == kind).map(|o| o.health).unwrap()
+    }
+
+    pub fn count(&self, type_name: &str) -> usize {
+        self.pulses.iter().filter(|p| p.payload.type_name() == type_name).count()
+    }
+}
+
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc;
+    use crate::kernel::{BusHandle, PulseKind, WorkContext, WorkerPool};
+    use std::time::Instant;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    #[test]
+    fn an_hour_passes_without_sleeping() {
+        let started = Instant::now();
+        let mut h = Harness::new();
+        h.advance(HOUR);
+
+        assert_eq!(h.clock.elapsed(), HOUR);
+        assert_eq!(h.count("heartbeat"), 3600);
+        assert_eq!(h.count("status"), 720);
+        assert_eq!(h.count("policy_decision"), 1800);
+        let last = h.pulses.last().unwrap();
+        assert!(last.timestamp_ms - h.pulses[0].timestamp_ms >= 3_599_000);
+        assert!(started.elapsed() < Duration::from_secs(30));
+    }
+
+    #[test]
+    fn virtual_runs_repeat_exactly() {
+        let run = || {
+            let mut h = Harness::new();
+            h.command("sim level high");
+            h.advance(Duration::from_secs(600));
+            h.pulses
+                .iter()
+                .map(|p| format!("{} {} {}", p.timestamp_ms, p.source, p.payload.summary()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn commands_run_at_the_current_virtual_time() {
+        let mut h = Harness::new();
+        h.advance(Duration::from_secs(90));
+        let out = h.command("mode");
+        assert!(out.contains("sim_level=Low"), "{}", out);
+        assert_eq!(h.clock.elapsed(), Duration::from_secs(90));
+
+        h.command("sim level off");
+        assert_eq!(h.bus.sim_level, SimLevel::Off);
+    }
+
+    #[test]
+    fn damaged_organ_recovers_under_telemetry() {
+        let mut h = Harness::new();
+        h.command("sim level off");
+        h.command("damage memory 0.6");
+        let damaged = h.health(OrganKind::Memory);
+        assert!(damaged < 0.5, "memory health {}", damaged);
+
+        h.advance(Duration::from_secs(120));
+        assert!(h.health(OrganKind::Memory) > 0.95, "memory health {}", h.health(OrganKind::Memory));
+        assert!(h.count("alert") >= 2, "expected an alert and its clearance");
+    }
+
+    #[test]
+    fn sampled_telemetry_is_applied_once_and_reported_stale() {
+        let mut h = Harness::new();
+        h.advance(Duration::from_secs(5));
+        assert!(h.command("daemon rm status").contains("requested"));
+        h.advance(Duration::ZERO);
+        let out = h.command("daemon add status every=5s telemetry=worker");
+        assert!(out.contains("daemon status added"), "{}", out);
+        h.command("damage memory 0.5");
+        let sample = |h: &Harness| h.state.telemetry_samples.fetch_add(1, Ordering::SeqCst);
+
+        // One new reading: applied and emitted once, however many ticks.
+        sample(&h);
+        let seen = h.count("metric_snapshot");
+        h.advance(Duration::from_secs(20));
+        assert_eq!(h.count("metric_snapshot"), seen + 1);
+        let held = h.health(OrganKind::Memory);
+        h.advance(Duration::from_secs(20));
+        assert_eq!(h.health(OrganKind::Memory), held);
+        let report = h.command("daemons");
+        assert!(report.contains("no new telemetry for"), "{}", report);
+
+        sample(&h);
+        h.advance(Duration::from_secs(5));
+        assert_eq!(h.count("metric_snapshot"), seen + 2);
+        let report = h.command("daemons");
+        assert!(!report.contains("no new telemetry"), "{}", report);
+    }
+
+    #[test]
+    fn alert_waits_for_the_dwell_time() {
+        let mut h = Harness::with_alert_dwell(Duration::from_secs(60));
+        h.command("sim level off");
+        h.command("damage cortex 0.9");
+
+        h.advance(Duration::from_secs(20));
+        assert_eq!(h.count("alert"), 0);
+
+        // Health recovers before the dwell expires, so nothing is reported.
+        h.advance(Duration::from_secs(600));
+        assert_eq!(h.count("alert"), 0);
+        assert!(h.health(OrganKind::Cortex) > 0.95);
+
+        // Keep it down past the dwell time and the alert goes out once.
+        let start_ms = h.bus.clock().unix_millis();
+        for _ in 0..24 {
+            h.command("damage cortex 0.5");
+            h.advance(Duration::from_secs(5));
+        }
+        let alerts: Vec<&Pulse> =
+            h.pulses.iter().filter(|p| p.payload.type_name() == "alert").collect();
+        assert_eq!(alerts.len(), 1, "{:?}", alerts);
+        assert!(alerts[0].timestamp_ms - start_ms >= 60_000);
+    }
+
+    /// Panics on every tick while `broken` is set.
+    struct Flaky {
+        broken: Arc<AtomicBool>,
+    }
+
+    impl Daemon for Flaky {
+        fn name(&self) -> &'static str {
+            "flaky"
+        }
+
+        fn tick(&mut self, _now: Instant, _bus: &mut Bus) {
+            if self.broken.load(Ordering::SeqCst) {
+                panic!("flaky is broken");
+            }
+        }
+    }
+
+    fn daemon_events(h: &Harness, event: &str) -> usize {
+        h.pulses
+            .iter()
+            .filter(|p| matches!(&p.payload, PulsePayload::DaemonEvent { event: e, .. } if e == event))
+            .count()
+    }
+
+    #[test]
+    fn crashing_daemon_backs_off_then_escalates() {
+        let broken = Arc::new(AtomicBool::new(true));
+        let mut h = Harness::new();
+        h.add_daemon(
+            Box::new(Flaky { broken: Arc::clone(&broken) }),
+            Schedule::every(Duration::from_secs(1)),
+        );
+
+        // Crash at 0s, restart at 1s, crash, restart at 3s, crash: failed.
+        h.advance(Duration::from_secs(2));
+        assert_eq!(daemon_events(&h, "crashed"), 2);
+        assert_eq!(daemon_events(&h, "failed"), 0);
+        h.advance(Duration::from_secs(60));
+        assert_eq!(daemon_events(&h, "crashed"), 2);
+        assert_eq!(daemon_events(&h, "failed"), 1);
+        assert_eq!(daemon_events(&h, "restarted"), 2);
+        assert!(h.pulses.iter().any(|p| matches!(&p.payload,
+            PulsePayload::Alert { organ, level, .. } if organ == "daemon:flaky" && level == "critical")));
+        let report = h.command("daemons");
+        assert!(report.contains("flaky [failed]"), "{}", report);
+        assert!(report.contains("flaky is broken"), "{}", report);
+
+        // The rest of the kernel kept running.
+        assert_eq!(h.count("heartbeat"), 62);
+
+        broken.store(false, Ordering::SeqCst);
+        h.command("daemon start flaky");
+        h.advance(Duration::from_secs(10));
+        assert_eq!(daemon_events(&h, "started"), 1);
+        assert!(h.pulses.iter().any(|p| matches!(&p.payload,
+            PulsePayload::Alert { organ, level, .. } if organ == "daemon:flaky" && level == "ok")));
+        assert!(h.command("daemons").contains("flaky [running]"));
+    }
+
+    /// Panics once while holding the topology lock, poisoning it.
+    struct Clumsy {
+        topology: Arc<Mutex<organism::SystemTopology>>,
+        panicked: bool,
+    }
+
+    impl Daemon for Clumsy {
+        fn name(&self) -> &'static str {
+            "clumsy"
+        }
+
+        fn tick(&mut self, _now: Instant, _bus: &mut Bus) {
+            let _topo = crate::kernel::lock(&self.topology);
+            if !self.panicked {
+                self.panicked = true;
+                panic!("dropped the topology");
+            }
+        }
+    }
+
+    #[test]
+    fn a_poisoned_lock_does_not_take_the_kernel_down() {
+        let mut h = Harness::new();
+        let topology = Arc::clone(&h.state.topology);
+        h.add_daemon(
+            Box::new(Clumsy { topology, panicked: false }),
+            Schedule::every(Duration::from_secs(1)),
+        );
+        h.advance(Duration::from_secs(1));
+        assert_eq!(daemon_events(&h, "crashed"), 1);
+        assert!(h.state.topology.is_poisoned());
+
+        // Everyone else carries on with the topology as it was left.
+        let statuses = h.count("status");
+        h.advance(Duration::from_secs(10));
+        assert!(h.count("status") > statuses);
+        assert!(h.command("damage cortex 0.2").contains("Cortex"));
+        assert!(h.command("organs").contains("Cortex"));
+        assert!(h.command("status").contains("health"));
+
+        // The restarted daemon takes the lock again without crashing.
+        assert_eq!(daemon_events(&h, "restarted"), 1);
+        assert_eq!(daemon_events(&h, "crashed"), 1);
+        assert!(h.command("daemons").contains("clumsy [running]"));
+    }
+
+    #[test]
+    fn stopped_daemon_stays_stopped_until_started() {
+        let mut h = Harness::new();
+        assert!(h.command("daemon stop heartbeat").contains("requested"));
+        let before = h.count("heartbeat");
+        h.advance(Duration::from_secs(30));
+        assert_eq!(h.count("heartbeat"), before);
+        assert!(h.command("daemons").contains("heartbeat [stopped]"));
+
+        h.command("daemon start heartbeat");
+        h.advance(Duration::from_millis(10_500));
+        assert_eq!(h.count("heartbeat"), before + 11);
+
+        assert!(h.command("daemon stop command").contains("refusing"));
+        assert!(h.command("daemon stop nope").contains("no daemon named"));
+    }
+
+    /// Blocks each run until released, or panics while `broken` is set.
+    struct Gate {
+        release: Receiver<()>,
+        broken: Arc<AtomicBool>,
+        bus: BusHandle,
+    }
+
+    impl Worker for Gate {
+        fn name(&self) -> &'static str {
+            "gate"
+        }
+
+        fn run(&mut self, _ctx: &WorkContext) {
+            if self.broken.load(Ordering::SeqCst) {
+                panic!("gate is broken");
+            }
+            let _ = self.release.recv();
+            self.bus.emit(PulseKind::Status, "gate", "gate passed");
+        }
+    }
+
+    #[test]
+    fn hung_worker_is_reported_without_stalling_the_kernel() {
+        let (release, rx) = mpsc::channel();
+        let broken = Arc::new(AtomicBool::new(false));
+        let mut h = Harness::new();
+        h.add_worker(
+            Box::new(Gate { release: rx, broken: Arc::clone(&broken), bus: h.bus.handle() }),
+            Placement::Thread,
+            Schedule::every(Duration::from_secs(1)).with_timeout(Duration::from_secs(5)),
+        );
+
+        // The first run starts at 1s and blocks; hung at 6s.
+        h.advance(Duration::from_secs(10));
+        assert_eq!(daemon_events(&h, "hung"), 1);
+        assert_eq!(h.count("heartbeat"), 10);
+        let report = h.command("daemons");
+        assert!(report.contains("gate [running]"), "{}", report);
+        assert!(report.contains("degraded: hung for"), "{}", report);
+
+        release.send(()).unwrap();
+        assert!(h.settle(|h| daemon_events(h, "recovered") == 1));
+        assert!(h.settle(|h| h.pulses.iter().any(|p| p.payload.summary().contains("gate passed"))));
+
+        // A panic on the worker thread is a crash like any other.
+        broken.store(true, Ordering::SeqCst);
+        h.advance(Duration::from_secs(1));
+        assert!(h.settle(|h| daemon_events(h, "crashed") == 1));
+        assert!(h.command("daemons").contains("gate is broken"));
+    }
+
+    #[test]
+    fn pool_runs_workers_side_by_side() {
+        let pool = Arc::new(WorkerPool::new("test", 2));
+        let mut h = Harness::new();
+        let mut releases = Vec::new();
+        for _ in 0..2 {
+            let (release, rx) = mpsc::channel();
+            releases.push(release);
+            h.add_worker(
+                Box::new(Gate { release: rx, broken: Arc::new(AtomicBool::new(false)), bus: h.bus.handle() }),
+                Placement::Pool(Arc::clone(&pool)),
+                Schedule::every(Duration::from_secs(1)),
+            );
+        }
+        h.advance(Duration::from_secs(1));
+        for release in &releases {
+            release.send(()).unwrap();
+        }
+        let passed = |h: &Harness| {
+            h.pulses.iter().filter(|p| p.payload.summary().contains("gate passed")).count()
+        };
+        assert!(h.settle(|h| passed(h) == 2));
+    }
+
+    #[test]
+    fn daemons_are_added_and_removed_at_runtime() {
+        let mut h = Harness::new();
+        assert!(h.command("daemon rm sim").contains("requested"));
+        h.advance(Duration::from_secs(1));
+        assert!(!h.command("daemons").contains("sim ["));
+        assert_eq!(daemon_events(&h, "removed"), 1);
+
+        let out = h.command("daemon add sim every=500ms jitter=100ms");
+        assert!(out.contains("daemon sim added :: every 500ms"), "{}", out);
+        h.advance(Duration::from_secs(5));
+        let report = h.command("daemons");
+        assert!(report.contains("sim [running] every 500ms (coalesce) +jitter 100ms"), "{}", report);
+
+        assert!(h.command("daemon add status").contains("already running"));
+        assert!(h.command("daemon add bogus").contains("unknown daemon kind"));
+        assert!(h.command("daemon add telemetry rate=2").contains("no parameter 'rate'"));
+        assert!(h.command("daemon add heartbeat every=soon").contains("invalid duration"));
+        assert!(h.command("daemon rm command").contains("refusing"));
+    }
+
+    #[test]
+    fn daemon_config_is_checked_line_by_line() {
+        let config = DaemonConfig::parse(
+            "# comment\n\
+             heartbeat every=2s missed=skip\n\
+             telemetry every=5s timeout=10s source=sim placement=thread\n\
+             status every=5s telemetry=worker alert_dwell=30s\n",
+        )
+        .unwrap();
+        assert_eq!(config.specs.len(), 3);
+        assert_eq!(config.specs[0].schedule.interval, Some(Duration::from_secs(2)));
+        assert_eq!(config.specs[2].param("alert_dwell"), Some("30s"));
+
+        let err = |text: &str| DaemonConfig::parse(text).unwrap_err();
+        assert!(err("heartbeat\nheartbeat").contains("line 2: heartbeat is listed twice"));
+        assert!(err("command every=1s").contains("always installed"));
+        assert!(err("sim every=0s").contains("greater than zero"));
+        assert!(err("sim missed=later").contains("missed must be"));
+    }
+
+    fn job_lines(h: &Harness, prefix: &str) -> usize {
+        h.pulses
+            .iter()
+            .filter(|p| p.source == "jobs" && p.payload.summary().starts_with(prefix))
+            .count()
+    }
+
+    #[test]
+    fn jobs_run_on_interval_and_cron() {
+        let mut h = Harness::new();
+        // The virtual clock starts at 2023-11-14 22:13:20 UTC.
+        assert!(h.command("job add beat every 10m => mode").contains("added"));
+        assert!(h.command("job add scan cron 0 */2 * * * => @health-scan").contains("added"));
+        assert!(h.command("job add bad every 1h => frobnicate").contains("added"));
+        let report = h.command("jobs");
+        assert!(report.contains("next 2023-11-14 22:23Z"), "{}", report);
+        assert!(report.contains("next 2023-11-15 00:00Z"), "{}", report);
+
+        h.advance(Duration::from_secs(3 * 3600 + 2));
+        assert_eq!(job_lines(&h, "job beat ok"), 18);
+        // Only 00:00 falls inside 22:13-01:13.
+        assert_eq!(job_lines(&h, "job scan"), 1);
+        assert_eq!(job_lines(&h, "job bad failed :: unknown command"), 3);
+
+        let report = h.command("jobs");
+        assert!(report.contains("bad :: every 3600s => frobnicate"), "{}", report);
+        assert!(report.contains("runs 3 :: failures 3"), "{}", report);
+        assert!(report.contains("FAILED: unknown command"), "{}", report);
+
+        assert!(h.command("job run scan").contains("due now"));
+        h.advance(Duration::from_secs(1));
+        assert_eq!(job_lines(&h, "job scan"), 2);
+        assert!(h.command("job rm bad").contains("removed"));
+        assert!(h.command("job add beat every 1h => mode").contains("already exists"));
+        assert!(h.command("job add x cron 61 * * * * => mode").contains("outside 0-59"));
+        assert!(h.command("job add x every 1h => @nope").contains("unknown built-in"));
+    }
+
+    #[test]
+    fn commands_come_from_the_registry() {
+        use crate::kernel::shell::{
+            ArgKind, ArgSpec, BuiltinCommand, CommandRegistry, OutputMode, ResultCode, ShellContext,
+            ShellError,
+        };
+
+        let mut h = Harness::new();
+        let help = h.command("help");
+        // `logs` and `jobs` are contributed by their own modules.
+        assert!(help.contains("logs") && help.contains("jobs"), "{}", help);
+        assert!(help.contains("(also: setpoints)"), "{}", help);
+        let damage = h.command("help damage");
+        assert!(damage.contains("usage: damage <organ> <amount>"), "{}", damage);
+        assert!(damage.contains(" - amount :: number"), "{}", damage);
+
+        assert!(h.command("damage cortex").starts_with("usage: damage"));
+        assert!(h.command("damage brain 0.1").starts_with("unknown organ 'brain'"));
+        assert!(h.command("damage cortex lots").starts_with("invalid amount: lots"));
+        assert!(h.command("sim level max").starts_with("unknown level 'max'"));
+        assert!(h.command("daemon pause sim").starts_with("unknown subcommand 'pause'"));
+        assert!(h.command("status now").starts_with("usage: status"));
+        assert!(h.command("setpoints").starts_with("Homeostasis"));
+        assert!(h.command("frobnicate").starts_with("unknown command"));
+
+        const ECHO_ARGS: &[ArgSpec] = &[
+            ArgSpec::required("word", ArgKind::Word),
+            ArgSpec::optional("more", ArgKind::Rest),
+        ];
+        let mut r = CommandRegistry::standard();
+        r.register(Box::new(BuiltinCommand {
+            name: "echo",
+            aliases: &["say"],
+            usage: "echo <word> [more]",
+            help: "repeat the arguments",
+            args: ECHO_ARGS,
+            run: |_, argv| Ok(argv[1..].join(" ")),
+        }));
+        // The code comes from the error, not from how its text reads.
+        const FUSSY_ARGS: &[ArgSpec] = &[ArgSpec::optional("word", ArgKind::Word)];
+        r.register(Box::new(BuiltinCommand {
+            name: "fussy",
+            aliases: &[],
+            usage: "fussy [word]",
+            help: "fail one way or another",
+            args: FUSSY_ARGS,
+            run: |_, argv| match argv.get(1) {
+                Some(_) => Err("usage: is not what this is".into()),
+                None => Err(ShellError {
+                    code: ResultCode::Usage,
+                    message: "give fussy a word".to_string(),
+                }),
+            },
+        }));
+        let mut watches = Vec::new();
+        let mut run = |line: &str| {
+            let mut ctx = ShellContext {
+                state: &h.state,
+                bus: &mut h.bus,
+                line,
+                watches: &mut watches,
+                output: OutputMode::Text,
+                quit: false,
+            };
+            r.dispatch(&mut ctx)
+        };
+        assert_eq!(run("say hello there").into_result(), Ok("hello there".to_string()));
+        assert_eq!(run("echo").into_result(), Err("usage: echo <word> [more]".to_string()));
+        assert_eq!(run("fussy").code, ResultCode::Usage);
+        assert_eq!(run("fussy x").code, ResultCode::Failed);
+        assert!(r.help().contains("echo"));
+    }
+
+    #[test]
+    fn lines_are_chained_and_piped() {
+        let mut h = Harness::new();
+        h.command(r#"mem set motd "hello world""#);
+        assert_eq!(h.command("mem get motd"), r#"mem[motd] = "hello world""#);
+
+        let out = h.command("damage cortex 0.9; organs | grep Cortex");
+        assert!(out.starts_with("damaged Cortex"), "{}", out);
+        assert!(out.ends_with("\n - Organ Cortex on Node 1 (health 0.08)"), "{}", out);
+        assert_eq!(h.command("organs | head 3 | count"), "3");
+        assert_eq!(h.command("organs | grep -v 'Organ '"), "Organs:");
+        assert!(h.command("heal brain 1 && awareness").starts_with("unknown organ"));
+        assert!(h.command("heal brain 1; awareness").contains("awareness index"));
+        assert!(h.command("organs | grep Cortex && awareness").contains("awareness index"));
+        assert_eq!(h.command("organs | grep Pancreas && awareness"), "");
+        assert!(h.command("organs |").starts_with("syntax error"));
+        assert!(h.command("organs | sort").starts_with("unknown filter 'sort'"));
+
+        assert!(h.command(r#"job add both every 1h => "mode; awareness""#).contains("added"));
+        assert!(h.command("job add greet every 1h => mem set motd 'hi there'").contains("added"));
+        let jobs = h.command("jobs");
+        assert!(jobs.contains("=> mode; awareness"), "{}", jobs);
+        assert!(jobs.contains(r#"=> mem set motd "hi there""#), "{}", jobs);
+    }
+
+    #[test]
+    fn results_carry_codes_and_render_as_json() {
+        use crate::json::JsonValue;
+        use crate::kernel::shell::{OutputMode, ResultCode};
+
+        let mut h = Harness::new();
+        let result = |h: &mut Harness, line: &str| {
+            let rx = h.state.shell.call(line);
+            h.advance(Duration::ZERO);
+            rx.try_recv().expect("no result")
+        };
+
+        let r = result(&mut h, "mem set x 1 && frobnicate && status");
+        assert_eq!(r.code, ResultCode::UnknownCommand);
+        assert_eq!(r.code.exit_code(), 2);
+        let codes: Vec<_> = r.steps.iter().map(|s| s.code).collect();
+        assert_eq!(codes, [ResultCode::Ok, ResultCode::UnknownCommand, ResultCode::Skipped]);
+
+        let json = JsonValue::parse(&r.to_json()).unwrap();
+        assert_eq!(json.get("ok"), Some(&JsonValue::Bool(false)));
+        assert_eq!(json.get("code").and_then(JsonValue::as_str), Some("unknown_command"));
+        assert!(json.get("error").and_then(JsonValue::as_str).unwrap().starts_with("unknown command"));
+        assert_eq!(json.get("results").and_then(JsonValue::as_array).map(|a| a.len()), Some(3));
+
+        assert_eq!(result(&mut h, "damage cortex lots").code, ResultCode::InvalidArgument);
+        assert_eq!(result(&mut h, "sim level").code, ResultCode::Usage);
+        assert_eq!(result(&mut h, "organs | grep nothing-here").code, ResultCode::NoMatch);
+        assert_eq!(result(&mut h, "status |").code, ResultCode::Syntax);
+        assert_eq!(result(&mut h, "--json status").mode, OutputMode::Json);
+
+        // The console keeps its mode; other callers have their own.
+        assert_eq!(h.command("output json"), "output: json");
+        assert_eq!(result(&mut h, "status").mode, OutputMode::Text);
+        assert_eq!(h.command("output"), "output: json");
+        let bad = result(&mut h, "output xml");
+        assert_eq!(bad.code, ResultCode::InvalidArgument);
+        assert_eq!(h.command("output xml"), "unknown mode 'xml' (expected text|json)");
+        assert_eq!(h.command("output"), "output: json");
+    }
+
+    #[test]
+    fn assert_checks_metrics() {
+        let mut h = Harness::new();
+        let health = h.command("assert health cortex > 0.5");
+        assert!(health.starts_with("ok: health cortex > 0.5"), "{}", health);
+        h.command("damage cortex 0.6");
+        let failed = h.command("assert health.cortex > 0.5");
+        assert!(failed.starts_with("assertion failed"), "{}", failed);
+        assert!(h.command("assert health cortex ~ 1").starts_with("bad operator"));
+    }
+
+    #[test]
+    fn control_clients_authenticate_and_keep_their_own_mode() {
+        use crate::json::JsonValue;
+        use std::io::{BufRead, BufReader, Write};
+        use std::os::unix::net::UnixStream;
+
+        let mut h = Harness::new();
+        let (_, socket) = h.start_control("control");
+
+        // Each client sends its lines and reads back `replies` lines.
+        let client = |lines: &'static [&'static str], replies: usize| {
+            let socket = socket.clone();
+            std::thread::spawn(move || {
+                let mut stream = UnixStream::connect(socket).unwrap();
+                for line in lines {
+                    writeln!(stream, "{}", line).unwrap();
+                }
+                BufReader::new(stream).lines().take(replies).map(Result::unwrap).collect::<Vec<_>>()
+            })
+        };
+        let a = client(&["status", "auth s3cret", "output json", "mem get nope"], 6);
+        let b = client(&["auth s3cret", "mem set k v && mem get k"], 5);
+        assert!(h.settle(|_| a.is_finished() && b.is_finished()));
+        let (a, b) = (a.join().unwrap(), b.join().unwrap());
+
+        assert_eq!(a[..3], ["error auth 1", "authenticate first: auth <token>", "ok 1"]);
+        assert!(a[3].starts_with("session "));
+        let failed = JsonValue::parse(&a[5]).unwrap();
+        assert_eq!(failed.get("code").and_then(JsonValue::as_str), Some("failed"));
+
+        // `output json` in one session leaves the other in text.
+        assert_eq!(b[2], "ok 2");
+        assert_eq!(b[4], r#"mem[k] = "v""#);
+        let _ = std::fs::remove_file(&socket);
+    }
+
+    #[test]
+    fn closed_stdin_is_only_a_fault_without_a_control_listener() {
+        let mut h = Harness::new();
+        h.shell.close_input();
+        h.advance(Duration::from_secs(1));
+        assert!(h.command("daemons").contains("command input closed"));
+
+        let (_, socket) = h.start_control("stdin");
+        h.advance(Duration::from_secs(1));
+        let report = h.command("daemons");
+        assert!(!report.contains("command input closed"), "{}", report);
+        let _ = std::fs::remove_file(&socket);
+    }
+
+    #[test]
+    fn control_client_exports_a_snapshot() {
+        use crate::json::JsonValue;
+        use crate::kernel::control::ControlClient;
+
+        let mut h = Harness::new();
+        let (config, socket) = h.start_control("client");
+
+        let client = std::thread::spawn(move || {
+            let mut client = ControlClient::connect(&config).unwrap();
+            (client.call("snapshot").unwrap(), client.call("mem get nope").unwrap())
+        });
+        assert!(h.settle(|_| client.is_finished()));
+        let (snapshot, missing) = client.join().unwrap();
+
+        assert_eq!(missing.get("exit").and_then(JsonValue::as_f64), Some(1.0));
+        let output = snapshot.get("output").and_then(JsonValue::as_str).unwrap();
+        let state = JsonValue::parse(output).unwrap();
+        assert_eq!(state.get("organs").and_then(JsonValue::as_array).map(<[_]>::len), Some(3));
+        assert!(state.get("health").and_then(|h| h.get("score")).is_some());
+        // What `aion snapshot export` writes reads back the same.
+        assert_eq!(JsonValue::parse(&state.to_json()).unwrap(), state);
+        let _ = std::fs::remove_file(&socket);
+    }
+}
@@ -0,0 +1,167 @@
+//! Pulse history: a bounded ring buffer of recent pulses.
+//!
+//! Heartbeats are sampled (one in `heartbeat_every`, 0 = none kept) so
+//! they don't crowd everything else out of the buffer.
+
+use std::collections::VecDeque;
+
+use crate::kernel::{Pulse, PulseFilter, PulseKind};
+
+pub const DEFAULT_CAPACITY: usize = 1000;
+pub const DEFAULT_HEARTBEAT_EVERY: u64 = 10;
+
+/// Lower bound for a history query.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Since {
+    Any,
+    /// Pulses with an ID greater than this one (for polling).
+    Id(u64),
+    /// Pulses at or after this Unix timestamp in milliseconds.
+    Millis(u64),
+}
+
+impl Since {
+    /// Parse a shell `--since` argument: `30s`, `5m`, `1h` (relative to
+    /// `now_ms`), or a bare pulse ID.
+    pub fn parse(s: &str, now_ms: u64) -> Option<Since> {
+        let (num, unit_ms) = match s.char_indices().last()? {
+            (i, 's') => (&s[..i], 1_000),
+            (i, 'm') => (&s[..i], 60_000),
+            (i, 'h') => (&s[..i], 3_600_000),
+            _ => return s.trim_start_matches('#').parse().ok().map(Since::Id),
+        };
+        let n: f64 = num.parse().ok().filter(|n: &f64| *n >= 0.0)?;
+        Some(Since::Millis(now_ms.saturating_sub((n * unit_ms as f64) as u64)))
+    }
+
+    pub fn admits(self, pulse: &Pulse) -> bool {
+        match self {
+            Since::Any => true,
+            Since::Id(id) => pulse.id > id,
+            Since::Millis(ms) => pulse.timestamp_ms >= ms,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PulseHistory {
+    buf: VecDeque<Pulse>,
+    capacity: usize,
+    heartbeat_every: u64,
+    heartbeats_seen: u64,
+}
+
+impl PulseHistory {
+    pub fn new(capacity: usize, heartbeat_every: u64) -> Self {
+        Self {
+            buf: VecDeque::with_capacity(capacity.min(DEFAULT_CAPACITY)),
+            capacity: capacity.max(1),
+            heartbeat_every,
+            heartbeats_seen: 0,
+        }
+    }
+
+    /// Size from `AION_PULSE_HISTORY`, heartbeat sampling from
+    /// `AION_PULSE_HEARTBEATS` (keep one in N, 0 = none).
+    pub fn from_env() -> Self {
+        let env = |key: &str| std::env::var(key).ok().and_then(|v| v.parse().ok());
+        Self::new(
+            env("AION_PULSE_HISTORY").unwrap_or(DEFAULT_CAPACITY as u64) as usize,
+            env("AION_PULSE_HEARTBEATS").unwrap_or(DEFAULT_HEARTBEAT_EVERY),
+        )
+    }
+
+    pub fn record(&mut self, pulse: &Pulse) {
+        if pulse.kind == PulseKind::Heartbeat {
+            self.heartbeats_seen += 1;
+            let keep = self.heartbeat_every > 0
+                && (self.heartbeats_seen - 1).is_multiple_of(self.heartbeat_every);
+            if !keep {
+                return;
+            }
+        }
+        if self.buf.len() == self.capacity {
+            self.buf.pop_front();
+        }
+        self.buf.push_back(pulse.clone());
+    }
+
+    /// The newest `limit` pulses matching `filter` and `since`, oldest first.
+    pub fn query(&self, filter: &PulseFilter, since: Since, limit: usize) -> Vec<Pulse> {
+        let mut out: Vec<Pulse> = self
+            .buf
+            .iter()
+            .rev()
+            .filter(|p| since.admits(p) && filter.matches(p))
+            .take(limit)
+            .cloned()
+            .collect();
+        out.reverse();
+        out
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::PulsePayload;
+
+    fn pulse(id: u64, kind: PulseKind) -> Pulse {
+        Pulse::new(id, id * 1000, kind, "test", PulsePayload::Heartbeat { beat: id })
+    }
+
+    #[test]
+    fn since_parses_durations_and_ids() {
+        let now = 100_000;
+        assert_eq!(Since::parse("30s", now), Some(Since::Millis(70_000)));
+        assert_eq!(Since::parse("1.5m", now), Some(Since::Millis(10_000)));
+        assert_eq!(Since::parse("2h", now), Some(Since::Millis(0)));
+        assert_eq!(Since::parse("42", now), Some(Since::Id(42)));
+        assert_eq!(Since::parse("#42", now), Some(Since::Id(42)));
+        for bad in ["", "s", "-5s", "NaNm", "10ms", "soon", "4.2"] {
+            assert_eq!(Since::parse(bad, now), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn since_bounds_are_exclusive_for_ids_and_inclusive_for_times() {
+        let p = pulse(5, PulseKind::Status);
+        assert!(Since::Any.admits(&p));
+        assert!(Since::Id(4).admits(&p) && !Since::Id(5).admits(&p));
+        assert!(Since::Millis(5000).admits(&p) && !Since::Millis(5001).admits(&p));
+    }
+
+    #[test]
+    fn history_is_bounded_and_samples_heartbeats() {
+        let mut history = PulseHistory::new(4, 3);
+        for id in 1..=7 {
+            history.record(&pulse(id, PulseKind::Heartbeat));
+        }
+        // Heartbeats 1, 4 and 7 are kept.
+        let ids = |ps: Vec<Pulse>| ps.iter().map(|p| p.id).collect::<Vec<_>>();
+        assert_eq!(ids(history.query(&PulseFilter::default(), Since::Any, 10)), [1, 4, 7]);
+
+        for id in 8..=10 {
+            history.record(&pulse(id, PulseKind::Status));
+        }
+        assert_eq!(history.len(), history.capacity());
+        assert_eq!(ids(history.query(&PulseFilter::default(), Since::Any, 10)), [7, 8, 9, 10]);
+        assert_eq!(ids(history.query(&PulseFilter::default(), Since::Id(7), 2)), [9, 10]);
+        let status = PulseFilter::parse(["kind=status"]).unwrap();
+        assert_eq!(ids(history.query(&status, Since::Millis(9000), 10)), [9, 10]);
+
+        let mut none = PulseHistory::new(0, 0);
+        none.record(&pulse(1, PulseKind::Heartbeat));
+        none.record(&pulse(2, PulseKind::Status));
+        none.record(&pulse(3, PulseKind::Status));
+        assert_eq!(ids(none.query(&PulseFilter::default(), Since::Any, 10)), [3]);
+    }
+}
@@ -0,0 +1,614 @@
+//! Scheduled maintenance jobs.
+//!
+//! A job runs on a fixed interval or a cron expression and either runs
+//! a built-in routine (`@health-scan`) or a shell command line through
+//! the command daemon. One job per line, in AION_JOBS files and in
+//! `job add`:
+//!
+//! ```text
+//! checkpoint   every 1h          => save state
+//! deep-scan    cron 0 3 * * *    => @health-scan
+//! ```
+//!
+//! Cron expressions have five fields (minute, hour, day of month, month,
+//! day of week) with `*`, lists, ranges and `/step`, or one of @hourly,
+//! @daily, @weekly, @monthly. Times are UTC.
+
+use std::fs;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::kernel::registry::parse_duration;
+use crate::kernel::shell::{
+    quote, ArgKind, ArgSpec, BuiltinCommand, CommandRegistry, ShellContext, ShellError, ShellResult,
+};
+use crate::kernel::{
+    classify_health, compute_overall_health, lock, Bus, Daemon, KernelState, PulseKind, ShellHandle,
+};
+use crate::organism::SystemTopology;
+
+const MINUTE_MS: u64 = 60_000;
+const DAY_MINUTES: u64 = 24 * 60;
+
+/// Built-in routines, by the name used after `@`.
+pub const BUILTIN_JOBS: &[(&str, &str)] = &[(
+    "health-scan",
+    "check every organ and report those that are not ok",
+)];
+
+/// A set of allowed values per field, as bits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronExpr {
+    text: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether day of month / day of week were restricted, for the usual
+    /// cron rule: if both are, either may match.
+    days_any: bool,
+    weekdays_any: bool,
+}
+
+fn parse_field(field: &str, min: u64, max: u64) -> Result<u64, String> {
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((r, s)) => (
+                r,
+                s.parse::<u64>()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| format!("bad step in '{}'", part))?,
+            ),
+            None => (part, 1),
+        };
+        let num = |s: &str| {
+            s.parse::<u64>()
+                .ok()
+                .filter(|n| (min..=max).contains(n))
+                .ok_or_else(|| format!("'{}' is outside {}-{}", s, min, max))
+        };
+        let (lo, hi) = match range {
+            "*" => (min, max),
+            r => match r.split_once('-') {
+                Some((a, b)) => (num(a)?, num(b)?),
+                None if step > 1 => (num(r)?, max),
+                None => {
+                    let n = num(r)?;
+                    (n, n)
+                }
+            },
+        };
+        if lo > hi {
+            return Err(format!("empty range '{}'", range));
+        }
+        for v in (lo..=hi).step_by(step as usize) {
+            bits |= 1 << v;
+        }
+    }
+    Ok(bits)
+}
+
+impl CronExpr {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let expanded = match text {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!(
+                "cron needs 5 fields (minute hour day month weekday), got '{}'",
+                text
+            ));
+        }
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        // 7 is Sunday too.
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            text: text.to_string(),
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays,
+            days_any: fields[2] == "*",
+            weekdays_any: fields[4] == "*",
+        })
+    }
+
+    fn day_matches(&self, days_since_epoch: u64) -> bool {
+        let (_, month, day) = civil_from_days(days_since_epoch);
+        if self.months & (1 << month) == 0 {
+            return false;
+        }
+        let dom = self.days & (1 << day) != 0;
+        let dow = self.weekdays & (1 << weekday(days_since_epoch)) != 0;
+        match (self.days_any, self.weekdays_any) {
+            (false, false) => dom || dow,
+            _ => dom && dow,
+        }
+    }
+
+    /// The first matching minute strictly after `after_ms`, within a
+    /// few years.
+    pub fn next_after(&self, after_ms: u64) -> Option<u64> {
+        let mut minute = after_ms / MINUTE_MS + 1;
+        let limit = minute + 5 * 366 * DAY_MINUTES;
+        while minute < limit {
+            let day = minute / DAY_MINUTES;
+            if !self.day_matches(day) {
+                minute = (day + 1) * DAY_MINUTES;
+                continue;
+            }
+            let of_day = minute % DAY_MINUTES;
+            if self.hours & (1 << (of_day / 60)) != 0 && self.minutes & (1 << (of_day % 60)) != 0 {
+                return Some(minute * MINUTE_MS);
+            }
+            minute += 1;
+        }
+        None
+    }
+}
+
+/// Days since 1970-01-01 to (year, month, day), proleptic Gregorian.
+fn civil_from_days(days: u64) -> (i64, u64, u64) {
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u64;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u64;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// 0 = Sunday. 1970-01-01 was a Thursday.
+fn weekday(days: u64) -> u64 {
+    (days + 4) % 7
+}
+
+/// `2024-03-01 03:00Z`
+pub fn format_utc(ms: u64) -> String {
+    let minutes = ms / MINUTE_MS;
+    let (y, m, d) = civil_from_days(minutes / DAY_MINUTES);
+    let of_day = minutes % DAY_MINUTES;
+    format!("{:04}-{:02}-{:02} {:02}:{:02}Z", y, m, d, of_day / 60, of_day % 60)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobSchedule {
+    Every(Duration),
+    Cron(CronExpr),
+}
+
+impl JobSchedule {
+    fn next_after(&self, ms: u64) -> Option<u64> {
+        match self {
+            JobSchedule::Every(d) => Some(ms.saturating_add(d.as_millis() as u64)),
+            JobSchedule::Cron(c) => c.next_after(ms),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            JobSchedule::Every(d) if d.subsec_millis() == 0 && !d.is_zero() => {
+                format!("every {}s", d.as_secs())
+            }
+            JobSchedule::Every(d) => format!("every {}ms", d.as_millis()),
+            JobSchedule::Cron(c) => format!("cron {}", c.text),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobAction {
+    Builtin(&'static str),
+    Command(String),
+}
+
+impl JobAction {
+    pub fn describe(&self) -> String {
+        match self {
+            JobAction::Builtin(name) => format!("@{}", name),
+            JobAction::Command(line) => line.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub name: String,
+    pub schedule: JobSchedule,
+    pub action: JobAction,
+    /// Unix milliseconds, on the kernel clock.
+    pub next_run: Option<u64>,
+    pub last_run: Option<u64>,
+    pub last_outcome: Option<Result<String, String>>,
+    pub running: bool,
+    pub runs: u64,
+    pub failures: u64,
+}
+
+impl Job {
+    /// `<name> <every <dur> | cron <expr> | @daily ..> => <action>`
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let usage = "expected '<name> every <duration> => <action>' or '<name> cron <m h dom mon dow> => <action>'";
+        let (lhs, action) = line.split_once("=>").ok_or_else(|| usage.to_string())?;
+        let mut words = lhs.split_whitespace();
+        let name = words.next().ok_or_else(|| usage.to_string())?.to_string();
+        let schedule = match words.next() {
+            Some("every") => {
+                let d = parse_duration(words.next().ok_or_else(|| usage.to_string())?)?;
+                if d.is_zero() {
+                    return Err("interval must be greater than zero".to_string());
+                }
+                JobSchedule::Every(d)
+            }
+            Some("cron") => JobSchedule::Cron(CronExpr::parse(&words.by_ref().collect::<Vec<_>>().join(" "))?),
+            Some(m) if m.starts_with('@') => JobSchedule::Cron(CronExpr::parse(m)?),
+            _ => return Err(usage.to_string()),
+        };
+        if words.next().is_some() {
+            return Err(usage.to_string());
+        }
+
+        let action = action.trim();
+        let action = match action.strip_prefix('@') {
+            Some(b) => JobAction::Builtin(
+                BUILTIN_JOBS
+                    .iter()
+                    .find(|(n, _)| *n == b)
+                    .map(|(n, _)| *n)
+                    .ok_or_else(|| format!("unknown built-in job '@{}'", b))?,
+            ),
+            None if action.is_empty() => return Err("missing action".to_string()),
+            None => JobAction::Command(action.to_string()),
+        };
+        Ok(Self {
+            name,
+            schedule,
+            action,
+            next_run: None,
+            last_run: None,
+            last_outcome: None,
+            running: false,
+            runs: 0,
+            failures: 0,
+        })
+    }
+}
+
+/// Every job, shared by the jobs daemon and the shell.
+#[derive(Debug, Default)]
+pub struct JobTable {
+    jobs: Vec<Job>,
+}
+
+impl JobTable {
+    /// One job per line; `#` comments.
+    pub fn load(&mut self, path: &str, now_ms: u64) -> Result<usize, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read jobs file {}: {}", path, e))?;
+        self.load_text(&content, now_ms)
+            .map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn load_text(&mut self, text: &str, now_ms: u64) -> Result<usize, String> {
+        let mut count = 0;
+        for (lineno, raw) in text.lines().enumerate() {
+            let line = raw.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let job = Job::parse(line).map_err(|e| format!("line {}: {}", lineno + 1, e))?;
+            self.add(job, now_ms)
+                .map_err(|e| format!("line {}: {}", lineno + 1, e))?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    pub fn add(&mut self, mut job: Job, now_ms: u64) -> Result<(), String> {
+        if self.jobs.iter().any(|j| j.name == job.name) {
+            return Err(format!("job '{}' already exists", job.name));
+        }
+        job.next_run = job.schedule.next_after(now_ms);
+        self.jobs.push(job);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.jobs.len();
+        self.jobs.retain(|j| j.name != name);
+        self.jobs.len() != before
+    }
+
+    /// Make a job due now.
+    pub fn run_now(&mut self, name: &str, now_ms: u64) -> bool {
+        match self.jobs.iter_mut().find(|j| j.name == name) {
+            Some(job) => {
+                job.next_run = Some(now_ms);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Start every due job that isn't still running, and schedule its
+    /// next run.
+    fn start_due(&mut self, now_ms: u64) -> Vec<(String, JobAction)> {
+        let mut due = Vec::new();
+        for job in &mut self.jobs {
+            if job.running || job.next_run.is_none_or(|t| t > now_ms) {
+                continue;
+            }
+            job.running = true;
+            job.last_run = Some(now_ms);
+            job.next_run = job.schedule.next_after(now_ms);
+            due.push((job.name.clone(), job.action.clone()));
+        }
+        due
+    }
+
+    fn finish(&mut self, name: &str, outcome: Result<String, String>) {
+        if let Some(job) = self.jobs.iter_mut().find(|j| j.name == name) {
+            job.running = false;
+            job.runs += 1;
+            if outcome.is_err() {
+                job.failures += 1;
+            }
+            job.last_outcome = Some(outcome);
+        }
+    }
+
+    pub fn report(&self, now_ms: u64) -> String {
+        if self.jobs.is_empty() {
+            return "no jobs (add one with `job add <name> every <duration> => <command>`)".to_string();
+        }
+        let mut out = format!("Jobs ({}), times UTC:\n", self.jobs.len());
+        for j in &self.jobs {
+            let next = match j.next_run {
+                Some(t) => format!(
+                    "{} (in {}s)",
+                    format_utc(t),
+                    t.saturating_sub(now_ms) / 1000
+                ),
+                None => "never".to_string(),
+            };
+            out.push_str(&format!(
+                " - {} :: {} => {} :: next {} :: runs {} :: failures {}\n",
+                j.name,
+                j.schedule.describe(),
+                j.action.describe(),
+                next,
+                j.runs,
+                j.failures
+            ));
+            let last = match (&j.last_run, &j.last_outcome, j.running) {
+                (Some(t), _, true) => format!("run started {}, still running", format_utc(*t)),
+                (Some(t), Some(Ok(out)), _) => format!("{} ok: {}", format_utc(*t), first_line(out)),
+                (Some(t), Some(Err(e)), _) => format!("{} FAILED: {}", format_utc(*t), first_line(e)),
+                _ => continue,
+            };
+            out.push_str(&format!("     last {}\n", last));
+        }
+        out
+    }
+}
+
+fn first_line(s: &str) -> &str {
+    s.lines().next().unwrap_or("")
+}
+
+/// Runs due jobs. Built-ins run inline; command lines go to the command
+/// daemon and their output is collected on a later tick.
+pub struct JobDaemon {
+    jobs: Arc<Mutex<JobTable>>,
+    shell: ShellHandle,
+    topology: Arc<Mutex<SystemTopology>>,
+    pending: Vec<(String, Receiver<ShellResult>)>,
+}
+
+impl JobDaemon {
+    pub fn new(state: &KernelState) -> Self {
+        Self {
+            jobs: Arc::clone(&state.jobs),
+            shell: state.shell.clone(),
+            topology: Arc::clone(&state.topology),
+            pending: Vec::new(),
+        }
+    }
+
+    fn run_builtin(&self, name: &str) -> Result<String, String> {
+        match name {
+            "health-scan" => {
+                let topo = lock(&self.topology);
+                let weak: Vec<String> = topo
+                    .organs
+                    .iter()
+                    .filter(|o| classify_health(o.health) != "ok")
+                    .map(|o| format!("{:?} {:.2} ({})", o.kind, o.health, classify_health(o.health)))
+                    .collect();
+                let overall = compute_overall_health(&topo);
+                if weak.is_empty() {
+                    Ok(format!("{} organs ok, overall {:.2}", topo.organs.len(), overall))
+                } else {
+                    Err(format!("overall {:.2}; not ok: {}", overall, weak.join(", ")))
+                }
+            }
+            other => Err(format!("unknown built-in job '@{}'", other)),
+        }
+    }
+
+    fn finish(&self, name: &str, outcome: Result<String, String>, bus: &mut Bus) {
+        let summary = match &outcome {
+            Ok(out) => format!("job {} ok :: {}", name, first_line(out)),
+            Err(e) => format!("job {} failed :: {}", name, first_line(e)),
+        };
+        lock(&self.jobs).finish(name, outcome);
+        bus.emit(PulseKind::Status, self.name(), summary);
+    }
+}
+
+impl Daemon for JobDaemon {
+    fn name(&self) -> &'static str {
+        "jobs"
+    }
+
+    fn tick(&mut self, _now: Instant, bus: &mut Bus) {
+        let mut finished = Vec::new();
+        self.pending.retain(|(name, rx)| match rx.try_recv() {
+            Ok(result) => {
+                finished.push((name.clone(), result.into_result()));
+                false
+            }
+            Err(TryRecvError::Empty) => true,
+            Err(TryRecvError::Disconnected) => {
+                finished.push((name.clone(), Err("command produced no output".to_string())));
+                false
+            }
+        });
+        for (name, outcome) in finished {
+            self.finish(&name, outcome, bus);
+        }
+
+        let now_ms = bus.clock().unix_millis();
+        let due = lock(&self.jobs).start_due(now_ms);
+        for (name, action) in due {
+            match action {
+                JobAction::Builtin(b) => {
+                    let outcome = self.run_builtin(b);
+                    self.finish(&name, outcome, bus);
+                }
+                JobAction::Command(line) => {
+                    let rx = self.shell.call(line);
+                    self.pending.push((name, rx));
+                }
+            }
+        }
+    }
+
+    /// Commands already handed to the shell can't be called back; their
+    /// jobs are marked failed so they run again on schedule.
+    fn stop(&mut self, bus: &mut Bus) {
+        for (name, _) in std::mem::take(&mut self.pending) {
+            self.finish(&name, Err("jobs daemon stopped before the command finished".to_string()), bus);
+        }
+    }
+}
+
+const JOB_USAGE: &str = "job add <name> every <duration> => <command|@builtin> | \
+                         job add <name> cron <m h dom mon dow> => <command|@builtin> | job rm|run <name>";
+
+const COMMANDS: &[BuiltinCommand] = &[
+    BuiltinCommand {
+        name: "jobs",
+        aliases: &[],
+        usage: "jobs",
+        help: "scheduled jobs with their next and last runs",
+        args: &[],
+        run: list_jobs,
+    },
+    BuiltinCommand {
+        name: "job",
+        aliases: &[],
+        usage: JOB_USAGE,
+        help: "add, remove or run a scheduled job",
+        args: &[
+            ArgSpec::required("subcommand", ArgKind::Choice(&["add", "rm", "run"])),
+            ArgSpec::required("name", ArgKind::Word),
+            ArgSpec::optional("schedule", ArgKind::Rest),
+        ],
+        run: job,
+    },
+];
+
+pub fn register_commands(r: &mut CommandRegistry) {
+    r.register_all(COMMANDS);
+}
+
+fn list_jobs(ctx: &mut ShellContext, _argv: &[&str]) -> Result<String, ShellError> {
+    Ok(lock(&ctx.state.jobs).report(ctx.bus.clock().unix_millis()))
+}
+
+fn job(ctx: &mut ShellContext, argv: &[&str]) -> Result<String, ShellError> {
+    let now_ms = ctx.bus.clock().unix_millis();
+    let mut jobs = lock(&ctx.state.jobs);
+    match argv[1] {
+        "add" => {
+            // A single word after `=>` is the whole command line, so
+            // `=> "save state; jobs"` chains; several words are re-quoted.
+            let spec = match argv.iter().position(|w| *w == "=>") {
+                Some(i) if argv.len() == i + 2 => format!("{} => {}", argv[2..i].join(" "), argv[i + 1]),
+                _ => argv[2..].iter().map(|w| quote(w)).collect::<Vec<_>>().join(" "),
+            };
+            let job = Job::parse(&spec)?;
+            let name = job.name.clone();
+            jobs.add(job, now_ms)?;
+            Ok(format!("job {} added", name))
+        }
+        "rm" if argv.len() == 3 => match jobs.remove(argv[2]) {
+            true => Ok(format!("job {} removed", argv[2])),
+            false => Err(format!("no job named '{}'", argv[2]).into()),
+        },
+        "run" if argv.len() == 3 => {
+            if !jobs.run_now(argv[2], now_ms) {
+                return Err(format!("no job named '{}'", argv[2]).into());
+            }
+            if let Some(waker) = lock(&ctx.state.daemons).waker_for("jobs") {
+                waker.wake();
+            }
+            Ok(format!("job {} is due now", argv[2]))
+        }
+        _ => Err(ShellError::usage(JOB_USAGE)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cron_matches_the_calendar() {
+        // 2024-02-28 23:59 UTC, a Wednesday, in a leap year.
+        let start = 1_709_164_740_000;
+        let next = |expr: &str| format_utc(CronExpr::parse(expr).unwrap().next_after(start).unwrap());
+        assert_eq!(next("* * * * *"), "2024-02-29 00:00Z");
+        assert_eq!(next("30 3 * * *"), "2024-02-29 03:30Z");
+        assert_eq!(next("0 0 1 * *"), "2024-03-01 00:00Z");
+        assert_eq!(next("0 12 * * 7"), "2024-03-03 12:00Z");
+        assert_eq!(next("0 9 * * 1-5"), "2024-02-29 09:00Z");
+        // Day of month and day of week both set: either matches.
+        assert_eq!(next("0 0 15 * 5"), "2024-03-01 00:00Z");
+        assert_eq!(next("@weekly"), "2024-03-03 00:00Z");
+        assert!(CronExpr::parse("0 0 * *").is_err());
+        assert!(CronExpr::parse("0 0 30-10 * *").is_err());
+    }
+
+    #[test]
+    fn default_jobs_only_run_built_in_routines() {
+        for line in crate::kernel::DEFAULT_JOBS.lines() {
+            let job = Job::parse(line).unwrap();
+            assert!(matches!(job.action, JobAction::Builtin(_)), "{}", line);
+        }
+    }
+
+    #[test]
+    fn intervals_describe_in_seconds_or_milliseconds() {
+        let every = |ms| JobSchedule::Every(Duration::from_millis(ms)).describe();
+        assert_eq!(every(250), "every 250ms");
+        assert_eq!(every(1500), "every 1500ms");
+        assert_eq!(every(60_000), "every 60s");
+    }
+}
@@ -0,0 +1,740 @@
+//! Log sinks: where bus pulses get written.
+//!
+//! Every pulse the bus delivers is offered to each enabled sink whose
+//! `SinkFilter` matches it. Three sinks ship with the kernel: the console
+//! (the classic `[BUS]` lines, or JSON), a JSON Lines file that rotates
+//! by size, and syslog over a local Unix datagram socket.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::kernel::editor;
+use crate::kernel::pulse::Severity;
+use crate::kernel::shell::{ArgKind, ArgSpec, BuiltinCommand, CommandRegistry, ShellContext, ShellError};
+use crate::kernel::{Pulse, PulseFilter, PulseKind};
+
+pub const DEFAULT_SYSLOG_SOCKET: &str = "/dev/log";
+
+/// Console presets, kept as shorthand for the common cases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFilter {
+    All,
+    CommandsOnly,
+    Silent,
+}
+
+impl LogFilter {
+    /// The equivalent sink filter; `None` means the sink is switched off.
+    pub fn sink_filter(self) -> Option<SinkFilter> {
+        match self {
+            LogFilter::All => Some(SinkFilter::default()),
+            LogFilter::CommandsOnly => Some(SinkFilter {
+                pulses: PulseFilter {
+                    kinds: vec![PulseKind::Command, PulseKind::Reflex],
+                    ..PulseFilter::default()
+                },
+                min_severity: Severity::Debug,
+            }),
+            LogFilter::Silent => None,
+        }
+    }
+}
+
+/// Which pulses a sink writes: a pulse filter plus a minimum severity.
+#[derive(Debug, Clone)]
+pub struct SinkFilter {
+    pub pulses: PulseFilter,
+    pub min_severity: Severity,
+}
+
+impl Default for SinkFilter {
+    fn default() -> Self {
+        Self {
+            pulses: PulseFilter::default(),
+            min_severity: Severity::Debug,
+        }
+    }
+}
+
+impl SinkFilter {
+    /// Parse `kind=`, `source=`, `type=` and `severity=` terms.
+    pub fn parse<'a>(terms: impl IntoIterator<Item = &'a str>) -> Result<Self, String> {
+        let mut min_severity = Severity::Debug;
+        let mut rest = Vec::new();
+        for term in terms {
+            match term.strip_prefix("severity=") {
+                Some(name) => {
+                    min_severity = Severity::from_name(name)
+                        .ok_or_else(|| format!("unknown severity '{}'", name))?;
+                }
+                None => rest.push(term),
+            }
+        }
+        Ok(Self {
+            pulses: PulseFilter::parse(rest)?,
+            min_severity,
+        })
+    }
+
+    pub fn matches(&self, pulse: &Pulse) -> bool {
+        pulse.severity() >= self.min_severity && self.pulses.matches(pulse)
+    }
+
+    pub fn describe(&self) -> String {
+        match (self.min_severity, self.pulses.describe()) {
+            (Severity::Debug, pulses) => pulses,
+            (min, pulses) => format!("{} severity>={}", pulses, min.name()),
+        }
+    }
+}
+
+/// Somewhere pulses can be written.
+pub trait LogSink: Send {
+    /// Where output goes and in what format, for `logs`.
+    fn describe(&self) -> String;
+
+    fn write(&mut self, pulse: &Pulse) -> Result<(), String>;
+
+    /// Switch between JSON and text. Returns false if the sink has a
+    /// fixed format.
+    fn set_json(&mut self, _json: bool) -> bool {
+        false
+    }
+}
+
+/// Standard output.
+pub struct ConsoleSink {
+    pub json: bool,
+}
+
+impl LogSink for ConsoleSink {
+    fn describe(&self) -> String {
+        format!("stdout ({})", if self.json { "json" } else { "text" })
+    }
+
+    fn write(&mut self, pulse: &Pulse) -> Result<(), String> {
+        if self.json {
+            editor::print_line(&pulse.to_json());
+        } else {
+            editor::print_line(&pulse.log_line());
+        }
+        Ok(())
+    }
+
+    fn set_json(&mut self, json: bool) -> bool {
+        self.json = json;
+        true
+    }
+}
+
+/// A log file, JSON Lines by default. When it would grow past
+/// `max_bytes` it is renamed to `<path>.1` (older ones shift up to
+/// `<path>.<keep>`) and a fresh file is started.
+pub struct FileSink {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    json: bool,
+    file: Option<File>,
+    size: u64,
+}
+
+impl FileSink {
+    pub fn open(path: impl Into<PathBuf>, max_bytes: u64, keep: usize) -> Result<Self, String> {
+        let mut sink = Self {
+            path: path.into(),
+            max_bytes: max_bytes.max(1024),
+            keep,
+            json: true,
+            file: None,
+            size: 0,
+        };
+        if let Some(dir) = sink.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+        }
+        sink.reopen()?;
+        Ok(sink)
+    }
+
+    fn reopen(&mut self) -> Result<(), String> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| format!("failed to open {}: {}", self.path.display(), e))?;
+        self.size = file.metadata().map(|m| m.len()).unwrap_or(0);
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> Result<(), String> {
+        self.file = None;
+        if self.keep == 0 {
+            let _ = fs::remove_file(&self.path);
+        } else {
+            let _ = fs::remove_file(self.rotated(self.keep));
+            for n in (1..self.keep).rev() {
+                let _ = fs::rename(self.rotated(n), self.rotated(n + 1));
+            }
+            fs::rename(&self.path, self.rotated(1))
+                .map_err(|e| format!("failed to rotate {}: {}", self.path.display(), e))?;
+        }
+        self.reopen()
+    }
+}
+
+impl LogSink for FileSink {
+    fn describe(&self) -> String {
+        format!(
+            "{} ({}, rotate at {} KiB, keep {})",
+            self.path.display(),
+            if self.json { "jsonl" } else { "text" },
+            self.max_bytes / 1024,
+            self.keep
+        )
+    }
+
+    fn write(&mut self, pulse: &Pulse) -> Result<(), String> {
+        let line = if self.json {
+            format!("{}\n", pulse.to_json())
+        } else {
+            format!("{}\n", pulse.log_line())
+        };
+        if self.size > 0 && self.size + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        if self.file.is_none() {
+            self.reopen()?;
+        }
+        let file = self.file.as_mut().ok_or("no open log file")?;
+        file.write_all(line.as_bytes()).map_err(|e| e.to_string())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn set_json(&mut self, json: bool) -> bool {
+        self.json = json;
+        true
+    }
+}
+
+/// Syslog over a local Unix datagram socket, facility `daemon`.
+pub struct SyslogSink {
+    socket: PathBuf,
+    #[cfg(unix)]
+    conn: Option<std::os::unix::net::UnixDatagram>,
+}
+
+impl SyslogSink {
+    #[cfg(unix)]
+    pub fn connect(socket: impl Into<PathBuf>) -> Result<Self, String> {
+        let mut sink = Self {
+            socket: socket.into(),
+            conn: None,
+        };
+        sink.reconnect()?;
+        Ok(sink)
+    }
+
+    #[cfg(not(unix))]
+    pub fn connect(_socket: impl Into<PathBuf>) -> Result<Self, String> {
+        Err("syslog sink needs Unix sockets".to_string())
+    }
+
+    #[cfg(unix)]
+    fn reconnect(&mut self) -> Result<(), String> {
+        let conn = std::os::unix::net::UnixDatagram::unbound().map_err(|e| e.to_string())?;
+        conn.connect(&self.socket)
+            .map_err(|e| format!("failed to connect to {}: {}", self.socket.display(), e))?;
+        self.conn = Some(conn);
+        Ok(())
+    }
+
+    /// `<PRI>aion[pid]: message`, which local syslog daemons stamp with
+    /// their own time and host name.
+    fn format(pulse: &Pulse) -> String {
+        const FACILITY_DAEMON: u8 = 3;
+        format!(
+            "<{}>aion[{}]: {}",
+            FACILITY_DAEMON * 8 + pulse.severity().syslog_code(),
+            std::process::id(),
+            pulse.log_line()
+        )
+    }
+}
+
+impl LogSink for SyslogSink {
+    fn describe(&self) -> String {
+        format!("syslog {} (text)", self.socket.display())
+    }
+
+    #[cfg(unix)]
+    fn write(&mut self, pulse: &Pulse) -> Result<(), String> {
+        let msg = Self::format(pulse);
+        let sent = self.conn.as_ref().map(|c| c.send(msg.as_bytes()));
+        if let Some(Ok(_)) = sent {
+            return Ok(());
+        }
+        // The syslog daemon may have restarted; try once more.
+        self.reconnect()?;
+        let conn = self.conn.as_ref().ok_or("not connected")?;
+        conn.send(msg.as_bytes()).map(|_| ()).map_err(|e| e.to_string())
+    }
+
+    #[cfg(not(unix))]
+    fn write(&mut self, pulse: &Pulse) -> Result<(), String> {
+        let _ = Self::format(pulse);
+        Err("syslog sink needs Unix sockets".to_string())
+    }
+}
+
+struct SinkEntry {
+    name: String,
+    sink: Box<dyn LogSink>,
+    filter: SinkFilter,
+    enabled: bool,
+    written: u64,
+    errors: u64,
+    last_error: Option<String>,
+}
+
+/// Every configured sink, in the order pulses are offered to them.
+pub struct LogSinks {
+    sinks: Vec<SinkEntry>,
+    /// Skip the console, for output the shell has already printed.
+    console_muted: bool,
+}
+
+impl Default for LogSinks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LogSinks {
+    /// Just the console, showing commands and reflexes.
+    pub fn new() -> Self {
+        let mut sinks = Self {
+            sinks: Vec::new(),
+            console_muted: false,
+        };
+        sinks.add("console", Box::new(ConsoleSink { json: false }), SinkFilter::default());
+        sinks.apply_preset(LogFilter::CommandsOnly);
+        sinks
+    }
+
+    /// Extra sinks from `AION_LOG_FILE=<path>` (with `AION_LOG_FILE_KB`
+    /// and `AION_LOG_FILE_KEEP`) and `AION_LOG_SYSLOG=<socket|on>`.
+    /// Returns a message for each sink that could not be opened.
+    pub fn add_from_env(&mut self) -> Vec<String> {
+        let mut errors = Vec::new();
+        let env = |key: &str| std::env::var(key).ok().filter(|v| !v.is_empty());
+        let num = |key: &str| env(key).and_then(|v| v.parse::<u64>().ok());
+
+        if let Some(path) = env("AION_LOG_FILE") {
+            let keep = num("AION_LOG_FILE_KEEP").unwrap_or(3) as usize;
+            let sink = kib(num("AION_LOG_FILE_KB").unwrap_or(1024))
+                .and_then(|max_bytes| FileSink::open(&path, max_bytes, keep));
+            match sink {
+                Ok(sink) => {
+                    self.add("file", Box::new(sink), SinkFilter::default());
+                }
+                Err(e) => errors.push(format!("log file disabled: {}", e)),
+            }
+        }
+        if let Some(socket) = env("AION_LOG_SYSLOG").filter(|v| v != "off") {
+            let socket = if socket == "on" { DEFAULT_SYSLOG_SOCKET.to_string() } else { socket };
+            let filter = SinkFilter {
+                min_severity: Severity::Notice,
+                ..SinkFilter::default()
+            };
+            match SyslogSink::connect(&socket) {
+                Ok(sink) => {
+                    self.add("syslog", Box::new(sink), filter);
+                }
+                Err(e) => errors.push(format!("syslog disabled: {}", e)),
+            }
+        }
+        errors
+    }
+
+    /// Add a sink under a unique name derived from `name` (`file`,
+    /// `file2`, ...), which is returned.
+    pub fn add(&mut self, name: &str, sink: Box<dyn LogSink>, filter: SinkFilter) -> String {
+        let mut unique = name.to_string();
+        let mut n = 1;
+        while self.sinks.iter().any(|s| s.name == unique) {
+            n += 1;
+            unique = format!("{}{}", name, n);
+        }
+        self.sinks.push(SinkEntry {
+            name: unique.clone(),
+            sink,
+            filter,
+            enabled: true,
+            written: 0,
+            errors: 0,
+            last_error: None,
+        });
+        unique
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.sinks.len();
+        self.sinks.retain(|s| s.name != name);
+        self.sinks.len() != before
+    }
+
+    fn get_mut(&mut self, name: &str) -> Result<&mut SinkEntry, String> {
+        self.sinks
+            .iter_mut()
+            .find(|s| s.name == name)
+            .ok_or_else(|| format!("no log sink named '{}'", name))
+    }
+
+    pub fn set_filter(&mut self, name: &str, filter: SinkFilter) -> Result<(), String> {
+        self.get_mut(name)?.filter = filter;
+        Ok(())
+    }
+
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        self.get_mut(name)?.enabled = enabled;
+        Ok(())
+    }
+
+    pub fn set_json(&mut self, name: &str, json: bool) -> Result<(), String> {
+        let entry = self.get_mut(name)?;
+        if entry.sink.set_json(json) {
+            Ok(())
+        } else {
+            Err(format!("log sink '{}' has a fixed format", name))
+        }
+    }
+
+    /// Apply a console preset. Does nothing if the console was removed.
+    pub fn apply_preset(&mut self, preset: LogFilter) {
+        if let Ok(console) = self.get_mut("console") {
+            match preset.sink_filter() {
+                Some(filter) => {
+                    console.filter = filter;
+                    console.enabled = true;
+                }
+                None => console.enabled = false,
+            }
+        }
+    }
+
+    /// Keep pulses off the console until unmuted.
+    pub fn mute_console(&mut self, muted: bool) {
+        self.console_muted = muted;
+    }
+
+    /// One-line state of a sink, for actuation pulses.
+    pub fn state(&self, name: &str) -> String {
+        match self.sinks.iter().find(|s| s.name == name) {
+            Some(s) if s.enabled => format!("{} :: {}", s.sink.describe(), s.filter.describe()),
+            Some(s) => format!("{} :: off", s.sink.describe()),
+            None => "absent".to_string(),
+        }
+    }
+
+    /// Offer a pulse to every enabled sink that wants it. Write errors
+    /// are counted per sink, never fatal.
+    pub fn dispatch(&mut self, pulse: &Pulse) {
+        let muted = self.console_muted;
+        for s in self.sinks.iter_mut() {
+            if !s.enabled || !s.filter.matches(pulse) || (muted && s.name == "console") {
+                continue;
+            }
+            match s.sink.write(pulse) {
+                Ok(()) => s.written += 1,
+                Err(e) => {
+                    s.errors += 1;
+                    s.last_error = Some(e);
+                }
+            }
+        }
+    }
+
+    pub fn report(&self) -> String {
+        let mut out = format!("Log sinks ({}):\n", self.sinks.len());
+        for s in &self.sinks {
+            out.push_str(&format!(
+                " - {} [{}] {} :: {} :: written {} :: errors {}",
+                s.name,
+                if s.enabled { "on" } else { "off" },
+                s.sink.describe(),
+                s.filter.describe(),
+                s.written,
+                s.errors
+            ));
+            if let Some(e) = &s.last_error {
+                out.push_str(&format!(" (last: {})", e));
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+const LOGS_USAGE: &str = "logs [all|commands|silent] | logs json|text [sink] | \
+                          logs filter <sink> [kind=..] [source=..] [type=..] [severity=..] | \
+                          logs file <path> [max_kb=N] [keep=N] [filters] | \
+                          logs syslog [socket=<path>] [filters] | logs on|off|rm <sink>";
+
+const COMMANDS: &[BuiltinCommand] = &[BuiltinCommand {
+    name: "logs",
+    aliases: &[],
+    usage: LOGS_USAGE,
+    help: "list log sinks, or add, filter and switch them",
+    args: &[
+        ArgSpec::optional(
+            "subcommand",
+            ArgKind::Choice(&[
+                "all", "commands", "silent", "json", "text", "filter", "file", "syslog", "on", "off", "rm",
+            ]),
+        ),
+        ArgSpec::optional("args", ArgKind::Rest),
+    ],
+    run: logs,
+}];
+
+/// A size in KiB as bytes.
+fn kib(kb: u64) -> Result<u64, String> {
+    kb.checked_mul(1024).ok_or_else(|| format!("max_kb {} is too large", kb))
+}
+
+pub fn register_commands(r: &mut CommandRegistry) {
+    r.register_all(COMMANDS);
+}
+
+fn logs(ctx: &mut ShellContext, argv: &[&str]) -> Result<String, ShellError> {
+    let usage = || ShellError::usage(LOGS_USAGE);
+    let sinks = &mut ctx.bus.log_sinks;
+
+    match argv.get(1).copied() {
+        None => Ok(sinks.report()),
+        Some("all") => {
+            sinks.apply_preset(LogFilter::All);
+            Ok("logging: ALL pulses".to_string())
+        }
+        Some("commands") => {
+            sinks.apply_preset(LogFilter::CommandsOnly);
+            Ok("logging: COMMANDS ONLY".to_string())
+        }
+        Some("silent") | Some("off") if argv.len() == 2 => {
+            sinks.apply_preset(LogFilter::Silent);
+            Ok("logging: SILENT".to_string())
+        }
+        Some(fmt @ ("json" | "text")) => {
+            let name = argv.get(2).copied().unwrap_or("console");
+            sinks.set_json(name, fmt == "json")?;
+            Ok(format!("log sink {} format: {}", name, fmt.to_uppercase()))
+        }
+        Some(state @ ("on" | "off")) if argv.len() == 3 => {
+            sinks.set_enabled(argv[2], state == "on")?;
+            Ok(format!("log sink {} {}", argv[2], state))
+        }
+        Some("rm") if argv.len() == 3 => match sinks.remove(argv[2]) {
+            true => Ok(format!("removed log sink {}", argv[2])),
+            false => Err(format!("no log sink named '{}'", argv[2]).into()),
+        },
+        Some("filter") if argv.len() >= 3 => {
+            let filter = SinkFilter::parse(argv[3..].iter().copied())?;
+            let described = filter.describe();
+            sinks.set_filter(argv[2], filter)?;
+            Ok(format!("log sink {} :: {}", argv[2], described))
+        }
+        Some("file") if argv.len() >= 3 => {
+            let mut max_kb = 1024;
+            let mut keep = 3;
+            let mut terms = Vec::new();
+            for term in &argv[3..] {
+                if let Some(v) = term.strip_prefix("max_kb=") {
+                    max_kb = v.parse::<u64>().map_err(|_| usage())?;
+                } else if let Some(v) = term.strip_prefix("keep=") {
+                    keep = v.parse::<usize>().map_err(|_| usage())?;
+                } else {
+                    terms.push(*term);
+                }
+            }
+            let filter = SinkFilter::parse(terms)?;
+            let sink = FileSink::open(argv[2], kib(max_kb)?, keep)?;
+            let name = sinks.add("file", Box::new(sink), filter);
+            Ok(format!("log sink {} :: {}", name, sinks.state(&name)))
+        }
+        Some("syslog") => {
+            let mut socket = DEFAULT_SYSLOG_SOCKET;
+            let mut terms = Vec::new();
+            for term in &argv[2..] {
+                match term.strip_prefix("socket=") {
+                    Some(path) => socket = path,
+                    None => terms.push(*term),
+                }
+            }
+            let filter = SinkFilter::parse(terms)?;
+            let sink = SyslogSink::connect(socket)?;
+            let name = sinks.add("syslog", Box::new(sink), filter);
+            Ok(format!("log sink {} :: {}", name, sinks.state(&name)))
+        }
+        _ => Err(usage()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::JsonValue;
+    use crate::kernel::harness::Harness;
+    use crate::kernel::PulsePayload;
+    use std::sync::{Arc, Mutex};
+
+    fn pulse(id: u64, kind: PulseKind, source: &str, payload: PulsePayload) -> Pulse {
+        Pulse::new(id, 0, kind, source, payload)
+    }
+
+    fn text(id: u64, kind: PulseKind, source: &str) -> Pulse {
+        pulse(id, kind, source, PulsePayload::Text(format!("pulse {}", id)))
+    }
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("aion-logs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("aion.jsonl")
+    }
+
+    /// Records the IDs of the pulses written to it.
+    struct Recorder(Arc<Mutex<Vec<u64>>>);
+
+    impl LogSink for Recorder {
+        fn describe(&self) -> String {
+            "recorder".to_string()
+        }
+
+        fn write(&mut self, pulse: &Pulse) -> Result<(), String> {
+            self.0.lock().unwrap().push(pulse.id);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn sink_filters_match_kind_source_and_severity() {
+        let filter = SinkFilter::parse(["kind=command", "source=shell"]).unwrap();
+        assert!(filter.matches(&text(1, PulseKind::Command, "shell")));
+        assert!(!filter.matches(&text(2, PulseKind::Status, "shell")));
+        assert!(!filter.matches(&text(3, PulseKind::Command, "http")));
+
+        let warnings = SinkFilter::parse(["severity=warning"]).unwrap();
+        let beat = pulse(4, PulseKind::Heartbeat, "heartbeat", PulsePayload::Heartbeat { beat: 1 });
+        let event = PulsePayload::DaemonEvent {
+            daemon: "flaky".to_string(),
+            event: "crashed".to_string(),
+            detail: "boom".to_string(),
+        };
+        assert!(!warnings.matches(&beat));
+        assert!(warnings.matches(&pulse(5, PulseKind::Status, "scheduler", event)));
+        assert!(warnings.describe().ends_with("severity>=warning"), "{}", warnings.describe());
+        assert!(SinkFilter::default().matches(&beat));
+        assert_eq!(SinkFilter::parse(["severity=loud"]).unwrap_err(), "unknown severity 'loud'");
+    }
+
+    #[test]
+    fn presets_map_to_sink_filters() {
+        assert!(LogFilter::All.sink_filter().unwrap().matches(&text(1, PulseKind::Status, "status")));
+        let commands = LogFilter::CommandsOnly.sink_filter().unwrap();
+        assert!(commands.matches(&text(2, PulseKind::Command, "shell")));
+        assert!(commands.matches(&text(3, PulseKind::Reflex, "reflex")));
+        assert!(!commands.matches(&text(4, PulseKind::Heartbeat, "heartbeat")));
+        assert!(LogFilter::Silent.sink_filter().is_none());
+    }
+
+    #[test]
+    fn sinks_are_filtered_switched_and_removed() {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let mut sinks = LogSinks::new();
+        sinks.apply_preset(LogFilter::Silent);
+        let name = sinks.add("rec", Box::new(Recorder(Arc::clone(&written))), SinkFilter::default());
+        assert_eq!(sinks.add("rec", Box::new(Recorder(Arc::clone(&written))), SinkFilter::default()), "rec2");
+        assert!(sinks.remove("rec2") && !sinks.remove("rec2"));
+
+        sinks.dispatch(&text(1, PulseKind::Status, "status"));
+        sinks.set_filter(&name, SinkFilter::parse(["kind=command"]).unwrap()).unwrap();
+        sinks.dispatch(&text(2, PulseKind::Status, "status"));
+        sinks.dispatch(&text(3, PulseKind::Command, "shell"));
+        sinks.set_enabled(&name, false).unwrap();
+        sinks.dispatch(&text(4, PulseKind::Command, "shell"));
+        sinks.set_enabled(&name, true).unwrap();
+        sinks.dispatch(&text(5, PulseKind::Command, "shell"));
+        assert_eq!(*written.lock().unwrap(), [1, 3, 5]);
+
+        assert_eq!(sinks.set_json(&name, true).unwrap_err(), "log sink 'rec' has a fixed format");
+        assert_eq!(sinks.set_enabled("nope", true).unwrap_err(), "no log sink named 'nope'");
+        assert!(sinks.report().contains(" - rec [on] recorder :: kind=command :: written 3 :: errors 0"), "{}", sinks.report());
+    }
+
+    #[test]
+    fn file_sinks_rotate_and_keep_the_newest() {
+        let path = scratch("rotate");
+        let rotated = |n: usize| PathBuf::from(format!("{}.{}", path.display(), n));
+        let mut sink = FileSink::open(&path, 1024, 2).unwrap();
+        // About 100 bytes each: a rotation every ten pulses.
+        for id in 0..40 {
+            sink.write(&text(id, PulseKind::Command, "shell")).unwrap();
+        }
+        let first_id = |p: &PathBuf| {
+            let line = fs::read_to_string(p).unwrap().lines().next().unwrap().to_string();
+            JsonValue::parse(&line).unwrap().get("id").and_then(JsonValue::as_f64).unwrap() as u64
+        };
+        assert!(fs::metadata(&path).unwrap().len() <= 1024);
+        assert!(first_id(&rotated(2)) < first_id(&rotated(1)));
+        assert!(first_id(&rotated(1)) < first_id(&path));
+        assert!(!rotated(3).exists());
+
+        // keep=0 starts over in place.
+        let path = scratch("keep0");
+        let mut sink = FileSink::open(&path, 1024, 0).unwrap();
+        for id in 0..40 {
+            sink.write(&text(id, PulseKind::Command, "shell")).unwrap();
+        }
+        assert!(fs::metadata(&path).unwrap().len() <= 1024);
+        assert!(first_id(&path) > 0);
+        assert!(!PathBuf::from(format!("{}.1", path.display())).exists());
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+        let _ = fs::remove_dir_all(rotated(1).parent().unwrap());
+    }
+
+    #[test]
+    fn logs_commands_manage_sinks() {
+        let path = scratch("commands");
+        let mut h = Harness::new();
+        let out = h.command(&format!("logs file {} max_kb=1 keep=1 kind=command", path.display()));
+        assert!(out.starts_with("log sink file :: "), "{}", out);
+        assert!(out.ends_with("rotate at 1 KiB, keep 1) :: kind=command"), "{}", out);
+
+        assert_eq!(h.command("logs filter file severity=warning"), "log sink file :: all pulses severity>=warning");
+        assert_eq!(h.command("logs off file"), "log sink file off");
+        assert!(h.command("logs").contains(" - file [off] "));
+        assert_eq!(h.command("logs on file"), "log sink file on");
+        assert_eq!(h.command("logs text file"), "log sink file format: TEXT");
+        assert_eq!(h.command("logs filter nope"), "no log sink named 'nope'");
+        assert_eq!(h.command("logs rm file"), "removed log sink file");
+        assert_eq!(h.command("logs rm file"), "no log sink named 'file'");
+        assert!(h.command("logs file x max_kb=lots").starts_with("usage: logs"));
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn file_sizes_do_not_overflow() {
+        assert_eq!(kib(512), Ok(512 * 1024));
+        assert_eq!(kib(u64::MAX / 1024), Ok(u64::MAX / 1024 * 1024));
+        assert_eq!(kib(u64::MAX / 1024 + 1), Err(format!("max_kb {} is too large", u64::MAX / 1024 + 1)));
+    }
+}
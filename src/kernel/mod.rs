@@ -1,31 +1,69 @@
-use std::fs;
-use std::io::{self, BufRead, Write};
+pub mod clock;
+pub mod commands;
+pub mod control;
+pub mod editor;
+pub mod handle;
+#[cfg(test)]
+pub mod harness;
+pub mod history;
+pub mod jobs;
+pub mod log_sink;
+pub mod pubsub;
+pub mod pulse;
+pub mod pulse_journal;
+pub mod registry;
+pub mod scheduler;
+pub mod script;
+pub mod shell;
+pub mod supervisor;
+pub mod worker;
+
+pub use clock::{Clock, SharedClock};
+pub use control::ControlConfig;
+pub use handle::{BusHandle, BusRequest, SessionId, ShellHandle, ShellRequest, CONSOLE_SESSION};
+pub use history::{PulseHistory, Since};
+pub use jobs::{JobDaemon, JobTable};
+pub use log_sink::{LogFilter, LogSink, LogSinks};
+pub use pubsub::{PubSub, PulseFilter, Subscriber};
+pub use pulse::{Pulse, PulseKind, PulsePayload};
+pub use pulse_journal::{JournalConfig, PulseJournal, ReplayOptions};
+pub use registry::{DaemonConfig, DaemonRegistry, DaemonSpec, FactoryContext};
+pub use scheduler::{MissedTick, Schedule, Scheduler, Waker};
+pub use script::Script;
+pub use shell::{CommandRegistry, OutputMode, ShellContext};
+pub use supervisor::{DaemonControl, DaemonTable};
+pub use worker::{Placement, WorkContext, Worker, WorkerDaemon, WorkerPool};
+
+use std::collections::HashMap;
 use std::process;
-use std::sync::mpsc::{self, Receiver, TryRecvError};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 use std::thread;
 use std::time::{Duration, Instant};
 
-use crate::http::HttpServer;
+use crate::capabilities::{self, CapabilityRegistry};
+use crate::cortex::advisor::{AdvisorBrain, AdvisorConfig};
+use crate::cortex::brain::RuleBrain;
+use crate::cortex::{CortexBrains, DecisionJournal, Observation, OrganAlert, PolicySet};
+use crate::homeostasis::Homeostasis;
+use crate::http::{HttpServer, ShellEndpoint};
+use crate::json::JsonValue;
 use crate::memory::{MemoryBus, MemoryScope};
 use crate::organism::{
     self, format_topology_brief, Organ, OrganKind, SystemTopology,
 };
+use crate::reflex::{ReflexAction, ReflexArcs};
 use crate::telemetry::{
     self, TelemetryProvider, SimLevel,
-    sim::SimulatedTelemetry,
-    real::RealTelemetry,
     CpuGpuMetrics, MemoryMetrics, IoMetrics,
 };
 
-/// Different categories of pulses travelling on the bus.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum PulseKind {
-    Heartbeat,
-    Status,
-    Command,
-    Ai,
-    Sim,
+/// Lock shared kernel state. A daemon that panics while holding a lock
+/// poisons it; the supervisor restarts the daemon, so everyone else
+/// carries on with the state as it was left rather than failing forever.
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
 }
 
 /// Which telemetry backend is currently active.
@@ -35,61 +73,184 @@ pub enum TelemetryMode {
     Real,
 }
 
-/// Log filtering for bus output.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum LogFilter {
-    All,
-    CommandsOnly,
-    Silent,
-}
-
 /// Simple message bus. Right now it just logs, but it also
 /// tracks global "world" state like awareness and sim level.
 /// It now carries a shared MemoryBus (from src/memory).
 pub struct Bus {
     next_id: u64,
-    pub log_filter: LogFilter,
+    /// Console, file and syslog outputs, each with its own filter.
+    pub log_sinks: LogSinks,
     pub sim_level: SimLevel,
     pub awareness_score: f32,
     pub telemetry_mode: TelemetryMode,
+    /// Workload throttle (0.0–1.0) set by homeostasis.
+    pub workload_throttle: f32,
     pub memory: MemoryBus,
+    /// Subscribers receiving pulses through bounded channels.
+    pubsub: Arc<Mutex<PubSub>>,
+    /// Recent pulses, for `pulses` and `/events`.
+    history: Arc<Mutex<PulseHistory>>,
+    /// Durable on-disk record of every pulse, when enabled.
+    journal: Option<PulseJournal>,
+    /// Requests queued by `BusHandle`s on other threads.
+    requests_tx: Sender<BusRequest>,
+    requests_rx: Receiver<BusRequest>,
+    /// Wakes the scheduler when a handle queues a request.
+    waker: Option<Waker>,
+    /// Source of pulse timestamps; virtual in tests.
+    clock: SharedClock,
 }
 
+/// Upper bound on handle requests applied per kernel loop iteration, so a
+/// flood from one thread can't starve the daemons.
+const MAX_REQUESTS_PER_DRAIN: usize = 1024;
+
 impl Bus {
     pub fn new() -> Self {
+        let (requests_tx, requests_rx) = mpsc::channel();
         Self {
             next_id: 0,
-            log_filter: LogFilter::CommandsOnly,
+            log_sinks: LogSinks::new(),
             sim_level: SimLevel::Low,
             awareness_score: 1.0,
             telemetry_mode: TelemetryMode::Simulated,
+            workload_throttle: 0.0,
             memory: MemoryBus::new(),
+            pubsub: Arc::new(Mutex::new(PubSub::new())),
+            history: Arc::new(Mutex::new(PulseHistory::from_env())),
+            journal: None,
+            requests_tx,
+            requests_rx,
+            waker: None,
+            clock: clock::real(),
         }
     }
 
-    pub fn emit(&mut self, kind: PulseKind, source: &'static str, data: impl Into<String>) {
+    /// Put a pulse on the bus.
+    pub fn emit(
+        &mut self,
+        kind: PulseKind,
+        source: impl Into<String>,
+        payload: impl Into<PulsePayload>,
+    ) {
         self.next_id += 1;
-        let data = data.into();
+        let pulse = Pulse::new(self.next_id, self.clock.unix_millis(), kind, source, payload.into());
+        self.deliver(&pulse);
+    }
 
-        match self.log_filter {
-            LogFilter::All => {
-                println!(
-                    "[BUS][{:?}] pulse#{} from {} => {}",
-                    kind, self.next_id, source, data
-                );
-            }
-            LogFilter::CommandsOnly => {
-                if kind == PulseKind::Command {
-                    println!(
-                        "[BUS][{:?}] pulse#{} from {} => {}",
-                        kind, self.next_id, source, data
+    /// Shared subscriber table, for subscribing from other threads.
+    pub fn pubsub(&self) -> Arc<Mutex<PubSub>> {
+        Arc::clone(&self.pubsub)
+    }
+
+    /// Shared pulse history, for querying from other threads.
+    pub fn history(&self) -> Arc<Mutex<PulseHistory>> {
+        Arc::clone(&self.history)
+    }
+
+    /// A cloneable, thread-safe handle for publishing and requesting state
+    /// changes from outside the kernel loop.
+    pub fn handle(&self) -> BusHandle {
+        BusHandle {
+            tx: self.requests_tx.clone(),
+            pubsub: self.pubsub(),
+            history: self.history(),
+            waker: self.waker.clone(),
+        }
+    }
+
+    pub fn clock(&self) -> &SharedClock {
+        &self.clock
+    }
+
+    /// Use another time source, e.g. a `VirtualClock` in tests. Set it
+    /// before any daemon runs.
+    #[cfg(test)]
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock;
+    }
+
+    /// Have handles made from now on wake the scheduler.
+    pub fn set_waker(&mut self, waker: Waker) {
+        self.waker = Some(waker);
+    }
+
+    /// Apply queued handle requests in arrival order. Called by the kernel
+    /// loop before the daemons tick; returns how many were applied.
+    pub fn drain_requests(&mut self) -> usize {
+        let mut applied = 0;
+        while applied < MAX_REQUESTS_PER_DRAIN {
+            let Ok(req) = self.requests_rx.try_recv() else {
+                break;
+            };
+            applied += 1;
+            match req {
+                BusRequest::Emit {
+                    kind,
+                    source,
+                    payload,
+                } => self.emit(kind, source, payload),
+                BusRequest::SetSimLevel { level, source } => {
+                    let from = format!("{:?}", self.sim_level);
+                    self.sim_level = level;
+                    self.emit(
+                        PulseKind::Command,
+                        source,
+                        PulsePayload::Actuation {
+                            knob: "sim_level".to_string(),
+                            from,
+                            to: format!("{:?}", level),
+                        },
+                    );
+                }
+                BusRequest::SetLogFilter {
+                    filter,
+                    json,
+                    source,
+                } => {
+                    let from = self.log_sinks.state("console");
+                    if let Some(f) = filter {
+                        self.log_sinks.apply_preset(f);
+                    }
+                    if let Some(j) = json {
+                        let _ = self.log_sinks.set_json("console", j);
+                    }
+                    let to = self.log_sinks.state("console");
+                    self.emit(
+                        PulseKind::Command,
+                        source,
+                        PulsePayload::Actuation {
+                            knob: "logs.console".to_string(),
+                            from,
+                            to,
+                        },
                     );
                 }
             }
-            LogFilter::Silent => {
-                // print nothing
-            }
         }
+        applied
+    }
+
+    /// Start writing every pulse to a durable journal. Pulse IDs carry on
+    /// from the last one journaled, so they stay unique across restarts.
+    pub fn attach_journal(&mut self, journal: PulseJournal) {
+        self.next_id = self.next_id.max(journal.last_id());
+        self.journal = Some(journal);
+    }
+
+    pub fn pulse_journal(&self) -> Option<&PulseJournal> {
+        self.journal.as_ref()
+    }
+
+    /// Hand a pulse to its consumers: journal, history, subscribers, then
+    /// the log sinks.
+    fn deliver(&mut self, pulse: &Pulse) {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.append(pulse);
+        }
+        lock(&self.history).record(pulse);
+        lock(&self.pubsub).publish(pulse);
+        self.log_sinks.dispatch(pulse);
     }
 }
 
@@ -108,26 +269,159 @@ pub struct TelemetrySnapshot {
     pub io: IoMetrics,
 }
 
+impl TelemetrySnapshot {
+    /// JSON object used by `/metrics` and the cortex advisor.
+    pub fn to_json(self) -> String {
+        format!(
+            concat!(
+                r#"{{"cpu":{{"cpu_load":{:.3},"cpu_temp_c":{:.1},"throttling_events":{},"gpu_load":{:.3},"gpu_mem_util":{:.3}}},"#,
+                r#""memory":{{"ram_used_ratio":{:.3},"swap_used_ratio":{:.3},"major_page_faults":{:.3},"disk_latency_ms":{:.3}}},"#,
+                r#""io":{{"net_packet_loss":{:.3},"net_latency_ms":{:.3},"io_queue_depth":{:.3},"io_error_rate":{:.3}}}}}"#
+            ),
+            self.cpu.cpu_load,
+            self.cpu.cpu_temp_c,
+            self.cpu.throttling_events,
+            self.cpu.gpu_load,
+            self.cpu.gpu_mem_util,
+            self.mem.ram_used_ratio,
+            self.mem.swap_used_ratio,
+            self.mem.major_page_faults,
+            self.mem.disk_latency_ms,
+            self.io.net_packet_loss,
+            self.io.net_latency_ms,
+            self.io.io_queue_depth,
+            self.io.io_error_rate,
+        )
+    }
+
+    /// Inverse of `to_json`, for replaying journaled pulses.
+    pub fn from_json(v: &JsonValue) -> Option<Self> {
+        let f = |group: &str, key: &str| -> Option<f32> {
+            v.get(group)?.get(key)?.as_f64().map(|n| n as f32)
+        };
+        Some(Self {
+            cpu: CpuGpuMetrics {
+                cpu_load: f("cpu", "cpu_load")?,
+                cpu_temp_c: f("cpu", "cpu_temp_c")?,
+                throttling_events: f("cpu", "throttling_events")? as u32,
+                gpu_load: f("cpu", "gpu_load")?,
+                gpu_mem_util: f("cpu", "gpu_mem_util")?,
+            },
+            mem: MemoryMetrics {
+                ram_used_ratio: f("memory", "ram_used_ratio")?,
+                swap_used_ratio: f("memory", "swap_used_ratio")?,
+                major_page_faults: f("memory", "major_page_faults")?,
+                disk_latency_ms: f("memory", "disk_latency_ms")?,
+            },
+            io: IoMetrics {
+                net_packet_loss: f("io", "net_packet_loss")?,
+                net_latency_ms: f("io", "net_latency_ms")?,
+                io_queue_depth: f("io", "io_queue_depth")?,
+                io_error_rate: f("io", "io_error_rate")?,
+            },
+        })
+    }
+}
+
 /// Basic interface for any long-running kernel task.
-pub trait Daemon {
+pub trait Daemon: Send {
     fn name(&self) -> &'static str;
     fn tick(&mut self, now: Instant, bus: &mut Bus);
+
+    /// Called before the first tick and on every restart. An error
+    /// counts as a crash.
+    fn start(&mut self, _now: Instant, _bus: &mut Bus) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Called when the daemon is stopped or restarted from the shell.
+    fn stop(&mut self, _bus: &mut Bus) {}
+
+    /// Called instead of `tick` when the daemon is woken between its
+    /// scheduled runs.
+    fn on_wake(&mut self, now: Instant, bus: &mut Bus) {
+        self.tick(now, bus)
+    }
+
+    /// When the current run started, for daemons whose work continues
+    /// off the kernel thread after `tick` returns.
+    fn busy_since(&self) -> Option<Instant> {
+        None
+    }
+
+    /// `Err(reason)` while the daemon runs but can't do its job.
+    fn health(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Handles to the shared organism state, handed to the daemons that
+/// need them.
+#[derive(Clone)]
+pub struct KernelState {
+    pub topology: Arc<Mutex<SystemTopology>>,
+    pub metrics_snapshot: Arc<Mutex<Option<TelemetrySnapshot>>>,
+    /// Readings a `TelemetrySampler` has written to `metrics_snapshot`,
+    /// bumped under its lock.
+    pub telemetry_samples: Arc<AtomicU64>,
+    pub journal: Arc<Mutex<DecisionJournal>>,
+    pub brains: Arc<Mutex<CortexBrains>>,
+    pub homeostasis: Arc<Mutex<Homeostasis>>,
+    pub capabilities: Arc<Mutex<CapabilityRegistry>>,
+    pub reflexes: Arc<Mutex<ReflexArcs>>,
+    /// Daemon status and lifecycle requests, shared with the scheduler.
+    pub daemons: Arc<Mutex<DaemonTable>>,
+    /// Threads for daemons whose work would block the kernel loop.
+    pub workers: Arc<WorkerPool>,
+    /// Factories for `daemon add` and the daemon config.
+    pub registry: Arc<DaemonRegistry>,
+    /// Scheduled jobs, run by the jobs daemon.
+    pub jobs: Arc<Mutex<JobTable>>,
+    /// Shell commands, dispatched by the command daemon.
+    pub commands: Arc<CommandRegistry>,
+    /// Submits command lines to the command daemon.
+    pub shell: ShellHandle,
+}
+
+impl KernelState {
+    /// Default homeostasis and reflex arcs, and a capability registry
+    /// derived from the topology.
+    pub fn new(
+        topology: Arc<Mutex<SystemTopology>>,
+        metrics_snapshot: Arc<Mutex<Option<TelemetrySnapshot>>>,
+        journal: Arc<Mutex<DecisionJournal>>,
+        brains: CortexBrains,
+        daemons: Arc<Mutex<DaemonTable>>,
+        shell: ShellHandle,
+    ) -> Self {
+        let capability_registry = capabilities::registry_from_topology(&lock(&topology));
+        Self {
+            topology,
+            metrics_snapshot,
+            telemetry_samples: Arc::new(AtomicU64::new(0)),
+            journal,
+            brains: Arc::new(Mutex::new(brains)),
+            homeostasis: Arc::new(Mutex::new(Homeostasis::with_defaults())),
+            capabilities: Arc::new(Mutex::new(capability_registry)),
+            reflexes: Arc::new(Mutex::new(ReflexArcs::with_defaults())),
+            daemons,
+            workers: Arc::new(WorkerPool::new("pool", WorkerPool::threads_from_env())),
+            registry: Arc::new(DaemonRegistry::standard()),
+            jobs: Arc::new(Mutex::new(JobTable::default())),
+            commands: Arc::new(CommandRegistry::standard()),
+            shell,
+        }
+    }
 }
 
 /// A simple daemon that prints a heartbeat every N milliseconds.
 pub struct HeartbeatDaemon {
-    last_run: Instant,
-    interval: Duration,
     counter: u64,
 }
 
 impl HeartbeatDaemon {
-    pub fn new(interval: Duration) -> Self {
-        Self {
-            last_run: Instant::now(),
-            interval,
-            counter: 0,
-        }
+    pub fn new() -> Self {
+        Self { counter: 0 }
     }
 }
 
@@ -136,17 +430,13 @@ impl Daemon for HeartbeatDaemon {
         "heartbeat"
     }
 
-    fn tick(&mut self, now: Instant, bus: &mut Bus) {
-        if now.duration_since(self.last_run) >= self.interval {
-            self.counter += 1;
-            self.last_run = now;
-
-            bus.emit(
-                PulseKind::Heartbeat,
-                self.name(),
-                format!("beat #{}", self.counter),
-            );
-        }
+    fn tick(&mut self, _now: Instant, bus: &mut Bus) {
+        self.counter += 1;
+        bus.emit(
+            PulseKind::Heartbeat,
+            self.name(),
+            PulsePayload::Heartbeat { beat: self.counter },
+        );
     }
 }
 
@@ -154,30 +444,161 @@ impl Daemon for HeartbeatDaemon {
 /// In Phase 1 it also uses a TelemetryProvider to gently
 /// pull organ health toward values derived from metrics.
 pub struct StatusDaemon {
-    last_run: Instant,
-    interval: Duration,
     counter: u64,
     topology: Arc<Mutex<SystemTopology>>,
-    telemetry: Box<dyn TelemetryProvider>,
+    /// `None` when a `TelemetrySampler` worker fills the snapshot instead.
+    telemetry: Option<Box<dyn TelemetryProvider>>,
     /// Shared snapshot for the `metrics` command / HTTP.
     metrics_snapshot: Arc<Mutex<Option<TelemetrySnapshot>>>,
+    /// The sampler's reading count, and the last one this daemon used.
+    telemetry_samples: Arc<AtomicU64>,
+    seen_sample: u64,
+    /// When a new sampled reading last arrived (or the first tick).
+    fresh_since: Option<Instant>,
+    /// How long the sampled reading has gone without an update, once
+    /// that passes TELEMETRY_STALE_AFTER.
+    stale_for: Option<Duration>,
+    /// Reflex arcs, evaluated synchronously on every tick.
+    reflexes: Arc<Mutex<ReflexArcs>>,
+    capabilities: Arc<Mutex<CapabilityRegistry>>,
+    /// Alert level per organ, to report only changes.
+    alerts: Vec<AlertState>,
+    /// How long a new alert level must hold before it is reported.
+    alert_dwell: Duration,
+}
+
+/// How long sampled telemetry may go without a new reading before the
+/// status daemon reports it stale.
+pub const TELEMETRY_STALE_AFTER: Duration = Duration::from_secs(30);
+
+/// An organ's reported alert level and any change still dwelling.
+struct AlertState {
+    organ: String,
+    reported: &'static str,
+    pending: &'static str,
+    since: Instant,
 }
 
 impl StatusDaemon {
     pub fn new(
-        interval: Duration,
-        topology: Arc<Mutex<SystemTopology>>,
-        telemetry: Box<dyn TelemetryProvider>,
-        metrics_snapshot: Arc<Mutex<Option<TelemetrySnapshot>>>,
+        alert_dwell: Duration,
+        telemetry: Option<Box<dyn TelemetryProvider>>,
+        state: &KernelState,
     ) -> Self {
         Self {
-            last_run: Instant::now(),
-            interval,
             counter: 0,
-            topology,
+            topology: Arc::clone(&state.topology),
             telemetry,
-            metrics_snapshot,
+            metrics_snapshot: Arc::clone(&state.metrics_snapshot),
+            telemetry_samples: Arc::clone(&state.telemetry_samples),
+            seen_sample: 0,
+            fresh_since: None,
+            stale_for: None,
+            reflexes: Arc::clone(&state.reflexes),
+            capabilities: Arc::clone(&state.capabilities),
+            alerts: Vec::new(),
+            alert_dwell,
+        }
+    }
+
+    /// Report organs whose alert level changed and has held for the
+    /// dwell time.
+    fn emit_alert_changes(
+        alerts: &mut Vec<AlertState>,
+        dwell: Duration,
+        now: Instant,
+        topology: &SystemTopology,
+        bus: &mut Bus,
+    ) {
+        for organ in &topology.organs {
+            let name = format!("{:?}", organ.kind);
+            let level = classify_health(organ.health);
+            let state = match alerts.iter().position(|a| a.organ == name) {
+                Some(i) => &mut alerts[i],
+                None => {
+                    alerts.push(AlertState {
+                        organ: name.clone(),
+                        reported: "ok",
+                        pending: "ok",
+                        since: now,
+                    });
+                    alerts.last_mut().unwrap()
+                }
+            };
+            if level != state.pending {
+                state.pending = level;
+                state.since = now;
+            }
+            if level == state.reported || now.duration_since(state.since) < dwell {
+                continue;
+            }
+            let previous = std::mem::replace(&mut state.reported, level);
+            bus.emit(
+                PulseKind::Status,
+                "status",
+                PulsePayload::Alert {
+                    organ: name,
+                    health: organ.health,
+                    level: level.to_string(),
+                    previous: previous.to_string(),
+                },
+            );
+        }
+    }
+
+    /// Fire any reflexes whose condition holds on the fresh readings and
+    /// apply their actions immediately, without waiting for the cortex.
+    fn run_reflexes(
+        &self,
+        now: Instant,
+        snapshot: &TelemetrySnapshot,
+        topology: &SystemTopology,
+        bus: &mut Bus,
+    ) {
+        let firings = lock(&self.reflexes).evaluate(now, |m| metric_value(m, Some(snapshot), topology));
+
+        for f in firings {
+            let effect = match f.action {
+                ReflexAction::DisableCapability(kind) | ReflexAction::EnableCapability(kind) => {
+                    let enable = matches!(f.action, ReflexAction::EnableCapability(_));
+                    let changed = lock(&self.capabilities).set_enabled_by_kind(kind, enable);
+                    format!("{} capability(ies) changed", changed.len())
+                }
+                ReflexAction::SimLevel(level) => {
+                    let prev = bus.sim_level;
+                    bus.sim_level = level;
+                    format!("sim_level {:?} -> {:?}", prev, level)
+                }
+            };
+            bus.emit(
+                PulseKind::Reflex,
+                "reflex",
+                PulsePayload::ReflexFired {
+                    id: f.id,
+                    condition: f.condition,
+                    value: f.value,
+                    action: f.action.label(),
+                    effect,
+                },
+            );
+        }
+    }
+
+    /// The sampler's reading if it is new since the last tick; an old one
+    /// was already applied and emitted.
+    fn take_sample(&mut self, now: Instant) -> Option<TelemetrySnapshot> {
+        let snapshot = lock(&self.metrics_snapshot);
+        let seq = self.telemetry_samples.load(Ordering::SeqCst);
+        let since = *self.fresh_since.get_or_insert(now);
+        if seq != self.seen_sample {
+            self.seen_sample = seq;
+            self.fresh_since = Some(now);
+            self.stale_for = None;
+            return *snapshot;
         }
+        let age = now.duration_since(since);
+        self.stale_for = (age >= TELEMETRY_STALE_AFTER).then_some(age);
+        None
     }
 
     /// Blend current organ health toward a target health (0.0–1.0).
@@ -223,78 +644,167 @@ impl Daemon for StatusDaemon {
     }
 
     fn tick(&mut self, now: Instant, bus: &mut Bus) {
-        if now.duration_since(self.last_run) < self.interval {
-            return;
-        }
-
         self.counter += 1;
-        self.last_run = now;
 
-        // Pull metrics from telemetry.
-        let cpu_gpu = self.telemetry.read_cpu_gpu_metrics();
-        let mem = self.telemetry.read_memory_metrics();
-        let io = self.telemetry.read_io_metrics();
+        let snapshot = match &mut self.telemetry {
+            Some(telemetry) => {
+                // Pull metrics from telemetry (honouring any homeostatic throttle).
+                telemetry.set_workload_throttle(bus.workload_throttle);
+                let snapshot = TelemetrySnapshot {
+                    cpu: telemetry.read_cpu_gpu_metrics(),
+                    mem: telemetry.read_memory_metrics(),
+                    io: telemetry.read_io_metrics(),
+                };
+                // Update shared metrics snapshot for the `metrics` command + HTTP.
+                *lock(&self.metrics_snapshot) = Some(snapshot);
+                Some(snapshot)
+            }
+            // Sampled off the kernel thread; use only a new reading.
+            None => self.take_sample(now),
+        };
 
-        // Update shared metrics snapshot for the `metrics` command + HTTP.
-        if let Ok(mut guard) = self.metrics_snapshot.lock() {
-            *guard = Some(TelemetrySnapshot { cpu: cpu_gpu, mem, io });
+        let mut topo = lock(&self.topology);
+        if let Some(snapshot) = &snapshot {
+            // Apply telemetry-driven health adjustments.
+            Self::apply_telemetry_to_topology(&mut topo, &snapshot.cpu, &snapshot.mem, &snapshot.io);
+
+            // Reflexes react to this reading before anything else does.
+            self.run_reflexes(now, snapshot, &topo, bus);
         }
 
-        let brief;
+        // Recompute awareness from updated topology.
+        let awareness = organism::compute_awareness(&topo);
+        let awareness_label = organism::describe_awareness(awareness);
 
-        if let Ok(mut topo) = self.topology.lock() {
-            // Apply telemetry-driven health adjustments.
-            Self::apply_telemetry_to_topology(&mut *topo, &cpu_gpu, &mem, &io);
+        let brief = format_topology_brief(&topo);
 
-            // Recompute awareness from updated topology.
-            let awareness = organism::compute_awareness(&*topo);
-            let awareness_label = organism::describe_awareness(awareness);
+        let overall_health = compute_overall_health(&topo);
+        let health_label = classify_health(overall_health);
 
-            brief = format_topology_brief(&*topo);
+        bus.awareness_score = awareness;
 
-            let overall_health = compute_overall_health(&*topo);
-            let health_label = classify_health(overall_health);
+        let status = PulsePayload::Status {
+            tick: self.counter,
+            brief,
+            health: overall_health,
+            health_label: health_label.to_string(),
+            awareness,
+            awareness_label: awareness_label.to_string(),
+        };
 
-            bus.awareness_score = awareness;
+        // Store the last status line in memory (global scope).
+        bus.memory
+            .set_text(MemoryScope::Global, "kernel.last_status", status.summary());
 
-            let msg = format!(
-                "status tick #{} :: {} :: health {:.2} ({}) :: awareness {:.2} ({})",
-                self.counter, brief, overall_health, health_label, awareness, awareness_label
-            );
+        if let Some(snapshot) = snapshot {
+            bus.emit(PulseKind::Status, self.name(), PulsePayload::MetricSnapshot(snapshot));
+        }
+        Self::emit_alert_changes(&mut self.alerts, self.alert_dwell, now, &topo, bus);
+        bus.emit(PulseKind::Status, self.name(), status);
+    }
 
-            // Store the last status line in memory (global scope).
-            bus.memory
-                .set_text(MemoryScope::Global, "kernel.last_status", msg.clone());
+    fn health(&self) -> Result<(), String> {
+        match self.stale_for {
+            Some(age) => Err(format!("no new telemetry for {}s", age.as_secs())),
+            None => Ok(()),
+        }
+    }
+}
 
-            bus.emit(PulseKind::Status, self.name(), msg);
-        } else {
-            bus.emit(
-                PulseKind::Status,
-                self.name(),
-                "status tick: failed to lock topology",
-            );
+/// Reads a blocking telemetry provider on a worker thread and publishes
+/// the result in the shared metrics snapshot for `StatusDaemon`.
+pub struct TelemetrySampler {
+    telemetry: Box<dyn TelemetryProvider>,
+    metrics_snapshot: Arc<Mutex<Option<TelemetrySnapshot>>>,
+    telemetry_samples: Arc<AtomicU64>,
+}
+
+impl TelemetrySampler {
+    pub fn new(telemetry: Box<dyn TelemetryProvider>, state: &KernelState) -> Self {
+        Self {
+            telemetry,
+            metrics_snapshot: Arc::clone(&state.metrics_snapshot),
+            telemetry_samples: Arc::clone(&state.telemetry_samples),
         }
     }
 }
 
+impl Worker for TelemetrySampler {
+    fn name(&self) -> &'static str {
+        "telemetry"
+    }
+
+    fn run(&mut self, ctx: &WorkContext) {
+        self.telemetry.set_workload_throttle(ctx.workload_throttle);
+        let snapshot = TelemetrySnapshot {
+            cpu: self.telemetry.read_cpu_gpu_metrics(),
+            mem: self.telemetry.read_memory_metrics(),
+            io: self.telemetry.read_io_metrics(),
+        };
+        // Tag the reading so the status daemon uses it only once.
+        let mut slot = lock(&self.metrics_snapshot);
+        *slot = Some(snapshot);
+        self.telemetry_samples.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
 /// A daemon representing the AI Cortex: all high-level intelligence lives here.
 ///
-/// In this early phase it observes awareness and logs a coarse "policy"
-/// about how the system should behave, and writes that decision into the MemoryBus.
+/// Each cycle it builds an `Observation` of the organism, asks the active
+/// `CortexBrain` for a decision, applies its actions and writes the policy
+/// into the MemoryBus. Every decision is also appended to the cortex
+/// `DecisionJournal`, and resolved outcomes are fed back to the brains.
 pub struct AiDaemon {
-    last_run: Instant,
-    interval: Duration,
     cycle: u64,
     topology: Arc<Mutex<SystemTopology>>,
+    metrics_snapshot: Arc<Mutex<Option<TelemetrySnapshot>>>,
+    journal: Arc<Mutex<DecisionJournal>>,
+    brains: Arc<Mutex<CortexBrains>>,
 }
 
 impl AiDaemon {
-    pub fn new(interval: Duration, topology: Arc<Mutex<SystemTopology>>) -> Self {
+    pub fn new(
+        topology: Arc<Mutex<SystemTopology>>,
+        metrics_snapshot: Arc<Mutex<Option<TelemetrySnapshot>>>,
+        journal: Arc<Mutex<DecisionJournal>>,
+        brains: Arc<Mutex<CortexBrains>>,
+    ) -> Self {
         Self {
-            last_run: Instant::now(),
-            interval,
             cycle: 0,
             topology,
+            metrics_snapshot,
+            journal,
+            brains,
+        }
+    }
+
+    fn observe(&self, bus: &Bus) -> Observation {
+        let topology = lock(&self.topology).clone();
+        let awareness = if topology.organs.is_empty() {
+            bus.awareness_score
+        } else {
+            organism::compute_awareness(&topology)
+        };
+        let alerts = topology
+            .organs
+            .iter()
+            .filter(|o| classify_health(o.health) != "ok")
+            .map(|o| OrganAlert {
+                organ: format!("{:?}", o.kind),
+                health: o.health,
+                level: classify_health(o.health),
+            })
+            .collect();
+        let metrics = *lock(&self.metrics_snapshot);
+
+        Observation {
+            cycle: self.cycle,
+            topology,
+            metrics,
+            memory: bus.memory.clone(),
+            alerts,
+            awareness,
+            sim_level: bus.sim_level,
         }
     }
 }
@@ -304,683 +814,414 @@ impl Daemon for AiDaemon {
         "ai-cortex"
     }
 
-    fn tick(&mut self, now: Instant, bus: &mut Bus) {
-        if now.duration_since(self.last_run) < self.interval {
-            return;
-        }
-
+    fn tick(&mut self, _now: Instant, bus: &mut Bus) {
         self.cycle += 1;
-        self.last_run = now;
 
-        let awareness = if let Ok(topo) = self.topology.lock() {
-            organism::compute_awareness(&*topo)
-        } else {
-            bus.awareness_score
-        };
+        let obs = self.observe(bus);
+        let awareness = obs.awareness;
         let label = organism::describe_awareness(awareness);
+        let inputs = obs.inputs();
 
-        // Tiny policy brain: decide what we *would* do.
-        let policy = if awareness >= 0.85 {
-            "policy=push_capacity"          // safe to run heavy workloads
-        } else if awareness >= 0.60 {
-            "policy=maintain_load"          // keep as is
-        } else if awareness >= 0.35 {
-            "policy=reduce_load"            // consider reducing sim/load
-        } else if awareness > 0.0 {
-            // Critical: also force sim_level off as a protective reflex.
-            if bus.sim_level != SimLevel::Off {
-                bus.sim_level = SimLevel::Off;
+        // Close out older decisions whose outcome is now known.
+        let resolved = lock(&self.journal).resolve_outcomes(self.cycle, awareness);
+
+        let (brain, decision) = {
+            let mut brains = lock(&self.brains);
+            for rec in &resolved {
+                brains.feedback(rec);
             }
-            "policy=protect_core(sim_off)"   // emergency mode
-        } else {
-            "policy=recover_offline"        // unconscious
+            let brain = brains.active_name();
+            (brain, brains.active_mut().decide(&obs))
         };
 
+        let actions = decision.effective_actions(&inputs.sim_level);
+        for action in &actions {
+            // e.g. critical awareness forces sim_level off as a protective reflex.
+            if let Some(("sim_level", level)) = action.split_once('=') {
+                if let Some(level) = SimLevel::from_name(level) {
+                    bus.sim_level = level;
+                }
+            }
+        }
+        let policy = decision.policy.as_str();
+
         // Write policy + awareness into the shared MemoryBus (global scope).
         bus.memory
             .set_text(MemoryScope::Global, "cortex.policy", policy);
+        bus.memory
+            .set_text(MemoryScope::Global, "cortex.brain", brain);
         bus.memory
             .set_text(MemoryScope::Global, "cortex.awareness", format!("{:.3}", awareness));
         bus.memory
             .set_text(MemoryScope::Global, "cortex.awareness_label", label);
 
-        let msg = format!(
-            "cortex cycle #{} :: awareness {:.2} ({}) :: {}",
-            self.cycle, awareness, label, policy
-        );
+        lock(&self.journal).record(self.cycle, inputs, brain, &decision.rule, policy, actions.clone());
 
-        bus.emit(PulseKind::Ai, self.name(), msg);
+        let payload = PulsePayload::PolicyDecision {
+            cycle: self.cycle,
+            awareness,
+            awareness_label: label.to_string(),
+            brain: brain.to_string(),
+            policy: policy.to_string(),
+            actions,
+        };
+        bus.emit(PulseKind::Ai, self.name(), payload);
     }
 }
 
-/// A daemon that simulates environmental pressure / recovery.
-/// This is separate from telemetry and purely synthetic, controlled by sim_level.
-pub struct SimulationDaemon {
-    last_run: Instant,
-    interval: Duration,
-    tick: u64,
+/// A daemon that runs the homeostatic controllers: reads each setpoint's
+/// metric, steps its PID and moves the actuator knobs.
+pub struct HomeostasisDaemon {
+    /// When the controllers last stepped, for the PID time delta.
+    last_run: Option<Instant>,
     topology: Arc<Mutex<SystemTopology>>,
+    metrics_snapshot: Arc<Mutex<Option<TelemetrySnapshot>>>,
+    homeostasis: Arc<Mutex<Homeostasis>>,
+    capabilities: Arc<Mutex<CapabilityRegistry>>,
+    /// Capabilities a reflex switched stay put until it recovers.
+    reflexes: Arc<Mutex<ReflexArcs>>,
 }
 
-impl SimulationDaemon {
-    pub fn new(interval: Duration, topology: Arc<Mutex<SystemTopology>>) -> Self {
+impl HomeostasisDaemon {
+    pub fn new(
+        topology: Arc<Mutex<SystemTopology>>,
+        metrics_snapshot: Arc<Mutex<Option<TelemetrySnapshot>>>,
+        homeostasis: Arc<Mutex<Homeostasis>>,
+        capabilities: Arc<Mutex<CapabilityRegistry>>,
+        reflexes: Arc<Mutex<ReflexArcs>>,
+    ) -> Self {
         Self {
-            last_run: Instant::now(),
-            interval,
-            tick: 0,
+            last_run: None,
             topology,
+            metrics_snapshot,
+            homeostasis,
+            capabilities,
+            reflexes,
         }
     }
-
-    fn nudge_health(organ: &mut Organ, delta: f32) {
-        organ.health = (organ.health + delta).clamp(0.0, 1.0);
-    }
 }
 
-impl Daemon for SimulationDaemon {
+impl Daemon for HomeostasisDaemon {
     fn name(&self) -> &'static str {
-        "sim"
+        "homeostasis"
     }
 
-    fn tick(&mut self, now: Instant, bus: &mut Bus) {
-        if now.duration_since(self.last_run) < self.interval {
-            return;
-        }
-
-        self.last_run = now;
-        self.tick = self.tick.wrapping_add(1);
-
-        if bus.sim_level == SimLevel::Off {
-            return;
-        }
+    /// Don't integrate the controllers over time spent stopped.
+    fn start(&mut self, _now: Instant, _bus: &mut Bus) -> Result<(), String> {
+        self.last_run = None;
+        Ok(())
+    }
 
-        if let Ok(mut topo) = self.topology.lock() {
-            if topo.organs.is_empty() {
-                return;
-            }
+    fn tick(&mut self, now: Instant, bus: &mut Bus) {
+        let elapsed = self.last_run.map_or(Duration::ZERO, |t| now.duration_since(t));
+        self.last_run = Some(now);
 
-            let idx = (self.tick % topo.organs.len() as u64) as usize;
-            let organ = &mut topo.organs[idx];
+        let snapshot = *lock(&self.metrics_snapshot);
+        let topology = lock(&self.topology).clone();
+        let held = lock(&self.reflexes).held_capabilities(now);
 
-            let (delta, label) = match bus.sim_level {
-                SimLevel::Low => {
-                    // Mostly small negative hits, occasional recovery.
-                    if self.tick % 5 == 0 {
-                        (0.02, "recovery")
-                    } else {
-                        (-0.01, "stress")
-                    }
-                }
-                SimLevel::High => {
-                    if self.tick % 3 == 0 {
-                        (0.03, "recovery")
-                    } else {
-                        (-0.04, "stress")
-                    }
-                }
-                SimLevel::Off => {
-                    return;
-                }
-            };
+        let outputs = lock(&self.homeostasis).step(elapsed.as_secs_f32(), bus.sim_level, &held, |m| {
+            metric_value(m, snapshot.as_ref(), &topology)
+        });
 
-            Self::nudge_health(organ, delta);
+        if (outputs.throttle - bus.workload_throttle).abs() >= 0.05
+            || (outputs.throttle == 0.0 && bus.workload_throttle != 0.0)
+        {
+            bus.emit(
+                PulseKind::Status,
+                self.name(),
+                PulsePayload::Actuation {
+                    knob: "workload_throttle".to_string(),
+                    from: format!("{:.2}", bus.workload_throttle),
+                    to: format!("{:.2}", outputs.throttle),
+                },
+            );
+        }
+        bus.workload_throttle = outputs.throttle;
 
-            let msg = format!(
-                "{} tick on {:?}: health now {:.2}",
-                label, organ.kind, organ.health
+        if let Some(level) = outputs.sim_level {
+            bus.emit(
+                PulseKind::Status,
+                self.name(),
+                PulsePayload::Actuation {
+                    knob: "sim_level".to_string(),
+                    from: format!("{:?}", bus.sim_level),
+                    to: format!("{:?}", level),
+                },
             );
-            bus.emit(PulseKind::Sim, self.name(), msg);
+            bus.sim_level = level;
+        }
+
+        if !outputs.capabilities.is_empty() {
+            Homeostasis::apply_capabilities(&outputs, &mut lock(&self.capabilities));
+            for (kind, disabled) in &outputs.capabilities {
+                bus.emit(
+                    PulseKind::Status,
+                    self.name(),
+                    PulsePayload::Actuation {
+                        knob: format!("caps:{:?}", kind),
+                        from: if *disabled { "enabled" } else { "disabled" }.to_string(),
+                        to: if *disabled { "disabled" } else { "enabled" }.to_string(),
+                    },
+                );
+            }
         }
     }
 }
 
-/// A daemon that processes user commands from stdin.
-/// This is the first AION "shell" interface.
-pub struct CommandDaemon {
-    rx: Receiver<String>,
+/// A daemon that simulates environmental pressure / recovery.
+/// This is separate from telemetry and purely synthetic, controlled by sim_level.
+pub struct SimulationDaemon {
+    tick: u64,
     topology: Arc<Mutex<SystemTopology>>,
-    metrics_snapshot: Arc<Mutex<Option<TelemetrySnapshot>>>,
 }
 
-impl CommandDaemon {
-    pub fn new(
-        rx: Receiver<String>,
-        topology: Arc<Mutex<SystemTopology>>,
-        metrics_snapshot: Arc<Mutex<Option<TelemetrySnapshot>>>,
-    ) -> Self {
+impl SimulationDaemon {
+    pub fn new(topology: Arc<Mutex<SystemTopology>>) -> Self {
         Self {
-            rx,
+            tick: 0,
             topology,
-            metrics_snapshot,
         }
     }
 
-    fn parse_organ_kind(name: &str) -> Option<OrganKind> {
-        match name.to_lowercase().as_str() {
-            "cortex" => Some(OrganKind::Cortex),
-            "memory" => Some(OrganKind::Memory),
-            "iobridge" | "io" => Some(OrganKind::IoBridge),
-            "sensorhub" => Some(OrganKind::SensorHub),
-            "motorcontrol" | "motor" => Some(OrganKind::MotorControl),
-            "network" => Some(OrganKind::Network),
-            "storage" => Some(OrganKind::Storage),
-            _ => None,
-        }
+    fn nudge_health(organ: &mut Organ, delta: f32) {
+        organ.health = (organ.health + delta).clamp(0.0, 1.0);
     }
+}
 
-    fn organ_health_report(topology: &SystemTopology) -> String {
-        let mut out = String::new();
-        out.push_str("Organ health:\n");
-        for organ in &topology.organs {
-            let label = classify_health(organ.health);
-            out.push_str(&format!(" - {:?}: {:.2} ({})\n", organ.kind, organ.health, label));
-        }
-        out
+impl Daemon for SimulationDaemon {
+    fn name(&self) -> &'static str {
+        "sim"
     }
 
-    fn alerts_report(topology: &SystemTopology) -> String {
-        let mut out = String::new();
-        out.push_str("Alerts:\n");
-
-        let mut any = false;
-        let mut min_health: f32 = 1.0;
-
-        for organ in &topology.organs {
-            min_health = min_health.min(organ.health);
-            let label = classify_health(organ.health);
-            if label != "ok" {
-                any = true;
-                out.push_str(&format!(" - {:?}: {:.2} [{}]\n", organ.kind, organ.health, label));
-            }
-        }
+    fn tick(&mut self, _now: Instant, bus: &mut Bus) {
+        self.tick = self.tick.wrapping_add(1);
 
-        if !any {
-            out.push_str(" (no active alerts; all organs healthy)\n");
-        } else {
-            out.push_str(&format!("overall: {}\n", classify_health(min_health)));
+        if bus.sim_level == SimLevel::Off {
+            return;
         }
 
-        out
-    }
-
-    fn sim_status_report(topology: &SystemTopology, bus: &Bus) -> String {
-        let min_health = compute_overall_health(topology);
-        let awareness = bus.awareness_score;
-        format!(
-            "simulation status: level={:?} :: min health {:.2} :: awareness {:.2}",
-            bus.sim_level, min_health, awareness
-        )
-    }
-
-    fn handle_damage(
-        &self,
-        parts: &[&str],
-        bus: &mut Bus,
-    ) -> Option<String> {
-        if parts.len() != 3 {
-            return Some("usage: damage <organ> <amount>".to_string());
+        let mut topo = lock(&self.topology);
+        if topo.organs.is_empty() {
+            return;
         }
-        let organ_name = parts[1];
-        let amount_str = parts[2];
-        let amount: f32 = match amount_str.parse() {
-            Ok(v) => v,
-            Err(_) => {
-                return Some(format!("invalid amount: {}", amount_str));
-            }
-        };
 
-        let kind = match Self::parse_organ_kind(organ_name) {
-            Some(k) => k,
-            None => {
-                return Some(format!("unknown organ '{}'", organ_name));
-            }
-        };
+        let idx = (self.tick % topo.organs.len() as u64) as usize;
+        let organ = &mut topo.organs[idx];
 
-        if let Ok(mut topo) = self.topology.lock() {
-            let mut new_health = None;
-            for organ in &mut topo.organs {
-                if organ.kind == kind {
-                    organ.health = (organ.health - amount).clamp(0.0, 1.0);
-                    new_health = Some(organ.health);
-                    break;
+        let (delta, label) = match bus.sim_level {
+            SimLevel::Low => {
+                // Mostly small negative hits, occasional recovery.
+                if self.tick.is_multiple_of(5) {
+                    (0.02, "recovery")
+                } else {
+                    (-0.01, "stress")
                 }
             }
-            if let Some(h) = new_health {
-                let awareness = organism::compute_awareness(&*topo);
-                bus.awareness_score = awareness;
-                let label = organism::describe_awareness(awareness);
-                return Some(format!(
-                    "damaged {:?} by {:.2}, new health {:.2} (awareness {:.2} {})",
-                    kind, amount, h, awareness, label
-                ));
-            } else {
-                return Some(format!("organ {:?} not found in topology", kind));
+            SimLevel::High => {
+                if self.tick.is_multiple_of(3) {
+                    (0.03, "recovery")
+                } else {
+                    (-0.04, "stress")
+                }
             }
-        } else {
-            Some("failed to lock topology for damage".to_string())
-        }
-    }
-
-    fn handle_heal(
-        &self,
-        parts: &[&str],
-        bus: &mut Bus,
-    ) -> Option<String> {
-        if parts.len() != 3 {
-            return Some("usage: heal <organ> <amount>".to_string());
-        }
-        let organ_name = parts[1];
-        let amount_str = parts[2];
-        let amount: f32 = match amount_str.parse() {
-            Ok(v) => v,
-            Err(_) => {
-                return Some(format!("invalid amount: {}", amount_str));
+            SimLevel::Off => {
+                return;
             }
         };
 
-        let kind = match Self::parse_organ_kind(organ_name) {
-            Some(k) => k,
-            None => {
-                return Some(format!("unknown organ '{}'", organ_name));
-            }
-        };
+        let before = organ.health;
+        Self::nudge_health(organ, delta);
 
-        if let Ok(mut topo) = self.topology.lock() {
-            let mut new_health = None;
-            for organ in &mut topo.organs {
-                if organ.kind == kind {
-                    organ.health = (organ.health + amount).clamp(0.0, 1.0);
-                    new_health = Some(organ.health);
-                    break;
-                }
-            }
-            if let Some(h) = new_health {
-                let awareness = organism::compute_awareness(&*topo);
-                bus.awareness_score = awareness;
-                let label = organism::describe_awareness(awareness);
-                return Some(format!(
-                    "healed {:?} by {:.2}, new health {:.2} (awareness {:.2} {})",
-                    kind, amount, h, awareness, label
-                ));
-            } else {
-                return Some(format!("organ {:?} not found in topology", kind));
-            }
-        } else {
-            Some("failed to lock topology for heal".to_string())
-        }
+        let payload = PulsePayload::HealthChange {
+            organ: format!("{:?}", organ.kind),
+            cause: label.to_string(),
+            before,
+            after: organ.health,
+        };
+        bus.emit(PulseKind::Sim, self.name(), payload);
     }
+}
 
-    fn handle_save_state(&self, _bus: &mut Bus) -> String {
-        if let Ok(topo) = self.topology.lock() {
-            let mut lines = Vec::new();
-            for organ in &topo.organs {
-                lines.push(format!("{:?} {:.5}", organ.kind, organ.health));
-            }
-            match fs::write("aion_state.txt", lines.join("\n")) {
-                Ok(_) => "state saved to aion_state.txt".to_string(),
-                Err(e) => format!("failed to save state: {}", e),
-            }
-        } else {
-            "failed to lock topology for save".to_string()
+/// A shell session's state, kept by the command daemon.
+struct Session {
+    output: OutputMode,
+    /// Subscriptions opened from the session, passed on as they arrive.
+    watches: Vec<(u64, Subscriber)>,
+    /// Where a remote session's subscription output goes; the console
+    /// prints its own.
+    events: Option<Sender<String>>,
+}
+
+impl Session {
+    fn new(events: Option<Sender<String>>) -> Self {
+        Self {
+            output: OutputMode::Text,
+            watches: Vec::new(),
+            events,
         }
     }
+}
 
-    fn handle_load_state(&self, bus: &mut Bus) -> String {
-        let content = match fs::read_to_string("aion_state.txt") {
-            Ok(c) => c,
-            Err(e) => return format!("failed to load state: {}", e),
-        };
+/// A daemon that processes user commands from stdin.
+/// This is the first AION "shell" interface; control socket clients
+/// reach it too, each in a session of its own.
+pub struct CommandDaemon {
+    rx: Receiver<ShellRequest>,
+    state: KernelState,
+    sessions: HashMap<SessionId, Session>,
+    input_closed: bool,
+    /// A control listener can still bring in commands.
+    listening: bool,
+}
 
-        if let Ok(mut topo) = self.topology.lock() {
-            for line in content.lines() {
-                let mut parts = line.split_whitespace();
-                let kind_str = match parts.next() {
-                    Some(k) => k,
-                    None => continue,
-                };
-                let health_str = match parts.next() {
-                    Some(h) => h,
-                    None => continue,
-                };
+impl CommandDaemon {
+    pub fn new(rx: Receiver<ShellRequest>, state: &KernelState) -> Self {
+        Self {
+            rx,
+            state: state.clone(),
+            sessions: HashMap::from([(CONSOLE_SESSION, Session::new(None))]),
+            input_closed: false,
+            listening: false,
+        }
+    }
 
-                let kind = match Self::parse_organ_kind(kind_str) {
-                    Some(k) => k,
-                    None => continue,
+    /// Pass on whatever arrived on each session's subscriptions. A
+    /// session that has gone away loses its subscriptions.
+    fn drain_watches(&mut self) {
+        for session in self.sessions.values_mut() {
+            let (output, events) = (session.output, &session.events);
+            session.watches.retain(|(id, rx)| loop {
+                let pulse = match rx.try_recv() {
+                    Ok(pulse) => pulse,
+                    Err(TryRecvError::Empty) => break true,
+                    Err(TryRecvError::Disconnected) => break false,
                 };
-
-                let h: f32 = match health_str.parse() {
-                    Ok(v) => v,
-                    Err(_) => continue,
+                let line = match output {
+                    OutputMode::Text => format!("[SUB#{}] {}", id, pulse.log_line()),
+                    OutputMode::Json => format!(r#"{{"subscription":{},"pulse":{}}}"#, id, pulse.to_json()),
                 };
-
-                for organ in &mut topo.organs {
-                    if organ.kind == kind {
-                        organ.health = h.clamp(0.0, 1.0);
-                        break;
+                match events {
+                    Some(tx) => {
+                        if tx.send(line).is_err() {
+                            break false;
+                        }
                     }
+                    None => editor::print_line(&line),
                 }
-            }
-
-            let awareness = organism::compute_awareness(&*topo);
-            bus.awareness_score = awareness;
-            let label = organism::describe_awareness(awareness);
-            format!(
-                "state loaded from aion_state.txt (awareness {:.2} {})",
-                awareness, label
-            )
-        } else {
-            "failed to lock topology for load".to_string()
+            });
         }
     }
 
-    fn handle_mem(parts: &[&str], bus: &mut Bus) -> Option<String> {
-        // mem / mem ls – list everything (text dump)
-        if parts.len() == 1 || (parts.len() == 2 && parts[1] == "ls") {
-            return Some(bus.memory.dump());
-        }
-
-        // mem get <key>
-        if parts.len() >= 3 && parts[1] == "get" {
-            let key = parts[2];
-            match bus.memory.get(MemoryScope::Global, key) {
-                Some(v) => Some(format!("mem[{}] = {}", key, v)),
-                None => Some(format!("mem: key '{}' not found", key)),
-            }
-        }
-        // mem set <key> <value>
-        else if parts.len() >= 4 && parts[1] == "set" {
-            let key = parts[2];
-            let value = parts[3..].join(" ");
-            bus.memory.set_text(MemoryScope::Global, key, value);
-            Some(format!("mem[{}] updated", key))
-        } else {
-            Some(
-                "usage: mem [ls] | mem get <key> | mem set <key> <value>".to_string(),
-            )
+    fn unsubscribe(watches: &mut Vec<(u64, Subscriber)>, bus: &Bus) {
+        let pubsub = bus.pubsub();
+        let mut pubsub = lock(&pubsub);
+        for (id, _) in watches.drain(..) {
+            pubsub.unsubscribe(id);
         }
     }
 }
 
 impl Daemon for CommandDaemon {
     fn name(&self) -> &'static str {
-        "command"
+        registry::COMMAND_DAEMON
     }
 
     fn tick(&mut self, _now: Instant, bus: &mut Bus) {
+        self.drain_watches();
+
         loop {
             match self.rx.try_recv() {
-                Ok(cmd) => {
-                    let trimmed = cmd.trim();
+                Ok(ShellRequest::Run { line, session, reply }) => {
+                    let trimmed = line.trim();
                     if trimmed.is_empty() {
                         continue;
                     }
+                    // Lines outside a session share the console's
+                    // subscriptions.
+                    let Some(s) = self.sessions.get_mut(&session.unwrap_or(CONSOLE_SESSION)) else {
+                        continue;
+                    };
 
-                    let parts: Vec<&str> = trimmed.split_whitespace().collect();
-
-                    let response = match parts[0] {
-                        "help" => Some(
-                            "commands: help, status, topology, nodes, organs, peripherals, health, \
-                             awareness, metrics, mode, alerts, sim status, sim level <off|low|high>, \
-                             mem, mem get <key>, mem set <key> <value>, \
-                             save state, load state, damage <organ> <amount>, heal <organ> <amount>, \
-                             logs all, logs commands, logs silent, quit"
-                                .to_string(),
-                        ),
-
-                        "status" => {
-                            if let Ok(topo) = self.topology.lock() {
-                                let brief = format_topology_brief(&*topo);
-                                let overall_health = compute_overall_health(&*topo);
-                                let health_label = classify_health(overall_health);
-                                let awareness = bus.awareness_score;
-                                let awareness_label = organism::describe_awareness(awareness);
-                                Some(format!(
-                                    "manual status :: {} :: health {:.2} ({}) :: awareness {:.2} ({})",
-                                    brief, overall_health, health_label, awareness, awareness_label
-                                ))
-                            } else {
-                                Some("failed to lock topology".to_string())
-                            }
-                        }
-
-                        "topology" => {
-                            if let Ok(topo) = self.topology.lock() {
-                                let mut details = String::new();
-                                details.push_str("Topology detail:\n");
-                                for node in &topo.nodes {
-                                    details.push_str(&format!(
-                                        " - Node {} [{}]: {}\n",
-                                        node.id.0, node.label, node.role
-                                    ));
-                                }
-                                for organ in &topo.organs {
-                                    details.push_str(&format!(
-                                        "   - Organ {:?} on Node {} (health {:.2})\n",
-                                        organ.kind, organ.node.0, organ.health
-                                    ));
-                                }
-                                Some(details)
-                            } else {
-                                Some("failed to lock topology".to_string())
-                            }
-                        }
-
-                        "nodes" => {
-                            if let Ok(topo) = self.topology.lock() {
-                                let mut details = String::new();
-                                details.push_str("Nodes:\n");
-                                for node in &topo.nodes {
-                                    details.push_str(&format!(
-                                        " - Node {} [{}]: {}\n",
-                                        node.id.0, node.label, node.role
-                                    ));
-                                }
-                                Some(details)
-                            } else {
-                                Some("failed to lock topology".to_string())
-                            }
-                        }
-
-                        "organs" => {
-                            if let Ok(topo) = self.topology.lock() {
-                                let mut details = String::new();
-                                details.push_str("Organs:\n");
-                                for organ in &topo.organs {
-                                    details.push_str(&format!(
-                                        " - Organ {:?} on Node {} (health {:.2})\n",
-                                        organ.kind, organ.node.0, organ.health
-                                    ));
-                                }
-                                Some(details)
-                            } else {
-                                Some("failed to lock topology".to_string())
-                            }
-                        }
-
-                        "peripherals" => {
-                            if let Ok(topo) = self.topology.lock() {
-                                let mut details = String::new();
-                                details.push_str("Peripherals by organ:\n");
-                                for organ in &topo.organs {
-                                    if organ.peripherals.is_empty() {
-                                        continue;
-                                    }
-                                    details.push_str(&format!(" - Organ {:?}:\n", organ.kind));
-                                    for p in &organ.peripherals {
-                                        details.push_str(&format!("    - {:?}: {}\n", p.kind, p.name));
-                                    }
-                                }
-                                if !details.contains("Organ") {
-                                    details.push_str(" (no peripherals registered)\n");
-                                }
-                                Some(details)
-                            } else {
-                                Some("failed to lock topology".to_string())
-                            }
-                        }
-
-                        "health" => {
-                            if let Ok(topo) = self.topology.lock() {
-                                Some(Self::organ_health_report(&*topo))
-                            } else {
-                                Some("failed to lock topology for health".to_string())
-                            }
-                        }
-
-                        "awareness" => {
-                            if let Ok(topo) = self.topology.lock() {
-                                let awareness = organism::compute_awareness(&*topo);
-                                let label = organism::describe_awareness(awareness);
-                                Some(format!("awareness index: {:.2} :: {}", awareness, label))
-                            } else {
-                                Some("failed to lock topology for awareness".to_string())
-                            }
-                        }
-
-                        "alerts" => {
-                            if let Ok(topo) = self.topology.lock() {
-                                Some(Self::alerts_report(&*topo))
-                            } else {
-                                Some("failed to lock topology for alerts".to_string())
-                            }
-                        }
-
-                        "mode" => {
-                            let tele_str = match bus.telemetry_mode {
-                                TelemetryMode::Simulated => "simulated",
-                                TelemetryMode::Real => "real",
-                            };
-                            Some(format!(
-                                "mode :: telemetry={} :: sim_level={:?}",
-                                tele_str, bus.sim_level
-                            ))
-                        }
-
-                        "metrics" => {
-                            match self.metrics_snapshot.lock() {
-                                Ok(guard) => {
-                                    if let Some(snap) = *guard {
-                                        let mut out = String::new();
-                                        out.push_str("Metrics snapshot (from status daemon):\n");
-                                        out.push_str(" Cortex / CPU+GPU:\n");
-                                        out.push_str(&format!(
-                                            "  cpu_load      : {:.2}\n  cpu_temp_c    : {:.1}\n  throttling    : {}\n  gpu_load      : {:.2}\n  gpu_mem_util  : {:.2}\n",
-                                            snap.cpu.cpu_load,
-                                            snap.cpu.cpu_temp_c,
-                                            snap.cpu.throttling_events,
-                                            snap.cpu.gpu_load,
-                                            snap.cpu.gpu_mem_util,
-                                        ));
-                                        out.push_str(" Memory:\n");
-                                        out.push_str(&format!(
-                                            "  ram_used      : {:.2}\n  swap_used     : {:.2}\n  page_faults   : {:.1}\n  disk_latency  : {:.1} ms\n",
-                                            snap.mem.ram_used_ratio,
-                                            snap.mem.swap_used_ratio,
-                                            snap.mem.major_page_faults,
-                                            snap.mem.disk_latency_ms,
-                                        ));
-                                        out.push_str(" IoBridge / IO+Net:\n");
-                                        out.push_str(&format!(
-                                            "  net_loss      : {:.3}\n  net_latency   : {:.1} ms\n  io_queue      : {:.2}\n  io_error_rate : {:.3}\n",
-                                            snap.io.net_packet_loss,
-                                            snap.io.net_latency_ms,
-                                            snap.io.io_queue_depth,
-                                            snap.io.io_error_rate,
-                                        ));
-                                        Some(out)
-                                    } else {
-                                        Some(
-                                            "metrics not yet available (status daemon has not produced a snapshot)"
-                                                .to_string(),
-                                        )
-                                    }
-                                }
-                                Err(_) => Some("failed to lock metrics snapshot".to_string()),
-                            }
-                        }
-
-                        "sim" if parts.len() > 1 && parts[1] == "status" => {
-                            if let Ok(topo) = self.topology.lock() {
-                                Some(Self::sim_status_report(&*topo, bus))
-                            } else {
-                                Some("failed to lock topology for sim status".to_string())
-                            }
-                        }
-
-                        "sim" if parts.len() > 2 && parts[1] == "level" => {
-                            let level_str = parts[2].to_lowercase();
-                            match level_str.as_str() {
-                                "off" => {
-                                    bus.sim_level = SimLevel::Off;
-                                    Some("simulation level set to off".to_string())
-                                }
-                                "low" => {
-                                    bus.sim_level = SimLevel::Low;
-                                    Some("simulation level set to low".to_string())
-                                }
-                                "high" => {
-                                    bus.sim_level = SimLevel::High;
-                                    Some("simulation level set to high".to_string())
-                                }
-                                _ => Some(
-                                    "usage: sim level <off|low|high>".to_string(),
-                                ),
-                            }
-                        }
-
-                        "mem" => Self::handle_mem(&parts, bus),
-
-                        "damage" => self.handle_damage(&parts, bus),
-                        "heal" => self.handle_heal(&parts, bus),
-
-                        "save" if parts.len() > 1 && parts[1] == "state" => {
-                            Some(self.handle_save_state(bus))
-                        }
-
-                        "load" if parts.len() > 1 && parts[1] == "state" => {
-                            Some(self.handle_load_state(bus))
-                        }
-
-                        "logs" if parts.len() > 1 && parts[1] == "all" => {
-                            bus.log_filter = LogFilter::All;
-                            Some("logging: ALL pulses".to_string())
-                        }
-                        "logs" if parts.len() > 1 && parts[1] == "commands" => {
-                            bus.log_filter = LogFilter::CommandsOnly;
-                            Some("logging: COMMANDS ONLY".to_string())
-                        }
-                        "logs" if parts.len() > 1 && (parts[1] == "silent" || parts[1] == "off") => {
-                            bus.log_filter = LogFilter::Silent;
-                            Some("logging: SILENT".to_string())
-                        }
-
-                        "quit" => {
-                            Some("shutting down kernel (process::exit(0))".to_string())
-                        }
-
-                        _ => Some(format!("unknown command: '{}'", trimmed)),
+                    let mut ctx = ShellContext {
+                        state: &self.state,
+                        bus,
+                        line: trimmed,
+                        watches: &mut s.watches,
+                        output: if session.is_some() { s.output } else { OutputMode::Text },
+                        quit: false,
                     };
+                    let result = self.state.commands.dispatch(&mut ctx);
+                    let quit = ctx.quit;
+                    if session.is_some() {
+                        s.output = ctx.output;
+                    }
 
-                    if let Some(msg) = response {
-                        bus.emit(PulseKind::Command, self.name(), msg);
+                    let payload = PulsePayload::CommandResult {
+                        command: trimmed.to_string(),
+                        output: result.output.clone(),
+                    };
+                    // The console shows JSON results in place of the pulse.
+                    let json_console = session == Some(CONSOLE_SESSION) && result.mode == OutputMode::Json;
+                    if json_console {
+                        editor::print_line(&result.to_json());
+                        bus.log_sinks.mute_console(true);
+                    }
+                    bus.emit(PulseKind::Command, self.name(), payload);
+                    bus.log_sinks.mute_console(false);
+                    if let Some(reply) = reply {
+                        let _ = reply.send(result);
+                    }
 
-                        if trimmed == "quit" {
-                            process::exit(0);
-                        }
+                    if quit {
+                        editor::restore_terminal();
+                        process::exit(0);
                     }
                 }
+                Ok(ShellRequest::Complete { line, reply }) => {
+                    let _ = reply.send(self.state.commands.complete(&self.state, bus, &line));
+                }
+                Ok(ShellRequest::OpenSession { session, events }) => {
+                    self.sessions.insert(session, Session::new(Some(events)));
+                }
+                Ok(ShellRequest::CloseSession(session)) => {
+                    if let Some(mut s) = self.sessions.remove(&session) {
+                        Self::unsubscribe(&mut s.watches, bus);
+                    }
+                }
+                Ok(ShellRequest::ListenerOpened) => self.listening = true,
                 Err(TryRecvError::Empty) => break,
-                Err(TryRecvError::Disconnected) => {
-                    bus.emit(
-                        PulseKind::Command,
-                        self.name(),
-                        "command input channel disconnected",
-                    );
+                Ok(ShellRequest::InputClosed) | Err(TryRecvError::Disconnected) => {
+                    // Report once; stdin stays closed but the daemon keeps running.
+                    if !self.input_closed {
+                        self.input_closed = true;
+                        bus.emit(
+                            PulseKind::Command,
+                            self.name(),
+                            "command input channel disconnected",
+                        );
+                    }
                     break;
                 }
             }
         }
     }
+
+    fn stop(&mut self, bus: &mut Bus) {
+        for s in self.sessions.values_mut() {
+            Self::unsubscribe(&mut s.watches, bus);
+        }
+    }
+
+    fn health(&self) -> Result<(), String> {
+        // Under a service manager stdin is closed from the start; that
+        // is only a fault when nothing else can send commands.
+        if self.input_closed && !self.listening {
+            Err("command input closed".to_string())
+        } else {
+            Ok(())
+        }
+    }
 }
 
 /// Compute an overall health score from the topology.
@@ -995,6 +1236,85 @@ pub fn compute_overall_health(topo: &SystemTopology) -> f32 {
         .fold(1.0, |acc, h| acc.min(h))
 }
 
+/// Metric names understood by `metric_value`, besides `health.<organ>`.
+pub const METRIC_NAMES: &[&str] = &[
+    "awareness",
+    "health",
+    "cpu_load",
+    "cpu_temp_c",
+    "throttling_events",
+    "gpu_load",
+    "gpu_mem_util",
+    "ram_used_ratio",
+    "swap_used_ratio",
+    "major_page_faults",
+    "disk_latency_ms",
+    "net_packet_loss",
+    "net_latency_ms",
+    "io_queue_depth",
+    "io_error_rate",
+];
+
+/// Organ names as typed in the shell and in metric names.
+pub fn parse_organ_kind(name: &str) -> Option<OrganKind> {
+    match name.to_lowercase().as_str() {
+        "cortex" => Some(OrganKind::Cortex),
+        "memory" => Some(OrganKind::Memory),
+        "iobridge" | "io" => Some(OrganKind::IoBridge),
+        "sensorhub" => Some(OrganKind::SensorHub),
+        "motorcontrol" | "motor" => Some(OrganKind::MotorControl),
+        "network" => Some(OrganKind::Network),
+        "storage" => Some(OrganKind::Storage),
+        _ => None,
+    }
+}
+
+/// Is `name` something `metric_value` can resolve?
+pub fn is_known_metric(name: &str) -> bool {
+    match name.strip_prefix("health.") {
+        Some(organ) => parse_organ_kind(organ).is_some(),
+        None => METRIC_NAMES.contains(&name),
+    }
+}
+
+/// Look up a named metric: any field of the telemetry snapshot
+/// (`cpu_temp_c`, `ram_used_ratio`, …), `awareness`, `health` (overall),
+/// or `health.<organ>`.
+pub fn metric_value(
+    name: &str,
+    snapshot: Option<&TelemetrySnapshot>,
+    topology: &SystemTopology,
+) -> Option<f32> {
+    match name {
+        "awareness" => return Some(organism::compute_awareness(topology)),
+        "health" => return Some(compute_overall_health(topology)),
+        _ => {}
+    }
+    if let Some(organ) = name.strip_prefix("health.") {
+        let kind = parse_organ_kind(organ)?;
+        return topology.organs.iter().find(|o| o.kind == kind).map(|o| o.health);
+    }
+
+    let s = snapshot?;
+    let v = match name {
+        "cpu_load" => s.cpu.cpu_load,
+        "cpu_temp_c" => s.cpu.cpu_temp_c,
+        "throttling_events" => s.cpu.throttling_events as f32,
+        "gpu_load" => s.cpu.gpu_load,
+        "gpu_mem_util" => s.cpu.gpu_mem_util,
+        "ram_used_ratio" => s.mem.ram_used_ratio,
+        "swap_used_ratio" => s.mem.swap_used_ratio,
+        "major_page_faults" => s.mem.major_page_faults,
+        "disk_latency_ms" => s.mem.disk_latency_ms,
+        "net_packet_loss" => s.io.net_packet_loss,
+        "net_latency_ms" => s.io.net_latency_ms,
+        "io_queue_depth" => s.io.io_queue_depth,
+        "io_error_rate" => s.io.io_error_rate,
+        _ => return None,
+    };
+    Some(v)
+}
+
 /// Turn a health score into a simple label.
 fn classify_health(h: f32) -> &'static str {
     if h >= 0.85 {
@@ -1011,7 +1331,9 @@ fn classify_health(h: f32) -> &'static str {
 }
 
 /// Very simple blocking kernel loop that runs all daemons and uses the bus.
-pub fn run_loop(mut bus: Bus) {
+/// `input` runs on its own thread once boot output is done and feeds the
+/// shell: `editor::run` for the console, or a batch script.
+pub fn run_loop(mut bus: Bus, input: impl FnOnce(KernelState) + Send + 'static) {
     println!("[AION-KERNEL] Entering daemon loop. Ctrl+C to exit.");
 
     let topology = Arc::new(Mutex::new(organism::sample_topology()));
@@ -1020,95 +1342,165 @@ pub fn run_loop(mut bus: Bus) {
     let metrics_snapshot: Arc<Mutex<Option<TelemetrySnapshot>>> =
         Arc::new(Mutex::new(None));
 
-    // Start tiny HTTP server (status & metrics & mem).
+    // Cortex decision journal, persisted next to the state file.
+    let journal = Arc::new(Mutex::new(DecisionJournal::open(
+        "aion_cortex_journal.log",
+        Arc::clone(bus.clock()),
+    )));
+
+    // The scheduler owns daemon intervals and sleeps until the next one
+    // is due; bus handles and stdin wake it early.
+    let mut scheduler = Scheduler::new(Arc::clone(bus.clock()));
+    bus.set_waker(scheduler.waker(None));
+    let (shell, cmd_rx) = ShellHandle::channel(scheduler.waker(Some("command")));
+
+    // Control socket for clients without a terminal (AION_CONTROL). Its
+    // token also guards the HTTP shell endpoint.
+    let shell_token = match ControlConfig::from_env() {
+        Ok(Some(cfg)) => match control::start(&cfg, shell.clone()) {
+            Ok((addr, token)) => {
+                println!("[AION-CONTROL] Listening on {}", addr);
+                Some(token)
+            }
+            Err(e) => {
+                println!("[AION-KERNEL] control socket disabled: {}", e);
+                None
+            }
+        },
+        Ok(None) => None,
+        Err(e) => {
+            println!("[AION-KERNEL] control socket disabled: {}", e);
+            None
+        }
+    };
+
+    // Start tiny HTTP server (status & metrics & mem & cortex history).
     let http_server = HttpServer::new("127.0.0.1:8080");
     let mem_for_http = bus.memory.clone();
     http_server.start(
         Arc::clone(&topology),
         Arc::clone(&metrics_snapshot),
         mem_for_http,
+        Arc::clone(&journal),
+        bus.handle(),
+        shell_token.map(|token| ShellEndpoint {
+            shell: shell.clone(),
+            token,
+        }),
     );
 
-    // Set up a channel + thread to read stdin commands.
-    let (cmd_tx, cmd_rx) = mpsc::channel::<String>();
-
-    thread::spawn(move || {
-        let stdin = io::stdin();
-        println!("[AION-CMD] Type commands: help, status, topology");
-        print!("AION> ");
-        io::stdout().flush().unwrap();
-
-        for line in stdin.lock().lines() {
-            match line {
-                Ok(cmd) => {
-                    let cmd = cmd.trim().to_string();
-                    if !cmd.is_empty() {
-                        let _ = cmd_tx.send(cmd);
-                    }
-                    print!("AION> ");
-                    io::stdout().flush().unwrap();
-                }
-                Err(_) => break,
-            }
+    // Durable pulse journal (AION_PULSE_JOURNAL=off disables it).
+    if let Some(cfg) = JournalConfig::from_env() {
+        match PulseJournal::open(cfg, Arc::clone(bus.clock())) {
+            Ok(journal) => bus.attach_journal(journal),
+            Err(e) => println!("[AION-KERNEL] pulse journal disabled: {}", e),
         }
-    });
+    }
 
-    let mut daemons: Vec<Box<dyn Daemon>> = Vec::new();
-
-    // Shared topology for all daemons.
-    let topo_for_status = Arc::clone(&topology);
-    let topo_for_ai = Arc::clone(&topology);
-    let topo_for_sim = Arc::clone(&topology);
-    let topo_for_cmd = Arc::clone(&topology);
-
-    // Metrics snapshot clones.
-    let metrics_for_status = Arc::clone(&metrics_snapshot);
-    let metrics_for_cmd = Arc::clone(&metrics_snapshot);
-
-    // Telemetry provider: select from env var AION_TELEMETRY.
-    let telemetry: Box<dyn TelemetryProvider> = {
-        let mode = std::env::var("AION_TELEMETRY").unwrap_or_else(|_| "sim".to_string());
-        match mode.as_str() {
-            "real" => {
-                bus.telemetry_mode = TelemetryMode::Real;
-                Box::new(RealTelemetry::new(SimLevel::Low))
-            }
-            _ => {
-                bus.telemetry_mode = TelemetryMode::Simulated;
-                Box::new(SimulatedTelemetry::new(SimLevel::Low))
+    // Extra log sinks (AION_LOG_FILE, AION_LOG_SYSLOG).
+    for e in bus.log_sinks.add_from_env() {
+        println!("[AION-KERNEL] {}", e);
+    }
+
+    // Which daemons run: AION_DAEMONS=<path>, else the built-in set
+    // (shaped by AION_TELEMETRY and AION_ALERT_DWELL_SECS).
+    let daemon_config = DaemonConfig::from_env().unwrap_or_else(|e| {
+        println!("[AION-KERNEL] {}; using built-in daemons", e);
+        DaemonConfig::builtin("sim", Duration::ZERO)
+    });
+    bus.telemetry_mode = daemon_config.telemetry_mode();
+
+    // Cortex brains. The ladder is active by default; AION_POLICY loads a
+    // policy file into the rule brain and selects it, AION_ADVISOR_URL adds
+    // an external advisor (falling back to the local rules), and AION_BRAIN
+    // picks any brain by name.
+    let mut rules = PolicySet::default_ladder();
+    let mut initial_brain = "ladder".to_string();
+    if let Ok(path) = std::env::var("AION_POLICY") {
+        match PolicySet::load(&path) {
+            Ok(set) => {
+                rules = set;
+                initial_brain = "rules".to_string();
             }
+            Err(e) => println!("[AION-KERNEL] {}; keeping default brain", e),
         }
+    }
+    let mut cortex_brains = CortexBrains::standard(rules.clone(), bus.clock().unix_millis());
+    if let Some(cfg) = AdvisorConfig::from_env() {
+        println!("[AION-KERNEL] Cortex advisor at {}", cfg.endpoint);
+        cortex_brains.install(Box::new(AdvisorBrain::new(
+            cfg,
+            Box::new(RuleBrain::new(rules)),
+        )));
+        initial_brain = "advisor".to_string();
+    }
+    if let Ok(name) = std::env::var("AION_BRAIN") {
+        initial_brain = name;
+    }
+    if let Err(e) = cortex_brains.select(&initial_brain) {
+        println!("[AION-KERNEL] {}", e);
+    }
+
+    let state = KernelState::new(
+        Arc::clone(&topology),
+        Arc::clone(&metrics_snapshot),
+        journal,
+        cortex_brains,
+        scheduler.table(),
+        shell,
+    );
+    // Scheduled jobs: AION_JOBS=<path>, else a nightly health scan.
+    let now_ms = bus.clock().unix_millis();
+    let loaded = match std::env::var("AION_JOBS") {
+        Ok(path) => lock(&state.jobs).load(&path, now_ms),
+        Err(_) => lock(&state.jobs).load_text(DEFAULT_JOBS, now_ms),
     };
+    if let Err(e) = loaded {
+        println!("[AION-KERNEL] {}", e);
+    }
+    for e in install_daemons(&mut scheduler, &state, &daemon_config, cmd_rx) {
+        println!("[AION-KERNEL] {}", e);
+    }
 
-    // Later: build this list from config, discovery, etc.
-    daemons.push(Box::new(HeartbeatDaemon::new(Duration::from_millis(1000))));
-    daemons.push(Box::new(StatusDaemon::new(
-        Duration::from_millis(5000),
-        topo_for_status,
-        telemetry,
-        metrics_for_status,
-    )));
-    daemons.push(Box::new(AiDaemon::new(
-        Duration::from_millis(2000),
-        topo_for_ai,
-    )));
-    daemons.push(Box::new(SimulationDaemon::new(
-        Duration::from_millis(2500),
-        topo_for_sim,
-    )));
-    daemons.push(Box::new(CommandDaemon::new(
-        cmd_rx,
-        topo_for_cmd,
-        metrics_for_cmd,
-    )));
+    let input_state = state.clone();
+    thread::spawn(move || input(input_state));
 
     loop {
-        let now = Instant::now();
+        // Requests from other threads land first, in arrival order.
+        bus.drain_requests();
 
-        for daemon in daemons.iter_mut() {
-            daemon.tick(now, &mut bus);
-        }
+        scheduler.run_due(bus.clock().now(), &mut bus);
+        scheduler.wait(None);
+    }
+}
 
-        thread::sleep(Duration::from_millis(50));
+/// Jobs scheduled when AION_JOBS is not set. Built-in routines only:
+/// nothing here writes to disk.
+pub const DEFAULT_JOBS: &str = "health-scan cron 0 3 * * * => @health-scan\n";
+
+/// Build the configured daemons and register them with their
+/// schedules, then the command daemon. Returns the specs that failed.
+pub fn install_daemons(
+    scheduler: &mut Scheduler,
+    state: &KernelState,
+    config: &DaemonConfig,
+    commands: Receiver<ShellRequest>,
+) -> Vec<String> {
+    let mut errors = Vec::new();
+    for spec in &config.specs {
+        let waker = match state.registry.kind(&spec.kind) {
+            Some(kind) => scheduler.waker(Some(kind)),
+            None => scheduler.waker(None),
+        };
+        match state.registry.build(spec, &FactoryContext { state, waker }) {
+            Ok(daemon) => scheduler.add(daemon, spec.schedule),
+            Err(e) => errors.push(format!("daemon {}: {}", spec.kind, e)),
+        }
     }
+    // Runs on input, and often enough to print subscription output.
+    scheduler.add(
+        Box::new(CommandDaemon::new(commands, state)),
+        Schedule::every(Duration::from_millis(250)),
+    );
+    errors
 }
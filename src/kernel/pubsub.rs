@@ -0,0 +1,293 @@
+//! Publish/subscribe on the kernel bus.
+//!
+//! A subscriber registers a `PulseFilter` and gets a bounded channel of
+//! matching pulses. Delivery never blocks the kernel loop: when a
+//! subscriber's queue is full the pulse is dropped for that subscriber
+//! and counted. Subscribers that hang up are removed on the next publish
+//! or report, whether or not their filter matches.
+
+use std::ops::Deref;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Weak};
+
+use crate::kernel::pulse::PAYLOAD_TYPES;
+use crate::kernel::{Pulse, PulseKind};
+
+/// Default queue depth for a subscriber.
+pub const DEFAULT_CAPACITY: usize = 256;
+/// Deepest queue a subscriber may ask for; the channel allocates every
+/// slot up front.
+pub const MAX_CAPACITY: usize = 4096;
+
+/// A `cap=N` value, 1 to `MAX_CAPACITY`.
+pub fn parse_capacity(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(n) if (1..=MAX_CAPACITY).contains(&n) => Ok(n),
+        _ => Err(format!("cap must be a number from 1 to {}, got '{}'", MAX_CAPACITY, s)),
+    }
+}
+
+/// Which pulses a subscriber wants. Empty lists match everything.
+#[derive(Debug, Clone, Default)]
+pub struct PulseFilter {
+    pub kinds: Vec<PulseKind>,
+    pub sources: Vec<String>,
+    /// Payload type names, e.g. `alert` or `policy_decision`.
+    pub types: Vec<String>,
+}
+
+impl PulseFilter {
+    /// Parse `key=a,b` terms: `kind=`, `source=` and `type=`.
+    pub fn parse<'a>(terms: impl IntoIterator<Item = &'a str>) -> Result<Self, String> {
+        let mut filter = PulseFilter::default();
+        for term in terms {
+            let (key, values) = term
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got '{}'", term))?;
+            let values = values.split(',').filter(|v| !v.is_empty());
+            match key {
+                "kind" => {
+                    for v in values {
+                        let kind = PulseKind::from_name(v)
+                            .ok_or_else(|| format!("unknown pulse kind '{}'", v))?;
+                        filter.kinds.push(kind);
+                    }
+                }
+                "source" => filter.sources.extend(values.map(str::to_string)),
+                "type" => {
+                    for v in values {
+                        if !PAYLOAD_TYPES.contains(&v) {
+                            return Err(format!("unknown payload type '{}'", v));
+                        }
+                        filter.types.push(v.to_string());
+                    }
+                }
+                _ => return Err(format!("unknown filter key '{}'", key)),
+            }
+        }
+        Ok(filter)
+    }
+
+    pub fn matches(&self, pulse: &Pulse) -> bool {
+        (self.kinds.is_empty() || self.kinds.contains(&pulse.kind))
+            && (self.sources.is_empty() || self.sources.contains(&pulse.source))
+            && (self.types.is_empty() || self.types.iter().any(|t| t == pulse.payload.type_name()))
+    }
+
+    pub fn describe(&self) -> String {
+        let mut terms = Vec::new();
+        if !self.kinds.is_empty() {
+            let kinds: Vec<&str> = self.kinds.iter().map(|k| k.name()).collect();
+            terms.push(format!("kind={}", kinds.join(",")));
+        }
+        if !self.sources.is_empty() {
+            terms.push(format!("source={}", self.sources.join(",")));
+        }
+        if !self.types.is_empty() {
+            terms.push(format!("type={}", self.types.join(",")));
+        }
+        if terms.is_empty() {
+            "all pulses".to_string()
+        } else {
+            terms.join(" ")
+        }
+    }
+}
+
+/// The receiving end of a subscription. Dropping it ends the
+/// subscription.
+pub struct Subscriber {
+    rx: Receiver<Pulse>,
+    _alive: Arc<()>,
+}
+
+impl Deref for Subscriber {
+    type Target = Receiver<Pulse>;
+
+    fn deref(&self) -> &Receiver<Pulse> {
+        &self.rx
+    }
+}
+
+struct Subscription {
+    id: u64,
+    name: String,
+    filter: PulseFilter,
+    tx: SyncSender<Pulse>,
+    /// Gone once the `Subscriber` is dropped.
+    alive: Weak<()>,
+    capacity: usize,
+    delivered: u64,
+    dropped: u64,
+}
+
+/// The subscriber table. Shared between the bus and anyone who wants to
+/// subscribe from another thread.
+#[derive(Default)]
+pub struct PubSub {
+    subs: Vec<Subscription>,
+    next_id: u64,
+}
+
+impl PubSub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a subscriber; `name` says who it is in `subscriptions`.
+    /// The capacity is clamped to 1..=`MAX_CAPACITY`.
+    pub fn subscribe(
+        &mut self,
+        name: &str,
+        filter: PulseFilter,
+        capacity: usize,
+    ) -> (u64, Subscriber) {
+        let capacity = capacity.clamp(1, MAX_CAPACITY);
+        let (tx, rx) = mpsc::sync_channel(capacity);
+        let alive = Arc::new(());
+        self.next_id += 1;
+        self.subs.push(Subscription {
+            id: self.next_id,
+            name: name.to_string(),
+            filter,
+            tx,
+            alive: Arc::downgrade(&alive),
+            capacity,
+            delivered: 0,
+            dropped: 0,
+        });
+        (self.next_id, Subscriber { rx, _alive: alive })
+    }
+
+    pub fn len(&self) -> usize {
+        self.subs.len()
+    }
+
+    pub fn unsubscribe(&mut self, id: u64) -> bool {
+        let before = self.subs.len();
+        self.subs.retain(|s| s.id != id);
+        self.subs.len() != before
+    }
+
+    /// Forget subscribers whose receiving end was dropped.
+    fn prune(&mut self) {
+        self.subs.retain(|sub| sub.alive.strong_count() > 0);
+    }
+
+    /// Offer a pulse to every matching subscriber without blocking.
+    pub fn publish(&mut self, pulse: &Pulse) {
+        self.subs.retain_mut(|sub| {
+            if sub.alive.strong_count() == 0 {
+                return false;
+            }
+            if !sub.filter.matches(pulse) {
+                return true;
+            }
+            match sub.tx.try_send(pulse.clone()) {
+                Ok(()) => {
+                    sub.delivered += 1;
+                    true
+                }
+                Err(TrySendError::Full(_)) => {
+                    sub.dropped += 1;
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+    }
+
+    /// Shell listing with delivery and drop counts.
+    pub fn report(&mut self) -> String {
+        self.prune();
+        let mut out = format!("Subscriptions ({}):\n", self.subs.len());
+        for s in &self.subs {
+            out.push_str(&format!(
+                " - #{} {} :: {} :: capacity {} :: delivered {} :: dropped {}\n",
+                s.id,
+                s.name,
+                s.filter.describe(),
+                s.capacity,
+                s.delivered,
+                s.dropped
+            ));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::PulsePayload;
+
+    fn pulse(id: u64, kind: PulseKind, source: &str) -> Pulse {
+        Pulse::new(id, 0, kind, source, PulsePayload::Heartbeat { beat: id })
+    }
+
+    #[test]
+    fn filters_match_kind_source_and_type() {
+        let filter = PulseFilter::parse(["kind=status,command", "source=a,b", "type=heartbeat"]).unwrap();
+        assert!(filter.matches(&pulse(1, PulseKind::Status, "a")));
+        assert!(filter.matches(&pulse(2, PulseKind::Command, "b")));
+        assert!(!filter.matches(&pulse(3, PulseKind::Sim, "a")));
+        assert!(!filter.matches(&pulse(4, PulseKind::Status, "c")));
+        let text = Pulse::new(5, 0, PulseKind::Status, "a", PulsePayload::Text("hi".to_string()));
+        assert!(!filter.matches(&text));
+        assert_eq!(filter.describe(), "kind=status,command source=a,b type=heartbeat");
+        assert!(PulseFilter::default().matches(&text));
+
+        assert_eq!(PulseFilter::parse(["kind=loud"]).unwrap_err(), "unknown pulse kind 'loud'");
+        assert_eq!(PulseFilter::parse(["type=nope"]).unwrap_err(), "unknown payload type 'nope'");
+        assert_eq!(PulseFilter::parse(["colour=red"]).unwrap_err(), "unknown filter key 'colour'");
+        assert_eq!(PulseFilter::parse(["kind"]).unwrap_err(), "expected key=value, got 'kind'");
+    }
+
+    #[test]
+    fn full_queues_drop_and_count() {
+        let mut pubsub = PubSub::new();
+        let (_, rx) = pubsub.subscribe("slow", PulseFilter::default(), 2);
+        for id in 1..=5 {
+            pubsub.publish(&pulse(id, PulseKind::Status, "a"));
+        }
+        let ids: Vec<u64> = rx.try_iter().map(|p| p.id).collect();
+        assert_eq!(ids, [1, 2]);
+        assert!(pubsub.report().contains("capacity 2 :: delivered 2 :: dropped 3"));
+        pubsub.publish(&pulse(6, PulseKind::Status, "a"));
+        assert_eq!(rx.try_recv().map(|p| p.id), Ok(6));
+    }
+
+    #[test]
+    fn subscribers_that_hang_up_are_removed() {
+        let mut pubsub = PubSub::new();
+        let (_, all) = pubsub.subscribe("all", PulseFilter::default(), 4);
+        let (_, rare) = pubsub.subscribe("rare", PulseFilter::parse(["kind=reflex"]).unwrap(), 4);
+        let (_, kept) = pubsub.subscribe("kept", PulseFilter::default(), 4);
+        drop(all);
+        drop(rare);
+        // The rare filter never matches, and it goes all the same.
+        pubsub.publish(&pulse(1, PulseKind::Status, "a"));
+        assert_eq!(pubsub.len(), 1);
+        assert_eq!(kept.try_recv().map(|p| p.id), Ok(1));
+
+        let (id, quiet) = pubsub.subscribe("quiet", PulseFilter::default(), 4);
+        drop(quiet);
+        let report = pubsub.report();
+        assert!(report.starts_with("Subscriptions (1):"), "{}", report);
+        assert!(!pubsub.unsubscribe(id));
+    }
+
+    #[test]
+    fn capacity_is_bounded() {
+        assert_eq!(parse_capacity("16"), Ok(16));
+        assert!(parse_capacity("0").is_err());
+        assert!(parse_capacity("4097").is_err());
+        assert!(parse_capacity("1000000000000").is_err());
+
+        let mut pubsub = PubSub::new();
+        pubsub.subscribe("big", PulseFilter::default(), usize::MAX);
+        pubsub.subscribe("empty", PulseFilter::default(), 0);
+        let caps: Vec<usize> = pubsub.subs.iter().map(|s| s.capacity).collect();
+        assert_eq!(caps, [MAX_CAPACITY, 1]);
+    }
+}
@@ -0,0 +1,662 @@
+//! Pulses: the messages carried by the kernel `Bus`.
+//!
+//! A pulse has a unique ID, a timestamp, the name of whoever sent it, a
+//! coarse `PulseKind` and a typed `PulsePayload`. Printing a pulse is just
+//! one way to consume it; anything on the bus can match on the payload
+//! instead of parsing log text.
+
+use crate::json::{escape, JsonValue};
+use crate::kernel::TelemetrySnapshot;
+
+/// The kinds of pulses that can travel on the bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PulseKind {
+    Heartbeat,
+    Status,
+    Command,
+    Ai,
+    Sim,
+    Reflex,
+}
+
+impl PulseKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            PulseKind::Heartbeat => "heartbeat",
+            PulseKind::Status => "status",
+            PulseKind::Command => "command",
+            PulseKind::Ai => "ai",
+            PulseKind::Sim => "sim",
+            PulseKind::Reflex => "reflex",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "heartbeat" => Some(PulseKind::Heartbeat),
+            "status" => Some(PulseKind::Status),
+            "command" => Some(PulseKind::Command),
+            "ai" | "cortex" => Some(PulseKind::Ai),
+            "sim" => Some(PulseKind::Sim),
+            "reflex" => Some(PulseKind::Reflex),
+            _ => None,
+        }
+    }
+}
+
+/// How much a pulse matters, lowest first. Names and numbering follow
+/// syslog, so sinks can filter on a minimum and forward it unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Debug,
+    Info,
+    Notice,
+    Warning,
+    Error,
+    Critical,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Debug => "debug",
+            Severity::Info => "info",
+            Severity::Notice => "notice",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+            Severity::Critical => "critical",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "debug" => Some(Severity::Debug),
+            "info" => Some(Severity::Info),
+            "notice" => Some(Severity::Notice),
+            "warning" | "warn" => Some(Severity::Warning),
+            "error" | "err" => Some(Severity::Error),
+            "critical" | "crit" => Some(Severity::Critical),
+            _ => None,
+        }
+    }
+
+    /// The syslog severity code (0 = emergency .. 7 = debug).
+    pub fn syslog_code(self) -> u8 {
+        match self {
+            Severity::Debug => 7,
+            Severity::Info => 6,
+            Severity::Notice => 5,
+            Severity::Warning => 4,
+            Severity::Error => 3,
+            Severity::Critical => 2,
+        }
+    }
+}
+
+/// What a pulse is about.
+#[derive(Debug, Clone)]
+pub enum PulsePayload {
+    /// Free-form text, for messages that have no structure of their own.
+    Text(String),
+    Heartbeat {
+        beat: u64,
+    },
+    /// One StatusDaemon tick.
+    Status {
+        tick: u64,
+        brief: String,
+        health: f32,
+        health_label: String,
+        awareness: f32,
+        awareness_label: String,
+    },
+    MetricSnapshot(TelemetrySnapshot),
+    /// An organ's health moved, e.g. under simulated stress.
+    HealthChange {
+        organ: String,
+        cause: String,
+        before: f32,
+        after: f32,
+    },
+    /// An organ's alert level changed (`ok` means the alert cleared).
+    Alert {
+        organ: String,
+        health: f32,
+        level: String,
+        previous: String,
+    },
+    PolicyDecision {
+        cycle: u64,
+        awareness: f32,
+        awareness_label: String,
+        brain: String,
+        policy: String,
+        actions: Vec<String>,
+    },
+    /// A homeostatic or reflex knob moved.
+    Actuation {
+        knob: String,
+        from: String,
+        to: String,
+    },
+    ReflexFired {
+        id: u32,
+        condition: String,
+        value: f32,
+        action: String,
+        effect: String,
+    },
+    CommandResult {
+        command: String,
+        output: String,
+    },
+    /// A daemon started, stopped, crashed or was given up on.
+    DaemonEvent {
+        daemon: String,
+        event: String,
+        detail: String,
+    },
+}
+
+/// Every `PulsePayload::type_name`, for validating filters.
+pub const PAYLOAD_TYPES: &[&str] = &[
+    "text",
+    "heartbeat",
+    "status",
+    "metric_snapshot",
+    "health_change",
+    "alert",
+    "policy_decision",
+    "actuation",
+    "reflex_fired",
+    "command_result",
+    "daemon_event",
+];
+
+impl PulsePayload {
+    /// Stable payload type name used in JSON and filters.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            PulsePayload::Text(_) => "text",
+            PulsePayload::Heartbeat { .. } => "heartbeat",
+            PulsePayload::Status { .. } => "status",
+            PulsePayload::MetricSnapshot(_) => "metric_snapshot",
+            PulsePayload::HealthChange { .. } => "health_change",
+            PulsePayload::Alert { .. } => "alert",
+            PulsePayload::PolicyDecision { .. } => "policy_decision",
+            PulsePayload::Actuation { .. } => "actuation",
+            PulsePayload::ReflexFired { .. } => "reflex_fired",
+            PulsePayload::CommandResult { .. } => "command_result",
+            PulsePayload::DaemonEvent { .. } => "daemon_event",
+        }
+    }
+
+    /// Derived from the payload: routine traffic is debug/info, knobs
+    /// moving are notices, reflexes are warnings, and alerts follow
+    /// their level.
+    pub fn severity(&self) -> Severity {
+        match self {
+            PulsePayload::Heartbeat { .. } | PulsePayload::MetricSnapshot(_) => Severity::Debug,
+            PulsePayload::Text(_)
+            | PulsePayload::Status { .. }
+            | PulsePayload::PolicyDecision { .. }
+            | PulsePayload::CommandResult { .. } => Severity::Info,
+            PulsePayload::HealthChange { .. } | PulsePayload::Actuation { .. } => Severity::Notice,
+            PulsePayload::ReflexFired { .. } => Severity::Warning,
+            PulsePayload::DaemonEvent { event, .. } => match event.as_str() {
+                "crashed" | "hung" => Severity::Error,
+                "overran" => Severity::Warning,
+                "failed" => Severity::Critical,
+                _ => Severity::Notice,
+            },
+            PulsePayload::Alert { level, .. } => match level.as_str() {
+                "ok" => Severity::Notice,
+                "degraded" => Severity::Warning,
+                "impaired" => Severity::Error,
+                _ => Severity::Critical,
+            },
+        }
+    }
+
+    /// Human-readable one-liner, as printed on the console.
+    pub fn summary(&self) -> String {
+        match self {
+            PulsePayload::Text(s) => s.clone(),
+            PulsePayload::Heartbeat { beat } => format!("beat #{}", beat),
+            PulsePayload::Status {
+                tick,
+                brief,
+                health,
+                health_label,
+                awareness,
+                awareness_label,
+            } => format!(
+                "status tick #{} :: {} :: health {:.2} ({}) :: awareness {:.2} ({})",
+                tick, brief, health, health_label, awareness, awareness_label
+            ),
+            PulsePayload::MetricSnapshot(s) => format!(
+                "metrics :: cpu {:.2} @ {:.1}C :: gpu {:.2} :: ram {:.2} :: net loss {:.3}",
+                s.cpu.cpu_load,
+                s.cpu.cpu_temp_c,
+                s.cpu.gpu_load,
+                s.mem.ram_used_ratio,
+                s.io.net_packet_loss
+            ),
+            PulsePayload::HealthChange {
+                organ,
+                cause,
+                after,
+                ..
+            } => format!("{} tick on {}: health now {:.2}", cause, organ, after),
+            PulsePayload::Alert {
+                organ,
+                health,
+                level,
+                previous,
+            } => format!(
+                "alert {} :: {} -> {} (health {:.2})",
+                organ, previous, level, health
+            ),
+            PulsePayload::PolicyDecision {
+                cycle,
+                awareness,
+                awareness_label,
+                brain,
+                policy,
+                ..
+            } => format!(
+                "cortex cycle #{} :: awareness {:.2} ({}) :: {} :: {}",
+                cycle, awareness, awareness_label, brain, policy
+            ),
+            PulsePayload::Actuation { knob, from, to } => format!("{} {} -> {}", knob, from, to),
+            PulsePayload::ReflexFired {
+                id,
+                condition,
+                value,
+                action,
+                effect,
+            } => format!(
+                "reflex #{} [{}] fired at {:.2} => {} :: {}",
+                id, condition, value, action, effect
+            ),
+            PulsePayload::CommandResult { output, .. } => output.clone(),
+            PulsePayload::DaemonEvent {
+                daemon,
+                event,
+                detail,
+            } if detail.is_empty() => format!("daemon {} {}", daemon, event),
+            PulsePayload::DaemonEvent {
+                daemon,
+                event,
+                detail,
+            } => format!("daemon {} {} :: {}", daemon, event, detail),
+        }
+    }
+
+    /// The payload's fields as a JSON object.
+    pub fn to_json(&self) -> String {
+        let fields = match self {
+            PulsePayload::Text(s) => format!(r#""text":"{}""#, escape(s)),
+            PulsePayload::Heartbeat { beat } => format!(r#""beat":{}"#, beat),
+            PulsePayload::Status {
+                tick,
+                brief,
+                health,
+                health_label,
+                awareness,
+                awareness_label,
+            } => format!(
+                r#""tick":{},"brief":"{}","health":{:.3},"health_label":"{}","awareness":{:.3},"awareness_label":"{}""#,
+                tick,
+                escape(brief),
+                health,
+                health_label,
+                awareness,
+                awareness_label
+            ),
+            PulsePayload::MetricSnapshot(s) => format!(r#""metrics":{}"#, s.to_json()),
+            PulsePayload::HealthChange {
+                organ,
+                cause,
+                before,
+                after,
+            } => format!(
+                r#""organ":"{}","cause":"{}","before":{:.3},"after":{:.3}"#,
+                escape(organ),
+                escape(cause),
+                before,
+                after
+            ),
+            PulsePayload::Alert {
+                organ,
+                health,
+                level,
+                previous,
+            } => format!(
+                r#""organ":"{}","health":{:.3},"level":"{}","previous":"{}""#,
+                escape(organ),
+                health,
+                level,
+                previous
+            ),
+            PulsePayload::PolicyDecision {
+                cycle,
+                awareness,
+                awareness_label,
+                brain,
+                policy,
+                actions,
+            } => format!(
+                r#""cycle":{},"awareness":{:.3},"awareness_label":"{}","brain":"{}","policy":"{}","actions":[{}]"#,
+                cycle,
+                awareness,
+                awareness_label,
+                escape(brain),
+                escape(policy),
+                actions
+                    .iter()
+                    .map(|a| format!("\"{}\"", escape(a)))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            PulsePayload::Actuation { knob, from, to } => format!(
+                r#""knob":"{}","from":"{}","to":"{}""#,
+                escape(knob),
+                escape(from),
+                escape(to)
+            ),
+            PulsePayload::ReflexFired {
+                id,
+                condition,
+                value,
+                action,
+                effect,
+            } => format!(
+                r#""id":{},"condition":"{}","value":{:.3},"action":"{}","effect":"{}""#,
+                id,
+                escape(condition),
+                value,
+                escape(action),
+                escape(effect)
+            ),
+            PulsePayload::CommandResult { command, output } => format!(
+                r#""command":"{}","output":"{}""#,
+                escape(command),
+                escape(output)
+            ),
+            PulsePayload::DaemonEvent {
+                daemon,
+                event,
+                detail,
+            } => format!(
+                r#""daemon":"{}","event":"{}","detail":"{}""#,
+                escape(daemon),
+                escape(event),
+                escape(detail)
+            ),
+        };
+        format!("{{{}}}", fields)
+    }
+}
+
+impl PulsePayload {
+    /// Rebuild a payload from its type name and JSON fields.
+    fn from_json(type_name: &str, v: &JsonValue) -> Result<PulsePayload, String> {
+        let text = |key: &str| -> Result<String, String> {
+            v.get(key)
+                .and_then(JsonValue::as_str)
+                .map(str::to_string)
+                .ok_or_else(|| format!("{}: missing '{}'", type_name, key))
+        };
+        let num = |key: &str| -> Result<f64, String> {
+            v.get(key)
+                .and_then(JsonValue::as_f64)
+                .ok_or_else(|| format!("{}: missing '{}'", type_name, key))
+        };
+
+        Ok(match type_name {
+            "text" => PulsePayload::Text(text("text")?),
+            "heartbeat" => PulsePayload::Heartbeat {
+                beat: num("beat")? as u64,
+            },
+            "status" => PulsePayload::Status {
+                tick: num("tick")? as u64,
+                brief: text("brief")?,
+                health: num("health")? as f32,
+                health_label: text("health_label")?,
+                awareness: num("awareness")? as f32,
+                awareness_label: text("awareness_label")?,
+            },
+            "metric_snapshot" => PulsePayload::MetricSnapshot(
+                v.get("metrics")
+                    .and_then(TelemetrySnapshot::from_json)
+                    .ok_or("metric_snapshot: bad metrics")?,
+            ),
+            "health_change" => PulsePayload::HealthChange {
+                organ: text("organ")?,
+                cause: text("cause")?,
+                before: num("before")? as f32,
+                after: num("after")? as f32,
+            },
+            "alert" => PulsePayload::Alert {
+                organ: text("organ")?,
+                health: num("health")? as f32,
+                level: text("level")?,
+                previous: text("previous")?,
+            },
+            "policy_decision" => PulsePayload::PolicyDecision {
+                cycle: num("cycle")? as u64,
+                awareness: num("awareness")? as f32,
+                awareness_label: text("awareness_label")?,
+                brain: text("brain")?,
+                policy: text("policy")?,
+                actions: v
+                    .get("actions")
+                    .and_then(JsonValue::as_array)
+                    .unwrap_or(&[])
+                    .iter()
+                    .filter_map(|a| a.as_str().map(str::to_string))
+                    .collect(),
+            },
+            "actuation" => PulsePayload::Actuation {
+                knob: text("knob")?,
+                from: text("from")?,
+                to: text("to")?,
+            },
+            "reflex_fired" => PulsePayload::ReflexFired {
+                id: num("id")? as u32,
+                condition: text("condition")?,
+                value: num("value")? as f32,
+                action: text("action")?,
+                effect: text("effect")?,
+            },
+            "command_result" => PulsePayload::CommandResult {
+                command: text("command")?,
+                output: text("output")?,
+            },
+            "daemon_event" => PulsePayload::DaemonEvent {
+                daemon: text("daemon")?,
+                event: text("event")?,
+                detail: text("detail")?,
+            },
+            other => return Err(format!("unknown payload type '{}'", other)),
+        })
+    }
+}
+
+impl From<String> for PulsePayload {
+    fn from(s: String) -> Self {
+        PulsePayload::Text(s)
+    }
+}
+
+impl From<&str> for PulsePayload {
+    fn from(s: &str) -> Self {
+        PulsePayload::Text(s.to_string())
+    }
+}
+
+/// One message on the bus.
+#[derive(Debug, Clone)]
+pub struct Pulse {
+    pub id: u64,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    pub source: String,
+    pub kind: PulseKind,
+    pub payload: PulsePayload,
+    /// Set on pulses fed back from the pulse journal.
+    pub replayed: bool,
+}
+
+impl Pulse {
+    pub fn new(
+        id: u64,
+        timestamp_ms: u64,
+        kind: PulseKind,
+        source: impl Into<String>,
+        payload: PulsePayload,
+    ) -> Self {
+        Self {
+            id,
+            timestamp_ms,
+            source: source.into(),
+            kind,
+            payload,
+            replayed: false,
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.payload.severity()
+    }
+
+    /// The classic console line.
+    pub fn log_line(&self) -> String {
+        format!(
+            "[{}][{:?}] pulse#{} from {} => {}",
+            if self.replayed { "REPLAY" } else { "BUS" },
+            self.kind,
+            self.id,
+            self.source,
+            self.payload.summary()
+        )
+    }
+
+    pub fn to_json(&self) -> String {
+        format!(
+            r#"{{"id":{},"timestamp_ms":{},"source":"{}","kind":"{}","type":"{}","payload":{}{}}}"#,
+            self.id,
+            self.timestamp_ms,
+            escape(&self.source),
+            self.kind.name(),
+            self.payload.type_name(),
+            self.payload.to_json(),
+            if self.replayed { r#","replayed":true"# } else { "" }
+        )
+    }
+
+    /// Parse a pulse written by `to_json`.
+    pub fn from_json(text: &str) -> Result<Pulse, String> {
+        let v = JsonValue::parse(text)?;
+        let num = |key: &str| v.get(key).and_then(JsonValue::as_f64);
+        let kind = v
+            .get("kind")
+            .and_then(JsonValue::as_str)
+            .and_then(PulseKind::from_name)
+            .ok_or("missing or unknown kind")?;
+        let type_name = v.get("type").and_then(JsonValue::as_str).ok_or("missing type")?;
+        let payload = v.get("payload").ok_or("missing payload")?;
+
+        Ok(Pulse {
+            id: num("id").ok_or("missing id")? as u64,
+            timestamp_ms: num("timestamp_ms").ok_or("missing timestamp_ms")? as u64,
+            source: v
+                .get("source")
+                .and_then(JsonValue::as_str)
+                .ok_or("missing source")?
+                .to_string(),
+            kind,
+            payload: PulsePayload::from_json(type_name, payload)?,
+            replayed: v.get("replayed") == Some(&JsonValue::Bool(true)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::sim::SimulatedTelemetry;
+    use crate::telemetry::{SimLevel, TelemetryProvider};
+
+    /// One payload of every type.
+    fn payloads() -> Vec<PulsePayload> {
+        let mut sim = SimulatedTelemetry::new(SimLevel::Off);
+        let text = |s: &str| s.to_string();
+        vec![
+            PulsePayload::Text(text("a \"quoted\"\tline\nand ünïcode")),
+            PulsePayload::Heartbeat { beat: 7 },
+            PulsePayload::Status {
+                tick: 3,
+                brief: text("3 organs"),
+                health: 0.75,
+                health_label: text("degraded"),
+                awareness: 0.5,
+                awareness_label: text("dim"),
+            },
+            PulsePayload::MetricSnapshot(TelemetrySnapshot {
+                cpu: sim.read_cpu_gpu_metrics(),
+                mem: sim.read_memory_metrics(),
+                io: sim.read_io_metrics(),
+            }),
+            PulsePayload::HealthChange { organ: text("Cortex"), cause: text("stress"), before: 0.5, after: 0.25 },
+            PulsePayload::Alert { organ: text("Memory"), health: 0.3, level: text("critical"), previous: text("ok") },
+            PulsePayload::PolicyDecision {
+                cycle: 9,
+                awareness: 0.25,
+                awareness_label: text("dim"),
+                brain: text("ladder"),
+                policy: text("policy=protect_core(sim_off)"),
+                actions: vec![text("sim_level=off")],
+            },
+            PulsePayload::Actuation { knob: text("throttle"), from: text("0.00"), to: text("0.50") },
+            PulsePayload::ReflexFired {
+                id: 2,
+                condition: text("cpu_temp_c > 90"),
+                value: 95.5,
+                action: text("disable GpuWorkload"),
+                effect: text("2 capabilities disabled"),
+            },
+            PulsePayload::CommandResult { command: text("status"), output: text("line 1\nline 2") },
+            PulsePayload::DaemonEvent { daemon: text("flaky"), event: text("crashed"), detail: text("boom") },
+        ]
+    }
+
+    #[test]
+    fn every_payload_type_round_trips() {
+        let payloads = payloads();
+        let types: Vec<&str> = payloads.iter().map(PulsePayload::type_name).collect();
+        assert_eq!(types, PAYLOAD_TYPES);
+
+        for (i, payload) in payloads.into_iter().enumerate() {
+            let mut pulse = Pulse::new(i as u64 + 1, 1_700_000_000_000, PulseKind::Status, "tester", payload);
+            pulse.replayed = i % 2 == 1;
+            let json = pulse.to_json();
+            let back = Pulse::from_json(&json).unwrap_or_else(|e| panic!("{}: {}", e, json));
+            assert_eq!(back.to_json(), json);
+            assert_eq!((back.id, back.replayed, back.source.as_str()), (pulse.id, pulse.replayed, "tester"));
+        }
+    }
+
+    #[test]
+    fn malformed_pulses_are_rejected() {
+        let pulse = Pulse::new(1, 0, PulseKind::Ai, "ai", PulsePayload::Heartbeat { beat: 1 });
+        let json = pulse.to_json();
+        let broken = |from: &str, to: &str| Pulse::from_json(&json.replace(from, to)).unwrap_err();
+        assert_eq!(broken(r#""kind":"ai""#, r#""kind":"mood""#), "missing or unknown kind");
+        assert_eq!(broken(r#""type":"heartbeat""#, r#""type":"sneeze""#), "unknown payload type 'sneeze'");
+        assert_eq!(broken(r#""beat""#, r#""bpm""#), "heartbeat: missing 'beat'");
+        assert_eq!(broken(r#""id":1,"#, ""), "missing id");
+        assert!(Pulse::from_json("{").is_err());
+    }
+}
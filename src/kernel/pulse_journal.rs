@@ -0,0 +1,411 @@
+//! Durable pulse journal.
+//!
+//! Every pulse is appended as a JSON line to the current segment file in
+//! the journal directory (`pulses-000001.jsonl`, `pulses-000002.jsonl`,
+//! ...). A segment is closed once it reaches `segment_bytes`; closed
+//! segments are deleted when there are more than `max_segments` of them
+//! or they are older than `max_age`.
+//!
+//! `replay` feeds a journal back through the bus subscribers, at the
+//! original pace (scaled by `speed`) or as fast as possible.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::kernel::{lock, BusHandle, PubSub, Pulse, PulseFilter, PulseKind, SharedClock, Since};
+
+pub const DEFAULT_DIR: &str = "aion_pulses";
+
+#[derive(Debug, Clone)]
+pub struct JournalConfig {
+    pub dir: PathBuf,
+    pub segment_bytes: u64,
+    pub max_segments: usize,
+    pub max_age: Duration,
+}
+
+impl JournalConfig {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            segment_bytes: 1024 * 1024,
+            max_segments: 16,
+            max_age: Duration::from_secs(72 * 3600),
+        }
+    }
+
+    /// `AION_PULSE_JOURNAL=<dir|off>`, `AION_PULSE_SEGMENT_KB`,
+    /// `AION_PULSE_SEGMENTS` and `AION_PULSE_RETENTION_HOURS`.
+    /// Returns `None` when the journal is switched off.
+    pub fn from_env() -> Option<Self> {
+        let dir = std::env::var("AION_PULSE_JOURNAL").unwrap_or_else(|_| DEFAULT_DIR.to_string());
+        if dir == "off" || dir.is_empty() {
+            return None;
+        }
+        let env = |key: &str| std::env::var(key).ok().and_then(|v| v.parse::<u64>().ok());
+        let mut cfg = Self::new(dir);
+        if let Some(kb) = env("AION_PULSE_SEGMENT_KB") {
+            cfg.segment_bytes = kb.max(1).saturating_mul(1024);
+        }
+        if let Some(n) = env("AION_PULSE_SEGMENTS") {
+            cfg.max_segments = n.max(1) as usize;
+        }
+        if let Some(h) = env("AION_PULSE_RETENTION_HOURS") {
+            cfg.max_age = Duration::from_secs(h.saturating_mul(3600));
+        }
+        Some(cfg)
+    }
+}
+
+/// Append-only writer for the pulse journal.
+pub struct PulseJournal {
+    cfg: JournalConfig,
+    /// Decides when a closed segment is too old.
+    clock: SharedClock,
+    file: Option<File>,
+    segment: u64,
+    size: u64,
+    written: u64,
+    errors: u64,
+    last_error: Option<String>,
+    /// Highest pulse ID found on disk when the journal was opened.
+    last_id: u64,
+}
+
+impl PulseJournal {
+    pub fn open(cfg: JournalConfig, clock: SharedClock) -> Result<Self, String> {
+        fs::create_dir_all(&cfg.dir)
+            .map_err(|e| format!("failed to create {}: {}", cfg.dir.display(), e))?;
+        let segment = segments(&cfg.dir).last().map(|(n, _)| *n).unwrap_or(1);
+        let mut journal = Self {
+            cfg,
+            clock,
+            file: None,
+            segment,
+            size: 0,
+            written: 0,
+            errors: 0,
+            last_error: None,
+            last_id: 0,
+        };
+        journal.last_id = journal.scan_last_id();
+        journal.open_segment()?;
+        journal.enforce_retention();
+        Ok(journal)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.cfg.dir
+    }
+
+    /// Highest pulse ID already journaled, so a new session can carry on
+    /// numbering after it.
+    pub fn last_id(&self) -> u64 {
+        self.last_id
+    }
+
+    fn scan_last_id(&self) -> u64 {
+        let Some((_, path)) = segments(&self.cfg.dir).pop() else {
+            return 0;
+        };
+        fs::read_to_string(path)
+            .unwrap_or_default()
+            .lines()
+            .rev()
+            .find_map(|line| Pulse::from_json(line).ok())
+            .map(|p| p.id)
+            .unwrap_or(0)
+    }
+
+    fn open_segment(&mut self) -> Result<(), String> {
+        let path = segment_path(&self.cfg.dir, self.segment);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+        self.size = file.metadata().map(|m| m.len()).unwrap_or(0);
+        self.file = Some(file);
+        Ok(())
+    }
+
+    /// Write one pulse. Errors are counted, never fatal.
+    pub fn append(&mut self, pulse: &Pulse) {
+        let line = format!("{}\n", pulse.to_json());
+        if self.size > 0 && self.size + line.len() as u64 > self.cfg.segment_bytes {
+            self.segment += 1;
+            if let Err(e) = self.open_segment() {
+                self.fail(e);
+                return;
+            }
+            self.enforce_retention();
+        }
+        let result = match self.file.as_mut() {
+            Some(f) => f.write_all(line.as_bytes()).map_err(|e| e.to_string()),
+            None => Err("no open segment".to_string()),
+        };
+        match result {
+            Ok(()) => {
+                self.size += line.len() as u64;
+                self.written += 1;
+            }
+            Err(e) => self.fail(e),
+        }
+    }
+
+    fn fail(&mut self, e: String) {
+        self.errors += 1;
+        self.last_error = Some(e);
+    }
+
+    /// Drop closed segments beyond the count and age limits.
+    fn enforce_retention(&mut self) {
+        let closed: Vec<(u64, PathBuf)> = segments(&self.cfg.dir)
+            .into_iter()
+            .filter(|(n, _)| *n != self.segment)
+            .collect();
+        let excess = (closed.len() + 1).saturating_sub(self.cfg.max_segments);
+        let now = UNIX_EPOCH + Duration::from_millis(self.clock.unix_millis());
+        for (i, (_, path)) in closed.iter().enumerate() {
+            let too_old = fs::metadata(path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| now.duration_since(t).ok())
+                .is_some_and(|age| age > self.cfg.max_age);
+            if i < excess || too_old {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+
+    pub fn report(&self) -> String {
+        let segs = segments(&self.cfg.dir);
+        let total: u64 = segs
+            .iter()
+            .filter_map(|(_, p)| fs::metadata(p).ok())
+            .map(|m| m.len())
+            .sum();
+        let mut out = format!(
+            "Pulse journal {} :: {} segment(s), {} bytes :: current #{} ({} bytes)\n",
+            self.cfg.dir.display(),
+            segs.len(),
+            total,
+            self.segment,
+            self.size
+        );
+        out.push_str(&format!(
+            "limits :: segment {} KiB :: keep {} segment(s) :: max age {}h\n",
+            self.cfg.segment_bytes / 1024,
+            self.cfg.max_segments,
+            self.cfg.max_age.as_secs() / 3600
+        ));
+        out.push_str(&format!("written {} pulse(s) this session :: {} error(s)", self.written, self.errors));
+        if let Some(e) = &self.last_error {
+            out.push_str(&format!(" (last: {})", e));
+        }
+        out.push('\n');
+        out
+    }
+}
+
+fn segment_path(dir: &Path, n: u64) -> PathBuf {
+    dir.join(format!("pulses-{:06}.jsonl", n))
+}
+
+/// Segment files in a journal directory, oldest first.
+pub fn segments(dir: &Path) -> Vec<(u64, PathBuf)> {
+    let mut out: Vec<(u64, PathBuf)> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            let n = name.strip_prefix("pulses-")?.strip_suffix(".jsonl")?.parse().ok()?;
+            Some((n, e.path()))
+        })
+        .collect();
+    out.sort();
+    out
+}
+
+/// How to replay a journal.
+#[derive(Debug, Clone)]
+pub struct ReplayOptions {
+    /// `None` replays as fast as possible; `Some(2.0)` at twice the
+    /// original pace.
+    pub speed: Option<f64>,
+    pub filter: PulseFilter,
+    pub since: Since,
+}
+
+/// What a replay did.
+#[derive(Debug, Default, Clone, Copy)]
+struct ReplayStats {
+    replayed: u64,
+    skipped: u64,
+    bad_lines: u64,
+}
+
+/// Feed the journal in `dir` to every bus subscriber on a background
+/// thread. Pulses keep their original IDs and timestamps and are marked
+/// `replayed`. When done, the thread reports its stats as a pulse through
+/// the bus handle.
+pub fn replay(dir: &Path, opts: ReplayOptions, bus: BusHandle) -> Result<(), String> {
+    let segs = segments(dir);
+    if segs.is_empty() {
+        return Err(format!("no pulse journal segments in {}", dir.display()));
+    }
+
+    thread::spawn(move || {
+        let stats = replay_segments(&segs, &opts, &bus.pubsub());
+        bus.emit(
+            PulseKind::Command,
+            "replay",
+            format!(
+                "replay finished :: {} pulse(s) replayed, {} skipped, {} unreadable line(s)",
+                stats.replayed, stats.skipped, stats.bad_lines
+            ),
+        );
+    });
+    Ok(())
+}
+
+fn replay_segments(
+    segs: &[(u64, PathBuf)],
+    opts: &ReplayOptions,
+    pubsub: &Arc<Mutex<PubSub>>,
+) -> ReplayStats {
+    let mut stats = ReplayStats::default();
+    let mut prev_ts: Option<u64> = None;
+
+    for (_, path) in segs {
+        let Ok(file) = File::open(path) else {
+            continue;
+        };
+        for line in BufReader::new(file).lines().map_while(Result::ok) {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut pulse = match Pulse::from_json(&line) {
+                Ok(p) => p,
+                Err(_) => {
+                    stats.bad_lines += 1;
+                    continue;
+                }
+            };
+            if !opts.since.admits(&pulse) || !opts.filter.matches(&pulse) {
+                stats.skipped += 1;
+                continue;
+            }
+
+            if let (Some(speed), Some(prev)) = (opts.speed, prev_ts) {
+                let gap = pulse.timestamp_ms.saturating_sub(prev) as f64 / speed;
+                thread::sleep(Duration::from_millis(gap as u64));
+            }
+            prev_ts = Some(pulse.timestamp_ms);
+
+            pulse.replayed = true;
+            lock(pubsub).publish(&pulse);
+            stats.replayed += 1;
+        }
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::clock::real;
+    use crate::kernel::PulsePayload;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("aion-pulses-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn pulse(id: u64, kind: PulseKind) -> Pulse {
+        Pulse::new(id, 1_000 * id, kind, "test", PulsePayload::Heartbeat { beat: id })
+    }
+
+    #[test]
+    fn segments_rotate_and_old_ones_are_dropped() {
+        let dir = scratch("rotate");
+        let mut cfg = JournalConfig::new(&dir);
+        cfg.segment_bytes = 1024;
+        cfg.max_segments = 3;
+        let mut journal = PulseJournal::open(cfg.clone(), real()).unwrap();
+        for id in 1..=100 {
+            journal.append(&pulse(id, PulseKind::Status));
+        }
+
+        let segs = segments(&dir);
+        assert_eq!(segs.len(), 3);
+        assert!(segs.iter().all(|(_, p)| fs::metadata(p).unwrap().len() <= 1024));
+        assert_eq!(segs.last().map(|(n, _)| *n), Some(journal.segment));
+        assert!(journal.report().contains("written 100 pulse(s) this session :: 0 error(s)"));
+
+        // Reopening carries on in the last segment, after the last ID.
+        let reopened = PulseJournal::open(cfg, real()).unwrap();
+        assert_eq!((reopened.segment, reopened.last_id()), (journal.segment, 100));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn segment_age_is_judged_by_the_kernel_clock() {
+        use crate::kernel::clock::{VirtualClock, VIRTUAL_EPOCH_MS};
+        use crate::kernel::Clock;
+
+        let dir = scratch("age");
+        let mut cfg = JournalConfig::new(&dir);
+        cfg.segment_bytes = 1024;
+        cfg.max_age = Duration::from_secs(3600);
+        let clock = Arc::new(VirtualClock::new());
+        let mut journal = PulseJournal::open(cfg, clock.clone()).unwrap();
+        for id in 1..=30 {
+            journal.append(&pulse(id, PulseKind::Status));
+        }
+        assert!(segments(&dir).len() > 2);
+
+        // Two hours past the files' real modification times.
+        let ahead = real().unix_millis() - VIRTUAL_EPOCH_MS + 2 * 3600 * 1000;
+        clock.advance_to(clock.now() + Duration::from_millis(ahead));
+        for id in 31..=40 {
+            journal.append(&pulse(id, PulseKind::Status));
+        }
+        assert!(segments(&dir).len() <= 2, "{:?}", segments(&dir));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn replay_honours_the_filter_and_skips_bad_lines() {
+        let dir = scratch("replay");
+        let mut journal = PulseJournal::open(JournalConfig::new(&dir), real()).unwrap();
+        for id in 1..=6 {
+            let kind = if id % 2 == 0 { PulseKind::Status } else { PulseKind::Ai };
+            journal.append(&pulse(id, kind));
+        }
+        drop(journal);
+        let mut first = OpenOptions::new().append(true).open(segment_path(&dir, 1)).unwrap();
+        writeln!(first, "not json\n").unwrap();
+
+        let pubsub = Arc::new(Mutex::new(PubSub::new()));
+        let (_, rx) = lock(&pubsub).subscribe("test", PulseFilter::default(), 16);
+        let opts = ReplayOptions {
+            speed: None,
+            filter: PulseFilter::parse(["kind=status"]).unwrap(),
+            since: Since::Id(2),
+        };
+        let stats = replay_segments(&segments(&dir), &opts, &pubsub);
+        assert_eq!((stats.replayed, stats.skipped, stats.bad_lines), (2, 4, 1));
+
+        let got: Vec<Pulse> = rx.try_iter().collect();
+        assert_eq!(got.iter().map(|p| p.id).collect::<Vec<_>>(), [4, 6]);
+        assert!(got.iter().all(|p| p.replayed));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
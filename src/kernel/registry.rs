@@ -0,0 +1,371 @@
+//! Daemon registry and configuration.
+//!
+//! Every daemon kind registers a factory under its name. A daemon
+//! config lists which daemons run, one per line:
+//!
+//! ```text
+//! # kind       schedule                   parameters
+//! heartbeat    every=1s missed=skip
+//! status       every=5s                   alert_dwell=30s telemetry=sim
+//! sim          every=2500ms jitter=500ms
+//! ```
+//!
+//! Schedule keys are `every`, `jitter`, `missed` (skip, catch-up,
+//! coalesce) and `timeout`; everything else is passed to the factory.
+//! The config comes from AION_DAEMONS=<path>, or the built-in one below.
+//! `daemon add` and `daemon rm` change the running set from the shell.
+
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::kernel::{
+    AiDaemon, Daemon, HeartbeatDaemon, HomeostasisDaemon, JobDaemon, KernelState, MissedTick, Placement,
+    Schedule, SimulationDaemon, StatusDaemon, TelemetryMode, TelemetrySampler, Waker,
+    WorkerDaemon,
+};
+use crate::telemetry::real::RealTelemetry;
+use crate::telemetry::sim::SimulatedTelemetry;
+use crate::telemetry::{SimLevel, TelemetryProvider};
+
+/// The shell's own daemon. It owns stdin, so it is always installed and
+/// can't be added or removed.
+pub const COMMAND_DAEMON: &str = "command";
+
+/// Longest duration `parse_duration` accepts.
+pub const MAX_DURATION: Duration = Duration::from_secs(365 * 24 * 3600);
+
+/// `500ms`, `2s`, `5m`, `1h`; a bare number is seconds. At most a year.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (num, unit_ms) = if let Some(n) = s.strip_suffix("ms") {
+        (n, 1.0)
+    } else if let Some(n) = s.strip_suffix('s') {
+        (n, 1_000.0)
+    } else if let Some(n) = s.strip_suffix('m') {
+        (n, 60_000.0)
+    } else if let Some(n) = s.strip_suffix('h') {
+        (n, 3_600_000.0)
+    } else {
+        (s, 1_000.0)
+    };
+    match num.parse::<f64>() {
+        Ok(n) if n.is_finite() && n >= 0.0 => {
+            let ms = n * unit_ms;
+            if ms > MAX_DURATION.as_millis() as f64 {
+                return Err(format!("duration '{}' is longer than a year", s));
+            }
+            Ok(Duration::from_millis(ms as u64))
+        }
+        _ => Err(format!("invalid duration '{}' (try 500ms, 2s, 5m)", s)),
+    }
+}
+
+/// One configured daemon.
+#[derive(Debug, Clone)]
+pub struct DaemonSpec {
+    pub kind: String,
+    pub schedule: Schedule,
+    pub params: Vec<(String, String)>,
+}
+
+impl DaemonSpec {
+    /// `<kind> [every=..] [jitter=..] [missed=..] [timeout=..] [key=value ..]`
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut terms = line.split_whitespace();
+        let kind = terms
+            .next()
+            .ok_or_else(|| "missing daemon kind".to_string())?
+            .to_string();
+        let mut schedule = Schedule::every(Duration::from_secs(1));
+        let mut params = Vec::new();
+        for term in terms {
+            let (key, value) = term
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got '{}'", term))?;
+            match key {
+                "every" => schedule.interval = Some(parse_duration(value)?),
+                "jitter" => schedule = schedule.with_jitter(parse_duration(value)?),
+                "timeout" => schedule = schedule.with_timeout(parse_duration(value)?),
+                "missed" => {
+                    let missed = match value {
+                        "skip" => MissedTick::Skip,
+                        "catch-up" => MissedTick::CatchUp,
+                        "coalesce" => MissedTick::Coalesce,
+                        _ => return Err(format!("missed must be skip, catch-up or coalesce, got '{}'", value)),
+                    };
+                    schedule = schedule.with_missed(missed);
+                }
+                _ => params.push((key.to_string(), value.to_string())),
+            }
+        }
+        if schedule.interval.is_some_and(|i| i.is_zero()) {
+            return Err("every must be greater than zero".to_string());
+        }
+        Ok(Self {
+            kind,
+            schedule,
+            params,
+        })
+    }
+
+    pub fn param(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Reject parameters the factory doesn't know.
+    pub fn allow_params(&self, known: &[&str]) -> Result<(), String> {
+        match self.params.iter().find(|(k, _)| !known.contains(&k.as_str())) {
+            Some((k, _)) if known.is_empty() => Err(format!("{} takes no parameter '{}'", self.kind, k)),
+            Some((k, _)) => Err(format!(
+                "{} has no parameter '{}' (known: {})",
+                self.kind,
+                k,
+                known.join(", ")
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Which daemons run, in registration order.
+#[derive(Debug, Clone)]
+pub struct DaemonConfig {
+    pub specs: Vec<DaemonSpec>,
+}
+
+impl DaemonConfig {
+    /// The standard set. `telemetry=real` samples on the worker pool.
+    pub fn builtin(telemetry: &str, alert_dwell: Duration) -> Self {
+        let status = match telemetry {
+            "real" => "telemetry every=5s timeout=10s source=real\n\
+                       status every=5s telemetry=worker"
+                .to_string(),
+            _ => "status every=5s telemetry=sim".to_string(),
+        };
+        let text = format!(
+            "heartbeat every=1s missed=skip\n\
+             {} alert_dwell={}ms\n\
+             ai-cortex every=2s\n\
+             homeostasis every=1s missed=catch-up\n\
+             jobs every=1s\n\
+             sim every=2500ms jitter=500ms\n",
+            status,
+            alert_dwell.as_millis()
+        );
+        Self::parse(&text).expect("built-in daemon config must parse")
+    }
+
+    /// AION_DAEMONS=<path>, else the built-in set. AION_TELEMETRY=real
+    /// and AION_ALERT_DWELL_SECS shape the built-in set.
+    pub fn from_env() -> Result<Self, String> {
+        if let Ok(path) = std::env::var("AION_DAEMONS") {
+            return Self::load(&path);
+        }
+        let telemetry = std::env::var("AION_TELEMETRY").unwrap_or_else(|_| "sim".to_string());
+        let alert_dwell = std::env::var("AION_ALERT_DWELL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map_or(Duration::ZERO, Duration::from_secs);
+        Ok(Self::builtin(&telemetry, alert_dwell))
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read daemon config {}: {}", path, e))?;
+        Self::parse(&content).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut specs: Vec<DaemonSpec> = Vec::new();
+        for (lineno, raw) in text.lines().enumerate() {
+            let line = raw.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let spec = DaemonSpec::parse(line).map_err(|e| format!("line {}: {}", lineno + 1, e))?;
+            if spec.kind == COMMAND_DAEMON {
+                return Err(format!("line {}: the command daemon is always installed", lineno + 1));
+            }
+            if specs.iter().any(|s| s.kind == spec.kind) {
+                return Err(format!("line {}: {} is listed twice", lineno + 1, spec.kind));
+            }
+            specs.push(spec);
+        }
+        Ok(Self { specs })
+    }
+
+    /// Real if any daemon reads real telemetry.
+    pub fn telemetry_mode(&self) -> TelemetryMode {
+        let real = self
+            .specs
+            .iter()
+            .any(|s| match s.kind.as_str() {
+                "status" => s.param("telemetry") == Some("real"),
+                "telemetry" => s.param("source").unwrap_or("real") == "real",
+                _ => false,
+            });
+        if real {
+            TelemetryMode::Real
+        } else {
+            TelemetryMode::Simulated
+        }
+    }
+}
+
+/// What a factory gets to build a daemon with.
+pub struct FactoryContext<'a> {
+    pub state: &'a KernelState,
+    /// Wakes the daemon being built.
+    pub waker: Waker,
+}
+
+pub type DaemonFactory = fn(&DaemonSpec, &FactoryContext) -> Result<Box<dyn Daemon>, String>;
+
+struct Factory {
+    kind: &'static str,
+    about: &'static str,
+    build: DaemonFactory,
+}
+
+/// Daemon factories by kind. The kind is also the daemon's name.
+#[derive(Default)]
+pub struct DaemonRegistry {
+    factories: Vec<Factory>,
+}
+
+impl DaemonRegistry {
+    /// Every daemon that ships with the kernel.
+    pub fn standard() -> Self {
+        let mut r = Self::default();
+        r.register("heartbeat", "pulse once per tick", |spec, _| {
+            spec.allow_params(&[])?;
+            Ok(Box::new(HeartbeatDaemon::new()))
+        });
+        r.register(
+            "status",
+            "apply telemetry to organ health, alerts and reflexes [telemetry=sim|real|worker] [alert_dwell=30s]",
+            build_status,
+        );
+        r.register(
+            "telemetry",
+            "sample telemetry off the kernel thread [source=real|sim] [placement=pool|thread]",
+            build_telemetry,
+        );
+        r.register("ai-cortex", "run the active cortex brain", |spec, ctx| {
+            spec.allow_params(&[])?;
+            let s = ctx.state;
+            Ok(Box::new(AiDaemon::new(
+                Arc::clone(&s.topology),
+                Arc::clone(&s.metrics_snapshot),
+                Arc::clone(&s.journal),
+                Arc::clone(&s.brains),
+            )))
+        });
+        r.register("homeostasis", "drive the setpoint controllers", |spec, ctx| {
+            spec.allow_params(&[])?;
+            let s = ctx.state;
+            Ok(Box::new(HomeostasisDaemon::new(
+                Arc::clone(&s.topology),
+                Arc::clone(&s.metrics_snapshot),
+                Arc::clone(&s.homeostasis),
+                Arc::clone(&s.capabilities),
+                Arc::clone(&s.reflexes),
+            )))
+        });
+        r.register("jobs", "run scheduled jobs (see `jobs`)", |spec, ctx| {
+            spec.allow_params(&[])?;
+            Ok(Box::new(JobDaemon::new(ctx.state)))
+        });
+        r.register("sim", "simulated organ health drift", |spec, ctx| {
+            spec.allow_params(&[])?;
+            Ok(Box::new(SimulationDaemon::new(Arc::clone(&ctx.state.topology))))
+        });
+        r
+    }
+
+    /// Add a factory; a later registration of the same kind replaces it.
+    pub fn register(&mut self, kind: &'static str, about: &'static str, build: DaemonFactory) {
+        self.factories.retain(|f| f.kind != kind);
+        self.factories.push(Factory { kind, about, build });
+    }
+
+    /// The registered (static) name for `kind`.
+    pub fn kind(&self, kind: &str) -> Option<&'static str> {
+        self.factories.iter().find(|f| f.kind == kind).map(|f| f.kind)
+    }
+
+    pub fn build(&self, spec: &DaemonSpec, ctx: &FactoryContext) -> Result<Box<dyn Daemon>, String> {
+        let factory = self
+            .factories
+            .iter()
+            .find(|f| f.kind == spec.kind)
+            .ok_or_else(|| format!("unknown daemon kind '{}' (see `daemon kinds`)", spec.kind))?;
+        (factory.build)(spec, ctx)
+    }
+
+    pub fn describe(&self) -> String {
+        let mut out = format!("Daemon kinds ({}):\n", self.factories.len());
+        for f in &self.factories {
+            out.push_str(&format!(" - {} :: {}\n", f.kind, f.about));
+        }
+        out
+    }
+}
+
+fn telemetry_provider(name: &str) -> Result<Box<dyn TelemetryProvider>, String> {
+    match name {
+        "sim" => Ok(Box::new(SimulatedTelemetry::new(SimLevel::Low))),
+        "real" => Ok(Box::new(RealTelemetry::new(SimLevel::Low))),
+        _ => Err(format!("unknown telemetry source '{}' (sim, real)", name)),
+    }
+}
+
+fn build_status(spec: &DaemonSpec, ctx: &FactoryContext) -> Result<Box<dyn Daemon>, String> {
+    spec.allow_params(&["telemetry", "alert_dwell"])?;
+    let alert_dwell = match spec.param("alert_dwell") {
+        Some(d) => parse_duration(d)?,
+        None => Duration::ZERO,
+    };
+    let telemetry = match spec.param("telemetry").unwrap_or("sim") {
+        // A `telemetry` daemon fills the shared snapshot.
+        "worker" => None,
+        source => Some(telemetry_provider(source)?),
+    };
+    Ok(Box::new(StatusDaemon::new(alert_dwell, telemetry, ctx.state)))
+}
+
+fn build_telemetry(spec: &DaemonSpec, ctx: &FactoryContext) -> Result<Box<dyn Daemon>, String> {
+    spec.allow_params(&["source", "placement"])?;
+    let telemetry = telemetry_provider(spec.param("source").unwrap_or("real"))?;
+    let placement = match spec.param("placement").unwrap_or("pool") {
+        "pool" => Placement::Pool(Arc::clone(&ctx.state.workers)),
+        "thread" => Placement::Thread,
+        other => return Err(format!("placement must be pool or thread, got '{}'", other)),
+    };
+    let sampler = TelemetrySampler::new(telemetry, ctx.state);
+    Ok(Box::new(WorkerDaemon::new(
+        Box::new(sampler),
+        placement,
+        ctx.waker.clone(),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_are_finite_and_at_most_a_year() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("1.5"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("8760h"), Ok(MAX_DURATION));
+        for bad in ["inf", "infs", "NaN", "-1s", "1e20s", "8761h", "soon"] {
+            assert!(parse_duration(bad).is_err(), "{}", bad);
+        }
+        assert!(DaemonSpec::parse("sim every=1s jitter=1e20s").is_err());
+        assert!(DaemonSpec::parse("sim every=inf").is_err());
+    }
+}
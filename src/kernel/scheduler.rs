@@ -0,0 +1,643 @@
+//! Daemon scheduler.
+//!
+//! The scheduler owns every daemon's interval and next deadline. Each
+//! pass it ticks whatever is due, then sleeps until the earliest deadline
+//! or until a `Waker` fires, e.g. when the command channel receives
+//! input or another thread queues a bus request. On a virtual clock it
+//! jumps to the deadline instead of sleeping.
+//!
+//! Every daemon hook runs under supervision; see `supervisor`.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::kernel::supervisor::{
+    panic_message, CrashOutcome, DaemonControl, DaemonState, DaemonStatus, DaemonTable, Supervision,
+};
+use crate::kernel::{lock, Bus, Daemon, PulseKind, PulsePayload, SharedClock};
+
+/// How many missed slots `MissedTick::CatchUp` will replay back to back
+/// before giving up on the rest.
+const MAX_CATCH_UP: u32 = 16;
+
+/// What to do about slots missed while the loop was busy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissedTick {
+    /// Drop missed slots and stay on the original cadence.
+    Skip,
+    /// Run once per missed slot, back to back (up to `MAX_CATCH_UP`).
+    CatchUp,
+    /// Run once and restart the interval from now.
+    Coalesce,
+}
+
+impl MissedTick {
+    pub fn name(self) -> &'static str {
+        match self {
+            MissedTick::Skip => "skip",
+            MissedTick::CatchUp => "catch-up",
+            MissedTick::Coalesce => "coalesce",
+        }
+    }
+}
+
+/// When a daemon runs.
+#[derive(Debug, Clone, Copy)]
+pub struct Schedule {
+    /// `None` runs the daemon only when it is woken.
+    pub interval: Option<Duration>,
+    /// Up to this much random delay is added to every deadline, so
+    /// daemons with equal intervals drift apart.
+    pub jitter: Duration,
+    pub missed: MissedTick,
+    /// A run longer than this is reported: as `overran` after an inline
+    /// tick, or as `hung` while a worker is still busy.
+    pub timeout: Option<Duration>,
+}
+
+impl Schedule {
+    pub fn every(interval: Duration) -> Self {
+        Self {
+            interval: Some(interval),
+            jitter: Duration::ZERO,
+            missed: MissedTick::Coalesce,
+            timeout: None,
+        }
+    }
+
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn with_missed(mut self, missed: MissedTick) -> Self {
+        self.missed = missed;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn describe(&self) -> String {
+        let mut out = match self.interval {
+            Some(i) => format!("every {}ms ({})", i.as_millis(), self.missed.name()),
+            None => "on wake".to_string(),
+        };
+        if !self.jitter.is_zero() {
+            out.push_str(&format!(" +jitter {}ms", self.jitter.as_millis()));
+        }
+        if let Some(t) = self.timeout {
+            out.push_str(&format!(" timeout {}ms", t.as_millis()));
+        }
+        out
+    }
+}
+
+/// Wakes the scheduler from another thread. A waker for a named daemon
+/// also makes that daemon run on the next pass, whatever its deadline.
+#[derive(Clone)]
+pub struct Waker {
+    tx: Sender<Option<&'static str>>,
+    daemon: Option<&'static str>,
+}
+
+impl Waker {
+    pub fn wake(&self) {
+        let _ = self.tx.send(self.daemon);
+    }
+
+    /// A waker on the same scheduler for another daemon.
+    pub fn for_daemon(&self, daemon: &'static str) -> Waker {
+        Waker {
+            tx: self.tx.clone(),
+            daemon: Some(daemon),
+        }
+    }
+}
+
+struct Entry {
+    daemon: Box<dyn Daemon>,
+    schedule: Schedule,
+    /// The next slot on the daemon's cadence, before jitter.
+    slot: Instant,
+    /// When it actually runs next: `slot` plus jitter.
+    due: Instant,
+    woken: bool,
+    sup: Supervision,
+}
+
+pub struct Scheduler {
+    entries: Vec<Entry>,
+    wake_tx: Sender<Option<&'static str>>,
+    wake_rx: Receiver<Option<&'static str>>,
+    clock: SharedClock,
+    rng: u64,
+    /// Status rows and lifecycle requests shared with the shell.
+    table: Arc<Mutex<DaemonTable>>,
+}
+
+impl Scheduler {
+    pub fn new(clock: SharedClock) -> Self {
+        let (wake_tx, wake_rx) = mpsc::channel();
+        // Seeded from the clock, so virtual runs jitter the same way.
+        let seed = clock.unix_millis().wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        let table = DaemonTable::new(Waker {
+            tx: wake_tx.clone(),
+            daemon: None,
+        });
+        Self {
+            entries: Vec::new(),
+            wake_tx,
+            wake_rx,
+            clock,
+            rng: seed | 1,
+            table: Arc::new(Mutex::new(table)),
+        }
+    }
+
+    pub fn table(&self) -> Arc<Mutex<DaemonTable>> {
+        Arc::clone(&self.table)
+    }
+
+    /// A waker for the loop itself (`None`) or for one daemon by name.
+    pub fn waker(&self, daemon: Option<&'static str>) -> Waker {
+        Waker {
+            tx: self.wake_tx.clone(),
+            daemon,
+        }
+    }
+
+    /// Register a daemon. Its first run is one interval from now.
+    pub fn add(&mut self, daemon: Box<dyn Daemon>, schedule: Schedule) {
+        let now = self.clock.now();
+        let slot = match schedule.interval {
+            Some(i) => later(now, i),
+            None => far_future(now),
+        };
+        let due = later(slot, self.jitter(schedule.jitter));
+        self.entries.push(Entry {
+            daemon,
+            schedule,
+            slot,
+            due,
+            woken: false,
+            sup: Supervision::default(),
+        });
+        self.publish();
+    }
+
+    /// xorshift64*; jitter doesn't need anything better.
+    fn jitter(&mut self, max: Duration) -> Duration {
+        let max = u64::try_from(max.as_millis()).unwrap_or(u64::MAX);
+        if max == 0 {
+            return Duration::ZERO;
+        }
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        let r = self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        Duration::from_millis(r % max.saturating_add(1))
+    }
+
+    /// Apply lifecycle requests, restart daemons whose backoff is over,
+    /// then tick every running daemon that is due or was woken, in
+    /// registration order.
+    pub fn run_due(&mut self, now: Instant, bus: &mut Bus) {
+        let (added, requests) = {
+            let mut table = lock(&self.table);
+            (table.take_added(), table.take_requests())
+        };
+        for (daemon, schedule) in added {
+            let name = daemon.name();
+            if self.entries.iter().any(|e| e.daemon.name() == name) {
+                emit_event(bus, name, "rejected", "a daemon by that name is already running");
+                continue;
+            }
+            self.add(daemon, schedule);
+            emit_event(bus, name, "added", &schedule.describe());
+        }
+        for (name, control) in requests {
+            if let Some(i) = self.entries.iter().position(|e| e.daemon.name() == name) {
+                self.control(i, control, now, bus);
+            }
+        }
+
+        for i in 0..self.entries.len() {
+            let e = &mut self.entries[i];
+            if e.sup.state == DaemonState::Backoff && e.sup.retry_at.is_some_and(|t| t <= now) {
+                e.sup.restarts += 1;
+                self.start(i, now, bus, Some("restarted"));
+            }
+            let e = &mut self.entries[i];
+            if e.sup.state != DaemonState::Running {
+                continue;
+            }
+            if !e.sup.started {
+                self.start(i, now, bus, None);
+                if self.entries[i].sup.state != DaemonState::Running {
+                    continue;
+                }
+            }
+
+            let e = &mut self.entries[i];
+            let periodic = e.due <= now;
+            if periodic || e.woken {
+                e.woken = false;
+                let started = Instant::now();
+                let ticked = panic::catch_unwind(AssertUnwindSafe(|| {
+                    if periodic {
+                        e.daemon.tick(now, bus)
+                    } else {
+                        e.daemon.on_wake(now, bus)
+                    }
+                }));
+                if periodic {
+                    e.sup.runs += 1;
+                    self.reschedule(i, now);
+                }
+                if let Err(payload) = ticked {
+                    self.crash(i, now, bus, format!("panicked: {}", panic_message(&*payload)));
+                    continue;
+                }
+                // Measured on the wall clock: this is time the loop was blocked.
+                let took = started.elapsed();
+                let e = &self.entries[i];
+                if e.schedule.timeout.is_some_and(|t| took > t) {
+                    let detail = format!(
+                        "tick took {}ms (timeout {}ms)",
+                        took.as_millis(),
+                        e.schedule.timeout.unwrap_or_default().as_millis()
+                    );
+                    emit_event(bus, e.daemon.name(), "overran", &detail);
+                }
+            }
+            self.watch(i, now, bus);
+        }
+
+        self.publish();
+    }
+
+    /// Report a worker daemon whose run has outlived its timeout, and
+    /// again when it finishes.
+    fn watch(&mut self, i: usize, now: Instant, bus: &mut Bus) {
+        let e = &mut self.entries[i];
+        let name = e.daemon.name();
+        match (e.daemon.busy_since(), e.schedule.timeout) {
+            (Some(since), Some(timeout)) if !e.sup.hung && now >= later(since, timeout) => {
+                e.sup.hung = true;
+                let detail = format!(
+                    "run in progress for {}ms (timeout {}ms)",
+                    (now - since).as_millis(),
+                    timeout.as_millis()
+                );
+                emit_event(bus, name, "hung", &detail);
+            }
+            (None, _) if e.sup.hung => {
+                e.sup.hung = false;
+                emit_event(bus, name, "recovered", "");
+            }
+            _ => {}
+        }
+    }
+
+    /// Run a daemon's `start` hook. `event` names the pulse to emit, if
+    /// any; the first start at boot is silent.
+    fn start(&mut self, i: usize, now: Instant, bus: &mut Bus, event: Option<&str>) {
+        let e = &mut self.entries[i];
+        let started = panic::catch_unwind(AssertUnwindSafe(|| e.daemon.start(now, bus)));
+        let error = match started {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(format!("start failed: {}", e)),
+            Err(payload) => Some(format!("start panicked: {}", panic_message(&*payload))),
+        };
+        if let Some(error) = error {
+            self.crash(i, now, bus, error);
+            return;
+        }
+        let e = &mut self.entries[i];
+        e.sup.state = DaemonState::Running;
+        e.sup.started = true;
+        e.sup.retry_at = None;
+        e.sup.hung = false;
+        if let Some(event) = event {
+            // Run promptly, then settle back onto the cadence.
+            e.slot = now;
+            e.due = now;
+            let name = e.daemon.name();
+            emit_event(bus, name, event, "");
+        }
+    }
+
+    fn stop(&mut self, i: usize, bus: &mut Bus) {
+        let e = &mut self.entries[i];
+        if e.sup.started {
+            let stopped = panic::catch_unwind(AssertUnwindSafe(|| e.daemon.stop(bus)));
+            if let Err(payload) = stopped {
+                e.sup.last_error = Some(format!("stop panicked: {}", panic_message(&*payload)));
+            }
+        }
+        e.sup.started = false;
+        e.sup.state = DaemonState::Stopped;
+        e.woken = false;
+    }
+
+    fn crash(&mut self, i: usize, now: Instant, bus: &mut Bus, error: String) {
+        let e = &mut self.entries[i];
+        let name = e.daemon.name();
+        match e.sup.crashed(now, error.clone()) {
+            CrashOutcome::RetryAt(at) => {
+                let detail = format!("{}; restarting in {}ms", error, (at - now).as_millis());
+                emit_event(bus, name, "crashed", &detail);
+            }
+            CrashOutcome::GiveUp => {
+                let detail = format!("{}; crashed {} times, giving up", error, e.sup.crashes);
+                emit_event(bus, name, "failed", &detail);
+                emit_alert(bus, name, "critical", "ok");
+            }
+        }
+    }
+
+    fn control(&mut self, i: usize, control: DaemonControl, now: Instant, bus: &mut Bus) {
+        let name = self.entries[i].daemon.name();
+        let state = self.entries[i].sup.state;
+        match control {
+            DaemonControl::Remove => {
+                self.stop(i, bus);
+                if state == DaemonState::Failed {
+                    emit_alert(bus, name, "ok", "critical");
+                }
+                self.entries.remove(i);
+                emit_event(bus, name, "removed", "");
+            }
+            DaemonControl::Stop if state == DaemonState::Stopped => {}
+            DaemonControl::Stop => {
+                self.stop(i, bus);
+                emit_event(bus, name, "stopped", "");
+            }
+            DaemonControl::Start if state == DaemonState::Running => {}
+            DaemonControl::Start | DaemonControl::Restart => {
+                if state == DaemonState::Running {
+                    self.stop(i, bus);
+                }
+                if state == DaemonState::Failed {
+                    emit_alert(bus, name, "ok", "critical");
+                }
+                self.entries[i].sup.reset_crashes();
+                let event = if control == DaemonControl::Start { "started" } else { "restarted" };
+                self.start(i, now, bus, Some(event));
+            }
+        }
+    }
+
+    /// Refresh the shared status rows.
+    fn publish(&self) {
+        let now = self.clock.now();
+        let rows = self
+            .entries
+            .iter()
+            .map(|e| DaemonStatus {
+                name: e.daemon.name(),
+                state: e.sup.state,
+                schedule: e.schedule.describe(),
+                runs: e.sup.runs,
+                crashes: e.sup.crashes,
+                restarts: e.sup.restarts,
+                health: match (e.sup.state, e.daemon.busy_since()) {
+                    (DaemonState::Running, Some(since)) if e.sup.hung => Err(format!(
+                        "hung for {}s",
+                        now.saturating_duration_since(since).as_secs()
+                    )),
+                    (DaemonState::Running, _) => e.daemon.health(),
+                    _ => Ok(()),
+                },
+                last_error: e.sup.last_error.clone(),
+            })
+            .collect();
+        lock(&self.table).publish(rows);
+    }
+
+    fn reschedule(&mut self, i: usize, now: Instant) {
+        let e = &self.entries[i];
+        let Some(interval) = e.schedule.interval else {
+            return;
+        };
+        let behind = now.saturating_duration_since(e.slot).as_nanos() / interval.as_nanos().max(1);
+        let slot = match e.schedule.missed {
+            MissedTick::Coalesce => later(now, interval),
+            MissedTick::Skip => later(e.slot, times(interval, behind + 1)),
+            MissedTick::CatchUp => {
+                later(e.slot, times(interval, behind.saturating_sub(MAX_CATCH_UP as u128) + 1))
+            }
+        };
+        let jitter = self.jitter(e.schedule.jitter);
+        let e = &mut self.entries[i];
+        e.slot = slot;
+        e.due = later(slot, jitter);
+    }
+
+    /// Sleep until the earliest deadline or a wake-up, whichever is
+    /// first, but not past `limit`. A virtual clock is moved forward to
+    /// that point instead.
+    pub fn wait(&mut self, limit: Option<Instant>) {
+        let now = self.clock.now();
+        let mut earliest = self
+            .entries
+            .iter()
+            .filter_map(|e| match e.sup.state {
+                DaemonState::Running if e.woken || !e.sup.started => Some(now),
+                DaemonState::Running => match (e.daemon.busy_since(), e.schedule.timeout) {
+                    (Some(since), Some(timeout)) if !e.sup.hung => Some(e.due.min(later(since, timeout))),
+                    _ => Some(e.due),
+                },
+                DaemonState::Backoff => e.sup.retry_at,
+                DaemonState::Stopped | DaemonState::Failed => None,
+            })
+            .min()
+            .unwrap_or_else(|| far_future(now));
+        if let Some(limit) = limit {
+            earliest = earliest.min(limit);
+        }
+        if self.clock.is_virtual() {
+            while let Ok(target) = self.wake_rx.try_recv() {
+                self.mark_woken(target);
+                earliest = now;
+            }
+            self.clock.advance_to(earliest);
+            return;
+        }
+        match self.wake_rx.recv_timeout(earliest.saturating_duration_since(now)) {
+            Ok(target) => {
+                self.mark_woken(target);
+                while let Ok(target) = self.wake_rx.try_recv() {
+                    self.mark_woken(target);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => {}
+        }
+    }
+
+    fn mark_woken(&mut self, target: Option<&'static str>) {
+        if let Some(name) = target {
+            for e in self.entries.iter_mut().filter(|e| e.daemon.name() == name) {
+                e.woken = true;
+            }
+        }
+    }
+}
+
+/// A deadline that never comes, for wake-only daemons.
+fn far_future(now: Instant) -> Instant {
+    now.checked_add(Duration::from_secs(365 * 24 * 3600)).unwrap_or(now)
+}
+
+/// `interval * n`, saturating rather than wrapping.
+fn times(interval: Duration, n: u128) -> Duration {
+    let nanos = interval.as_nanos().saturating_mul(n);
+    Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+}
+
+/// `t + by`, or the far future where that would overflow.
+fn later(t: Instant, by: Duration) -> Instant {
+    t.checked_add(by).unwrap_or_else(|| far_future(t))
+}
+
+fn emit_event(bus: &mut Bus, daemon: &str, event: &str, detail: &str) {
+    bus.emit(
+        PulseKind::Status,
+        "supervisor",
+        PulsePayload::DaemonEvent {
+            daemon: daemon.to_string(),
+            event: event.to_string(),
+            detail: detail.to_string(),
+        },
+    );
+}
+
+/// Repeated crashes are escalated as an alert on `daemon:<name>`.
+fn emit_alert(bus: &mut Bus, daemon: &str, level: &str, previous: &str) {
+    bus.emit(
+        PulseKind::Status,
+        "supervisor",
+        PulsePayload::Alert {
+            organ: format!("daemon:{}", daemon),
+            health: if level == "ok" { 1.0 } else { 0.0 },
+            level: level.to_string(),
+            previous: previous.to_string(),
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::clock::VirtualClock;
+    use crate::kernel::{Clock, LogFilter};
+
+    const MS: Duration = Duration::from_millis(1);
+    const SEC: Duration = Duration::from_secs(1);
+
+    /// Counts its ticks.
+    struct Counter(Arc<Mutex<u32>>);
+
+    impl Daemon for Counter {
+        fn name(&self) -> &'static str {
+            "counter"
+        }
+
+        fn tick(&mut self, _now: Instant, _bus: &mut Bus) {
+            *self.0.lock().unwrap() += 1;
+        }
+    }
+
+    struct Run {
+        clock: Arc<VirtualClock>,
+        start: Instant,
+        scheduler: Scheduler,
+        bus: Bus,
+        ticks: Arc<Mutex<u32>>,
+    }
+
+    impl Run {
+        fn new(schedule: Schedule) -> Self {
+            let clock = Arc::new(VirtualClock::new());
+            let mut bus = Bus::new();
+            bus.log_sinks.apply_preset(LogFilter::Silent);
+            let mut scheduler = Scheduler::new(clock.clone());
+            let ticks = Arc::new(Mutex::new(0));
+            scheduler.add(Box::new(Counter(Arc::clone(&ticks))), schedule);
+            Self {
+                start: clock.now(),
+                clock,
+                scheduler,
+                bus,
+                ticks,
+            }
+        }
+
+        /// Jump straight to `at` (as if the loop had been blocked until
+        /// then) and run whatever is due there. Returns the tick count.
+        fn at(&mut self, at: Duration) -> u32 {
+            let before = *self.ticks.lock().unwrap();
+            self.clock.advance_to(self.start + at);
+            for _ in 0..100 {
+                self.scheduler.run_due(self.clock.now(), &mut self.bus);
+            }
+            *self.ticks.lock().unwrap() - before
+        }
+    }
+
+    #[test]
+    fn coalesce_runs_once_and_restarts_the_interval() {
+        let mut run = Run::new(Schedule::every(SEC).with_missed(MissedTick::Coalesce));
+        assert_eq!(run.at(SEC), 1);
+        assert_eq!(run.at(5 * SEC + 500 * MS), 1);
+        assert_eq!(run.at(6 * SEC), 0);
+        assert_eq!(run.at(6 * SEC + 500 * MS), 1);
+    }
+
+    #[test]
+    fn skip_runs_once_and_keeps_the_cadence() {
+        let mut run = Run::new(Schedule::every(SEC).with_missed(MissedTick::Skip));
+        assert_eq!(run.at(SEC), 1);
+        assert_eq!(run.at(5 * SEC + 500 * MS), 1);
+        assert_eq!(run.at(6 * SEC), 1);
+        assert_eq!(run.at(6 * SEC + 500 * MS), 0);
+    }
+
+    #[test]
+    fn catch_up_replays_missed_slots_up_to_the_limit() {
+        let mut run = Run::new(Schedule::every(SEC).with_missed(MissedTick::CatchUp));
+        assert_eq!(run.at(SEC), 1);
+        // Slots 2, 3, 4 and 5 were missed.
+        assert_eq!(run.at(5 * SEC + 500 * MS), 4);
+        assert_eq!(run.at(6 * SEC), 1);
+        // Slots 7 to 101: slot 7, then only the last MAX_CATCH_UP.
+        assert_eq!(run.at(101 * SEC + 500 * MS), 1 + MAX_CATCH_UP);
+        assert_eq!(run.at(102 * SEC), 1);
+    }
+
+    #[test]
+    fn long_stalls_do_not_wrap_the_slot_count() {
+        // More than u32::MAX missed 1ms slots.
+        let stall = Duration::from_secs(50 * 24 * 3600);
+        let mut run = Run::new(Schedule::every(MS).with_missed(MissedTick::Skip));
+        assert_eq!(run.at(MS), 1);
+        assert_eq!(run.at(stall + MS / 2), 1);
+        assert_eq!(run.at(stall + MS), 1);
+    }
+
+    #[test]
+    fn huge_jitter_saturates() {
+        let mut run = Run::new(Schedule::every(SEC).with_jitter(Duration::MAX));
+        for _ in 0..100 {
+            run.scheduler.jitter(Duration::MAX);
+        }
+        // Pushed out to the far future rather than panicking.
+        assert_eq!(run.at(SEC), 0);
+        assert_eq!(run.at(100 * SEC), 0);
+    }
+}
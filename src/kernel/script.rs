@@ -0,0 +1,193 @@
+//! Batch scripts: shell lines read from a file, for CI scenarios.
+//!
+//! ```text
+//! # push the cortex and check the kernel notices
+//! sim level off
+//! damage cortex 0.6
+//! sleep 2s
+//! wait-until health cortex < 0.5 timeout=10s
+//! assert awareness < 0.9
+//! alerts | grep Cortex
+//! ```
+//!
+//! Lines starting with `#` are comments. `sleep <duration>` pauses and
+//! `wait-until <condition> [timeout=<duration>]` re-checks a condition
+//! (as `assert` takes it) until it holds, 30s by default. Every other
+//! line runs through the shell like a typed one. The script stops at
+//! the first line that fails and the process exits with that line's
+//! exit code.
+
+use std::fmt;
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::kernel::commands::{check_condition, parse_condition};
+use crate::kernel::registry::parse_duration;
+use crate::kernel::shell::{parse_line, ResultCode};
+use crate::kernel::KernelState;
+
+const DEFAULT_WAIT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long a line may take before the script gives up on it.
+const LINE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    Run(String),
+    Sleep(Duration),
+    WaitUntil { condition: String, timeout: Duration },
+}
+
+impl Step {
+    fn parse(line: &str) -> Result<Self, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["sleep", duration] => Ok(Step::Sleep(parse_duration(duration)?)),
+            ["sleep", ..] => Err("usage: sleep <duration>".to_string()),
+            ["wait-until", condition @ ..] => {
+                let (condition, timeout) = match condition.split_last() {
+                    Some((last, rest)) if last.starts_with("timeout=") => {
+                        (rest, parse_duration(&last["timeout=".len()..])?)
+                    }
+                    _ => (condition, DEFAULT_WAIT),
+                };
+                parse_condition(condition).map_err(|e| format!("wait-until: {}", e))?;
+                Ok(Step::WaitUntil {
+                    condition: condition.join(" "),
+                    timeout,
+                })
+            }
+            _ => {
+                parse_line(line)?;
+                Ok(Step::Run(line.to_string()))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Run(line) => write!(f, "{}", line),
+            Step::Sleep(d) => write!(f, "sleep {:?}", d),
+            Step::WaitUntil { condition, timeout } => {
+                write!(f, "wait-until {} timeout={:?}", condition, timeout)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Script {
+    pub name: String,
+    /// Steps with their line numbers.
+    pub steps: Vec<(usize, Step)>,
+}
+
+impl Script {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+        Self::parse(path, &text)
+    }
+
+    /// Parse the whole script up front, so a typo on the last line fails
+    /// before anything runs.
+    pub fn parse(name: &str, text: &str) -> Result<Self, String> {
+        let mut steps = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let step = Step::parse(line).map_err(|e| format!("{}:{}: {}", name, i + 1, e))?;
+            steps.push((i + 1, step));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            steps,
+        })
+    }
+
+    /// Run every step against a running kernel. Returns the exit code.
+    pub fn run(&self, state: &KernelState) -> i32 {
+        for (n, step) in &self.steps {
+            println!("[AION-SCRIPT] {}:{} > {}", self.name, n, step);
+            if let Err((code, e)) = run_step(step, state) {
+                println!("[AION-SCRIPT] {}:{}: {}", self.name, n, e);
+                println!("[AION-SCRIPT] FAILED ({})", code.name());
+                return code.exit_code();
+            }
+        }
+        println!("[AION-SCRIPT] passed ({} steps)", self.steps.len());
+        0
+    }
+}
+
+fn run_step(step: &Step, state: &KernelState) -> Result<(), (ResultCode, String)> {
+    match step {
+        Step::Run(line) => {
+            let result = state
+                .shell
+                .call(line.as_str())
+                .recv_timeout(LINE_TIMEOUT)
+                .map_err(|_| (ResultCode::Failed, "no reply from the command daemon".to_string()))?;
+            match result.error() {
+                None => Ok(()),
+                // A grep without a match fails silently.
+                Some("") => Err((result.code, format!("{}: {}", result.code.name(), line))),
+                Some(e) => Err((result.code, e.to_string())),
+            }
+        }
+        Step::Sleep(d) => {
+            thread::sleep(*d);
+            Ok(())
+        }
+        Step::WaitUntil { condition, timeout } => {
+            let words: Vec<&str> = condition.split_whitespace().collect();
+            // No deadline if it would not fit in an `Instant`.
+            let deadline = Instant::now().checked_add(*timeout);
+            loop {
+                match check_condition(state, &words) {
+                    Ok(_) => return Ok(()),
+                    Err(e) if deadline.is_some_and(|d| Instant::now() >= d) => {
+                        return Err((ResultCode::Failed, format!("timed out after {:?}: {}", timeout, e)))
+                    }
+                    Err(_) => thread::sleep(POLL_INTERVAL),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripts_parse_up_front() {
+        let script = Script::parse(
+            "t.aion",
+            "# setup\n\ndamage cortex 0.5\nsleep 2s\nwait-until health cortex < 0.6 timeout=5s\n",
+        )
+        .unwrap();
+        let steps: Vec<_> = script.steps.iter().map(|(n, s)| (*n, s.clone())).collect();
+        assert_eq!(
+            steps,
+            [
+                (3, Step::Run("damage cortex 0.5".to_string())),
+                (4, Step::Sleep(Duration::from_secs(2))),
+                (
+                    5,
+                    Step::WaitUntil {
+                        condition: "health cortex < 0.6".to_string(),
+                        timeout: Duration::from_secs(5),
+                    }
+                ),
+            ]
+        );
+        let err = Script::parse("t.aion", "status\nwait-until health brain < 1\n").unwrap_err();
+        assert!(err.starts_with("t.aion:2: wait-until: unknown metric"), "{}", err);
+        assert!(Script::parse("t.aion", "status |").unwrap_err().starts_with("t.aion:1: syntax"));
+    }
+}
@@ -0,0 +1,922 @@
+//! Shell commands and the registry that dispatches them.
+//!
+//! A `ShellCommand` has a name, aliases, a usage line, one line of help
+//! and an argument schema that the registry checks before `execute`
+//! runs. The kernel's own commands are in `commands`; other modules add
+//! theirs through `CommandRegistry::register` (`logs` comes from
+//! `log_sink`, `jobs` and `job` from `jobs`). `help` and `help <cmd>`
+//! are generated from whatever is registered.
+//!
+//! A line is tokenized like a small POSIX shell: words split on
+//! whitespace, 'single' and "double" quotes, backslash escapes. Commands
+//! chain with `;` (always run the next) and `&&` (run it only if the
+//! previous one succeeded), and a command's output can be piped through
+//! the built-in filters: `organs | grep critical | count`.
+//!
+//! Every line produces a `ShellResult`. Sessions show it as plain text
+//! or, after `output json` (or with `--json` in front of one line), as a
+//! single JSON object:
+//!
+//! ```text
+//! {"ok":false,"code":"usage","exit":2,"line":"mem get","output":"usage: ...",
+//!  "error":"usage: ...","results":[{"command":"mem get","ok":false,"code":"usage","output":"usage: ..."}]}
+//! ```
+//!
+//! `results` has one entry per command in the chain; those skipped after
+//! a failed `&&` have code `skipped`. Fields may be added, never renamed.
+
+
+use crate::json::escape;
+use crate::kernel::{commands, jobs, lock, log_sink, parse_organ_kind, Bus, KernelState, Subscriber};
+use crate::memory::MemoryScope;
+use crate::telemetry::SimLevel;
+use std::fmt;
+
+/// What a positional argument must look like.
+#[derive(Clone, Copy)]
+pub enum ArgKind {
+    /// Any single word.
+    Word,
+    Number,
+    Organ,
+    SimLevel,
+    MemoryKey,
+    /// A command name.
+    Command,
+    /// One of a fixed set of words, usually a subcommand.
+    Choice(&'static [&'static str]),
+    /// The rest of the line, left to the command.
+    Rest,
+}
+
+impl ArgKind {
+    pub fn describe(&self) -> String {
+        match self {
+            ArgKind::Word => "word".to_string(),
+            ArgKind::Number => "number".to_string(),
+            ArgKind::Organ => "organ (cortex|memory|io|sensorhub|motor|network|storage)".to_string(),
+            ArgKind::SimLevel => "off|low|high".to_string(),
+            ArgKind::MemoryKey => "memory key".to_string(),
+            ArgKind::Command => "command".to_string(),
+            ArgKind::Choice(words) => words.join("|"),
+            ArgKind::Rest => "text".to_string(),
+        }
+    }
+
+    /// Values to offer for tab completion. Commands are filled in by the
+    /// registry.
+    fn candidates(&self, state: &KernelState, bus: &Bus) -> Vec<String> {
+        match self {
+            ArgKind::Organ => lock(&state.topology)
+                .organs
+                .iter()
+                .map(|o| format!("{:?}", o.kind).to_lowercase())
+                .collect(),
+            ArgKind::SimLevel => ["off", "low", "high"].iter().map(|s| s.to_string()).collect(),
+            ArgKind::MemoryKey => bus
+                .memory
+                .entries(MemoryScope::Global)
+                .into_iter()
+                .map(|(k, _)| k)
+                .collect(),
+            ArgKind::Choice(words) => words.iter().map(|s| s.to_string()).collect(),
+            ArgKind::Word | ArgKind::Number | ArgKind::Command | ArgKind::Rest => Vec::new(),
+        }
+    }
+
+    fn check(&self, name: &str, value: &str) -> Result<(), String> {
+        let ok = match self {
+            ArgKind::Number => {
+                return match value.parse::<f64>() {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err(format!("invalid {}: {}", name, value)),
+                }
+            }
+            ArgKind::Organ => parse_organ_kind(value).is_some(),
+            ArgKind::SimLevel => SimLevel::from_name(value).is_some(),
+            ArgKind::Choice(words) => words.contains(&value),
+            ArgKind::Word | ArgKind::MemoryKey | ArgKind::Command | ArgKind::Rest => true,
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(format!("unknown {} '{}' (expected {})", name, value, self.describe()))
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ArgSpec {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub required: bool,
+}
+
+impl ArgSpec {
+    pub const fn required(name: &'static str, kind: ArgKind) -> Self {
+        Self { name, kind, required: true }
+    }
+
+    pub const fn optional(name: &'static str, kind: ArgKind) -> Self {
+        Self { name, kind, required: false }
+    }
+}
+
+/// What a command gets to work with.
+pub struct ShellContext<'a> {
+    pub state: &'a KernelState,
+    pub bus: &'a mut Bus,
+    /// The line being run.
+    pub line: &'a str,
+    /// Subscriptions opened from the shell; the command daemon prints
+    /// whatever arrives on them.
+    pub watches: &'a mut Vec<(u64, Subscriber)>,
+    /// The session's output mode; `output` changes it.
+    pub output: OutputMode,
+    /// Set by `quit`; the kernel exits once the result is out.
+    pub quit: bool,
+}
+
+/// How a session shows results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputMode {
+    #[default]
+    Text,
+    Json,
+}
+
+impl OutputMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(OutputMode::Text),
+            "json" => Some(OutputMode::Json),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputMode::Text => "text",
+            OutputMode::Json => "json",
+        }
+    }
+}
+
+/// Why a command succeeded or failed, stable for scripts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultCode {
+    Ok,
+    /// The line could not be parsed.
+    Syntax,
+    UnknownCommand,
+    /// Missing or extra arguments, or a bad subcommand.
+    Usage,
+    /// An argument of the wrong kind, e.g. an unknown organ.
+    InvalidArgument,
+    /// `grep` found nothing.
+    NoMatch,
+    /// The command ran and reported an error.
+    Failed,
+    /// Not run because an earlier `&&` failed.
+    Skipped,
+}
+
+impl ResultCode {
+    pub fn name(self) -> &'static str {
+        match self {
+            ResultCode::Ok => "ok",
+            ResultCode::Syntax => "syntax",
+            ResultCode::UnknownCommand => "unknown_command",
+            ResultCode::Usage => "usage",
+            ResultCode::InvalidArgument => "invalid_argument",
+            ResultCode::NoMatch => "no_match",
+            ResultCode::Failed => "failed",
+            ResultCode::Skipped => "skipped",
+        }
+    }
+
+    /// Process exit status: 0 for success, 1 when a command failed, 2
+    /// when it could not be run as written.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ResultCode::Ok | ResultCode::Skipped => 0,
+            ResultCode::NoMatch | ResultCode::Failed => 1,
+            ResultCode::Syntax
+            | ResultCode::UnknownCommand
+            | ResultCode::Usage
+            | ResultCode::InvalidArgument => 2,
+        }
+    }
+}
+
+/// One command of a line.
+#[derive(Debug, Clone)]
+pub struct StepResult {
+    pub command: String,
+    pub code: ResultCode,
+    /// The output, or the error message.
+    pub output: String,
+}
+
+impl StepResult {
+    fn to_json(&self) -> String {
+        format!(
+            r#"{{"command":"{}","ok":{},"code":"{}","output":"{}"}}"#,
+            escape(&self.command),
+            self.code == ResultCode::Ok,
+            self.code.name(),
+            escape(&self.output)
+        )
+    }
+}
+
+/// The outcome of a whole line.
+#[derive(Debug, Clone)]
+pub struct ShellResult {
+    pub line: String,
+    /// That of the last command that ran.
+    pub code: ResultCode,
+    /// Non-empty outputs and errors, joined by newlines.
+    pub output: String,
+    pub steps: Vec<StepResult>,
+    /// How this result should be shown.
+    pub mode: OutputMode,
+}
+
+impl ShellResult {
+    pub fn ok(&self) -> bool {
+        self.code == ResultCode::Ok
+    }
+
+    /// The error message of the command that failed.
+    pub fn error(&self) -> Option<&str> {
+        if self.ok() {
+            return None;
+        }
+        Some(
+            self.steps
+                .iter()
+                .rfind(|s| s.code != ResultCode::Skipped)
+                .map_or(self.output.as_str(), |s| s.output.as_str()),
+        )
+    }
+
+    pub fn to_json(&self) -> String {
+        let steps: Vec<String> = self.steps.iter().map(StepResult::to_json).collect();
+        format!(
+            r#"{{"ok":{},"code":"{}","exit":{},"line":"{}","output":"{}","error":{},"results":[{}]}}"#,
+            self.ok(),
+            self.code.name(),
+            self.code.exit_code(),
+            escape(&self.line),
+            escape(&self.output),
+            match self.error() {
+                Some(e) => format!(r#""{}""#, escape(e)),
+                None => "null".to_string(),
+            },
+            steps.join(",")
+        )
+    }
+
+    /// The result as its session shows it.
+    pub fn render(&self) -> String {
+        match self.mode {
+            OutputMode::Text => self.output.clone(),
+            OutputMode::Json => self.to_json(),
+        }
+    }
+
+    /// The output as `Ok` or the failure as `Err`, for callers that only
+    /// want the text.
+    pub fn into_result(self) -> Result<String, String> {
+        if self.ok() {
+            Ok(self.output)
+        } else {
+            Err(self.output)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Word(String),
+    /// `|`
+    Pipe,
+    /// `&&`
+    And,
+    /// `;`
+    Then,
+}
+
+/// Split a line into words and operators.
+pub fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    // Distinguishes an empty quoted word ("") from no word at all.
+    let mut in_word = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '\\' => {
+                word.extend(chars.next());
+                in_word = true;
+                continue;
+            }
+            '\'' => {
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated ' quote".to_string()),
+                    }
+                }
+                in_word = true;
+                continue;
+            }
+            '"' => {
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => word.push('\n'),
+                            Some('t') => word.push('\t'),
+                            Some(c @ ('"' | '\\')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err("unterminated \" quote".to_string()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated \" quote".to_string()),
+                    }
+                }
+                in_word = true;
+                continue;
+            }
+            '|' | ';' | '&' => {}
+            c => {
+                word.push(c);
+                in_word = true;
+                continue;
+            }
+        }
+
+        // Whitespace or an operator ends the current word.
+        if in_word {
+            tokens.push(Token::Word(std::mem::take(&mut word)));
+            in_word = false;
+        }
+        match c {
+            '|' => tokens.push(Token::Pipe),
+            ';' => tokens.push(Token::Then),
+            '&' if chars.next_if_eq(&'&').is_some() => tokens.push(Token::And),
+            '&' => return Err("unexpected '&' (did you mean '&&'?)".to_string()),
+            _ => {}
+        }
+    }
+    if in_word {
+        tokens.push(Token::Word(word));
+    }
+    Ok(tokens)
+}
+
+/// Quote `word` so `tokenize` reads it back as one word.
+pub fn quote(word: &str) -> String {
+    let plain = !word.is_empty()
+        && !word
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\' | '|' | ';' | '&'));
+    if plain {
+        return word.to_string();
+    }
+    let mut out = String::from("\"");
+    for c in word.chars() {
+        if matches!(c, '"' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('"');
+    out
+}
+
+/// How a command is joined to the one before it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Joint {
+    Then,
+    And,
+}
+
+/// A command and the filters its output goes through.
+#[derive(Debug)]
+pub struct Pipeline {
+    pub argv: Vec<String>,
+    pub filters: Vec<OutputFilter>,
+}
+
+/// Parse a line into pipelines joined by `;` and `&&`.
+pub fn parse_line(line: &str) -> Result<Vec<(Joint, Pipeline)>, String> {
+    let mut chain = Vec::new();
+    let mut joint = Joint::Then;
+    let mut stages: Vec<Vec<String>> = Vec::new();
+    let mut words = Vec::new();
+
+    for token in tokenize(line)? {
+        let op = match token {
+            Token::Word(w) => {
+                words.push(w);
+                continue;
+            }
+            Token::Pipe => "|",
+            Token::And => "&&",
+            Token::Then => ";",
+        };
+        if words.is_empty() {
+            return Err(format!("syntax error near '{}'", op));
+        }
+        stages.push(std::mem::take(&mut words));
+        if op != "|" {
+            chain.push((joint, pipeline(std::mem::take(&mut stages))?));
+            joint = if op == "&&" { Joint::And } else { Joint::Then };
+        }
+    }
+    if !words.is_empty() {
+        stages.push(words);
+        chain.push((joint, pipeline(stages)?));
+    } else if !stages.is_empty() || joint == Joint::And {
+        // `a |` or `a &&`; a trailing `;` is fine.
+        return Err("syntax error: line ends with an operator".to_string());
+    }
+    Ok(chain)
+}
+
+fn pipeline(mut stages: Vec<Vec<String>>) -> Result<Pipeline, String> {
+    let argv = stages.remove(0);
+    let filters = stages
+        .iter()
+        .map(|s| OutputFilter::parse(s))
+        .collect::<Result<_, _>>()?;
+    Ok(Pipeline { argv, filters })
+}
+
+/// What a command's output can be piped through.
+#[derive(Debug)]
+pub enum OutputFilter {
+    /// Lines containing the text (`-i` ignores case, `-v` inverts).
+    Grep {
+        pattern: String,
+        invert: bool,
+        ignore_case: bool,
+    },
+    Head(usize),
+    Tail(usize),
+    /// The number of lines.
+    Count,
+}
+
+const FILTERS: &[&str] = &["grep", "head", "tail", "count"];
+
+pub const FILTER_USAGE: &str = "grep [-v] [-i] <text> | head [n] | tail [n] | count";
+
+impl OutputFilter {
+    pub fn parse(argv: &[String]) -> Result<Self, String> {
+        let args: Vec<&str> = argv.iter().map(String::as_str).collect();
+        let count = |n: Option<&&str>| match n {
+            None => Ok(10),
+            Some(n) => n.parse().map_err(|_| format!("invalid line count: {}", n)),
+        };
+        match args.as_slice() {
+            ["grep", rest @ ..] => {
+                let (mut invert, mut ignore_case) = (false, false);
+                let mut words = rest.iter();
+                let pattern = loop {
+                    match words.next() {
+                        Some(&"-v") => invert = true,
+                        Some(&"-i") => ignore_case = true,
+                        Some(&"-vi") | Some(&"-iv") => (invert, ignore_case) = (true, true),
+                        Some(p) if words.len() == 0 => break p.to_string(),
+                        _ => return Err(format!("usage: {}", FILTER_USAGE)),
+                    }
+                };
+                Ok(OutputFilter::Grep {
+                    pattern,
+                    invert,
+                    ignore_case,
+                })
+            }
+            ["head"] | ["head", _] => Ok(OutputFilter::Head(count(args.get(1))?)),
+            ["tail"] | ["tail", _] => Ok(OutputFilter::Tail(count(args.get(1))?)),
+            ["count"] => Ok(OutputFilter::Count),
+            [name, ..] => Err(format!("unknown filter '{}' (filters: {})", name, FILTER_USAGE)),
+            [] => Err(format!("usage: {}", FILTER_USAGE)),
+        }
+    }
+
+    /// Filter `output`. `grep` without a match fails, as it does in a
+    /// POSIX shell, so `alerts | grep Cortex && ...` works.
+    pub fn apply(&self, output: &str) -> Result<String, String> {
+        let lines = output.lines();
+        match self {
+            OutputFilter::Grep {
+                pattern,
+                invert,
+                ignore_case,
+            } => {
+                let pattern = if *ignore_case { pattern.to_lowercase() } else { pattern.clone() };
+                let kept: Vec<&str> = lines
+                    .filter(|l| {
+                        let found = if *ignore_case {
+                            l.to_lowercase().contains(&pattern)
+                        } else {
+                            l.contains(&pattern)
+                        };
+                        found != *invert
+                    })
+                    .collect();
+                if kept.is_empty() {
+                    Err(String::new())
+                } else {
+                    Ok(kept.join("\n"))
+                }
+            }
+            OutputFilter::Head(n) => Ok(lines.take(*n).collect::<Vec<_>>().join("\n")),
+            OutputFilter::Tail(n) => {
+                let all: Vec<&str> = lines.collect();
+                Ok(all[all.len().saturating_sub(*n)..].join("\n"))
+            }
+            OutputFilter::Count => Ok(lines.count().to_string()),
+        }
+    }
+}
+
+pub trait ShellCommand: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Usage without the leading "usage: ".
+    fn usage(&self) -> &'static str;
+
+    fn help(&self) -> &'static str;
+
+    fn args(&self) -> &'static [ArgSpec] {
+        &[]
+    }
+
+    /// `argv[0]` is the name the command was invoked as. The arguments
+    /// have already been checked against `args()`.
+    fn execute(&self, ctx: &mut ShellContext, argv: &[&str]) -> Result<String, ShellError>;
+}
+
+/// Why a command failed, with the code clients see. Plain `String`
+/// errors convert to `Failed`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShellError {
+    pub code: ResultCode,
+    pub message: String,
+}
+
+impl ShellError {
+    /// A `Usage` error reading "usage: <text>".
+    pub fn usage(text: &str) -> Self {
+        Self {
+            code: ResultCode::Usage,
+            message: format!("usage: {}", text),
+        }
+    }
+}
+
+impl fmt::Display for ShellError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<String> for ShellError {
+    fn from(message: String) -> Self {
+        Self {
+            code: ResultCode::Failed,
+            message,
+        }
+    }
+}
+
+impl From<&str> for ShellError {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
+pub type CommandFn = fn(&mut ShellContext, &[&str]) -> Result<String, ShellError>;
+
+/// A command described by a table entry and a function.
+#[derive(Clone, Copy)]
+pub struct BuiltinCommand {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub usage: &'static str,
+    pub help: &'static str,
+    pub args: &'static [ArgSpec],
+    pub run: CommandFn,
+}
+
+impl ShellCommand for BuiltinCommand {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        self.aliases
+    }
+
+    fn usage(&self) -> &'static str {
+        self.usage
+    }
+
+    fn help(&self) -> &'static str {
+        self.help
+    }
+
+    fn args(&self) -> &'static [ArgSpec] {
+        self.args
+    }
+
+    fn execute(&self, ctx: &mut ShellContext, argv: &[&str]) -> Result<String, ShellError> {
+        (self.run)(ctx, argv)
+    }
+}
+
+#[derive(Default)]
+pub struct CommandRegistry {
+    commands: Vec<Box<dyn ShellCommand>>,
+}
+
+impl CommandRegistry {
+    /// The kernel's commands plus those contributed by other modules.
+    pub fn standard() -> Self {
+        let mut r = Self::default();
+        commands::register(&mut r);
+        log_sink::register_commands(&mut r);
+        jobs::register_commands(&mut r);
+        r
+    }
+
+    /// Add a command, replacing any registered under the same name.
+    pub fn register(&mut self, command: Box<dyn ShellCommand>) {
+        self.commands.retain(|c| c.name() != command.name());
+        self.commands.push(command);
+    }
+
+    pub fn register_all(&mut self, table: &[BuiltinCommand]) {
+        for c in table {
+            self.register(Box::new(*c));
+        }
+    }
+
+    pub fn find(&self, name: &str) -> Option<&dyn ShellCommand> {
+        self.commands
+            .iter()
+            .find(|c| c.name() == name)
+            .or_else(|| self.commands.iter().find(|c| c.aliases().contains(&name)))
+            .map(|c| c.as_ref())
+    }
+
+    /// Run `ctx.line`: each pipeline in turn, skipping those after a
+    /// failed `&&`. The outputs are joined; the result is that of the
+    /// last pipeline that ran. A leading `--json` or `--text` sets the
+    /// output mode for this line only.
+    pub fn dispatch(&self, ctx: &mut ShellContext) -> ShellResult {
+        let full = ctx.line;
+        let (flag, line) = match full.split_once(char::is_whitespace).unwrap_or((full, "")) {
+            ("--json", rest) => (Some(OutputMode::Json), rest.trim_start()),
+            ("--text", rest) => (Some(OutputMode::Text), rest.trim_start()),
+            _ => (None, full),
+        };
+
+        let mut steps = Vec::new();
+        let mut code = ResultCode::Ok;
+        match parse_line(line) {
+            Ok(chain) => {
+                for (joint, pipeline) in &chain {
+                    let command = pipeline.argv.iter().map(|w| quote(w)).collect::<Vec<_>>().join(" ");
+                    if *joint == Joint::And && code != ResultCode::Ok {
+                        steps.push(StepResult {
+                            command,
+                            code: ResultCode::Skipped,
+                            output: String::new(),
+                        });
+                        continue;
+                    }
+                    let (step_code, output) = match self.run(ctx, pipeline) {
+                        Ok(out) => (ResultCode::Ok, out),
+                        Err(failure) => failure,
+                    };
+                    code = step_code;
+                    steps.push(StepResult {
+                        command,
+                        code,
+                        output,
+                    });
+                }
+            }
+            Err(e) => {
+                code = ResultCode::Syntax;
+                steps.push(StepResult {
+                    command: line.to_string(),
+                    code,
+                    output: e,
+                });
+            }
+        }
+
+        let output = steps
+            .iter()
+            .map(|s| s.output.as_str())
+            .filter(|o| !o.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+        ShellResult {
+            line: line.to_string(),
+            code,
+            output,
+            steps,
+            mode: flag.unwrap_or(ctx.output),
+        }
+    }
+
+    /// Run one command and pipe its output through the filters. Errors
+    /// are not filtered.
+    fn run(&self, ctx: &mut ShellContext, pipeline: &Pipeline) -> Result<String, (ResultCode, String)> {
+        let argv: Vec<&str> = pipeline.argv.iter().map(String::as_str).collect();
+        let command = self.find(argv[0]).ok_or_else(|| {
+            (
+                ResultCode::UnknownCommand,
+                format!("unknown command: '{}' (see `help`)", argv.join(" ")),
+            )
+        })?;
+        check_args(command, &argv[1..])?;
+        let mut output = command.execute(ctx, &argv).map_err(|e| (e.code, e.message))?;
+        for filter in &pipeline.filters {
+            output = filter.apply(&output).map_err(|e| (ResultCode::NoMatch, e))?;
+        }
+        Ok(output)
+    }
+
+    /// Completions for the last word of `line`: command names first,
+    /// then whatever the command's argument schema expects there, or
+    /// filter names after a `|`.
+    pub fn complete(&self, state: &KernelState, bus: &Bus, line: &str) -> Vec<String> {
+        let tokens = match tokenize(line) {
+            Ok(t) => t,
+            Err(_) => return Vec::new(),
+        };
+        let start = tokens
+            .iter()
+            .rposition(|t| !matches!(t, Token::Word(_)))
+            .map_or(0, |i| i + 1);
+        let after_pipe = start > 0 && tokens[start - 1] == Token::Pipe;
+        let mut words: Vec<&str> = tokens[start..]
+            .iter()
+            .filter_map(|t| match t {
+                Token::Word(w) => Some(w.as_str()),
+                _ => None,
+            })
+            .collect();
+        // After whitespace or an operator a new word is starting.
+        let current = match line.ends_with(|c: char| c.is_whitespace() || "|;&".contains(c)) {
+            true => "",
+            false => words.pop().unwrap_or(""),
+        };
+
+        let names = || {
+            self.commands
+                .iter()
+                .flat_map(|c| std::iter::once(c.name()).chain(c.aliases().iter().copied()))
+                .map(String::from)
+                .collect()
+        };
+        let mut candidates: Vec<String> = match (after_pipe, words.first()) {
+            (true, None) => FILTERS.iter().map(|s| s.to_string()).collect(),
+            (true, Some(_)) => Vec::new(),
+            (false, None) => names(),
+            (false, Some(name)) => match self.find(name).and_then(|c| c.args().get(words.len() - 1)) {
+                Some(spec) if matches!(spec.kind, ArgKind::Command) => names(),
+                Some(spec) => spec.kind.candidates(state, bus),
+                None => Vec::new(),
+            },
+        };
+        candidates.retain(|c| c.starts_with(current));
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+
+    pub fn help(&self) -> String {
+        let mut out = format!(
+            "Commands ({}), `help <command>` for details:\n",
+            self.commands.len()
+        );
+        let width = self.commands.iter().map(|c| c.name().len()).max().unwrap_or(0);
+        for c in &self.commands {
+            out.push_str(&format!(" {:<width$}  {}", c.name(), c.help(), width = width));
+            if !c.aliases().is_empty() {
+                out.push_str(&format!(" (also: {})", c.aliases().join(", ")));
+            }
+            out.push('\n');
+        }
+        out.push_str(&format!(
+            "Chain commands with ; and &&, quote with '..' or \"..\", and pipe output through: {}\n",
+            FILTER_USAGE
+        ));
+        out.push_str("Start a line with --json, or run `output json`, for JSON results.\n");
+        out
+    }
+
+    pub fn help_for(&self, name: &str) -> Result<String, ShellError> {
+        let c = self
+            .find(name)
+            .ok_or_else(|| format!("unknown command '{}' (see `help`)", name))?;
+        let mut out = format!("{} :: {}\nusage: {}\n", c.name(), c.help(), c.usage());
+        if !c.aliases().is_empty() {
+            out.push_str(&format!("aliases: {}\n", c.aliases().join(", ")));
+        }
+        if !c.args().is_empty() {
+            out.push_str("arguments:\n");
+            for a in c.args() {
+                out.push_str(&format!(
+                    " - {} :: {}{}\n",
+                    a.name,
+                    a.kind.describe(),
+                    if a.required { "" } else { " (optional)" }
+                ));
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn check_args(command: &dyn ShellCommand, args: &[&str]) -> Result<(), (ResultCode, String)> {
+    let usage = || (ResultCode::Usage, format!("usage: {}", command.usage()));
+    let schema = command.args();
+    for (i, spec) in schema.iter().enumerate() {
+        match (args.get(i), spec.kind) {
+            (None, _) if spec.required => return Err(usage()),
+            (_, ArgKind::Rest) | (None, _) => return Ok(()),
+            (Some(value), kind) => kind
+                .check(spec.name, value)
+                .map_err(|e| (ResultCode::InvalidArgument, e))?,
+        }
+    }
+    if args.len() > schema.len() {
+        return Err(usage());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::harness::Harness;
+
+    #[test]
+    fn lines_are_quoted_and_split_on_operators() {
+        let words = |line: &str| -> Vec<Token> {
+            line.split('|').map(|w| Token::Word(w.to_string())).collect()
+        };
+        assert_eq!(tokenize(r#"a 'b c' "d \"e\"\n" f\ g """#).unwrap(), words("a|b c|d \"e\"\n|f g|"));
+        assert_eq!(
+            tokenize("a|b&&c;d").unwrap(),
+            vec![
+                Token::Word("a".into()),
+                Token::Pipe,
+                Token::Word("b".into()),
+                Token::And,
+                Token::Word("c".into()),
+                Token::Then,
+                Token::Word("d".into()),
+            ]
+        );
+        assert!(tokenize("mem set x 'open").is_err());
+    }
+
+    #[test]
+    fn completion_follows_the_argument_schema() {
+        let h = Harness::new();
+        h.bus.memory.set_text(MemoryScope::Global, "motd", "hi");
+        let complete = |line: &str| h.state.commands.complete(&h.state, &h.bus, line);
+        assert_eq!(complete("sub"), ["subscribe", "subscriptions"]);
+        assert_eq!(complete("help dam"), ["damage"]);
+        assert_eq!(complete("damage c"), ["cortex"]);
+        assert_eq!(complete("sim level "), ["high", "low", "off"]);
+        assert_eq!(complete("mem get m"), ["motd"]);
+        assert_eq!(complete("daemon re"), ["remove", "restart"]);
+        assert_eq!(complete("organs | c"), ["count"]);
+        assert_eq!(complete("status; aw"), ["awareness"]);
+        assert!(complete("damage cortex ").is_empty());
+    }
+}
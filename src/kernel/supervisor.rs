@@ -0,0 +1,250 @@
+//! Daemon supervision.
+//!
+//! The scheduler runs every daemon hook under supervision. A panic marks
+//! the daemon crashed, and it is restarted after an exponential backoff
+//! (1s, 2s, 4s ... up to a minute). A daemon that crashes
+//! `ESCALATE_AFTER` times within `ESCALATE_WINDOW` is given up on and
+//! raised as a critical alert until it is started again from the shell.
+
+use std::any::Any;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::kernel::{Daemon, Schedule, Waker};
+
+pub const BACKOFF_BASE: Duration = Duration::from_secs(1);
+pub const BACKOFF_MAX: Duration = Duration::from_secs(60);
+pub const ESCALATE_AFTER: usize = 3;
+pub const ESCALATE_WINDOW: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DaemonState {
+    Running,
+    /// Stopped from the shell.
+    Stopped,
+    /// Crashed; restarts at `Supervision::retry_at`.
+    Backoff,
+    /// Crashed too often; waits for `daemon start`.
+    Failed,
+}
+
+impl DaemonState {
+    pub fn name(self) -> &'static str {
+        match self {
+            DaemonState::Running => "running",
+            DaemonState::Stopped => "stopped",
+            DaemonState::Backoff => "backoff",
+            DaemonState::Failed => "failed",
+        }
+    }
+}
+
+/// A lifecycle request from the shell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DaemonControl {
+    Start,
+    Stop,
+    Restart,
+    /// Stop it and drop it from the scheduler.
+    Remove,
+}
+
+impl DaemonControl {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "start" => Some(DaemonControl::Start),
+            "stop" => Some(DaemonControl::Stop),
+            "restart" => Some(DaemonControl::Restart),
+            "rm" | "remove" => Some(DaemonControl::Remove),
+            _ => None,
+        }
+    }
+}
+
+/// What to do after a crash.
+pub enum CrashOutcome {
+    RetryAt(Instant),
+    GiveUp,
+}
+
+/// Supervision record for one daemon.
+pub struct Supervision {
+    pub state: DaemonState,
+    /// Whether `start` has run for the current life of the daemon.
+    pub started: bool,
+    pub retry_at: Option<Instant>,
+    /// Crash times inside the escalation window.
+    recent: VecDeque<Instant>,
+    pub crashes: u64,
+    pub restarts: u64,
+    pub runs: u64,
+    pub last_error: Option<String>,
+    /// A worker run has outlived the daemon's timeout.
+    pub hung: bool,
+}
+
+impl Default for Supervision {
+    fn default() -> Self {
+        Self {
+            state: DaemonState::Running,
+            started: false,
+            retry_at: None,
+            recent: VecDeque::new(),
+            crashes: 0,
+            restarts: 0,
+            runs: 0,
+            last_error: None,
+            hung: false,
+        }
+    }
+}
+
+impl Supervision {
+    /// Record a crash and decide whether to retry or give up.
+    pub fn crashed(&mut self, now: Instant, error: String) -> CrashOutcome {
+        self.crashes += 1;
+        self.started = false;
+        self.hung = false;
+        self.last_error = Some(error);
+        while self
+            .recent
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > ESCALATE_WINDOW)
+        {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(now);
+
+        if self.recent.len() >= ESCALATE_AFTER {
+            self.state = DaemonState::Failed;
+            self.retry_at = None;
+            return CrashOutcome::GiveUp;
+        }
+        let backoff = BACKOFF_BASE * 2u32.pow(self.recent.len() as u32 - 1);
+        let at = now + backoff.min(BACKOFF_MAX);
+        self.state = DaemonState::Backoff;
+        self.retry_at = Some(at);
+        CrashOutcome::RetryAt(at)
+    }
+
+    /// Forget recent crashes, e.g. after a manual start.
+    pub fn reset_crashes(&mut self) {
+        self.recent.clear();
+        self.retry_at = None;
+    }
+}
+
+/// The text of a panic payload.
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "panic".to_string()
+    }
+}
+
+/// One row of the `daemons` listing.
+#[derive(Debug, Clone)]
+pub struct DaemonStatus {
+    pub name: &'static str,
+    pub state: DaemonState,
+    pub schedule: String,
+    pub runs: u64,
+    pub crashes: u64,
+    pub restarts: u64,
+    pub health: Result<(), String>,
+    pub last_error: Option<String>,
+}
+
+/// Shared between the scheduler, which publishes daemon status and
+/// applies lifecycle requests, and the shell, which reads and queues
+/// them.
+#[derive(Default)]
+pub struct DaemonTable {
+    rows: Vec<DaemonStatus>,
+    requests: Vec<(String, DaemonControl)>,
+    /// Daemons built from the shell, waiting to be scheduled.
+    added: Vec<(Box<dyn Daemon>, Schedule)>,
+    waker: Option<Waker>,
+}
+
+impl DaemonTable {
+    pub fn new(waker: Waker) -> Self {
+        Self {
+            waker: Some(waker),
+            ..Self::default()
+        }
+    }
+
+    /// Queue a request for the scheduler and wake it.
+    pub fn request(&mut self, name: &str, control: DaemonControl) -> Result<(), String> {
+        if !self.contains(name) {
+            return Err(format!("no daemon named '{}'", name));
+        }
+        self.requests.push((name.to_string(), control));
+        if let Some(waker) = &self.waker {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    /// Queue a new daemon for the scheduler and wake it.
+    pub fn add(&mut self, daemon: Box<dyn Daemon>, schedule: Schedule) -> Result<(), String> {
+        let name = daemon.name();
+        if self.contains(name) || self.added.iter().any(|(d, _)| d.name() == name) {
+            return Err(format!("daemon '{}' is already running", name));
+        }
+        self.added.push((daemon, schedule));
+        if let Some(waker) = &self.waker {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.rows.iter().any(|r| r.name == name)
+    }
+
+    /// A waker for the named daemon, for daemons built outside the
+    /// scheduler.
+    pub fn waker_for(&self, name: &'static str) -> Option<Waker> {
+        self.waker.as_ref().map(|w| w.for_daemon(name))
+    }
+
+    pub fn take_requests(&mut self) -> Vec<(String, DaemonControl)> {
+        std::mem::take(&mut self.requests)
+    }
+
+    pub fn take_added(&mut self) -> Vec<(Box<dyn Daemon>, Schedule)> {
+        std::mem::take(&mut self.added)
+    }
+
+    pub fn publish(&mut self, rows: Vec<DaemonStatus>) {
+        self.rows = rows;
+    }
+
+    pub fn report(&self) -> String {
+        let mut out = format!("Daemons ({}):\n", self.rows.len());
+        for r in &self.rows {
+            out.push_str(&format!(
+                " - {} [{}] {} :: runs {} :: crashes {} :: restarts {}",
+                r.name,
+                r.state.name(),
+                r.schedule,
+                r.runs,
+                r.crashes,
+                r.restarts
+            ));
+            if let Err(reason) = &r.health {
+                out.push_str(&format!(" :: degraded: {}", reason));
+            }
+            if let Some(e) = &r.last_error {
+                out.push_str(&format!(" :: last error: {}", e));
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
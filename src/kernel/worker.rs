@@ -0,0 +1,193 @@
+//! Daemons that do blocking work off the kernel thread.
+//!
+//! A `Worker` runs on its own thread or on the shared `WorkerPool`; a
+//! `WorkerDaemon` wraps it so the scheduler can treat it like any other
+//! daemon. Each due tick hands the worker to its thread together with a
+//! `WorkContext` (a `BusHandle` and a copy of the bus state it may read);
+//! when the run finishes the worker comes back and the loop is woken. A
+//! tick that arrives while the previous run is still going is skipped,
+//! and the scheduler reports the daemon as hung once the run exceeds
+//! the daemon's timeout. A panic in the worker is re-raised on the
+//! kernel thread, where the supervisor handles it like any other crash.
+
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use crate::kernel::{Bus, Daemon, Waker};
+
+pub const DEFAULT_POOL_THREADS: usize = 4;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A fixed set of threads running jobs in submission order.
+pub struct WorkerPool {
+    name: String,
+    threads: usize,
+    tx: Sender<Job>,
+}
+
+impl WorkerPool {
+    pub fn new(name: &str, threads: usize) -> Self {
+        let threads = threads.max(1);
+        let (tx, rx) = mpsc::channel::<Job>();
+        let rx = Arc::new(Mutex::new(rx));
+        for i in 0..threads {
+            let rx = Arc::clone(&rx);
+            let spawned = thread::Builder::new()
+                .name(format!("aion-{}-{}", name, i))
+                .spawn(move || loop {
+                    // Hold the lock only while waiting for the next job.
+                    let job = match rx.lock() {
+                        Ok(rx) => rx.recv(),
+                        Err(_) => return,
+                    };
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => return,
+                    }
+                });
+            if let Err(e) = spawned {
+                println!("[AION-KERNEL] failed to start worker thread: {}", e);
+            }
+        }
+        Self {
+            name: name.to_string(),
+            threads,
+            tx,
+        }
+    }
+
+    /// Pool size from AION_WORKER_THREADS (default 4).
+    pub fn threads_from_env() -> usize {
+        std::env::var("AION_WORKER_THREADS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_POOL_THREADS)
+    }
+
+    pub fn submit(&self, job: Job) -> Result<(), String> {
+        self.tx
+            .send(job)
+            .map_err(|_| format!("worker pool '{}' has shut down", self.name))
+    }
+
+    pub fn describe(&self) -> String {
+        format!("{} ({} threads)", self.name, self.threads)
+    }
+}
+
+/// Where a worker runs.
+pub enum Placement {
+    /// A thread of its own, so nothing else can hold it up.
+    Thread,
+    /// The shared pool.
+    Pool(Arc<WorkerPool>),
+}
+
+/// What a worker gets for each run instead of `&mut Bus`. Workers read
+/// what they need.
+pub struct WorkContext {
+    pub workload_throttle: f32,
+}
+
+pub trait Worker: Send {
+    fn name(&self) -> &'static str;
+    fn run(&mut self, ctx: &WorkContext);
+}
+
+type Finished = (Box<dyn Worker>, Result<(), Box<dyn Any + Send>>);
+
+pub struct WorkerDaemon {
+    name: &'static str,
+    /// `None` while a run is in flight.
+    worker: Option<Box<dyn Worker>>,
+    pool: Arc<WorkerPool>,
+    waker: Waker,
+    done_tx: Sender<Finished>,
+    done_rx: Receiver<Finished>,
+    busy_since: Option<Instant>,
+}
+
+impl WorkerDaemon {
+    /// `waker` should wake this daemon (`Scheduler::waker(Some(name))`),
+    /// so finished runs are collected straight away.
+    pub fn new(worker: Box<dyn Worker>, placement: Placement, waker: Waker) -> Self {
+        let name = worker.name();
+        let pool = match placement {
+            Placement::Thread => Arc::new(WorkerPool::new(name, 1)),
+            Placement::Pool(pool) => pool,
+        };
+        let (done_tx, done_rx) = mpsc::channel();
+        Self {
+            name,
+            worker: Some(worker),
+            pool,
+            waker,
+            done_tx,
+            done_rx,
+            busy_since: None,
+        }
+    }
+
+    /// Take back a finished worker, re-raising its panic if it had one.
+    fn collect(&mut self) {
+        match self.done_rx.try_recv() {
+            Ok((worker, result)) => {
+                self.worker = Some(worker);
+                self.busy_since = None;
+                if let Err(payload) = result {
+                    panic::resume_unwind(payload);
+                }
+            }
+            Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => {}
+        }
+    }
+}
+
+impl Daemon for WorkerDaemon {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn tick(&mut self, now: Instant, bus: &mut Bus) {
+        self.collect();
+        let mut worker = match self.worker.take() {
+            Some(w) => w,
+            // Still running; the scheduler watches the timeout.
+            None => return,
+        };
+        let ctx = WorkContext {
+            workload_throttle: bus.workload_throttle,
+        };
+        let done = self.done_tx.clone();
+        let waker = self.waker.clone();
+        let submitted = self.pool.submit(Box::new(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| worker.run(&ctx)));
+            let _ = done.send((worker, result));
+            waker.wake();
+        }));
+        match submitted {
+            Ok(()) => self.busy_since = Some(now),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    fn on_wake(&mut self, _now: Instant, _bus: &mut Bus) {
+        self.collect();
+    }
+
+    fn busy_since(&self) -> Option<Instant> {
+        self.busy_since
+    }
+
+    fn health(&self) -> Result<(), String> {
+        if self.worker.is_none() && self.busy_since.is_none() {
+            return Err(format!("worker lost: pool {} is not running", self.pool.describe()));
+        }
+        Ok(())
+    }
+}
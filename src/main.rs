@@ -1,13 +1,36 @@
+mod cli;
 mod kernel;
+mod cortex;
 mod organism;
+mod reflex;
 mod telemetry;
+mod homeostasis;
 mod http;
+mod json;
 pub mod capabilities;
 pub mod memory;
 
-
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.as_slice() {
+        [] => run(),
+        [cmd] if cmd == "run" => run(),
+        [cmd, path] if cmd == "run-script" => {
+            let script = kernel::Script::load(path).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(2);
+            });
+            kernel::boot();
+            kernel::run_loop(kernel::Bus::new(), move |state| {
+                std::process::exit(script.run(&state))
+            });
+        }
+        _ => std::process::exit(cli::run(&args)),
+    }
+}
+
+/// Boot the kernel with the interactive shell on the terminal.
+fn run() {
     kernel::boot();
-    let bus = kernel::Bus::new();
-    kernel::run_loop(bus);
+    kernel::run_loop(kernel::Bus::new(), |state| kernel::editor::run(state.shell));
 }
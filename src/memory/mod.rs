@@ -2,7 +2,9 @@
 
 use std::collections::HashMap;
 use std::fmt;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::json;
 
 /// Logical scope for a memory entry.
 /// This is purely conceptual for now; later it can map to nodes/organs/tasks.
@@ -27,6 +29,25 @@ pub enum MemoryValue {
     Map(HashMap<String, MemoryValue>),
 }
 
+impl MemoryValue {
+    /// JSON form of the value (text becomes a string, maps objects).
+    pub fn to_json(&self) -> String {
+        match self {
+            MemoryValue::Text(s) => format!("\"{}\"", json::escape(s)),
+            MemoryValue::Number(n) if n.is_finite() => format!("{}", n),
+            MemoryValue::Number(_) => "null".to_string(),
+            MemoryValue::Flag(b) => format!("{}", b),
+            MemoryValue::Map(m) => {
+                let fields: Vec<String> = m
+                    .iter()
+                    .map(|(k, v)| format!("\"{}\":{}", json::escape(k), v.to_json()))
+                    .collect();
+                format!("{{{}}}", fields.join(","))
+            }
+        }
+    }
+}
+
 impl fmt::Display for MemoryValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -51,7 +72,7 @@ impl fmt::Display for MemoryValue {
 
 /// Internal store keyed by (scope, key).
 #[derive(Debug, Default)]
-struct MemoryStore {
+pub struct MemoryStore {
     data: HashMap<(MemoryScope, String), MemoryValue>,
 }
 
@@ -64,6 +85,17 @@ impl MemoryStore {
         self.data.get(&(scope, key.to_string()))
     }
 
+    fn entries(&self, scope: MemoryScope) -> Vec<(String, MemoryValue)> {
+        let mut out: Vec<(String, MemoryValue)> = self
+            .data
+            .iter()
+            .filter(|((s, _), _)| *s == scope)
+            .map(|((_, k), v)| (k.clone(), v.clone()))
+            .collect();
+        out.sort_by(|a, b| a.0.cmp(&b.0));
+        out
+    }
+
     fn dump(&self) -> String {
         let mut out = String::new();
         out.push_str("Working memory snapshot:\n");
@@ -83,6 +115,12 @@ pub struct MemoryBus {
     inner: Arc<Mutex<MemoryStore>>,
 }
 
+impl Default for MemoryBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MemoryBus {
     pub fn new() -> Self {
         Self {
@@ -104,7 +142,7 @@ impl MemoryBus {
         key: impl Into<String>,
         value: impl Into<String>,
     ) {
-        let mut guard = self.inner.lock().unwrap();
+        let mut guard = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
         guard.set(scope, key.into(), MemoryValue::Text(value.into()));
     }
 
@@ -115,7 +153,7 @@ impl MemoryBus {
         key: impl Into<String>,
         value: f64,
     ) {
-        let mut guard = self.inner.lock().unwrap();
+        let mut guard = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
         guard.set(scope, key.into(), MemoryValue::Number(value));
     }
 
@@ -126,7 +164,7 @@ impl MemoryBus {
         key: impl Into<String>,
         value: bool,
     ) {
-        let mut guard = self.inner.lock().unwrap();
+        let mut guard = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
         guard.set(scope, key.into(), MemoryValue::Flag(value));
     }
 
@@ -137,23 +175,30 @@ impl MemoryBus {
         key: impl Into<String>,
         value: HashMap<String, MemoryValue>,
     ) {
-        let mut guard = self.inner.lock().unwrap();
+        let mut guard = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
         guard.set(scope, key.into(), MemoryValue::Map(value));
     }
 
     /// Read anything back (if present).
     pub fn get(&self, scope: MemoryScope, key: &str) -> Option<MemoryValue> {
-        let guard = self.inner.lock().unwrap();
+        let guard = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
         guard.get(scope, key).cloned()
     }
 
+    /// All entries in one scope, sorted by key.
+    pub fn entries(&self, scope: MemoryScope) -> Vec<(String, MemoryValue)> {
+        let guard = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        guard.entries(scope)
+    }
+
     /// Produce a string dump for debugging / CLI.
     pub fn dump(&self) -> String {
-        let guard = self.inner.lock().unwrap();
+        let guard = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
         guard.dump()
     }
 
     /// Get a cloneable, shareable handle to the inner Arc.
+    #[allow(dead_code)]
     pub fn inner_arc(&self) -> Arc<Mutex<MemoryStore>> {
         Arc::clone(&self.inner)
     }
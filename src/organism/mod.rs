@@ -63,7 +63,7 @@ pub struct Organ {
 impl Organ {
     /// Does this organ provide a specific capability?
     pub fn has_capability(&self, cap: CapabilityKind) -> bool {
-        self.caps.iter().any(|c| *c == cap)
+        self.caps.contains(&cap)
     }
 
     /// Does this organ provide any of the listed capabilities?
@@ -242,10 +242,11 @@ pub fn describe_awareness(a: f32) -> &'static str {
 }
 
 /// Find all organs that provide a given capability.
-pub fn organs_with_capability<'a>(
-    topology: &'a SystemTopology,
+#[allow(dead_code)]
+pub fn organs_with_capability(
+    topology: &SystemTopology,
     cap: CapabilityKind,
-) -> Vec<&'a Organ> {
+) -> Vec<&Organ> {
     topology
         .organs
         .iter()
@@ -254,6 +255,7 @@ pub fn organs_with_capability<'a>(
 }
 
 /// Find all organs that provide *any* of the requested capabilities.
+#[allow(dead_code)]
 pub fn organs_with_any_capability<'a>(
     topology: &'a SystemTopology,
     caps: &[CapabilityKind],
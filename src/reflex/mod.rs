@@ -0,0 +1,312 @@
+//! Reflex arcs for AION.
+//!
+//! The cortex thinks every couple of seconds; a thermal runaway can't
+//! wait that long. A reflex is a hard-wired `condition => action` binding
+//! evaluated synchronously by the StatusDaemon as soon as telemetry
+//! arrives:
+//!
+//! ```text
+//! cpu_temp_c > 90      => disable gpu    ; refractory=30
+//! ram_used_ratio > 0.97 => sim_level=off ; refractory=30
+//! ```
+//!
+//! After firing, a reflex stays quiet for its refractory period (seconds)
+//! even if the condition still holds. A capability it switched is held
+//! for that period too: homeostasis leaves the kind alone meanwhile.
+
+use std::time::{Duration, Instant};
+
+use crate::capabilities::CapabilityKind;
+use crate::cortex::policy::Cmp;
+use crate::kernel::registry::parse_duration;
+use crate::telemetry::SimLevel;
+
+const DEFAULT_REFRACTORY: Duration = Duration::from_secs(30);
+
+/// What a reflex does when it fires.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReflexAction {
+    DisableCapability(CapabilityKind),
+    EnableCapability(CapabilityKind),
+    SimLevel(SimLevel),
+}
+
+impl ReflexAction {
+    fn parse(s: &str) -> Result<Self, String> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        match words.as_slice() {
+            ["disable", kind] => CapabilityKind::from_name(kind)
+                .map(ReflexAction::DisableCapability)
+                .ok_or_else(|| format!("unknown capability kind '{}'", kind)),
+            ["enable", kind] => CapabilityKind::from_name(kind)
+                .map(ReflexAction::EnableCapability)
+                .ok_or_else(|| format!("unknown capability kind '{}'", kind)),
+            [single] => match single.split_once('=') {
+                Some(("sim_level", level)) => SimLevel::from_name(level)
+                    .map(ReflexAction::SimLevel)
+                    .ok_or_else(|| format!("unknown sim level '{}'", level)),
+                _ => Err(format!("unknown action '{}'", s)),
+            },
+            _ => Err(format!("unknown action '{}'", s)),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            ReflexAction::DisableCapability(k) => format!("disable {:?}", k),
+            ReflexAction::EnableCapability(k) => format!("enable {:?}", k),
+            ReflexAction::SimLevel(l) => format!("sim_level={:?}", l),
+        }
+    }
+}
+
+/// One condition → action binding.
+#[derive(Debug, Clone)]
+pub struct Reflex {
+    pub id: u32,
+    /// The condition as written, e.g. `cpu_temp_c > 90`.
+    pub condition: String,
+    pub metric: String,
+    pub cmp: Cmp,
+    pub threshold: f32,
+    pub action: ReflexAction,
+    pub refractory: Duration,
+    pub last_fired: Option<Instant>,
+    pub fire_count: u64,
+}
+
+impl Reflex {
+    /// Parse `<metric> <op> <value> => <action> [; refractory=<duration>]`;
+    /// a bare refractory number is seconds.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (cond, rhs) = text
+            .split_once("=>")
+            .ok_or_else(|| "expected '<metric> <op> <value> => <action>'".to_string())?;
+
+        let words: Vec<&str> = cond.split_whitespace().collect();
+        let [metric, op, value] = words.as_slice() else {
+            return Err(format!("cannot parse condition '{}'", cond.trim()));
+        };
+        let cmp = Cmp::parse(op).ok_or_else(|| format!("bad operator '{}'", op))?;
+        let threshold = value
+            .parse()
+            .map_err(|_| format!("bad number '{}'", value))?;
+
+        let mut parts = rhs.split(';').map(str::trim);
+        let action = ReflexAction::parse(parts.next().unwrap_or(""))?;
+        let mut refractory = DEFAULT_REFRACTORY;
+        for opt in parts.filter(|p| !p.is_empty()) {
+            match opt.split_once('=') {
+                Some(("refractory", secs)) => {
+                    refractory = parse_duration(secs).map_err(|e| format!("refractory: {}", e))?;
+                }
+                _ => return Err(format!("unknown option '{}'", opt)),
+            }
+        }
+
+        Ok(Self {
+            id: 0,
+            condition: words.join(" "),
+            metric: metric.to_string(),
+            cmp,
+            threshold,
+            action,
+            refractory,
+            last_fired: None,
+            fire_count: 0,
+        })
+    }
+
+    fn refractory_left(&self, now: Instant) -> Duration {
+        match self.last_fired {
+            Some(t) => self.refractory.saturating_sub(now.duration_since(t)),
+            None => Duration::ZERO,
+        }
+    }
+}
+
+/// A reflex that fired during one evaluation.
+#[derive(Debug, Clone)]
+pub struct Firing {
+    pub id: u32,
+    pub condition: String,
+    pub value: f32,
+    pub action: ReflexAction,
+}
+
+/// The set of reflex arcs wired into the StatusDaemon.
+#[derive(Debug, Default)]
+pub struct ReflexArcs {
+    reflexes: Vec<Reflex>,
+    next_id: u32,
+}
+
+impl ReflexArcs {
+    pub fn new() -> Self {
+        Self {
+            reflexes: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    /// The stock reflexes: shed GPU work on thermal runaway, and stop the
+    /// simulation when memory is about to run out.
+    pub fn with_defaults() -> Self {
+        let mut arcs = Self::new();
+        for text in [
+            "cpu_temp_c > 90 => disable gpu ; refractory=30",
+            "ram_used_ratio > 0.97 => sim_level=off ; refractory=30",
+        ] {
+            arcs.add(Reflex::parse(text).expect("built-in reflex must parse"));
+        }
+        arcs
+    }
+
+    /// Add a parsed reflex, returning its ID.
+    pub fn add(&mut self, mut reflex: Reflex) -> u32 {
+        reflex.id = self.next_id;
+        self.next_id += 1;
+        let id = reflex.id;
+        self.reflexes.push(reflex);
+        id
+    }
+
+    pub fn remove(&mut self, id: u32) -> bool {
+        let before = self.reflexes.len();
+        self.reflexes.retain(|r| r.id != id);
+        self.reflexes.len() != before
+    }
+
+    /// Capability kinds switched by a reflex still in its refractory
+    /// period.
+    pub fn held_capabilities(&self, now: Instant) -> Vec<CapabilityKind> {
+        self.reflexes
+            .iter()
+            .filter(|r| r.refractory_left(now) > Duration::ZERO)
+            .filter_map(|r| match r.action {
+                ReflexAction::DisableCapability(k) | ReflexAction::EnableCapability(k) => Some(k),
+                ReflexAction::SimLevel(_) => None,
+            })
+            .collect()
+    }
+
+    /// Check every reflex against fresh readings. Reflexes that match and
+    /// are out of their refractory period fire and are returned.
+    pub fn evaluate(&mut self, now: Instant, read: impl Fn(&str) -> Option<f32>) -> Vec<Firing> {
+        let mut fired = Vec::new();
+        for r in self.reflexes.iter_mut() {
+            let Some(value) = read(&r.metric) else {
+                continue;
+            };
+            if !r.cmp.apply(value, r.threshold) || r.refractory_left(now) > Duration::ZERO {
+                continue;
+            }
+            r.last_fired = Some(now);
+            r.fire_count += 1;
+            fired.push(Firing {
+                id: r.id,
+                condition: r.condition.clone(),
+                value,
+                action: r.action,
+            });
+        }
+        fired
+    }
+
+    /// Shell listing of the reflexes and their refractory state.
+    pub fn report(&self, now: Instant) -> String {
+        let mut out = String::from("Reflex arcs:\n");
+        if self.reflexes.is_empty() {
+            out.push_str(" (none)\n");
+        }
+        for r in &self.reflexes {
+            let left = r.refractory_left(now);
+            let state = if left > Duration::ZERO {
+                format!("refractory {:.1}s", left.as_secs_f32())
+            } else {
+                "armed".to_string()
+            };
+            out.push_str(&format!(
+                " - #{} {} => {} :: refractory {}s :: fired {}x :: {}\n",
+                r.id,
+                r.condition,
+                r.action.label(),
+                r.refractory.as_secs_f32(),
+                r.fire_count,
+                state
+            ));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reflexes_parse() {
+        let r = Reflex::parse("cpu_temp_c  >  90 => disable gpu ; refractory=5s").unwrap();
+        assert_eq!((r.condition.as_str(), r.metric.as_str(), r.cmp, r.threshold), ("cpu_temp_c > 90", "cpu_temp_c", Cmp::Gt, 90.0));
+        assert_eq!(r.action, ReflexAction::DisableCapability(CapabilityKind::GpuWorkload));
+        assert_eq!(r.refractory, Duration::from_secs(5));
+
+        let r = Reflex::parse("ram_used_ratio >= 0.9 => sim_level=low").unwrap();
+        assert_eq!(r.action, ReflexAction::SimLevel(SimLevel::Low));
+        assert_eq!(r.refractory, DEFAULT_REFRACTORY);
+        assert_eq!(Reflex::parse("x < 1 => enable net ; refractory=500ms").unwrap().refractory, Duration::from_millis(500));
+        assert_eq!(ReflexArcs::with_defaults().reflexes.len(), 2);
+    }
+
+    #[test]
+    fn bad_reflexes_are_rejected() {
+        let err = |text| Reflex::parse(text).unwrap_err();
+        assert_eq!(err("cpu_temp_c > 90"), "expected '<metric> <op> <value> => <action>'");
+        assert_eq!(err("cpu_temp_c > => disable gpu"), "cannot parse condition 'cpu_temp_c >'");
+        assert_eq!(err("cpu_temp_c ~ 90 => disable gpu"), "bad operator '~'");
+        assert_eq!(err("cpu_temp_c > hot => disable gpu"), "bad number 'hot'");
+        assert_eq!(err("x > 1 => disable flux"), "unknown capability kind 'flux'");
+        assert_eq!(err("x > 1 => sim_level=max"), "unknown sim level 'max'");
+        assert_eq!(err("x > 1 => reboot now"), "unknown action 'reboot now'");
+        assert_eq!(err("x > 1 => sim_level=off ; after=3"), "unknown option 'after=3'");
+        assert!(err("x > 1 => sim_level=off ; refractory=inf").starts_with("refractory: invalid duration"));
+        assert!(err("x > 1 => sim_level=off ; refractory=1e30").ends_with("is longer than a year"));
+    }
+
+    #[test]
+    fn reflexes_fire_then_wait_out_the_refractory_period() {
+        let mut arcs = ReflexArcs::new();
+        let id = arcs.add(Reflex::parse("cpu_temp_c > 90 => disable gpu ; refractory=10").unwrap());
+        let t0 = Instant::now();
+        let at = |secs| t0 + Duration::from_secs(secs);
+
+        assert!(arcs.evaluate(at(0), |_| Some(80.0)).is_empty());
+        assert!(arcs.evaluate(at(0), |_| None).is_empty());
+        let fired = arcs.evaluate(at(1), |_| Some(95.0));
+        assert_eq!((fired.len(), fired[0].id, fired[0].value), (1, id, 95.0));
+        assert!(arcs.evaluate(at(5), |_| Some(95.0)).is_empty());
+        assert!(arcs.report(at(5)).contains("fired 1x :: refractory 6.0s"));
+        assert_eq!(arcs.evaluate(at(11), |_| Some(95.0)).len(), 1);
+        assert!(arcs.report(at(30)).contains("fired 2x :: armed"));
+
+        assert!(arcs.remove(id) && !arcs.remove(id));
+        assert!(arcs.report(at(30)).contains("(none)"));
+    }
+
+    #[test]
+    fn switched_capabilities_are_held_while_refractory() {
+        let mut arcs = ReflexArcs::with_defaults();
+        let t0 = Instant::now();
+        let at = |secs| t0 + Duration::from_secs(secs);
+        assert!(arcs.held_capabilities(at(0)).is_empty());
+
+        let hot = |m: &str| (m == "cpu_temp_c").then_some(95.0).or(Some(0.5));
+        arcs.evaluate(at(0), hot);
+        assert_eq!(arcs.held_capabilities(at(29)), [CapabilityKind::GpuWorkload]);
+        assert!(arcs.held_capabilities(at(30)).is_empty());
+
+        // A sim_level reflex holds no capability.
+        arcs.evaluate(at(40), |m| (m == "ram_used_ratio").then_some(0.99));
+        assert!(arcs.held_capabilities(at(41)).is_empty());
+    }
+}
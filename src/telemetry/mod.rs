@@ -11,8 +11,6 @@
 
 #![allow(dead_code)]
 
-use std::time::Instant;
-
 /// CPU / GPU related metrics.
 #[derive(Debug, Clone, Copy)]
 pub struct CpuGpuMetrics {
@@ -49,11 +47,27 @@ pub enum SimLevel {
     High,
 }
 
+impl SimLevel {
+    /// Parse `off` / `low` / `high` (case-insensitive).
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "off" => Some(SimLevel::Off),
+            "low" => Some(SimLevel::Low),
+            "high" => Some(SimLevel::High),
+            _ => None,
+        }
+    }
+}
+
 /// General interface for anything that supplies telemetry.
 pub trait TelemetryProvider: Send {
     fn read_cpu_gpu_metrics(&mut self) -> CpuGpuMetrics;
     fn read_memory_metrics(&mut self) -> MemoryMetrics;
     fn read_io_metrics(&mut self) -> IoMetrics;
+
+    /// Workload throttle requested by homeostasis (0.0 = none, 1.0 = max).
+    /// Providers that cannot act on it ignore it.
+    fn set_workload_throttle(&mut self, _throttle: f32) {}
 }
 
 /// ---------------------------------------------------------------------------
@@ -65,11 +79,29 @@ pub mod sim {
     pub struct SimulatedTelemetry {
         tick: u64,
         pub level: SimLevel,
+        throttle: f32,
     }
 
     impl SimulatedTelemetry {
         pub fn new(level: SimLevel) -> Self {
-            Self { tick: 0, level }
+            Self {
+                tick: 0,
+                level,
+                throttle: 0.0,
+            }
+        }
+
+        /// Throttling sheds up to half the load and pulls temperature
+        /// toward a 40°C idle.
+        fn apply_throttle(&self, m: CpuGpuMetrics) -> CpuGpuMetrics {
+            let t = self.throttle;
+            CpuGpuMetrics {
+                cpu_load: m.cpu_load * (1.0 - 0.5 * t),
+                cpu_temp_c: m.cpu_temp_c - (m.cpu_temp_c - 40.0).max(0.0) * 0.6 * t,
+                throttling_events: m.throttling_events,
+                gpu_load: m.gpu_load * (1.0 - 0.5 * t),
+                gpu_mem_util: m.gpu_mem_util,
+            }
         }
 
         fn next_phase(&mut self) -> f32 {
@@ -81,7 +113,7 @@ pub mod sim {
     impl TelemetryProvider for SimulatedTelemetry {
         fn read_cpu_gpu_metrics(&mut self) -> CpuGpuMetrics {
             let p = self.next_phase();
-            match self.level {
+            let raw = match self.level {
                 SimLevel::Off => CpuGpuMetrics {
                     cpu_load: 0.15,
                     cpu_temp_c: 45.0,
@@ -106,7 +138,8 @@ pub mod sim {
                         gpu_mem_util: 0.4 + 0.4 * p,
                     }
                 }
-            }
+            };
+            self.apply_throttle(raw)
         }
 
         fn read_memory_metrics(&mut self) -> MemoryMetrics {
@@ -141,6 +174,10 @@ pub mod sim {
                 io_error_rate: 0.0,
             }
         }
+
+        fn set_workload_throttle(&mut self, throttle: f32) {
+            self.throttle = throttle.clamp(0.0, 1.0);
+        }
     }
 }
 
@@ -204,12 +241,12 @@ pub mod real {
     }
 }
 
-/// ---------------------------------------------------------------------------
-/// Health computation utilities
-/// ---------------------------------------------------------------------------
+// ---------------------------------------------------------------------------
+// Health computation utilities
+// ---------------------------------------------------------------------------
 
 fn clamp01(x: f32) -> f32 {
-    x.max(0.0).min(1.0)
+    x.clamp(0.0, 1.0)
 }
 
 pub fn compute_cortex_health(m: &CpuGpuMetrics) -> f32 {